@@ -1,15 +1,11 @@
-use crate::{LoraCommsManager, DeviceInfo, MeshMessage, NodeInfo, LoraCommsError};
+use crate::{Config, ConfigSession, ConfigType, ConversationId, DeliveryStatus, LoraCommsManager, DeviceInfo, DeviceProfile, ModuleConfig, ModuleConfigType, NodeInfo, PortNum, ProfilePlan, SqliteStorage};
 use crate::radio::{RadioConfig, RadioManager, Region, RadioPreset};
 #[cfg(feature = "mqtt")]
-use crate::mqtt::{MqttGateway, MqttConfig, MqttGatewayManager, GatewayStats};
-use crate::protocol::{MessageType, PayloadVariant, MeshPacket, User, Position, TelemetryData};
-use std::sync::{Arc, Mutex};
+use crate::mqtt::{MqttGateway, MqttConfig, GatewayStats};
+use std::sync::{Arc, Mutex, OnceLock};
 use libc::c_void;
 use std::ffi::{CStr, CString};
 use std::ptr;
-use std::mem;
-use std::slice;
-use std::collections::HashMap;
 use libc::c_char;
 
 // Simple test function to verify FFI is working
@@ -21,6 +17,13 @@ pub extern "C" fn lora_comms_test() -> *mut c_char {
         Err(_) => ptr::null_mut(),
     }
 }
+/// Runtime shared by all FFI calls, so background tasks (device listeners,
+/// retransmissions) outlive the call that started them
+fn runtime() -> &'static tokio::runtime::Runtime {
+    static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| tokio::runtime::Runtime::new().expect("failed to start tokio runtime"))
+}
+
 // Global manager instance for C FFI
 static mut GLOBAL_MANAGER: Option<Arc<Mutex<LoraCommsManager>>> = None;
 
@@ -37,6 +40,32 @@ pub extern "C" fn lora_comms_init() -> *mut c_void {
     }
 }
 
+/// Initialize a manager that persists history and node records in the
+/// SQLite database at `db_path`, creating it if needed. Returns null if the
+/// database cannot be opened.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_init_with_storage(db_path: *const c_char) -> *mut c_void {
+    unsafe {
+        if db_path.is_null() {
+            return ptr::null_mut();
+        }
+
+        let path = CStr::from_ptr(db_path).to_string_lossy().to_string();
+        let storage = match SqliteStorage::open(&path) {
+            Ok(storage) => storage,
+            Err(e) => {
+                println!("[Bridge] Failed to open storage at {}: {}", path, e);
+                return ptr::null_mut();
+            }
+        };
+
+        let manager = Arc::new(Mutex::new(LoraCommsManager::with_storage(Arc::new(storage))));
+        GLOBAL_MANAGER = Some(manager.clone());
+        Arc::into_raw(manager) as *mut c_void
+    }
+}
+
 /// Cleanup and free the global manager
 #[no_mangle]
 pub extern "C" fn lora_comms_cleanup(manager: *mut c_void) {
@@ -89,6 +118,8 @@ pub struct CNodeInfo {
     pub battery_level: u32,
     pub voltage: f32,
     pub last_seen: i64,
+    pub snr: f32,
+    pub hops_away: i32,
 }
 
 /// C representation of RadioConfig for FFI
@@ -142,25 +173,32 @@ pub struct CNodeArray {
     pub count: usize,
 }
 
+/// Copy a string for C. Interior NUL bytes (e.g. in node names heard over
+/// the air) are dropped instead of panicking inside an `extern "C"` call.
+fn to_c_string(s: &str) -> *mut c_char {
+    let bytes: Vec<u8> = s.bytes().filter(|&byte| byte != 0).collect();
+    CString::new(bytes).unwrap_or_default().into_raw()
+}
+
 /// Convert Rust DeviceInfo to C representation
 fn device_info_to_c(device: &DeviceInfo) -> CDeviceInfo {
     CDeviceInfo {
-        id: CString::new(device.id.clone()).unwrap().into_raw(),
-        name: CString::new(device.name.clone()).unwrap().into_raw(),
-        path: CString::new(device.path.clone()).unwrap().into_raw(),
+        id: to_c_string(&device.id),
+        name: to_c_string(&device.name),
+        path: to_c_string(&device.path),
         device_type: match device.device_type {
             crate::DeviceType::Serial => 0,
             crate::DeviceType::Bluetooth => 1,
             crate::DeviceType::Tcp => 2,
         },
         manufacturer: device.manufacturer.as_ref()
-            .map(|s| CString::new(s.clone()).unwrap().into_raw())
+            .map(|s| to_c_string(s))
             .unwrap_or(ptr::null_mut()),
         vendor_id: device.vendor_id.as_ref()
-            .map(|s| CString::new(s.clone()).unwrap().into_raw())
+            .map(|s| to_c_string(s))
             .unwrap_or(ptr::null_mut()),
         product_id: device.product_id.as_ref()
-            .map(|s| CString::new(s.clone()).unwrap().into_raw())
+            .map(|s| to_c_string(s))
             .unwrap_or(ptr::null_mut()),
         is_available: device.is_available,
     }
@@ -169,15 +207,17 @@ fn device_info_to_c(device: &DeviceInfo) -> CDeviceInfo {
 /// Convert Rust NodeInfo to C representation
 fn node_info_to_c(node: &NodeInfo) -> CNodeInfo {
     CNodeInfo {
-        id: CString::new(node.id.clone()).unwrap().into_raw(),
-        name: CString::new(node.name.clone()).unwrap().into_raw(),
-        short_name: CString::new(node.short_name.clone()).unwrap().into_raw(),
+        id: to_c_string(&node.id),
+        name: to_c_string(&node.name),
+        short_name: to_c_string(&node.short_name),
         is_online: node.is_online,
-        hw_model: 0, // Default values for now
-        role: 0,
-        battery_level: 0,
-        voltage: 0.0,
-        last_seen: 0,
+        hw_model: i32::from(node.hw_model) as u32,
+        role: i32::from(node.role) as u32,
+        battery_level: node.battery_level.unwrap_or(0),
+        voltage: node.voltage.unwrap_or(0.0),
+        last_seen: node.last_heard as i64,
+        snr: node.snr,
+        hops_away: node.hops_away.map(|hops| hops as i32).unwrap_or(-1),
     }
 }
 
@@ -279,6 +319,7 @@ fn c_mqtt_config_to_rust(c_config: &CMqttConfig) -> Result<MqttConfig, std::ffi:
             keep_alive: c_config.keep_alive,
             qos: c_config.qos,
             retain: c_config.retain,
+            ..Default::default()
         })
     }
 }
@@ -387,6 +428,7 @@ pub extern "C" fn lora_comms_scan_devices(manager: *mut c_void) -> CDeviceArray
 }
 
 /// Connect to a device
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn lora_comms_connect_device(
     manager: *mut c_void,
@@ -416,7 +458,7 @@ pub extern "C" fn lora_comms_connect_device(
         let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
         let mut manager_guard = manager_arc.lock().unwrap();
         
-        let rt = tokio::runtime::Runtime::new().unwrap();
+        let rt = runtime();
         match rt.block_on(manager_guard.connect_device(&device_info)) {
             Ok(device_id) => CString::new(device_id).unwrap().into_raw(),
             Err(_) => ptr::null_mut(),
@@ -425,6 +467,7 @@ pub extern "C" fn lora_comms_connect_device(
 }
 
 /// Send a message
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn lora_comms_send_message(
     manager: *mut c_void,
@@ -448,16 +491,249 @@ pub extern "C" fn lora_comms_send_message(
         let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
         let manager_guard = manager_arc.lock().unwrap();
         
-        let rt = tokio::runtime::Runtime::new().unwrap();
+        let rt = runtime();
         rt.block_on(manager_guard.send_message(
             &device_id_str,
             &message_str,
             destination_str.as_deref(),
+            None,
         )).is_ok()
     }
 }
 
+/// Send a message on a named channel (NULL for the primary) and return its
+/// packet id for `lora_comms_get_delivery_status`, or 0 on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_send_tracked_message(
+    manager: *mut c_void,
+    device_id: *const c_char,
+    message: *const c_char,
+    destination: *const c_char, // NULL for broadcast
+    channel: *const c_char,     // NULL for the primary channel
+) -> u32 {
+    unsafe {
+        if manager.is_null() || device_id.is_null() || message.is_null() {
+            return 0;
+        }
+
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+        let message_str = CStr::from_ptr(message).to_string_lossy().to_string();
+        let optional = |s: *const c_char| {
+            (!s.is_null()).then(|| CStr::from_ptr(s).to_string_lossy().to_string())
+        };
+        let destination_str = optional(destination);
+        let channel_str = optional(channel);
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        let rt = runtime();
+        rt.block_on(manager_guard.send_message(
+            &device_id_str,
+            &message_str,
+            destination_str.as_deref(),
+            channel_str.as_deref(),
+        )).unwrap_or(0)
+    }
+}
+
+/// Send text of any length as one message, fragmented when it does not fit
+/// in a packet; returns the message id, or 0 on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_send_long_message(
+    manager: *mut c_void,
+    device_id: *const c_char,
+    message: *const c_char,
+    destination: *const c_char, // NULL for broadcast
+    channel: *const c_char,     // NULL for the primary channel
+) -> u32 {
+    unsafe {
+        if manager.is_null() || device_id.is_null() || message.is_null() {
+            return 0;
+        }
+
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+        let message_str = CStr::from_ptr(message).to_string_lossy().to_string();
+        let optional = |s: *const c_char| {
+            (!s.is_null()).then(|| CStr::from_ptr(s).to_string_lossy().to_string())
+        };
+        let destination_str = optional(destination);
+        let channel_str = optional(channel);
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        runtime().block_on(manager_guard.send_long_message(
+            &device_id_str,
+            &message_str,
+            destination_str.as_deref(),
+            channel_str.as_deref(),
+        )).unwrap_or(0)
+    }
+}
+
+/// Send `len` bytes of `data` on `portnum` in fragments; the receiver gets
+/// them as one packet on that port. Returns the message id, or 0 on failure.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_send_fragmented(
+    manager: *mut c_void,
+    device_id: *const c_char,
+    portnum: u32,
+    data: *const u8,
+    len: usize,
+    destination: *const c_char, // NULL for broadcast
+    channel: *const c_char,     // NULL for the primary channel
+) -> u32 {
+    unsafe {
+        if manager.is_null() || device_id.is_null() || (data.is_null() && len > 0) {
+            return 0;
+        }
+
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+        let payload = if len == 0 { &[][..] } else { std::slice::from_raw_parts(data, len) };
+        let optional = |s: *const c_char| {
+            (!s.is_null()).then(|| CStr::from_ptr(s).to_string_lossy().to_string())
+        };
+        let destination_str = optional(destination);
+        let channel_str = optional(channel);
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        runtime().block_on(manager_guard.send_fragmented(
+            &device_id_str,
+            PortNum(portnum),
+            payload,
+            destination_str.as_deref(),
+            channel_str.as_deref(),
+        )).unwrap_or(0)
+    }
+}
+
+/// Dry run for an outgoing message: returns the bytes its text takes, or -1
+/// on error, and stores the most that fit in one packet in `max_bytes` (if
+/// not NULL). Pass `reply_to` 0 for a plain message.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_message_size(
+    manager: *mut c_void,
+    message: *const c_char,
+    reply_to: u32,
+    is_reaction: bool,
+    max_bytes: *mut u32,
+) -> i32 {
+    unsafe {
+        if manager.is_null() || message.is_null() {
+            return -1;
+        }
+
+        let message_str = CStr::from_ptr(message).to_string_lossy().to_string();
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        let reply_to = (reply_to != 0).then_some(reply_to);
+        match manager_guard.message_size(&message_str, reply_to, is_reaction) {
+            Ok(size) => {
+                if !max_bytes.is_null() {
+                    *max_bytes = size.max_payload_bytes as u32;
+                }
+                size.payload_bytes as i32
+            }
+            Err(_) => -1,
+        }
+    }
+}
+
+/// Reply to the stored message with packet id `reply_to`; returns the new
+/// packet id, or 0 on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_send_reply(
+    manager: *mut c_void,
+    device_id: *const c_char,
+    reply_to: u32,
+    message: *const c_char,
+) -> u32 {
+    unsafe {
+        if manager.is_null() || device_id.is_null() || message.is_null() {
+            return 0;
+        }
+
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+        let message_str = CStr::from_ptr(message).to_string_lossy().to_string();
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        runtime().block_on(manager_guard.send_reply(&device_id_str, reply_to, &message_str)).unwrap_or(0)
+    }
+}
+
+/// React to the stored message with packet id `reply_to` with an emoji;
+/// returns the new packet id, or 0 on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_send_reaction(
+    manager: *mut c_void,
+    device_id: *const c_char,
+    reply_to: u32,
+    emoji: *const c_char,
+) -> u32 {
+    unsafe {
+        if manager.is_null() || device_id.is_null() || emoji.is_null() {
+            return 0;
+        }
+
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+        let emoji_str = CStr::from_ptr(emoji).to_string_lossy().to_string();
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        runtime().block_on(manager_guard.send_reaction(&device_id_str, reply_to, &emoji_str)).unwrap_or(0)
+    }
+}
+
+/// Delivery status of a tracked message: -1 unknown, 0 pending, 1 delivered,
+/// 2 implicitly acked, 3 failed, 4 not sent (local I/O error). On failure
+/// `error_reason` (if not NULL) receives the Meshtastic `Routing.Error` value.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_get_delivery_status(
+    manager: *mut c_void,
+    packet_id: u32,
+    error_reason: *mut u32,
+) -> i32 {
+    unsafe {
+        if manager.is_null() {
+            return -1;
+        }
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        match manager_guard.delivery_status(packet_id) {
+            None => -1,
+            Some(DeliveryStatus::Pending { .. }) => 0,
+            Some(DeliveryStatus::Delivered) => 1,
+            Some(DeliveryStatus::ImplicitAck) => 2,
+            Some(DeliveryStatus::Failed(error)) => {
+                if !error_reason.is_null() {
+                    *error_reason = i32::from(error) as u32;
+                }
+                3
+            }
+            Some(DeliveryStatus::SendFailed) => 4,
+        }
+    }
+}
+
 /// Get nodes for a device
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn lora_comms_get_nodes(
     manager: *mut c_void,
@@ -476,7 +752,7 @@ pub extern "C" fn lora_comms_get_nodes(
         let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
         let manager_guard = manager_arc.lock().unwrap();
         
-        let rt = tokio::runtime::Runtime::new().unwrap();
+        let rt = runtime();
         let nodes = match rt.block_on(manager_guard.get_nodes(&device_id_str)) {
             Ok(nodes) => nodes,
             Err(_) => return CNodeArray {
@@ -505,72 +781,146 @@ pub extern "C" fn lora_comms_get_nodes(
     }
 }
 
-/// Free device array
+/// Set how many seconds since a node was last heard it still counts as online
 #[no_mangle]
-pub extern "C" fn lora_comms_free_device_array(array: CDeviceArray) {
+pub extern "C" fn lora_comms_set_node_online_threshold(manager: *mut c_void, seconds: u32) -> bool {
     unsafe {
-        if !array.devices.is_null() {
-            let _ = Box::from_raw(std::slice::from_raw_parts_mut(array.devices, array.count));
+        if manager.is_null() {
+            return false;
         }
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let mut manager_guard = manager_arc.lock().unwrap();
+        runtime().block_on(manager_guard.set_node_online_threshold(std::time::Duration::from_secs(seconds as u64)));
+        true
     }
 }
 
-/// Free node array
+/// Trace the route to `node` (a node id such as `!12345678`), blocking until
+/// the reply arrives or `timeout_secs` pass (0 for the default of 60 s).
+/// Returns the route as JSON (`{"origin", "destination", "forward": [{"node",
+/// "snr"}], "back": [...]}`, SNR in dB or null), or NULL on failure.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
-pub extern "C" fn lora_comms_free_node_array(array: CNodeArray) {
+pub extern "C" fn lora_comms_traceroute(
+    manager: *mut c_void,
+    device_id: *const c_char,
+    node: *const c_char,
+    timeout_secs: u32,
+) -> *mut c_char {
     unsafe {
-        if !array.nodes.is_null() {
-            let _ = Box::from_raw(std::slice::from_raw_parts_mut(array.nodes, array.count));
+        if manager.is_null() || device_id.is_null() || node.is_null() {
+            return ptr::null_mut();
         }
+
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+        let node_str = CStr::from_ptr(node).to_string_lossy().to_string();
+        let timeout = match timeout_secs {
+            0 => crate::protocol::traceroute::TRACEROUTE_TIMEOUT,
+            secs => std::time::Duration::from_secs(secs as u64),
+        };
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        response_json(runtime().block_on(manager_guard.traceroute_with_timeout(&device_id_str, &node_str, timeout)), &node_str)
     }
 }
 
-/// Free a C string
+/// JSON for a request's result, or NULL (logging the error)
+fn response_json<T: serde::Serialize>(result: crate::Result<T>, node: &str) -> *mut c_char {
+    match result {
+        Ok(response) => {
+            let json_string = serde_json::to_string(&response).unwrap_or_default();
+            CString::new(json_string).unwrap().into_raw()
+        }
+        Err(e) => {
+            println!("[Bridge] Request to {} failed: {}", node, e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Ask `node` for its position, blocking until it answers or 60 s pass. Returns
+/// the `Position` as JSON, or NULL on failure.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
-pub extern "C" fn lora_comms_free_string(s: *mut c_char) {
+pub extern "C" fn lora_comms_request_position(
+    manager: *mut c_void,
+    device_id: *const c_char,
+    node: *const c_char,
+) -> *mut c_char {
     unsafe {
-        if !s.is_null() {
-            let _ = CString::from_raw(s);
+        if manager.is_null() || device_id.is_null() || node.is_null() {
+            return ptr::null_mut();
         }
+
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+        let node_str = CStr::from_ptr(node).to_string_lossy().to_string();
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        response_json(runtime().block_on(manager_guard.request_position(&device_id_str, &node_str)), &node_str)
     }
 }
 
-// =============================================================================
-// RADIO CONFIGURATION FFI FUNCTIONS
-// =============================================================================
+/// Ask `node` for its user record, blocking until it answers or 60 s pass. Returns
+/// the `User` as JSON, or NULL on failure.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_request_node_info(
+    manager: *mut c_void,
+    device_id: *const c_char,
+    node: *const c_char,
+) -> *mut c_char {
+    unsafe {
+        if manager.is_null() || device_id.is_null() || node.is_null() {
+            return ptr::null_mut();
+        }
 
-/// Set radio configuration for a device
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+        let node_str = CStr::from_ptr(node).to_string_lossy().to_string();
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        response_json(runtime().block_on(manager_guard.request_node_info(&device_id_str, &node_str)), &node_str)
+    }
+}
+
+/// Ask `node` for its device metrics, blocking until it answers or 60 s pass. Returns
+/// the `TelemetryData` as JSON, or NULL on failure.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
-pub extern "C" fn lora_comms_set_radio_config(
+pub extern "C" fn lora_comms_request_telemetry(
     manager: *mut c_void,
     device_id: *const c_char,
-    config: *const CRadioConfig,
-) -> bool {
+    node: *const c_char,
+) -> *mut c_char {
     unsafe {
-        if manager.is_null() || device_id.is_null() || config.is_null() {
-            return false;
+        if manager.is_null() || device_id.is_null() || node.is_null() {
+            return ptr::null_mut();
         }
 
         let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
-        let rust_config = c_radio_config_to_rust(&*config);
+        let node_str = CStr::from_ptr(node).to_string_lossy().to_string();
 
         let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
         let manager_guard = manager_arc.lock().unwrap();
-        
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        
-        // Create a RadioManager and apply configuration
-        let radio_manager = RadioManager::new();
-        rt.block_on(radio_manager.set_device_config(&device_id_str, rust_config)).is_ok()
+
+        response_json(runtime().block_on(manager_guard.request_telemetry(&device_id_str, &node_str)), &node_str)
     }
 }
 
-/// Get radio configuration for a device
+/// Get a device's channels as a Meshtastic channel URL; free with
+/// `lora_comms_free_string`
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
-pub extern "C" fn lora_comms_get_radio_config(
+pub extern "C" fn lora_comms_get_channel_url(
     manager: *mut c_void,
     device_id: *const c_char,
-) -> *mut CRadioConfig {
+) -> *mut c_char {
     unsafe {
         if manager.is_null() || device_id.is_null() {
             return ptr::null_mut();
@@ -580,49 +930,487 @@ pub extern "C" fn lora_comms_get_radio_config(
 
         let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
         let manager_guard = manager_arc.lock().unwrap();
-        
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        
-        // Create a RadioManager and get configuration
-        let radio_manager = RadioManager::new();
-        match rt.block_on(radio_manager.get_device_config(&device_id_str)) {
-            Ok(Some(config)) => {
-                let c_config = rust_radio_config_to_c(&config);
-                Box::into_raw(Box::new(c_config))
-            },
-            _ => ptr::null_mut(),
+
+        let rt = runtime();
+        match rt.block_on(manager_guard.get_channel_url(&device_id_str)) {
+            Ok(url) => CString::new(url).unwrap().into_raw(),
+            Err(_) => ptr::null_mut(),
         }
     }
 }
 
-/// Validate radio configuration
+/// Apply a Meshtastic channel URL to a device
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
-pub extern "C" fn lora_comms_validate_radio_config(
-    config: *const CRadioConfig,
+pub extern "C" fn lora_comms_set_channel_url(
+    manager: *mut c_void,
+    device_id: *const c_char,
+    url: *const c_char,
 ) -> bool {
     unsafe {
-        if config.is_null() {
+        if manager.is_null() || device_id.is_null() || url.is_null() {
             return false;
         }
 
-        let rust_config = c_radio_config_to_rust(&*config);
-        rust_config.validate().is_ok()
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+        let url_str = CStr::from_ptr(url).to_string_lossy().to_string();
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        let rt = runtime();
+        rt.block_on(manager_guard.set_channel_url(&device_id_str, &url_str)).is_ok()
     }
 }
 
-/// Get available radio presets for a region
+/// Read one config section (`config_type` 0 = device … 6 = Bluetooth, as in
+/// `AdminMessage.ConfigType`), blocking until the radio answers or 60 s pass.
+/// Returns the `Config` as JSON (`{"variant": {"Position": {...}}}`), or NULL
+/// on failure.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
-pub extern "C" fn lora_comms_get_radio_presets(
-    region: u32,
+pub extern "C" fn lora_comms_get_config(
+    manager: *mut c_void,
+    device_id: *const c_char,
+    config_type: u32,
 ) -> *mut c_char {
-    let region = match region {
-        0 => Region::US,
-        1 => Region::EU433,
-        2 => Region::EU868,
-        3 => Region::CN,
-        4 => Region::JP,
-        5 => Region::ANZ,
-        6 => Region::KR,
+    unsafe {
+        if manager.is_null() || device_id.is_null() || config_type as usize >= ConfigType::ALL.len() {
+            return ptr::null_mut();
+        }
+
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+        let config_type = ConfigType::ALL[config_type as usize];
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        response_json(runtime().block_on(manager_guard.get_config(&device_id_str, config_type)), &device_id_str)
+    }
+}
+
+/// Write one config section, given as `Config` JSON in the shape
+/// `lora_comms_get_config` returns
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_set_config(
+    manager: *mut c_void,
+    device_id: *const c_char,
+    config_json: *const c_char,
+) -> bool {
+    unsafe {
+        if manager.is_null() || device_id.is_null() || config_json.is_null() {
+            return false;
+        }
+
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+        let config: Config = match serde_json::from_str(&CStr::from_ptr(config_json).to_string_lossy()) {
+            Ok(config) => config,
+            Err(e) => {
+                println!("[Bridge] Invalid config JSON: {}", e);
+                return false;
+            }
+        };
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        runtime().block_on(manager_guard.set_config(&device_id_str, config)).is_ok()
+    }
+}
+
+/// Read one module's settings (`config_type` 0 = MQTT … 6 = canned
+/// messages, as in `AdminMessage.ModuleConfigType`), blocking until the radio
+/// answers or 60 s pass. Returns the `ModuleConfig` as JSON, or NULL on
+/// failure.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_get_module_config(
+    manager: *mut c_void,
+    device_id: *const c_char,
+    config_type: u32,
+) -> *mut c_char {
+    unsafe {
+        if manager.is_null() || device_id.is_null() || config_type as usize >= ModuleConfigType::ALL.len() {
+            return ptr::null_mut();
+        }
+
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+        let config_type = ModuleConfigType::ALL[config_type as usize];
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        response_json(runtime().block_on(manager_guard.get_module_config(&device_id_str, config_type)), &device_id_str)
+    }
+}
+
+/// Write one module's settings, given as `ModuleConfig` JSON in the shape
+/// `lora_comms_get_module_config` returns
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_set_module_config(
+    manager: *mut c_void,
+    device_id: *const c_char,
+    config_json: *const c_char,
+) -> bool {
+    unsafe {
+        if manager.is_null() || device_id.is_null() || config_json.is_null() {
+            return false;
+        }
+
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+        let config: ModuleConfig = match serde_json::from_str(&CStr::from_ptr(config_json).to_string_lossy()) {
+            Ok(config) => config,
+            Err(e) => {
+                println!("[Bridge] Invalid module config JSON: {}", e);
+                return false;
+            }
+        };
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        runtime().block_on(manager_guard.set_module_config(&device_id_str, config)).is_ok()
+    }
+}
+
+/// Canned messages stored on the radio as a JSON array of strings, or NULL on
+/// failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_get_canned_messages(
+    manager: *mut c_void,
+    device_id: *const c_char,
+) -> *mut c_char {
+    unsafe {
+        if manager.is_null() || device_id.is_null() {
+            return ptr::null_mut();
+        }
+
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        response_json(runtime().block_on(manager_guard.get_canned_messages(&device_id_str)), &device_id_str)
+    }
+}
+
+/// Replace the radio's canned messages with a JSON array of strings. Fails if
+/// a message contains `|` or the list exceeds 200 bytes.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_set_canned_messages(
+    manager: *mut c_void,
+    device_id: *const c_char,
+    messages_json: *const c_char,
+) -> bool {
+    unsafe {
+        if manager.is_null() || device_id.is_null() || messages_json.is_null() {
+            return false;
+        }
+
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+        let messages: Vec<String> = match serde_json::from_str(&CStr::from_ptr(messages_json).to_string_lossy()) {
+            Ok(messages) => messages,
+            Err(e) => {
+                println!("[Bridge] Invalid canned message JSON: {}", e);
+                return false;
+            }
+        };
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        match runtime().block_on(manager_guard.set_canned_messages(&device_id_str, &messages)) {
+            Ok(()) => true,
+            Err(e) => {
+                println!("[Bridge] Failed to set canned messages: {}", e);
+                false
+            }
+        }
+    }
+}
+
+/// Apply a JSON `ConfigSession` between begin and commit edit settings.
+/// Returns the `SessionReport` as JSON, or NULL if the session is invalid or
+/// could not be sent.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_apply_config_session(
+    manager: *mut c_void,
+    device_id: *const c_char,
+    session_json: *const c_char,
+) -> *mut c_char {
+    unsafe {
+        if manager.is_null() || device_id.is_null() || session_json.is_null() {
+            return ptr::null_mut();
+        }
+
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+        let session: ConfigSession = match serde_json::from_str(&CStr::from_ptr(session_json).to_string_lossy()) {
+            Ok(session) => session,
+            Err(e) => {
+                println!("[Bridge] Invalid config session JSON: {}", e);
+                return ptr::null_mut();
+            }
+        };
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        response_json(runtime().block_on(manager_guard.apply_session(&device_id_str, &session)), &device_id_str)
+    }
+}
+
+/// Back up a device's settings as a profile document, YAML if `yaml` is true
+/// and JSON otherwise. The Wi-Fi PSK and MQTT password are left out unless
+/// `include_secrets` is true. Reads every channel and config section, so
+/// blocks for a while. Returns NULL on failure.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_backup_profile(
+    manager: *mut c_void,
+    device_id: *const c_char,
+    yaml: bool,
+    include_secrets: bool,
+) -> *mut c_char {
+    unsafe {
+        if manager.is_null() || device_id.is_null() {
+            return ptr::null_mut();
+        }
+
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        let document = runtime().block_on(manager_guard.backup_profile(&device_id_str, include_secrets)).and_then(|profile| {
+            let document = if yaml { profile.to_yaml() } else { profile.to_json() };
+            document.map_err(Into::into)
+        });
+        match document {
+            Ok(document) => CString::new(document).unwrap().into_raw(),
+            Err(e) => {
+                println!("[Bridge] Failed to back up {}: {}", device_id_str, e);
+                ptr::null_mut()
+            }
+        }
+    }
+}
+
+/// Restore a YAML or JSON profile onto a device, writing only the sections
+/// that differ unless `full` is true. Returns the `RestoreReport` as JSON,
+/// or NULL if the profile is invalid or could not be applied.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_restore_profile(
+    manager: *mut c_void,
+    device_id: *const c_char,
+    profile: *const c_char,
+    full: bool,
+) -> *mut c_char {
+    unsafe {
+        if manager.is_null() || device_id.is_null() || profile.is_null() {
+            return ptr::null_mut();
+        }
+
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+        let profile = match DeviceProfile::parse(&CStr::from_ptr(profile).to_string_lossy()) {
+            Ok(profile) => profile,
+            Err(e) => {
+                println!("[Bridge] {}", e);
+                return ptr::null_mut();
+            }
+        };
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        response_json(runtime().block_on(manager_guard.restore_profile(&device_id_str, &profile, full)), &device_id_str)
+    }
+}
+
+/// Compare a device's settings with a YAML or JSON profile. Returns the
+/// `ProfilePlan` as JSON, listing each field that differs, or NULL on failure.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_plan_profile(
+    manager: *mut c_void,
+    device_id: *const c_char,
+    profile: *const c_char,
+) -> *mut c_char {
+    unsafe {
+        if manager.is_null() || device_id.is_null() || profile.is_null() {
+            return ptr::null_mut();
+        }
+
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+        let profile = match DeviceProfile::parse(&CStr::from_ptr(profile).to_string_lossy()) {
+            Ok(profile) => profile,
+            Err(e) => {
+                println!("[Bridge] {}", e);
+                return ptr::null_mut();
+            }
+        };
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        response_json(runtime().block_on(manager_guard.plan_profile(&device_id_str, &profile)), &device_id_str)
+    }
+}
+
+/// Send the sections a JSON `ProfilePlan` changes. Returns the
+/// `SessionReport` as JSON, `null` if the plan had nothing to send, or NULL
+/// on failure.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_apply_profile_plan(
+    manager: *mut c_void,
+    device_id: *const c_char,
+    plan_json: *const c_char,
+) -> *mut c_char {
+    unsafe {
+        if manager.is_null() || device_id.is_null() || plan_json.is_null() {
+            return ptr::null_mut();
+        }
+
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+        let plan: ProfilePlan = match serde_json::from_str(&CStr::from_ptr(plan_json).to_string_lossy()) {
+            Ok(plan) => plan,
+            Err(e) => {
+                println!("[Bridge] Invalid profile plan JSON: {}", e);
+                return ptr::null_mut();
+            }
+        };
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        response_json(runtime().block_on(manager_guard.apply_plan(&device_id_str, &plan)), &device_id_str)
+    }
+}
+
+/// Free device array
+#[no_mangle]
+pub extern "C" fn lora_comms_free_device_array(array: CDeviceArray) {
+    unsafe {
+        if !array.devices.is_null() {
+            let _ = Box::from_raw(ptr::slice_from_raw_parts_mut(array.devices, array.count));
+        }
+    }
+}
+
+/// Free node array
+#[no_mangle]
+pub extern "C" fn lora_comms_free_node_array(array: CNodeArray) {
+    unsafe {
+        if !array.nodes.is_null() {
+            let _ = Box::from_raw(ptr::slice_from_raw_parts_mut(array.nodes, array.count));
+        }
+    }
+}
+
+/// Free a C string
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_free_string(s: *mut c_char) {
+    unsafe {
+        if !s.is_null() {
+            let _ = CString::from_raw(s);
+        }
+    }
+}
+
+// =============================================================================
+// RADIO CONFIGURATION FFI FUNCTIONS
+// =============================================================================
+
+/// Set radio configuration for a device
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_set_radio_config(
+    manager: *mut c_void,
+    device_id: *const c_char,
+    config: *const CRadioConfig,
+) -> bool {
+    unsafe {
+        if manager.is_null() || device_id.is_null() || config.is_null() {
+            return false;
+        }
+
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+        let rust_config = c_radio_config_to_rust(&*config);
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let _manager_guard = manager_arc.lock().unwrap();
+        
+        let rt = runtime();
+        
+        // Create a RadioManager and apply configuration
+        let radio_manager = RadioManager::new();
+        rt.block_on(radio_manager.set_device_config(&device_id_str, rust_config)).is_ok()
+    }
+}
+
+/// Radio settings a device is using, read from the radio; NULL on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_get_radio_config(
+    manager: *mut c_void,
+    device_id: *const c_char,
+) -> *mut CRadioConfig {
+    unsafe {
+        if manager.is_null() || device_id.is_null() {
+            return ptr::null_mut();
+        }
+
+        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        match runtime().block_on(manager_guard.get_radio_config(&device_id_str)) {
+            Ok(config) => {
+                let c_config = rust_radio_config_to_c(&config);
+                Box::into_raw(Box::new(c_config))
+            },
+            Err(_) => ptr::null_mut(),
+        }
+    }
+}
+
+/// Validate radio configuration
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_validate_radio_config(
+    config: *const CRadioConfig,
+) -> bool {
+    unsafe {
+        if config.is_null() {
+            return false;
+        }
+
+        let rust_config = c_radio_config_to_rust(&*config);
+        rust_config.validate().is_ok()
+    }
+}
+
+/// Get available radio presets for a region
+#[no_mangle]
+pub extern "C" fn lora_comms_get_radio_presets(
+    region: u32,
+) -> *mut c_char {
+    let region = match region {
+        0 => Region::US,
+        1 => Region::EU433,
+        2 => Region::EU868,
+        3 => Region::CN,
+        4 => Region::JP,
+        5 => Region::ANZ,
+        6 => Region::KR,
         7 => Region::TW,
         8 => Region::RU,
         9 => Region::IN,
@@ -637,6 +1425,7 @@ pub extern "C" fn lora_comms_get_radio_presets(
 }
 
 /// Free radio config
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn lora_comms_free_radio_config(config: *mut CRadioConfig) {
     unsafe {
@@ -652,6 +1441,7 @@ pub extern "C" fn lora_comms_free_radio_config(config: *mut CRadioConfig) {
 
 #[cfg(feature = "mqtt")]
 /// Create MQTT gateway
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn lora_comms_create_mqtt_gateway(
     manager: *mut c_void,
@@ -663,20 +1453,18 @@ pub extern "C" fn lora_comms_create_mqtt_gateway(
             return false;
         }
 
-        let gateway_id_str = CStr::from_ptr(gateway_id).to_string_lossy().to_string();
+        let _gateway_id_str = CStr::from_ptr(gateway_id).to_string_lossy().to_string();
         let rust_config = match c_mqtt_config_to_rust(&*config) {
             Ok(config) => config,
             Err(_) => return false,
         };
 
         let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
-        let mut manager_guard = manager_arc.lock().unwrap();
-        
-        let rt = tokio::runtime::Runtime::new().unwrap();
+        let _manager_guard = manager_arc.lock().unwrap();
         
         // Create MQTT gateway and add to manager
-        match rt.block_on(MqttGateway::new(rust_config)) {
-            Ok(gateway) => {
+        match MqttGateway::new(rust_config) {
+            Ok(_gateway) => {
                 // For now, we'll just return true as the gateway creation succeeded
                 // In a real implementation, you'd want to store this in the manager
                 true
@@ -688,6 +1476,7 @@ pub extern "C" fn lora_comms_create_mqtt_gateway(
 
 #[cfg(feature = "mqtt")]
 /// Connect MQTT gateway
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn lora_comms_connect_mqtt_gateway(
     manager: *mut c_void,
@@ -698,12 +1487,10 @@ pub extern "C" fn lora_comms_connect_mqtt_gateway(
             return false;
         }
 
-        let gateway_id_str = CStr::from_ptr(gateway_id).to_string_lossy().to_string();
+        let _gateway_id_str = CStr::from_ptr(gateway_id).to_string_lossy().to_string();
 
         let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
-        let manager_guard = manager_arc.lock().unwrap();
-        
-        let rt = tokio::runtime::Runtime::new().unwrap();
+        let _manager_guard = manager_arc.lock().unwrap();
         
         // In a real implementation, you'd retrieve the gateway from the manager
         // and call its connect method
@@ -714,6 +1501,7 @@ pub extern "C" fn lora_comms_connect_mqtt_gateway(
 
 #[cfg(feature = "mqtt")]
 /// Disconnect MQTT gateway
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn lora_comms_disconnect_mqtt_gateway(
     manager: *mut c_void,
@@ -724,10 +1512,10 @@ pub extern "C" fn lora_comms_disconnect_mqtt_gateway(
             return false;
         }
 
-        let gateway_id_str = CStr::from_ptr(gateway_id).to_string_lossy().to_string();
+        let _gateway_id_str = CStr::from_ptr(gateway_id).to_string_lossy().to_string();
 
         let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
-        let manager_guard = manager_arc.lock().unwrap();
+        let _manager_guard = manager_arc.lock().unwrap();
         
         // In a real implementation, you'd retrieve the gateway from the manager
         // and call its disconnect method
@@ -738,6 +1526,7 @@ pub extern "C" fn lora_comms_disconnect_mqtt_gateway(
 
 #[cfg(feature = "mqtt")]
 /// Get MQTT gateway statistics
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn lora_comms_get_mqtt_gateway_stats(
     manager: *mut c_void,
@@ -748,10 +1537,10 @@ pub extern "C" fn lora_comms_get_mqtt_gateway_stats(
             return ptr::null_mut();
         }
 
-        let gateway_id_str = CStr::from_ptr(gateway_id).to_string_lossy().to_string();
+        let _gateway_id_str = CStr::from_ptr(gateway_id).to_string_lossy().to_string();
 
         let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
-        let manager_guard = manager_arc.lock().unwrap();
+        let _manager_guard = manager_arc.lock().unwrap();
         
         // In a real implementation, you'd retrieve the gateway from the manager
         // and get its statistics
@@ -783,7 +1572,7 @@ pub extern "C" fn lora_comms_list_mqtt_gateways(
         }
 
         let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
-        let manager_guard = manager_arc.lock().unwrap();
+        let _manager_guard = manager_arc.lock().unwrap();
         
         // In a real implementation, you'd get the list of gateways from the manager
         // For now, return an empty JSON array
@@ -796,6 +1585,7 @@ pub extern "C" fn lora_comms_list_mqtt_gateways(
 
 #[cfg(feature = "mqtt")]
 /// Free MQTT gateway stats
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn lora_comms_free_mqtt_gateway_stats(stats: *mut CGatewayStats) {
     unsafe {
@@ -813,21 +1603,21 @@ pub extern "C" fn lora_comms_free_mqtt_gateway_stats(stats: *mut CGatewayStats)
 #[no_mangle]
 pub extern "C" fn lora_comms_set_message_callback(
     manager: *mut c_void,
-    callback: extern "C" fn(*const CMeshMessage),
+    _callback: extern "C" fn(*const CMeshMessage),
 ) -> bool {
-    unsafe {
-        if manager.is_null() {
-            return false;
-        }
-
-        // In a real implementation, you'd store this callback in the manager
-        // and call it when messages are received
-        // For now, just return true
-        true
+    if manager.is_null() {
+        return false;
     }
+
+    // In a real implementation, you'd store this callback in the manager
+    // and call it when messages are received
+    // For now, just return true
+    true
 }
 
-/// Get message history for a device
+/// Get stored message history as a JSON array, oldest first. `limit` keeps
+/// only the most recent messages; 0 returns everything. History is shared
+/// by all devices on the manager, so `device_id` does not filter it.
 #[no_mangle]
 pub extern "C" fn lora_comms_get_message_history(
     manager: *mut c_void,
@@ -839,21 +1629,24 @@ pub extern "C" fn lora_comms_get_message_history(
             return ptr::null_mut();
         }
 
-        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
-
         let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
         let manager_guard = manager_arc.lock().unwrap();
-        
-        // In a real implementation, you'd retrieve message history from the manager
-        // For now, return an empty JSON array
-        let empty_history: Vec<String> = vec![];
-        let json_string = serde_json::to_string(&empty_history).unwrap_or_default();
-        
+
+        let limit = (limit > 0).then_some(limit as usize);
+        let history = match manager_guard.message_history(limit) {
+            Ok(history) => history,
+            Err(e) => {
+                println!("[Bridge] Failed to read message history: {}", e);
+                return ptr::null_mut();
+            }
+        };
+
+        let json_string = serde_json::to_string(&history).unwrap_or_default();
         CString::new(json_string).unwrap().into_raw()
     }
 }
 
-/// Clear message history for a device
+/// Delete the stored message history
 #[no_mangle]
 pub extern "C" fn lora_comms_clear_message_history(
     manager: *mut c_void,
@@ -864,18 +1657,278 @@ pub extern "C" fn lora_comms_clear_message_history(
             return false;
         }
 
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        match manager_guard.clear_message_history() {
+            Ok(_) => true,
+            Err(e) => {
+                println!("[Bridge] Failed to clear message history: {}", e);
+                false
+            }
+        }
+    }
+}
+
+/// Get the conversation list as a JSON array, most recently active first;
+/// free with `lora_comms_free_string`
+#[no_mangle]
+pub extern "C" fn lora_comms_get_conversations(manager: *mut c_void) -> *mut c_char {
+    unsafe {
+        if manager.is_null() {
+            return ptr::null_mut();
+        }
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        match manager_guard.conversations() {
+            Ok(conversations) => {
+                let json_string = serde_json::to_string(&conversations).unwrap_or_default();
+                CString::new(json_string).unwrap().into_raw()
+            }
+            Err(e) => {
+                println!("[Bridge] Failed to read conversations: {}", e);
+                ptr::null_mut()
+            }
+        }
+    }
+}
+
+/// Get a page of a conversation as JSON (`{"messages": [...], "next_cursor": ...}`).
+/// `conversation` is a key such as `direct:305419896` or `channel:0`; pass
+/// `before` 0 for the newest page and the returned `next_cursor` after that.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_get_conversation_messages(
+    manager: *mut c_void,
+    conversation: *const c_char,
+    before: i64,
+    limit: u32,
+) -> *mut c_char {
+    unsafe {
+        if manager.is_null() || conversation.is_null() {
+            return ptr::null_mut();
+        }
+
+        let key = CStr::from_ptr(conversation).to_string_lossy().to_string();
+        let Ok(conversation) = key.parse::<ConversationId>() else {
+            println!("[Bridge] Invalid conversation key: {}", key);
+            return ptr::null_mut();
+        };
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        let before = (before > 0).then_some(before);
+        match manager_guard.conversation_messages(conversation, before, limit as usize) {
+            Ok(page) => {
+                let json_string = serde_json::to_string(&page).unwrap_or_default();
+                CString::new(json_string).unwrap().into_raw()
+            }
+            Err(e) => {
+                println!("[Bridge] Failed to read conversation {}: {}", key, e);
+                ptr::null_mut()
+            }
+        }
+    }
+}
+
+/// Mark a conversation read up to message id `up_to`, or entirely if 0
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_mark_conversation_read(
+    manager: *mut c_void,
+    conversation: *const c_char,
+    up_to: i64,
+) -> bool {
+    unsafe {
+        if manager.is_null() || conversation.is_null() {
+            return false;
+        }
+
+        let key = CStr::from_ptr(conversation).to_string_lossy().to_string();
+        let Ok(conversation) = key.parse::<ConversationId>() else {
+            return false;
+        };
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+        manager_guard.mark_conversation_read(conversation, (up_to > 0).then_some(up_to)).is_ok()
+    }
+}
+
+// =============================================================================
+// FILE TRANSFER FFI FUNCTIONS
+// =============================================================================
+
+/// Task forwarding transfer updates to the callback set with
+/// `lora_comms_set_transfer_callback`
+static TRANSFER_CALLBACK: Mutex<Option<tokio::task::JoinHandle<()>>> = Mutex::new(None);
+
+/// Offer the file at `path` to `destination` (a node id such as `!12345678`);
+/// returns the transfer id, or 0 on failure
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_send_file(
+    manager: *mut c_void,
+    device_id: *const c_char,
+    path: *const c_char,
+    destination: *const c_char,
+    channel: *const c_char, // NULL for the primary channel
+) -> u32 {
+    unsafe {
+        if manager.is_null() || device_id.is_null() || path.is_null() || destination.is_null() {
+            return 0;
+        }
+
         let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+        let path_str = CStr::from_ptr(path).to_string_lossy().to_string();
+        let destination_str = CStr::from_ptr(destination).to_string_lossy().to_string();
+        let channel_str = (!channel.is_null()).then(|| CStr::from_ptr(channel).to_string_lossy().to_string());
 
         let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
-        let mut manager_guard = manager_arc.lock().unwrap();
-        
-        // In a real implementation, you'd clear the message history in the manager
-        // For now, return true
+        let manager_guard = manager_arc.lock().unwrap();
+
+        match runtime().block_on(manager_guard.send_file(
+            &device_id_str,
+            std::path::Path::new(&path_str),
+            &destination_str,
+            channel_str.as_deref(),
+        )) {
+            Ok(transfer_id) => transfer_id,
+            Err(e) => {
+                println!("[Bridge] Failed to send {}: {}", path_str, e);
+                0
+            }
+        }
+    }
+}
+
+/// Accept an offered file, to be written to `save_path` once verified
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn lora_comms_accept_file(manager: *mut c_void, transfer_id: u32, save_path: *const c_char) -> bool {
+    unsafe {
+        if manager.is_null() || save_path.is_null() {
+            return false;
+        }
+
+        let path = CStr::from_ptr(save_path).to_string_lossy().to_string();
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        manager_guard.accept_file(transfer_id, path.into()).is_ok()
+    }
+}
+
+/// Turn down an offered file, or stop receiving one
+#[no_mangle]
+pub extern "C" fn lora_comms_reject_file(manager: *mut c_void, transfer_id: u32) -> bool {
+    unsafe {
+        if manager.is_null() {
+            return false;
+        }
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        manager_guard.reject_file(transfer_id).is_ok()
+    }
+}
+
+/// Stop sending a file
+#[no_mangle]
+pub extern "C" fn lora_comms_cancel_file_transfer(manager: *mut c_void, transfer_id: u32) -> bool {
+    unsafe {
+        if manager.is_null() {
+            return false;
+        }
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        manager_guard.cancel_file_transfer(transfer_id).is_ok()
+    }
+}
+
+/// Continue a paused outgoing transfer
+#[no_mangle]
+pub extern "C" fn lora_comms_resume_file_transfer(manager: *mut c_void, transfer_id: u32) -> bool {
+    unsafe {
+        if manager.is_null() {
+            return false;
+        }
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        runtime().block_on(manager_guard.resume_file_transfer(transfer_id)).is_ok()
+    }
+}
+
+/// Every file transfer this session as a JSON array
+#[no_mangle]
+pub extern "C" fn lora_comms_get_file_transfers(manager: *mut c_void) -> *mut c_char {
+    unsafe {
+        if manager.is_null() {
+            return ptr::null_mut();
+        }
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        let json_string = serde_json::to_string(&manager_guard.file_transfers()).unwrap_or_default();
+        CString::new(json_string).unwrap().into_raw()
+    }
+}
+
+/// Call `callback` with a JSON transfer record whenever a transfer is offered,
+/// progresses or ends. The string is only valid during the call. Pass NULL to
+/// stop.
+#[no_mangle]
+pub extern "C" fn lora_comms_set_transfer_callback(
+    manager: *mut c_void,
+    callback: Option<extern "C" fn(*const c_char)>,
+) -> bool {
+    unsafe {
+        if manager.is_null() {
+            return false;
+        }
+
+        let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
+        let manager_guard = manager_arc.lock().unwrap();
+
+        let mut current = TRANSFER_CALLBACK.lock().unwrap();
+        if let Some(task) = current.take() {
+            task.abort();
+        }
+        let Some(callback) = callback else {
+            return true;
+        };
+
+        let mut updates = manager_guard.subscribe_transfers();
+        *current = Some(runtime().spawn(async move {
+            loop {
+                match updates.recv().await {
+                    Ok(update) => {
+                        let json_string = serde_json::to_string(&update).unwrap_or_default();
+                        if let Ok(json) = CString::new(json_string) {
+                            callback(json.as_ptr());
+                        }
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        }));
         true
     }
 }
 
 /// Get device statistics
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn lora_comms_get_device_stats(
     manager: *mut c_void,
@@ -886,10 +1939,10 @@ pub extern "C" fn lora_comms_get_device_stats(
             return ptr::null_mut();
         }
 
-        let device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
+        let _device_id_str = CStr::from_ptr(device_id).to_string_lossy().to_string();
 
         let manager_arc = &*(manager as *const Mutex<LoraCommsManager>);
-        let manager_guard = manager_arc.lock().unwrap();
+        let _manager_guard = manager_arc.lock().unwrap();
         
         // In a real implementation, you'd get device stats from the manager
         // For now, return placeholder stats as JSON
@@ -906,3 +1959,21 @@ pub extern "C" fn lora_comms_get_device_stats(
         CString::new(json_string).unwrap().into_raw()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_names_with_nul_bytes_do_not_panic() {
+        let node = NodeInfo::new("!00002222".to_string(), "a\0b".to_string(), "\0".to_string());
+        let c_node = node_info_to_c(&node);
+        unsafe {
+            assert_eq!(CStr::from_ptr(c_node.name).to_str().unwrap(), "ab");
+            assert_eq!(CStr::from_ptr(c_node.short_name).to_str().unwrap(), "");
+            drop(CString::from_raw(c_node.id));
+            drop(CString::from_raw(c_node.name));
+            drop(CString::from_raw(c_node.short_name));
+        }
+    }
+}
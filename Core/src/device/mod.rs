@@ -1,4 +1,5 @@
 pub mod serial;
+pub mod stream;
 #[cfg(feature = "bluetooth")]
 pub mod bluetooth;
 #[cfg(feature = "tcp")]
@@ -7,7 +8,9 @@ pub mod tcp;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
-use crate::protocol::{MeshMessage, NodeInfo};
+use tokio::sync::mpsc;
+use std::time::Duration;
+use crate::protocol::{Channel, ChannelTable, Config, MeshMessage, MeshPacket, NodeInfo, RadioConfig, User};
 
 #[derive(Debug, thiserror::Error)]
 pub enum DeviceError {
@@ -95,15 +98,50 @@ pub trait Device {
     
     /// Send a message through the device
     async fn send_message(&self, message: &MeshMessage) -> Result<(), DeviceError>;
+
+    /// Hand a fully built mesh packet to the radio
+    async fn send_packet(&self, packet: &MeshPacket) -> Result<(), DeviceError>;
+
+    /// Node number of the attached radio; 0 until connected
+    fn my_node_num(&self) -> u32 {
+        0
+    }
     
     /// Get the list of nodes visible to this device
     async fn get_nodes(&self) -> Result<Vec<NodeInfo>, DeviceError>;
+
+    /// How recently a node must have been heard for `get_nodes` to report it
+    /// online
+    fn set_online_threshold(&mut self, _threshold: Duration) {}
     
+    /// Channels configured on the device; empty until the config has been read
+    fn get_channels(&self) -> ChannelTable {
+        ChannelTable::default()
+    }
+
+    /// LoRa settings reported by the device, if known
+    fn get_lora_config(&self) -> Option<RadioConfig> {
+        None
+    }
+
+    /// User record the radio broadcasts for itself, if known
+    fn get_owner(&self) -> Option<User> {
+        None
+    }
+
+    /// Record a config section read back from the radio, so the settings
+    /// cached at connect stay current
+    fn update_config(&mut self, _config: &Config) {}
+
+    /// Record a channel written to or read back from the radio, so the
+    /// channel table cached at connect stays current
+    fn update_channel(&mut self, _channel: &Channel) {}
+
     /// Get device information
     async fn get_device_info(&self) -> Result<String, DeviceError>;
     
-    /// Start listening for incoming messages
-    async fn start_listening(&mut self) -> Result<(), DeviceError>;
+    /// Start listening for incoming packets, forwarding each to `packets`
+    async fn start_listening(&mut self, packets: mpsc::UnboundedSender<MeshPacket>) -> Result<(), DeviceError>;
     
     /// Stop listening for incoming messages
     async fn stop_listening(&mut self) -> Result<(), DeviceError>;
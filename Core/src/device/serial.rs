@@ -1,167 +1,69 @@
+use super::stream::{request_config, write_to_radio, ConfigSnapshot, FromRadioReader, CONFIG_TIMEOUT, WAKE_BYTES};
 use super::{Device, DeviceError, DeviceInfo, DeviceType};
-use crate::protocol::{MeshMessage, NodeInfo, ProtocolHandler, MeshPacket, decode_packet, encode_packet, extract_frame_from_buffer};
+use crate::protocol::{from_radio, ChannelTable, MeshMessage, NodeDb, NodeInfo, ProtocolHandler, MeshPacket, ToRadio};
 use crate::radio::RadioConfig;
 use async_trait::async_trait;
 use std::time::Duration;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncWriteExt, ReadHalf, WriteHalf};
 use tokio_serial::{SerialPortBuilderExt, SerialStream};
 use tokio::sync::mpsc;
-use tokio::time::{timeout, sleep};
-use bytes::BytesMut;
-use crc::{Crc, CRC_16_IBM_3740};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use tokio::sync::Mutex;
 
 pub struct SerialDevice {
     path: String,
-    port: Option<Arc<Mutex<SerialStream>>>,
+    writer: Option<Arc<Mutex<WriteHalf<SerialStream>>>>,
+    /// Read half of the port; moved into the listener task by `start_listening`
+    reader: Option<FromRadioReader<ReadHalf<SerialStream>>>,
     is_connected: bool,
+    #[allow(dead_code)]
     protocol_handler: ProtocolHandler,
     message_tx: Option<mpsc::UnboundedSender<MeshPacket>>,
     config_id: u32,
     my_node_num: u32,
-    buffer: BytesMut,
+    snapshot: Option<ConfigSnapshot>,
+    channels: ChannelTable,
+    /// Seeded from the config dump, then kept current by the listener task
+    node_db: Arc<RwLock<NodeDb>>,
 }
 
 impl SerialDevice {
     pub async fn new(path: &str) -> Result<Self, DeviceError> {
         Ok(Self {
             path: path.to_string(),
-            port: None,
+            writer: None,
+            reader: None,
             is_connected: false,
             protocol_handler: ProtocolHandler::new(),
             message_tx: None,
             config_id: rand::random(),
             my_node_num: 0,
-            buffer: BytesMut::new(),
+            snapshot: None,
+            channels: ChannelTable::default(),
+            node_db: Arc::new(RwLock::new(NodeDb::new())),
         })
     }
 
-    /// Send a protobuf message with proper framing
-    async fn send_protobuf_message(&self, packet: &MeshPacket) -> Result<(), DeviceError> {
-        if let Some(port) = &self.port {
-            let encoded = encode_packet(packet).map_err(|e| DeviceError::ConnectionFailed {
-                message: format!("Failed to encode packet: {}", e),
-            })?;
-            
-            // Meshtastic serial protocol uses HDLC-like framing
-            let framed = self.frame_message(&encoded);
-            
-            let mut port_guard = port.lock().await;
-            port_guard.write_all(&framed).await.map_err(|e| DeviceError::ConnectionFailed {
-                message: format!("Failed to write to serial port: {}", e),
-            })?;
-            port_guard.flush().await.map_err(|e| DeviceError::ConnectionFailed {
-                message: format!("Failed to flush serial port: {}", e),
-            })?;
-            
-            Ok(())
+    /// Radio state collected during the connect-time config exchange
+    pub fn config_snapshot(&self) -> Option<&ConfigSnapshot> {
+        self.snapshot.as_ref()
+    }
+
+    /// Send a ToRadio envelope over the serial link
+    async fn send_to_radio(&self, message: &ToRadio) -> Result<(), DeviceError> {
+        if let Some(writer) = &self.writer {
+            let mut writer = writer.lock().await;
+            write_to_radio(&mut *writer, message).await
         } else {
             Err(DeviceError::ConnectionFailed {
                 message: "Device not connected".to_string(),
             })
         }
     }
-    
-    /// Frame a message using HDLC-like framing for Meshtastic serial protocol
-    fn frame_message(&self, data: &[u8]) -> Vec<u8> {
-        const FRAME_START: u8 = 0x94;
-        const FRAME_END: u8 = 0x7E;
-        const ESCAPE: u8 = 0x7D;
-        const ESCAPE_XOR: u8 = 0x20;
-        
-        let mut framed = Vec::new();
-        framed.push(FRAME_START);
-        
-        // Calculate CRC16
-        let crc = Crc::<u16>::new(&CRC_16_IBM_3740);
-        let checksum = crc.checksum(data);
-        
-        // Escape and add data
-        for &byte in data {
-            if byte == FRAME_START || byte == FRAME_END || byte == ESCAPE {
-                framed.push(ESCAPE);
-                framed.push(byte ^ ESCAPE_XOR);
-            } else {
-                framed.push(byte);
-            }
-        }
-        
-        // Add CRC (little endian)
-        let crc_bytes = checksum.to_le_bytes();
-        for &byte in &crc_bytes {
-            if byte == FRAME_START || byte == FRAME_END || byte == ESCAPE {
-                framed.push(ESCAPE);
-                framed.push(byte ^ ESCAPE_XOR);
-            } else {
-                framed.push(byte);
-            }
-        }
-        
-        framed.push(FRAME_END);
-        framed
-    }
-    
-    /// Process incoming serial data and extract complete frames
-    async fn process_incoming_data(&mut self, new_data: &[u8]) -> Result<Vec<MeshPacket>, DeviceError> {
-        self.buffer.extend_from_slice(new_data);
-        let mut packets = Vec::new();
-        
-        while let Some(frame) = self.extract_frame() {
-            if let Ok(packet) = decode_packet(&frame) {
-                packets.push(packet);
-            }
-        }
-        
-        Ok(packets)
-    }
-    
-    /// Extract a complete frame from the buffer
-    fn extract_frame(&mut self) -> Option<Vec<u8>> {
-        const FRAME_START: u8 = 0x94;
-        const FRAME_END: u8 = 0x7E;
-        const ESCAPE: u8 = 0x7D;
-        const ESCAPE_XOR: u8 = 0x20;
-        
-        // Find frame boundaries
-        let start_pos = self.buffer.iter().position(|&b| b == FRAME_START)?;
-        let end_pos = self.buffer[start_pos + 1..].iter().position(|&b| b == FRAME_END)? + start_pos + 1;
-        
-        // Extract and remove the frame from buffer
-        let frame_data = self.buffer[start_pos + 1..end_pos].to_vec();
-        self.buffer = self.buffer.split_off(end_pos + 1);
-        
-        // Unescape the frame
-        let mut unescaped = Vec::new();
-        let mut i = 0;
-        while i < frame_data.len() {
-            if frame_data[i] == ESCAPE && i + 1 < frame_data.len() {
-                unescaped.push(frame_data[i + 1] ^ ESCAPE_XOR);
-                i += 2;
-            } else {
-                unescaped.push(frame_data[i]);
-                i += 1;
-            }
-        }
-        
-        // Verify CRC and return payload (without CRC)
-        if unescaped.len() >= 2 {
-            let payload_len = unescaped.len() - 2;
-            let payload = &unescaped[..payload_len];
-            let received_crc = u16::from_le_bytes([unescaped[payload_len], unescaped[payload_len + 1]]);
-            
-            let crc = Crc::<u16>::new(&CRC_16_IBM_3740);
-            let calculated_crc = crc.checksum(payload);
-            
-            if received_crc == calculated_crc {
-                Some(payload.to_vec())
-            } else {
-                eprintln!("CRC mismatch: received {:04x}, calculated {:04x}", received_crc, calculated_crc);
-                None
-            }
-        } else {
-            None
-        }
+
+    /// Send a mesh packet wrapped in a ToRadio envelope
+    async fn send_protobuf_message(&self, packet: &MeshPacket) -> Result<(), DeviceError> {
+        self.send_to_radio(&ToRadio::packet(packet.clone())).await
     }
     
     /// Configure device settings
@@ -169,7 +71,7 @@ impl SerialDevice {
         let config_packet = MeshPacket {
             from: self.my_node_num,
             to: self.my_node_num, // Send to self for configuration
-            id: self.config_id,
+            id: rand::random(),
             payload: Some(crate::protocol::PayloadVariant::Admin(config.to_admin_message())),
             hop_limit: 3,
             want_ack: true,
@@ -184,32 +86,34 @@ impl SerialDevice {
         self.send_protobuf_message(&config_packet).await
     }
 
-    async fn write_command(&mut self, command: &str) -> Result<(), DeviceError> {
-        if let Some(port) = &self.port {
-            let command_bytes = format!("{}\n", command).into_bytes();
-            let mut port_guard = port.lock().await;
-            port_guard.write_all(&command_bytes).await?;
-            port_guard.flush().await?;
-            Ok(())
-        } else {
-            Err(DeviceError::ConnectionFailed {
-                message: "Device not connected".to_string(),
-            })
-        }
-    }
+    /// Open the port at `baud_rate` and run the want_config exchange
+    async fn open_and_handshake(&mut self, baud_rate: u32) -> Result<(), DeviceError> {
+        let port = tokio_serial::new(&self.path, baud_rate)
+            .timeout(Duration::from_secs(2))
+            .open_native_async()
+            .map_err(|e| DeviceError::ConnectionFailed {
+                message: format!("Serial port error: {}", e),
+            })?;
 
-    async fn read_response(&mut self) -> Result<String, DeviceError> {
-        if let Some(port) = &self.port {
-            let mut buffer = vec![0; 1024];
-            let mut port_guard = port.lock().await;
-            let n = port_guard.read(&mut buffer).await?;
-            let response = String::from_utf8_lossy(&buffer[..n]).to_string();
-            Ok(response)
-        } else {
-            Err(DeviceError::ConnectionFailed {
-                message: "Device not connected".to_string(),
-            })
+        let (read_half, mut write_half) = tokio::io::split(port);
+        write_half.write_all(&WAKE_BYTES).await?;
+
+        let mut reader = FromRadioReader::new(read_half);
+        let snapshot = request_config(&mut reader, &mut write_half, self.config_id, CONFIG_TIMEOUT).await?;
+
+        self.my_node_num = snapshot.my_node_num();
+        self.channels = snapshot.channel_table();
+        {
+            let mut node_db = self.node_db.write().unwrap();
+            for node in &snapshot.nodes {
+                node_db.merge_dump(node);
+            }
         }
+        self.snapshot = Some(snapshot);
+        self.reader = Some(reader);
+        self.writer = Some(Arc::new(Mutex::new(write_half)));
+        self.is_connected = true;
+        Ok(())
     }
 }
 
@@ -223,21 +127,11 @@ impl Device for SerialDevice {
         for &baud_rate in &baud_rates {
             println!("[DEBUG] Trying to connect at {} baud", baud_rate);
             
-            match tokio_serial::new(&self.path, baud_rate)
-                .timeout(Duration::from_secs(2))
-                .open_native_async()
-            {
-                Ok(port) => {
-                    self.port = Some(Arc::new(Mutex::new(port)));
-                    self.is_connected = true;
-                    
-                    // Test connection by checking if we can communicate
-                    if self.is_connected() {
-                        println!("[DEBUG] Successfully connected at {} baud", baud_rate);
-                        return Ok(());
-                    } else {
-                        self.disconnect().await?;
-                    }
+            // Only a completed config exchange proves we picked the right rate
+            match self.open_and_handshake(baud_rate).await {
+                Ok(()) => {
+                    println!("[DEBUG] Connected at {} baud, node {:08x}", baud_rate, self.my_node_num);
+                    return Ok(());
                 }
                 Err(e) => {
                     last_error = Some(e);
@@ -253,12 +147,14 @@ impl Device for SerialDevice {
     
 
     async fn disconnect(&mut self) -> Result<(), DeviceError> {
-        if let Some(port) = self.port.take() {
-            drop(port);
+        if self.writer.is_some() {
+            // Best effort; the port may already be gone
+            let _ = self.send_to_radio(&ToRadio::disconnect()).await;
         }
+        self.writer = None;
+        self.reader = None;
         self.is_connected = false;
         self.message_tx = None;
-        self.buffer.clear();
         Ok(())
     }
 
@@ -269,13 +165,14 @@ impl Device for SerialDevice {
     async fn send_message(&self, message: &MeshMessage) -> Result<(), DeviceError> {
         let mesh_packet = MeshPacket {
             from: self.my_node_num,
-            to: if message.to == "broadcast" { 0xFFFFFFFF } else { 
-                message.to.parse().unwrap_or(0xFFFFFFFF) 
-            },
-            id: rand::random(),
+            to: crate::protocol::parse_node_id(&message.to).unwrap_or(crate::protocol::BROADCAST_ADDR),
+            id: message.packet_id.unwrap_or_else(rand::random),
             payload: Some(crate::protocol::PayloadVariant::Text(message.text.clone())),
-            hop_limit: 3,
+            hop_limit: message.hop_limit.unwrap_or(3),
             want_ack: message.want_ack.unwrap_or(false),
+            channel: message.channel.unwrap_or(0),
+            reply_id: message.reply_id.unwrap_or(0),
+            emoji: message.is_reaction as u32,
             priority: crate::protocol::MeshPacket_Priority::DEFAULT,
             rx_time: std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
@@ -288,71 +185,96 @@ impl Device for SerialDevice {
     }
 
     async fn get_nodes(&self) -> Result<Vec<NodeInfo>, DeviceError> {
-        // This would query the device for node information
-        // For now, return a mock node
-        Ok(vec![NodeInfo {
-            id: "broadcast".to_string(),
-            name: "All Nodes".to_string(),
-            short_name: "ALL".to_string(),
-            is_online: true,
-        }])
+        Ok(self.node_db.read().unwrap().nodes())
+    }
+
+    fn set_online_threshold(&mut self, threshold: Duration) {
+        self.node_db.write().unwrap().set_online_threshold(threshold);
+    }
+
+    async fn send_packet(&self, packet: &MeshPacket) -> Result<(), DeviceError> {
+        self.send_protobuf_message(packet).await
+    }
+
+    fn my_node_num(&self) -> u32 {
+        self.my_node_num
+    }
+
+    fn get_channels(&self) -> ChannelTable {
+        self.channels.clone()
+    }
+
+    fn get_owner(&self) -> Option<crate::protocol::User> {
+        self.snapshot.as_ref().and_then(|snapshot| snapshot.owner().cloned())
+    }
+
+    fn get_lora_config(&self) -> Option<crate::protocol::RadioConfig> {
+        self.snapshot.as_ref().and_then(|snapshot| snapshot.lora_config().cloned())
+    }
+
+    fn update_config(&mut self, config: &crate::protocol::Config) {
+        if let Some(snapshot) = self.snapshot.as_mut() {
+            snapshot.update_config(config);
+            // A new modem preset renames unnamed channels
+            self.channels = snapshot.channel_table();
+        }
+    }
+
+    fn update_channel(&mut self, channel: &crate::protocol::Channel) {
+        if let Some(snapshot) = self.snapshot.as_mut() {
+            snapshot.update_channel(channel);
+            self.channels = snapshot.channel_table();
+        }
     }
 
     async fn get_device_info(&self) -> Result<String, DeviceError> {
-        // This would query device information
-        // For now, return basic info
-        Ok(format!("Serial device connected on {}", self.path))
+        let firmware = self.snapshot.as_ref()
+            .and_then(|snapshot| snapshot.metadata.as_ref())
+            .map(|metadata| metadata.firmware_version.clone())
+            .unwrap_or_else(|| "unknown".to_string());
+        Ok(format!(
+            "Serial device connected on {} (node {:08x}, firmware {})",
+            self.path, self.my_node_num, firmware
+        ))
     }
 
-    async fn start_listening(&mut self) -> Result<(), DeviceError> {
-        if let Some(port) = &self.port {
-            let (tx, mut rx) = mpsc::unbounded_channel();
-            self.message_tx = Some(tx);
-            
-            let port_clone = Arc::clone(port);
-            let tx_clone = self.message_tx.as_ref().unwrap().clone();
-            
-            // Spawn background task to read from serial port
-            tokio::spawn(async move {
-                let mut buffer = [0u8; 1024];
-                let mut frame_buffer = BytesMut::new();
-                
-                loop {
-                    let mut port_guard = port_clone.lock().await;
-                    match port_guard.read(&mut buffer).await {
-                        Ok(n) if n > 0 => {
-                            drop(port_guard); // Release lock before processing
-                            
-                            frame_buffer.extend_from_slice(&buffer[..n]);
-                            
-                            // Process complete frames
-                            while let Some(frame) = extract_frame_from_buffer(&mut frame_buffer) {
-                                if let Ok(packet) = decode_packet(&frame) {
-                                    if tx_clone.send(packet).is_err() {
-                                        break; // Channel closed, exit task
-                                    }
-                                }
+    async fn start_listening(&mut self, packets: mpsc::UnboundedSender<MeshPacket>) -> Result<(), DeviceError> {
+        let Some(mut reader) = self.reader.take() else {
+            return Err(DeviceError::ConnectionFailed {
+                message: "Device not connected".to_string(),
+            });
+        };
+
+        self.message_tx = Some(packets.clone());
+        let tx_clone = packets;
+        let node_db = self.node_db.clone();
+        
+        // Spawn background task to read from serial port
+        tokio::spawn(async move {
+            loop {
+                match reader.next().await {
+                    Ok(Some(message)) => match message.variant {
+                        Some(from_radio::Variant::Packet(packet)) => {
+                            node_db.write().unwrap().update_from_packet(&packet);
+                            if tx_clone.send(packet).is_err() {
+                                break; // Channel closed, exit task
                             }
                         }
-                        Ok(_) => {
-                            // No data read, continue
-                            drop(port_guard);
-                            sleep(Duration::from_millis(10)).await;
-                        }
-                        Err(e) => {
-                            eprintln!("Serial read error: {}", e);
-                            break;
+                        Some(from_radio::Variant::NodeInfo(node)) => {
+                            node_db.write().unwrap().merge_dump(&node);
                         }
+                        other => log::debug!("Unhandled FromRadio: {:?}", other),
+                    },
+                    Ok(None) => break,
+                    Err(e) => {
+                        eprintln!("Serial read error: {}", e);
+                        break;
                     }
                 }
-            });
-            
-            Ok(())
-        } else {
-            Err(DeviceError::ConnectionFailed {
-                message: "Device not connected".to_string(),
-            })
-        }
+            }
+        });
+        
+        Ok(())
     }
 
     async fn stop_listening(&mut self) -> Result<(), DeviceError> {
@@ -468,3 +390,4 @@ pub async fn scan_serial_devices() -> Result<Vec<DeviceInfo>, DeviceError> {
 
     Ok(devices)
 }
+
@@ -0,0 +1,259 @@
+//! Transport-agnostic pieces of the Meshtastic stream API: reading and
+//! writing framed `FromRadio`/`ToRadio` envelopes and the connect-time
+//! `want_config_id` exchange.
+
+use super::DeviceError;
+use crate::protocol::{
+    config, decode_from_radio, encode_to_radio, from_radio, Channel, ChannelTable, Config,
+    DeviceMetadata, FrameCodec, FrameError, FromRadio, MeshNodeInfo, ModuleConfig, MyNodeInfo,
+    RadioConfig, ToRadio, User,
+};
+use bytes::BytesMut;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_util::codec::Decoder;
+
+/// How long to wait for the radio to finish its config dump
+pub const CONFIG_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sent before the first frame so a sleeping radio switches its serial
+/// console over to the protobuf API
+pub const WAKE_BYTES: [u8; 32] = [crate::protocol::codec::START2; 32];
+
+/// Initial snapshot of the radio's state, collected between `want_config_id`
+/// and the matching `config_complete_id`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConfigSnapshot {
+    pub my_info: Option<MyNodeInfo>,
+    pub nodes: Vec<MeshNodeInfo>,
+    pub channels: Vec<Channel>,
+    pub config: Vec<Config>,
+    pub module_config: Vec<ModuleConfig>,
+    pub metadata: Option<DeviceMetadata>,
+}
+
+impl ConfigSnapshot {
+    /// Node number of the attached radio, or 0 if MyNodeInfo never arrived
+    pub fn my_node_num(&self) -> u32 {
+        self.my_info.as_ref().map(|info| info.my_node_num).unwrap_or(0)
+    }
+
+    /// User record of the attached radio, from its own entry in the node dump
+    pub fn owner(&self) -> Option<&User> {
+        let my_node_num = self.my_node_num();
+        self.nodes.iter().find(|node| node.num == my_node_num)?.user.as_ref()
+    }
+
+    /// LoRa section of the radio's config, if it was part of the dump
+    pub fn lora_config(&self) -> Option<&RadioConfig> {
+        self.config.iter().find_map(|config| match &config.variant {
+            Some(config::Variant::Lora(lora)) => Some(lora),
+            _ => None,
+        })
+    }
+
+    /// Replace the section of the same type with `config`, as read back from
+    /// the radio after the dump
+    pub fn update_config(&mut self, config: &Config) {
+        let config_type = config.config_type();
+        self.config.retain(|section| section.config_type() != config_type);
+        self.config.push(config.clone());
+    }
+
+    /// Replace the channel in the same slot with `channel`
+    pub fn update_channel(&mut self, channel: &Channel) {
+        self.channels.retain(|slot| slot.index != channel.index);
+        self.channels.push(channel.clone());
+    }
+
+    /// Modem preset from the LoRa config section, used to name unnamed channels
+    pub fn modem_preset(&self) -> i32 {
+        self.lora_config().map(|lora| lora.modem_preset).unwrap_or(0)
+    }
+
+    /// Channel table built from the channels in the dump. Channels the
+    /// table rejects (bad PSK length, index out of range) are skipped.
+    pub fn channel_table(&self) -> ChannelTable {
+        let mut table = ChannelTable::new(self.modem_preset());
+        for channel in &self.channels {
+            if let Err(e) = table.upsert(channel) {
+                log::warn!("Skipping channel {}: {}", channel.index, e);
+            }
+        }
+        table
+    }
+}
+
+/// Frame and write a single ToRadio envelope
+pub async fn write_to_radio<W>(writer: &mut W, message: &ToRadio) -> Result<(), DeviceError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let encoded = encode_to_radio(message).map_err(|e| DeviceError::InvalidConfiguration {
+        message: format!("Failed to encode ToRadio: {}", e),
+    })?;
+    let framed = FrameCodec::frame(&encoded).map_err(|e| DeviceError::InvalidConfiguration {
+        message: format!("Failed to frame ToRadio: {}", e),
+    })?;
+
+    writer.write_all(&framed).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Reads FromRadio envelopes off a byte stream, skipping firmware log output
+/// and frames that fail to decode
+pub struct FromRadioReader<R> {
+    reader: R,
+    buffer: BytesMut,
+    codec: FrameCodec,
+}
+
+impl<R: AsyncRead + Unpin> FromRadioReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: BytesMut::with_capacity(1024),
+            codec: FrameCodec::new(),
+        }
+    }
+
+    /// Next envelope from the radio, or `None` once the stream is closed
+    pub async fn next(&mut self) -> Result<Option<FromRadio>, DeviceError> {
+        loop {
+            match self.codec.decode(&mut self.buffer) {
+                Ok(Some(frame)) => match decode_from_radio(&frame) {
+                    Ok(message) => return Ok(Some(message)),
+                    Err(e) => log::warn!("Dropping undecodable FromRadio frame: {}", e),
+                },
+                Ok(None) => {
+                    if self.reader.read_buf(&mut self.buffer).await? == 0 {
+                        return Ok(None);
+                    }
+                }
+                // Firmware log output between frames is expected
+                Err(e @ FrameError::Garbage { .. }) => log::debug!("{}", e),
+                Err(e) => log::warn!("Stream framing error: {}", e),
+            }
+        }
+    }
+}
+
+/// Ask the radio for its full state and collect the dump until it reports
+/// `config_complete_id` for our nonce
+pub async fn request_config<R, W>(
+    reader: &mut FromRadioReader<R>,
+    writer: &mut W,
+    config_id: u32,
+    timeout: Duration,
+) -> Result<ConfigSnapshot, DeviceError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    write_to_radio(writer, &ToRadio::want_config(config_id)).await?;
+
+    let collect = async {
+        let mut snapshot = ConfigSnapshot::default();
+        loop {
+            let Some(message) = reader.next().await? else {
+                return Err(DeviceError::ConnectionFailed {
+                    message: "Stream closed during config exchange".to_string(),
+                });
+            };
+
+            match message.variant {
+                Some(from_radio::Variant::MyInfo(info)) => snapshot.my_info = Some(info),
+                Some(from_radio::Variant::NodeInfo(node)) => snapshot.nodes.push(node),
+                Some(from_radio::Variant::Channel(channel)) => snapshot.channels.push(channel),
+                Some(from_radio::Variant::Config(config)) => snapshot.config.push(config),
+                Some(from_radio::Variant::ModuleConfig(config)) => snapshot.module_config.push(config),
+                Some(from_radio::Variant::Metadata(metadata)) => snapshot.metadata = Some(metadata),
+                Some(from_radio::Variant::ConfigCompleteId(id)) if id == config_id => return Ok(snapshot),
+                // A dump for some other client's nonce, or live traffic that
+                // raced the dump; neither belongs in our snapshot
+                other => log::debug!("Ignoring FromRadio during config exchange: {:?}", other),
+            }
+        }
+    };
+
+    tokio::time::timeout(timeout, collect)
+        .await
+        .map_err(|_| DeviceError::Timeout)?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{config, decode_to_radio, encode_from_radio, to_radio, RadioConfig};
+    use tokio::io::{duplex, split};
+
+    async fn send(writer: &mut (impl AsyncWrite + Unpin), variant: from_radio::Variant) {
+        let encoded = encode_from_radio(&FromRadio { id: 0, variant: Some(variant) }).unwrap();
+        writer.write_all(&FrameCodec::frame(&encoded).unwrap()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_request_config_collects_dump() {
+        let (client, radio) = duplex(4096);
+        let (client_rx, mut client_tx) = split(client);
+        let (radio_rx, mut radio_tx) = split(radio);
+
+        let fake_radio = tokio::spawn(async move {
+            // Expect a want_config_id request first
+            let mut buffer = BytesMut::new();
+            let mut radio_rx = radio_rx;
+            let frame = loop {
+                if let Some(frame) = FrameCodec::new().decode(&mut buffer).unwrap() {
+                    break frame;
+                }
+                radio_rx.read_buf(&mut buffer).await.unwrap();
+            };
+            let Some(to_radio::Variant::WantConfigId(id)) = decode_to_radio(&frame).unwrap().variant else {
+                panic!("expected want_config_id");
+            };
+
+            radio_tx.write_all(b"DEBUG | ??:??:?? 1 [Main] booting\r\n").await.unwrap();
+            send(&mut radio_tx, from_radio::Variant::MyInfo(MyNodeInfo { my_node_num: 0xdeadbeef, ..Default::default() })).await;
+            send(&mut radio_tx, from_radio::Variant::NodeInfo(MeshNodeInfo { num: 0x12345678, ..Default::default() })).await;
+            send(&mut radio_tx, from_radio::Variant::Channel(Channel { index: 0, ..Default::default() })).await;
+            send(&mut radio_tx, from_radio::Variant::Config(Config {
+                variant: Some(config::Variant::Lora(RadioConfig { hop_limit: 5, ..Default::default() })),
+            })).await;
+            send(&mut radio_tx, from_radio::Variant::ModuleConfig(ModuleConfig::default())).await;
+            send(&mut radio_tx, from_radio::Variant::Metadata(DeviceMetadata {
+                firmware_version: "2.3.2".to_string(),
+                ..Default::default()
+            })).await;
+            // Completion for a different nonce must not end our exchange
+            send(&mut radio_tx, from_radio::Variant::ConfigCompleteId(id.wrapping_add(1))).await;
+            send(&mut radio_tx, from_radio::Variant::ConfigCompleteId(id)).await;
+            radio_tx
+        });
+
+        let mut reader = FromRadioReader::new(client_rx);
+        let snapshot = request_config(&mut reader, &mut client_tx, 42, Duration::from_secs(5)).await.unwrap();
+        let _radio_tx = fake_radio.await.unwrap();
+
+        assert_eq!(snapshot.my_node_num(), 0xdeadbeef);
+        assert_eq!(snapshot.nodes.len(), 1);
+        assert_eq!(snapshot.channels.len(), 1);
+        assert!(matches!(
+            snapshot.config.as_slice(),
+            [Config { variant: Some(config::Variant::Lora(RadioConfig { hop_limit: 5, .. })) }]
+        ));
+        assert_eq!(snapshot.module_config.len(), 1);
+        assert_eq!(snapshot.metadata.unwrap().firmware_version, "2.3.2");
+    }
+
+    #[tokio::test]
+    async fn test_request_config_times_out() {
+        let (client, _radio) = duplex(4096);
+        let (client_rx, mut client_tx) = split(client);
+        let mut reader = FromRadioReader::new(client_rx);
+
+        let result = request_config(&mut reader, &mut client_tx, 7, Duration::from_millis(50)).await;
+        assert!(matches!(result, Err(DeviceError::Timeout)));
+    }
+}
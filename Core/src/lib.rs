@@ -2,6 +2,7 @@ pub mod bridge;
 pub mod device;
 pub mod protocol;
 pub mod radio;
+pub mod storage;
 #[cfg(feature = "mqtt")]
 pub mod mqtt;
 
@@ -9,11 +10,13 @@ use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
 use uuid::Uuid;
-use chrono::{DateTime, Utc};
 
 pub use device::*;
 pub use protocol::*;
 pub use bridge::*;
+pub use storage::{
+    ConversationId, ConversationSummary, MessagePage, Reaction, SqliteStorage, Storage, StorageError, StoredMessage,
+};
 
 /// Main error type for the library
 #[derive(Debug, thiserror::Error)]
@@ -30,25 +33,99 @@ pub enum LoraCommsError {
     Connection { message: String },
     #[error("Timeout error")]
     Timeout,
+    #[error("Channel not found: {0}")]
+    ChannelNotFound(String),
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+    #[error("No stored message with packet id {0:08x}")]
+    MessageNotFound(u32),
+    #[error("Request failed: {0:?}")]
+    RequestFailed(Routing_Error),
+    #[error("Request cancelled")]
+    RequestCancelled,
+    #[error("Unexpected response to request {0:08x}")]
+    UnexpectedResponse(u32),
+    #[error("File transfer error: {0}")]
+    Transfer(#[from] TransferError),
+    #[error("Config session error: {0}")]
+    Session(#[from] SessionError),
+    #[error("Profile error: {0}")]
+    Profile(#[from] ProfileError),
 }
 
 pub type Result<T> = std::result::Result<T, LoraCommsError>;
 
+impl From<RequestError> for LoraCommsError {
+    fn from(error: RequestError) -> Self {
+        match error {
+            RequestError::Timeout => LoraCommsError::Timeout,
+            RequestError::Cancelled => LoraCommsError::RequestCancelled,
+            RequestError::Failed(reason) => LoraCommsError::RequestFailed(reason),
+        }
+    }
+}
+
+/// A connected device, shared so callers can await on it without holding the
+/// device table lock
+type SharedDevice = Arc<tokio::sync::Mutex<Box<dyn Device + Send + Sync>>>;
+
 /// Core communication manager
 pub struct LoraCommsManager {
-    devices: Arc<Mutex<HashMap<String, Box<dyn Device + Send + Sync>>>>,
+    devices: Arc<Mutex<HashMap<String, SharedDevice>>>,
+    #[allow(dead_code)]
     message_sender: Option<mpsc::UnboundedSender<MeshMessage>>,
     message_receiver: Option<mpsc::UnboundedReceiver<MeshMessage>>,
+    processor: Arc<MessageProcessor>,
+    delivery: DeliveryTracker,
+    requests: PendingRequests,
+    dedup: DedupStore,
+    online_threshold: std::time::Duration,
+    fragments: Arc<FragmentLayer>,
+    transfers: TransferService,
+    /// Packets queued by `fragments` and `transfers`, and the messages
+    /// `fragments` has reassembled; drained once the first device is added
+    background_queues: Mutex<Option<(mpsc::UnboundedReceiver<MeshPacket>, mpsc::UnboundedReceiver<MeshPacket>)>>,
+    /// Device each node was last heard through
+    routes: Arc<Mutex<HashMap<u32, String>>>,
 }
 
 impl LoraCommsManager {
+    /// Manager that keeps history and node records in memory only
     pub fn new() -> Self {
+        let storage = SqliteStorage::in_memory().expect("in-memory SQLite database");
+        Self::with_storage(Arc::new(storage))
+    }
+
+    /// Manager that persists history and node records in `storage`
+    pub fn with_storage(storage: Arc<dyn Storage>) -> Self {
         let (tx, rx) = mpsc::unbounded_channel();
         
+        let dedup = DedupStore::default();
+        let (outbound_tx, outbound_rx) = mpsc::unbounded_channel();
+        let (completed_tx, completed_rx) = mpsc::unbounded_channel();
+        let fragments = Arc::new(FragmentLayer::new(outbound_tx.clone(), completed_tx));
+        let transfers = TransferService::new(outbound_tx);
+
         Self {
             devices: Arc::new(Mutex::new(HashMap::new())),
+            processor: Arc::new(
+                MessageProcessor::new()
+                    .with_message_channel(tx.clone())
+                    .with_dedup(dedup.clone())
+                    .with_storage(storage)
+                    .with_handler(PortNum::FRAGMENT_APP, fragments.clone())
+                    .with_handler(PortNum::FILE_TRANSFER_APP, Arc::new(transfers.clone())),
+            ),
+            dedup,
             message_sender: Some(tx),
             message_receiver: Some(rx),
+            delivery: DeliveryTracker::new(),
+            requests: PendingRequests::new(),
+            online_threshold: protocol::nodedb::DEFAULT_ONLINE_THRESHOLD,
+            fragments,
+            transfers,
+            background_queues: Mutex::new(Some((outbound_rx, completed_rx))),
+            routes: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -82,7 +159,7 @@ impl LoraCommsManager {
     pub async fn connect_device(&mut self, device_info: &DeviceInfo) -> Result<String> {
         let device_id = Uuid::new_v4().to_string();
         
-        let device: Box<dyn Device + Send + Sync> = match device_info.device_type {
+        let mut device: Box<dyn Device + Send + Sync> = match device_info.device_type {
             #[cfg(feature = "serial")]
             DeviceType::Serial => {
                 Box::new(device::serial::SerialDevice::new(&device_info.path).await?)
@@ -113,46 +190,833 @@ impl LoraCommsManager {
             },
         };
 
-        self.devices.lock().unwrap().insert(device_id.clone(), device);
+        // Opens the link and runs the config exchange with the radio
+        device.connect().await?;
+        self.add_device(device_id.clone(), device).await?;
         Ok(device_id)
     }
 
+    /// Start listening on a connected device and route its packets through
+    /// the delivery tracker and message processor
+    async fn add_device(&self, device_id: String, mut device: Box<dyn Device + Send + Sync>) -> Result<()> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        device.set_online_threshold(self.online_threshold);
+        device.start_listening(tx).await?;
+        if let Some(lora) = device.get_lora_config() {
+            self.transfers.set_radio_config(radio::RadioConfig::from(&lora));
+        }
+
+        let processor = Arc::clone(&self.processor);
+        let delivery = self.delivery.clone();
+        let requests = self.requests.clone();
+        let routes = Arc::clone(&self.routes);
+        let source = device_id.clone();
+        tokio::spawn(async move {
+            while let Some(packet) = rx.recv().await {
+                routes.lock().unwrap().insert(packet.from, source.clone());
+                delivery.handle_packet(&packet);
+                requests.handle_packet(&packet);
+                if let Err(e) = processor.process_packet(packet).await {
+                    log::warn!("Failed to process packet: {}", e);
+                }
+            }
+        });
+
+        self.devices.lock().unwrap().insert(device_id, Arc::new(tokio::sync::Mutex::new(device)));
+        self.start_background_tasks();
+        // Transfers that stalled while the radio was away pick up again
+        self.transfers.resume_paused();
+        Ok(())
+    }
+
+    /// Feed reassembled messages to the processor, send the packets queued
+    /// by the fragment layer and file transfers, and run the fragment timers.
+    /// Runs once, from the first `add_device`, where a runtime is known to be
+    /// available.
+    fn start_background_tasks(&self) {
+        let Some((mut outbound, mut completed)) = self.background_queues.lock().unwrap().take() else {
+            return;
+        };
+
+        let processor = Arc::clone(&self.processor);
+        tokio::spawn(async move {
+            while let Some(packet) = completed.recv().await {
+                if let Err(e) = processor.process_packet(packet).await {
+                    log::warn!("Failed to process reassembled packet: {}", e);
+                }
+            }
+        });
+
+        let devices = Arc::clone(&self.devices);
+        let routes = Arc::clone(&self.routes);
+        tokio::spawn(async move {
+            while let Some(packet) = outbound.recv().await {
+                let device = {
+                    let devices = devices.lock().unwrap();
+                    let route = routes.lock().unwrap().get(&packet.to).cloned();
+                    route.and_then(|id| devices.get(&id).cloned())
+                        .or_else(|| devices.values().next().cloned())
+                };
+                let Some(device) = device else {
+                    log::debug!("No device to send packet to {:08x}", packet.to);
+                    continue;
+                };
+                let device = device.lock().await;
+                let packet = MeshPacket { from: device.my_node_num(), ..packet };
+                if let Err(e) = device.send_packet(&packet).await {
+                    log::warn!("Failed to send packet to {:08x}: {}", packet.to, e);
+                }
+            }
+        });
+
+        let fragments = Arc::downgrade(&self.fragments);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                match fragments.upgrade() {
+                    Some(fragments) => fragments.tick(),
+                    None => break,
+                }
+            }
+        });
+    }
+
     pub async fn disconnect_device(&mut self, device_id: &str) -> Result<()> {
-        self.devices.lock().unwrap().remove(device_id);
+        let device = self.devices.lock().unwrap().remove(device_id);
+        if let Some(device) = device {
+            device.lock().await.disconnect().await?;
+        }
         Ok(())
     }
 
-    pub async fn send_message(&self, device_id: &str, message: &str, destination: Option<&str>) -> Result<()> {
-        let devices = self.devices.lock().unwrap();
-        let device = devices.get(device_id)
-            .ok_or_else(|| LoraCommsError::Connection { 
-                message: "Device not found".to_string() 
-            })?;
+    /// Send a text message and track its delivery. `destination` is a node
+    /// id (`!12345678`) or `None` to broadcast; `channel` names one of the
+    /// device's channels, `None` sends on the primary channel.
+    ///
+    /// Returns the packet id; follow it with `delivery_status` or
+    /// `subscribe_delivery`. Text that does not fit in one packet is
+    /// rejected with `ProtocolError::PayloadTooLarge`; see
+    /// `send_message_split` and `message_size`.
+    pub async fn send_message(
+        &self,
+        device_id: &str,
+        message: &str,
+        destination: Option<&str>,
+        channel: Option<&str>,
+    ) -> Result<u32> {
+        let (device, to, channel_index) = self.resolve_target(device_id, destination, channel).await?;
+        self.send_text(device, to, channel_index, message, None, false).await
+    }
+
+    /// Like `send_message`, but text too long for one packet is sent as
+    /// several, split at UTF-8 character boundaries. Returns the packet id of
+    /// every part, in order.
+    pub async fn send_message_split(
+        &self,
+        device_id: &str,
+        message: &str,
+        destination: Option<&str>,
+        channel: Option<&str>,
+    ) -> Result<Vec<u32>> {
+        let (device, to, channel_index) = self.resolve_target(device_id, destination, channel).await?;
+        let max = self.message_size("", None, false)?.max_payload_bytes;
 
-        let mesh_message = MeshMessage {
-            from: "local".to_string(),
-            to: destination.unwrap_or("broadcast").to_string(),
-            text: message.to_string(),
-            timestamp: Utc::now(),
-            want_ack: Some(false),
-            packet_id: Some(rand::random()),
-            hop_limit: Some(3),
-            channel: Some(0),
-            message_type: MessageType::Text,
+        let mut packet_ids = Vec::new();
+        for part in split_utf8(message, max) {
+            packet_ids.push(self.send_text(Arc::clone(&device), to, channel_index, part, None, false).await?);
+        }
+        Ok(packet_ids)
+    }
+
+    /// Dry run: how many bytes `text` takes as a message (or a reply or
+    /// reaction to `reply_to`) and how many fit in one packet
+    pub fn message_size(&self, text: &str, reply_to: Option<u32>, is_reaction: bool) -> Result<PayloadSize> {
+        let packet = MeshPacket {
+            reply_id: reply_to.unwrap_or(0),
+            emoji: is_reaction as u32,
+            ..MeshPacket::new_text_message(0, BROADCAST_ADDR, text)
         };
+        Ok(PayloadSize::of(&packet)?)
+    }
 
-        device.send_message(&mesh_message).await?;
-        Ok(())
+    /// Device, destination node and channel index for an outgoing message
+    async fn resolve_target(
+        &self,
+        device_id: &str,
+        destination: Option<&str>,
+        channel: Option<&str>,
+    ) -> Result<(SharedDevice, u32, u8)> {
+        let device = self.device(device_id)?;
+        let to = destination.map(parse_node_id).transpose()?.unwrap_or(BROADCAST_ADDR);
+        let channel_index = match channel {
+            Some(name) => device.lock().await.get_channels().by_name(name)
+                .map(|entry| entry.index as u8)
+                .ok_or_else(|| LoraCommsError::ChannelNotFound(name.to_string()))?,
+            None => 0,
+        };
+        Ok((device, to, channel_index))
+    }
+
+    /// Send a payload of any size on `portnum` as numbered chunks, which the
+    /// receiving side reassembles into one packet on `portnum` again. Peers
+    /// must run this library (or speak its fragment port). Returns the
+    /// message id the reassembled packet will carry.
+    pub async fn send_fragmented(
+        &self,
+        device_id: &str,
+        portnum: PortNum,
+        payload: &[u8],
+        destination: Option<&str>,
+        channel: Option<&str>,
+    ) -> Result<u32> {
+        let (device, to, channel_index) = self.resolve_target(device_id, destination, channel).await?;
+        self.send_chunks(&device, to, channel_index, portnum, payload).await
+    }
+
+    async fn send_chunks(
+        &self,
+        device: &SharedDevice,
+        to: u32,
+        channel_index: u8,
+        portnum: PortNum,
+        payload: &[u8],
+    ) -> Result<u32> {
+        let device = device.lock().await;
+        let (message_id, packets) = self.fragments.fragment(device.my_node_num(), to, channel_index, portnum, payload)?;
+        for packet in &packets {
+            device.send_packet(packet).await?;
+        }
+        Ok(message_id)
+    }
+
+    /// Send text of any length as one message, fragmented if it does not fit
+    /// in a packet, and add it to the conversation. Returns the message id.
+    pub async fn send_long_message(
+        &self,
+        device_id: &str,
+        message: &str,
+        destination: Option<&str>,
+        channel: Option<&str>,
+    ) -> Result<u32> {
+        let (device, to, channel_index) = self.resolve_target(device_id, destination, channel).await?;
+        if self.message_size(message, None, false)?.fits() {
+            return self.send_text(device, to, channel_index, message, None, false).await;
+        }
+
+        let message_id = self.send_chunks(&device, to, channel_index, PortNum::TEXT_MESSAGE_APP, message.as_bytes()).await?;
+        let from = device.lock().await.my_node_num();
+        let packet = MeshPacket { id: message_id, channel: channel_index, ..MeshPacket::new_text_message(from, to, message) };
+        let outgoing = MeshMessage::from_packet(&packet, message);
+        self.processor.storage().save_message(ConversationId::outgoing(&packet), true, &outgoing)?;
+        Ok(message_id)
+    }
+
+    /// Send `request` to its destination with `want_response` and wait up
+    /// to `timeout` for the packet answering it. `from` is filled in when 0.
+    /// Cancel it from elsewhere with `cancel_request(request.id)`.
+    pub async fn request(
+        &self,
+        device_id: &str,
+        mut request: MeshPacket,
+        timeout: std::time::Duration,
+    ) -> Result<MeshPacket> {
+        let device = self.device(device_id)?;
+        let pending = {
+            let device = device.lock().await;
+            if request.from == 0 {
+                request.from = device.my_node_num();
+            }
+            request.want_response = true;
+
+            let pending = self.requests.register(&request);
+            device.send_packet(&request).await?;
+            pending
+        };
+        Ok(pending.wait(timeout).await?)
+    }
+
+    /// Give up on a request started with `request` or one of its helpers; it
+    /// fails with `RequestCancelled`. Returns false if it was not pending.
+    pub fn cancel_request(&self, request_id: u32) -> bool {
+        self.requests.cancel(request_id)
+    }
+
+    /// Ask `node` (`!12345678`) for its current position
+    pub async fn request_position(&self, device_id: &str, node: &str) -> Result<Position> {
+        let request = MeshPacket { to: parse_node_id(node)?, ..MeshPacket::new_position(0, Position::default()) };
+        match self.request(device_id, request, protocol::rpc::REQUEST_TIMEOUT).await? {
+            MeshPacket { payload: Some(PayloadVariant::Position(position)), .. } => Ok(position),
+            reply => Err(LoraCommsError::UnexpectedResponse(reply.request_id)),
+        }
+    }
+
+    /// Ask `node` for its user record (names, hardware model, role). Like
+    /// the firmware, the request carries our own user record, read from the
+    /// radio if the device has not cached it.
+    pub async fn request_node_info(&self, device_id: &str, node: &str) -> Result<User> {
+        let to = parse_node_id(node)?;
+        let cached = self.device(device_id)?.lock().await.get_owner();
+        let owner = match cached {
+            Some(owner) => owner,
+            None => self.get_owner(device_id).await?,
+        };
+        let request = MeshPacket { to, ..MeshPacket::new_node_info(0, owner) };
+        match self.request(device_id, request, protocol::rpc::REQUEST_TIMEOUT).await? {
+            MeshPacket { payload: Some(PayloadVariant::NodeInfo(user)), .. } => Ok(user),
+            reply => Err(LoraCommsError::UnexpectedResponse(reply.request_id)),
+        }
+    }
+
+    /// Ask `node` for its device metrics
+    pub async fn request_telemetry(&self, device_id: &str, node: &str) -> Result<TelemetryData> {
+        let telemetry = TelemetryData {
+            time: 0,
+            variant: Some(TelemetryVariant::DeviceMetrics(DeviceMetrics::default())),
+        };
+        let request = MeshPacket { to: parse_node_id(node)?, ..MeshPacket::new_telemetry(0, telemetry) };
+        match self.request(device_id, request, protocol::rpc::REQUEST_TIMEOUT).await? {
+            MeshPacket { payload: Some(PayloadVariant::Telemetry(telemetry)), .. } => Ok(telemetry),
+            reply => Err(LoraCommsError::UnexpectedResponse(reply.request_id)),
+        }
+    }
+
+    /// Trace the route to `node` (`!12345678`), waiting up to
+    /// `TRACEROUTE_TIMEOUT` for the reply
+    pub async fn traceroute(&self, device_id: &str, node: &str) -> Result<TraceRoute> {
+        self.traceroute_with_timeout(device_id, node, protocol::traceroute::TRACEROUTE_TIMEOUT).await
+    }
+
+    pub async fn traceroute_with_timeout(
+        &self,
+        device_id: &str,
+        node: &str,
+        timeout: std::time::Duration,
+    ) -> Result<TraceRoute> {
+        let origin = self.device(device_id)?.lock().await.my_node_num();
+        let request = protocol::traceroute::traceroute_request(origin, parse_node_id(node)?, 0);
+
+        let reply = self.request(device_id, request, timeout).await?;
+        let discovery = match &reply.payload {
+            Some(PayloadVariant::App(PortNum::TRACEROUTE_APP, payload)) => protocol::traceroute::decode_route(payload)?,
+            // Older firmware answers with a routing message
+            Some(PayloadVariant::Routing(Routing { variant: Some(RoutingVariant::RouteReply(discovery)) })) => discovery.clone(),
+            _ => return Err(LoraCommsError::UnexpectedResponse(reply.request_id)),
+        };
+        Ok(TraceRoute::from_reply(origin, reply.from, &discovery))
+    }
+
+    /// Offer the file at `path` to node `destination` (`!12345678`) and send
+    /// it once accepted. Returns the transfer id; follow it with
+    /// `subscribe_transfers` or `file_transfers`.
+    pub async fn send_file(
+        &self,
+        device_id: &str,
+        path: &std::path::Path,
+        destination: &str,
+        channel: Option<&str>,
+    ) -> Result<u32> {
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let data = std::fs::read(path)?;
+        self.send_file_data(device_id, &name, data, destination, channel).await
+    }
+
+    /// Like `send_file`, with the contents and name given directly
+    pub async fn send_file_data(
+        &self,
+        device_id: &str,
+        name: &str,
+        data: Vec<u8>,
+        destination: &str,
+        channel: Option<&str>,
+    ) -> Result<u32> {
+        let (_, to, channel_index) = self.resolve_target(device_id, Some(destination), channel).await?;
+        if to == BROADCAST_ADDR {
+            return Err(ProtocolError::InvalidNodeId.into());
+        }
+        // Keep the transfer on the device it was started from
+        self.routes.lock().unwrap().insert(to, device_id.to_string());
+        Ok(self.transfers.send(to, channel_index, name, data)?)
+    }
+
+    /// Accept an offered file, to be written to `path` once received and
+    /// verified
+    pub fn accept_file(&self, transfer_id: u32, path: std::path::PathBuf) -> Result<()> {
+        Ok(self.transfers.accept(transfer_id, path)?)
+    }
+
+    /// Turn down an offered file, or stop receiving one
+    pub fn reject_file(&self, transfer_id: u32) -> Result<()> {
+        Ok(self.transfers.reject(transfer_id)?)
+    }
+
+    /// Stop sending a file
+    pub fn cancel_file_transfer(&self, transfer_id: u32) -> Result<()> {
+        Ok(self.transfers.cancel(transfer_id)?)
+    }
+
+    /// Continue a paused outgoing transfer from the last chunk the receiver
+    /// confirmed. Paused transfers also resume when a device is added.
+    pub async fn resume_file_transfer(&self, transfer_id: u32) -> Result<()> {
+        Ok(self.transfers.resume(transfer_id)?)
+    }
+
+    /// Every file transfer this session, outgoing and incoming
+    pub fn file_transfers(&self) -> Vec<TransferInfo> {
+        self.transfers.transfers()
+    }
+
+    /// Offers, progress and outcome of every file transfer
+    pub fn subscribe_transfers(&self) -> tokio::sync::broadcast::Receiver<TransferInfo> {
+        self.transfers.subscribe()
+    }
+
+    /// Reply to the stored message with packet id `reply_to`, in the same
+    /// conversation it came from
+    pub async fn send_reply(&self, device_id: &str, reply_to: u32, message: &str) -> Result<u32> {
+        self.send_in_thread_of(device_id, reply_to, message, false).await
+    }
+
+    /// React to the stored message with packet id `reply_to` with an emoji
+    pub async fn send_reaction(&self, device_id: &str, reply_to: u32, emoji: &str) -> Result<u32> {
+        self.send_in_thread_of(device_id, reply_to, emoji, true).await
+    }
+
+    async fn send_in_thread_of(&self, device_id: &str, reply_to: u32, text: &str, is_reaction: bool) -> Result<u32> {
+        let device = self.device(device_id)?;
+        let parent = self.processor.storage().message_by_packet_id(reply_to)?
+            .ok_or(LoraCommsError::MessageNotFound(reply_to))?;
+
+        let (to, channel_index) = match parent.conversation {
+            ConversationId::Direct { peer } => (peer, parent.message.channel.unwrap_or(0)),
+            ConversationId::Channel { index } => (BROADCAST_ADDR, index),
+        };
+        self.send_text(device, to, channel_index, text, Some(reply_to), is_reaction).await
+    }
+
+    async fn send_text(
+        &self,
+        device: SharedDevice,
+        to: u32,
+        channel_index: u8,
+        text: &str,
+        reply_id: Option<u32>,
+        is_reaction: bool,
+    ) -> Result<u32> {
+        let from = device.lock().await.my_node_num();
+        let packet = MeshPacket {
+            want_ack: true,
+            channel: channel_index,
+            reply_id: reply_id.unwrap_or(0),
+            emoji: is_reaction as u32,
+            ..MeshPacket::new_text_message(from, to, text)
+        };
+        PayloadSize::of(&packet)?.check()?;
+
+        let conversation = ConversationId::outgoing(&packet);
+        let outgoing = MeshMessage::from_packet(&packet, text);
+
+        let packet_id = self.delivery.send(packet, move |packet| {
+            let device = Arc::clone(&device);
+            async move { device.lock().await.send_packet(&packet).await }
+        }).await?;
+        self.processor.storage().save_message(conversation, true, &outgoing)?;
+        Ok(packet_id)
+    }
+
+    /// Stored message history, oldest first; `limit` keeps only the most
+    /// recent messages
+    pub fn message_history(&self, limit: Option<usize>) -> Result<Vec<MeshMessage>> {
+        Ok(self.processor.storage().messages(limit)?)
+    }
+
+    /// Delete the stored message history, returning how many messages it held
+    pub fn clear_message_history(&self) -> Result<usize> {
+        Ok(self.processor.storage().clear_messages()?)
+    }
+
+    /// Direct-message and channel threads, most recently active first
+    pub fn conversations(&self) -> Result<Vec<ConversationSummary>> {
+        Ok(self.processor.storage().conversations()?)
+    }
+
+    /// A page of one conversation. Start with `before = None` for the newest
+    /// messages and follow `next_cursor` for older ones.
+    pub fn conversation_messages(
+        &self,
+        conversation: ConversationId,
+        before: Option<i64>,
+        limit: usize,
+    ) -> Result<MessagePage> {
+        Ok(self.processor.storage().conversation_messages(conversation, before, limit)?)
+    }
+
+    /// Mark a conversation read up to message `up_to`, or entirely
+    pub fn mark_conversation_read(&self, conversation: ConversationId, up_to: Option<i64>) -> Result<()> {
+        Ok(self.processor.storage().mark_read(conversation, up_to)?)
+    }
+
+    /// Latest delivery status of a packet sent with `send_message`
+    pub fn delivery_status(&self, packet_id: u32) -> Option<DeliveryStatus> {
+        self.delivery.status(packet_id)
+    }
+
+    /// Delivery status changes for every message sent through this manager
+    pub fn subscribe_delivery(&self) -> tokio::sync::broadcast::Receiver<DeliveryUpdate> {
+        self.delivery.subscribe()
+    }
+
+    /// Dedup store shared by all devices on this manager; hand it to other
+    /// packet sources (e.g. an MQTT gateway) so they skip the same duplicates
+    pub fn dedup_store(&self) -> DedupStore {
+        self.dedup.clone()
+    }
+
+    /// Typed events for packets received on any connected device
+    pub fn subscribe_events(&self) -> tokio::sync::broadcast::Receiver<MeshEvent> {
+        self.processor.subscribe_events()
+    }
+
+    /// Register an application handler for packets on `portnum`
+    pub async fn register_handler(&self, portnum: PortNum, handler: Arc<dyn PortHandler>) {
+        self.processor.register_handler(portnum, handler).await;
     }
 
     pub async fn get_nodes(&self, device_id: &str) -> Result<Vec<NodeInfo>> {
-        let devices = self.devices.lock().unwrap();
-        let device = devices.get(device_id)
+        let device = self.device(device_id)?;
+
+        let nodes = device.lock().await.get_nodes().await?;
+        Ok(nodes)
+    }
+
+    /// How recently a node must have been heard to be reported online, for
+    /// every connected device and those connected later
+    pub async fn set_node_online_threshold(&mut self, threshold: std::time::Duration) {
+        self.online_threshold = threshold;
+        let devices: Vec<SharedDevice> = self.devices.lock().unwrap().values().cloned().collect();
+        for device in devices {
+            device.lock().await.set_online_threshold(threshold);
+        }
+    }
+
+    /// Channel table of a connected device
+    pub async fn get_channels(&self, device_id: &str) -> Result<ChannelTable> {
+        let device = self.device(device_id)?;
+        let channels = device.lock().await.get_channels();
+        Ok(channels)
+    }
+
+    /// Share a device's channels and LoRa settings as a
+    /// `https://meshtastic.org/e/#…` URL
+    pub async fn get_channel_url(&self, device_id: &str) -> Result<String> {
+        let device = self.device(device_id)?;
+        let device = device.lock().await;
+
+        let channels = device.get_channels();
+        if channels.is_empty() {
+            return Err(LoraCommsError::ChannelNotFound("primary".to_string()));
+        }
+        Ok(channels.channel_set(device.get_lora_config()).to_url())
+    }
+
+    /// Write the channels and LoRa settings from a channel URL to a device.
+    /// Slots the URL does not use are disabled. The device's cached channel
+    /// table follows each write. Returns the parsed set.
+    pub async fn set_channel_url(&self, device_id: &str, url: &str) -> Result<ChannelSet> {
+        let set = ChannelSet::from_url(url)?;
+        let device = self.device(device_id)?;
+        let mut device = device.lock().await;
+        let my_node_num = device.my_node_num();
+
+        let mut channels = set.channels();
+        for index in channels.len()..protocol::channels::MAX_CHANNELS {
+            if device.get_channels().by_index(index as u32).is_some() {
+                channels.push(Channel { index: index as u32, ..Default::default() });
+            }
+        }
+
+        for channel in channels {
+            let admin = AdminMessage { variant: Some(admin_message::Variant::SetChannel(channel.clone())) };
+            device.send_packet(&MeshPacket::new_admin_message(my_node_num, admin)).await?;
+            device.update_channel(&channel);
+        }
+        if let Some(lora) = &set.lora_config {
+            let config = Config { variant: Some(config::Variant::Lora(lora.clone())) };
+            let admin = AdminMessage { variant: Some(admin_message::Variant::SetConfig(config.clone())) };
+            device.send_packet(&MeshPacket::new_admin_message(my_node_num, admin)).await?;
+            self.record_config(&mut **device, &config);
+        }
+
+        Ok(set)
+    }
+
+    /// Owner (user record) of a connected device
+    pub async fn get_owner(&self, device_id: &str) -> Result<User> {
+        match self.admin_request(device_id, admin_message::Variant::GetOwner(GetOwnerRequest {})).await? {
+            (admin_message::Variant::GetOwnerResponse(owner), _) => Ok(owner),
+            (_, request_id) => Err(LoraCommsError::UnexpectedResponse(request_id)),
+        }
+    }
+
+    /// Read one channel slot from a connected device. Unused slots come
+    /// back with role `DISABLED`. The device's cached channel table is
+    /// refreshed with the answer.
+    pub async fn get_channel(&self, device_id: &str, index: u32) -> Result<Channel> {
+        let request = admin_message::Variant::GetChannel(GetChannelRequest { index });
+        let channel = match self.admin_request(device_id, request).await? {
+            (admin_message::Variant::GetChannelResponse(channel), _) if channel.index == index => channel,
+            (_, request_id) => return Err(LoraCommsError::UnexpectedResponse(request_id)),
+        };
+
+        self.device(device_id)?.lock().await.update_channel(&channel);
+        Ok(channel)
+    }
+
+    /// Read one config section from a connected device. The device's cached
+    /// copy of the section is refreshed with the answer.
+    pub async fn get_config(&self, device_id: &str, config_type: ConfigType) -> Result<Config> {
+        let request = admin_message::Variant::GetConfig(GetConfigRequest { config_type: i32::from(config_type) as u32 });
+        let config = match self.admin_request(device_id, request).await? {
+            (admin_message::Variant::GetConfigResponse(config), _) if config.config_type() == Some(config_type) => config,
+            (_, request_id) => return Err(LoraCommsError::UnexpectedResponse(request_id)),
+        };
+
+        self.record_config(&mut **self.device(device_id)?.lock().await, &config);
+        Ok(config)
+    }
+
+    /// Write one config section to a connected device. The radio reboots to
+    /// apply device, LoRa, network and Bluetooth changes. The device's cached
+    /// copy of the section follows the write.
+    pub async fn set_config(&self, device_id: &str, config: Config) -> Result<()> {
+        if config.variant.is_none() {
+            return Err(ProtocolError::InvalidFormat.into());
+        }
+        self.admin_send(device_id, admin_message::Variant::SetConfig(config.clone())).await?;
+        self.record_config(&mut **self.device(device_id)?.lock().await, &config);
+        Ok(())
+    }
+
+    /// Read one module's settings from a connected device
+    pub async fn get_module_config(&self, device_id: &str, config_type: ModuleConfigType) -> Result<ModuleConfig> {
+        let request = admin_message::Variant::GetModuleConfig(GetModuleConfigRequest { config_type: i32::from(config_type) as u32 });
+        match self.admin_request(device_id, request).await? {
+            (admin_message::Variant::GetModuleConfigResponse(config), _) if config.config_type() == Some(config_type) => {
+                Ok(config)
+            }
+            (_, request_id) => Err(LoraCommsError::UnexpectedResponse(request_id)),
+        }
+    }
+
+    /// Write one module's settings to a connected device
+    pub async fn set_module_config(&self, device_id: &str, config: ModuleConfig) -> Result<()> {
+        if config.variant.is_none() {
+            return Err(ProtocolError::InvalidFormat.into());
+        }
+        self.admin_send(device_id, admin_message::Variant::SetModuleConfig(config)).await
+    }
+
+    /// Canned messages stored on a connected device, in menu order
+    pub async fn get_canned_messages(&self, device_id: &str) -> Result<Vec<String>> {
+        let request = admin_message::Variant::GetCannedMessageModuleMessages(GetCannedMessageModuleMessagesRequest {});
+        match self.admin_request(device_id, request).await? {
+            (admin_message::Variant::GetCannedMessageModuleMessagesResponse(list), _) => {
+                Ok(protocol::split_canned_messages(&list))
+            }
+            (_, request_id) => Err(LoraCommsError::UnexpectedResponse(request_id)),
+        }
+    }
+
+    /// Replace the canned messages on a connected device. The list must fit
+    /// in `CANNED_MESSAGES_MAX_LEN` bytes once joined with `|`.
+    pub async fn set_canned_messages(&self, device_id: &str, messages: &[String]) -> Result<()> {
+        let list = protocol::join_canned_messages(messages)?;
+        self.admin_send(device_id, admin_message::Variant::SetCannedMessageModuleMessages(list)).await
+    }
+
+    /// Apply a batch of settings changes in one edit transaction: validate
+    /// it, then send `BeginEditSettings`, each change and
+    /// `CommitEditSettings`, waiting for the radio to ack each before the
+    /// next. The radio saves and reboots once, at the commit.
+    ///
+    /// The first rejected operation aborts the session: the rest are not
+    /// sent and neither is the commit. The radio is then rebooted after
+    /// `ABORT_REBOOT_SECONDS`, which closes the edit transaction and drops
+    /// the uncommitted changes, so it saves nothing. `aborted_at` in the
+    /// report names the operation and `abort_reboot` the reboot's answer.
+    pub async fn apply_session(&self, device_id: &str, session: &ConfigSession) -> Result<SessionReport> {
+        session.validate()?;
+        let device = self.device(device_id)?;
+
+        let begin = self.send_admin_tracked(&device, admin_message::Variant::BeginEditSettings(true)).await?;
+        let mut report = SessionReport { begin, operations: Vec::new(), aborted_at: None, abort_reboot: None, commit: None };
+        if begin.is_failed() {
+            return Ok(report);
+        }
+
+        for (index, operation) in session.operations.iter().enumerate() {
+            let variant = operation.to_admin_message().variant.expect("edit operations carry a variant");
+            let status = self.send_admin_tracked(&device, variant).await?;
+            report.operations.push(OperationReport { index, operation: operation.describe(), status });
+            if status.is_failed() {
+                report.aborted_at = Some(index);
+                let reboot = admin_message::Variant::Reboot(protocol::session::ABORT_REBOOT_SECONDS);
+                report.abort_reboot = Some(self.send_admin_tracked(&device, reboot).await?);
+                return Ok(report);
+            }
+        }
+        let commit = self.send_admin_tracked(&device, admin_message::Variant::CommitEditSettings(true)).await?;
+        report.commit = Some(commit);
+        if !commit.is_failed() {
+            let mut device = device.lock().await;
+            for operation in &session.operations {
+                match operation {
+                    EditOperation::SetConfig(config) => self.record_config(&mut **device, config),
+                    EditOperation::SetChannel(channel) => device.update_channel(channel),
+                    _ => {}
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Read a device's owner, channels, every config and module section and
+    /// its fixed position, if it uses one, into a profile. Everything is read
+    /// from the radio itself, not from what it reported at connect.
+    ///
+    /// Unless `include_secrets` is set, the Wi-Fi PSK and MQTT password are
+    /// left out of the profile (see [`DeviceProfile::redact_secrets`]). With
+    /// it they are written to the backup in plain text.
+    pub async fn backup_profile(&self, device_id: &str, include_secrets: bool) -> Result<DeviceProfile> {
+        let mut profile = DeviceProfile::new().with_owner(self.get_owner(device_id).await?);
+        for index in 0..protocol::channels::MAX_CHANNELS as u32 {
+            let channel = self.get_channel(device_id, index).await?;
+            if channel.role != Channel_Role::DISABLED {
+                profile.channels.push(channel);
+            }
+        }
+        for config_type in ConfigType::ALL {
+            profile.config.insert(self.get_config(device_id, config_type).await?);
+        }
+        for config_type in ModuleConfigType::ALL {
+            profile.module_config.insert(self.get_module_config(device_id, config_type).await?);
+        }
+
+        if profile.config.position.as_ref().is_some_and(|position| position.fixed_position) {
+            let device = self.device(device_id)?;
+            let device = device.lock().await;
+            let my_node_num = device.my_node_num();
+            profile.fixed_position = device.get_nodes().await?.into_iter()
+                .find(|node| node.num == my_node_num)
+                .and_then(|node| node.position);
+        }
+        if !include_secrets {
+            profile = profile.redact_secrets();
+        }
+        Ok(profile)
+    }
+
+    /// Write a profile to a device in one edit session. The device's current
+    /// settings are read first; only the sections that differ are sent,
+    /// unless `full` asks for every section to be rewritten. Nothing is sent
+    /// (and the radio does not reboot) if the device already matches.
+    pub async fn restore_profile(&self, device_id: &str, profile: &DeviceProfile, full: bool) -> Result<RestoreReport> {
+        profile.to_session().validate()?;
+
+        let current = self.backup_profile(device_id, true).await?;
+        let changes = current.changes_to(profile);
+        let session = current.restore_session(profile, full);
+        let session = if session.is_empty() { None } else { Some(self.apply_session(device_id, &session).await?) };
+        Ok(RestoreReport { changes, session })
+    }
+
+    /// Compare a device's live settings with `desired`: every field that
+    /// differs, and a session that writes only the sections holding them.
+    /// An empty plan means the device already matches.
+    pub async fn plan_profile(&self, device_id: &str, desired: &DeviceProfile) -> Result<ProfilePlan> {
+        Ok(self.backup_profile(device_id, true).await?.plan(desired))
+    }
+
+    /// Send the sections a plan changes in one edit session; `None` if the
+    /// plan has nothing to send
+    pub async fn apply_plan(&self, device_id: &str, plan: &ProfilePlan) -> Result<Option<SessionReport>> {
+        if plan.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(self.apply_session(device_id, &plan.session).await?))
+    }
+
+    /// Radio settings a connected device is using, read from its LoRa config
+    pub async fn get_radio_config(&self, device_id: &str) -> Result<radio::RadioConfig> {
+        match self.get_config(device_id, ConfigType::LORA_CONFIG).await?.variant {
+            Some(config::Variant::Lora(lora)) => Ok(radio::RadioConfig::from(&lora)),
+            _ => Err(ProtocolError::InvalidFormat.into()),
+        }
+    }
+
+    /// Send an admin message to a device's own node with `want_ack` and
+    /// wait until the radio acks or rejects it, or retries run out
+    async fn send_admin_tracked(&self, device: &SharedDevice, variant: admin_message::Variant) -> Result<DeliveryStatus> {
+        let my_node_num = device.lock().await.my_node_num();
+        let packet = MeshPacket::new_admin_message(my_node_num, AdminMessage { variant: Some(variant) });
+
+        let mut updates = self.delivery.subscribe();
+        let device = Arc::clone(device);
+        let packet_id = self.delivery.send(packet, move |packet| {
+            let device = Arc::clone(&device);
+            async move { device.lock().await.send_packet(&packet).await }
+        }).await?;
+
+        loop {
+            match self.delivery.status(packet_id) {
+                Some(status) if status.is_final() => return Ok(status),
+                _ => {}
+            }
+            match updates.recv().await {
+                Ok(_) | Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => return Err(LoraCommsError::Timeout),
+            }
+        }
+    }
+
+    /// Send an admin request to a device's own node and return the admin
+    /// answer along with the request id
+    /// Record a config section written to or read from a device, so its
+    /// cached settings (and, for LoRa, the transfer pacing) stay current
+    fn record_config(&self, device: &mut dyn Device, config: &Config) {
+        device.update_config(config);
+        if let Some(config::Variant::Lora(lora)) = &config.variant {
+            self.transfers.set_radio_config(radio::RadioConfig::from(lora));
+        }
+    }
+
+    async fn admin_request(&self, device_id: &str, variant: admin_message::Variant) -> Result<(admin_message::Variant, u32)> {
+        let my_node_num = self.device(device_id)?.lock().await.my_node_num();
+        let request = MeshPacket::new_admin_message(my_node_num, AdminMessage { variant: Some(variant) });
+
+        let reply = self.request(device_id, request, protocol::rpc::REQUEST_TIMEOUT).await?;
+        match reply.payload {
+            Some(PayloadVariant::Admin(AdminMessage { variant: Some(variant) })) => Ok((variant, reply.request_id)),
+            _ => Err(LoraCommsError::UnexpectedResponse(reply.request_id)),
+        }
+    }
+
+    /// Send an admin message to a device's own node without waiting for an
+    /// answer
+    async fn admin_send(&self, device_id: &str, variant: admin_message::Variant) -> Result<()> {
+        let device = self.device(device_id)?;
+        let device = device.lock().await;
+
+        let admin = AdminMessage { variant: Some(variant) };
+        device.send_packet(&MeshPacket::new_admin_message(device.my_node_num(), admin)).await?;
+        Ok(())
+    }
+
+    fn device(&self, device_id: &str) -> Result<SharedDevice> {
+        self.devices.lock().unwrap().get(device_id).cloned()
             .ok_or_else(|| LoraCommsError::Connection { 
                 message: "Device not found".to_string() 
-            })?;
-
-        device.get_nodes().await.map_err(LoraCommsError::from)
+            })
     }
 
     pub fn get_message_receiver(&mut self) -> Option<mpsc::UnboundedReceiver<MeshMessage>> {
@@ -176,6 +1040,586 @@ mod tests {
         assert!(manager.devices.lock().unwrap().is_empty());
     }
 
+    /// Device that records sent packets and lets the test inject incoming ones
+    #[derive(Default)]
+    struct LoopbackDevice {
+        sent: Arc<Mutex<Vec<MeshPacket>>>,
+        incoming: Arc<Mutex<Option<mpsc::UnboundedSender<MeshPacket>>>>,
+        channels: ChannelTable,
+        lora: Option<RadioConfig>,
+    }
+
+    #[async_trait::async_trait]
+    impl Device for LoopbackDevice {
+        async fn connect(&mut self) -> std::result::Result<(), DeviceError> { Ok(()) }
+        async fn disconnect(&mut self) -> std::result::Result<(), DeviceError> { Ok(()) }
+        fn is_connected(&self) -> bool { true }
+        async fn send_message(&self, _message: &MeshMessage) -> std::result::Result<(), DeviceError> { Ok(()) }
+        async fn send_packet(&self, packet: &MeshPacket) -> std::result::Result<(), DeviceError> {
+            self.sent.lock().unwrap().push(packet.clone());
+            Ok(())
+        }
+        fn my_node_num(&self) -> u32 { 0x1111 }
+        fn get_channels(&self) -> ChannelTable { self.channels.clone() }
+        fn update_channel(&mut self, channel: &Channel) { let _ = self.channels.upsert(channel); }
+        fn get_lora_config(&self) -> Option<RadioConfig> { self.lora.clone() }
+        fn update_config(&mut self, config: &Config) {
+            if let Some(config::Variant::Lora(lora)) = &config.variant {
+                self.lora = Some(lora.clone());
+            }
+        }
+        async fn get_nodes(&self) -> std::result::Result<Vec<NodeInfo>, DeviceError> { Ok(Vec::new()) }
+        async fn get_device_info(&self) -> std::result::Result<String, DeviceError> { Ok("loopback".to_string()) }
+        async fn start_listening(&mut self, packets: mpsc::UnboundedSender<MeshPacket>) -> std::result::Result<(), DeviceError> {
+            *self.incoming.lock().unwrap() = Some(packets);
+            Ok(())
+        }
+        async fn stop_listening(&mut self) -> std::result::Result<(), DeviceError> { Ok(()) }
+    }
+
+    #[tokio::test]
+    async fn test_send_message_reports_delivery() {
+        let manager = LoraCommsManager::new();
+        let device = LoopbackDevice::default();
+        let (sent, incoming) = (device.sent.clone(), device.incoming.clone());
+        manager.add_device("radio".to_string(), Box::new(device)).await.unwrap();
+
+        let mut updates = manager.subscribe_delivery();
+        let id = manager.send_message("radio", "hello", Some("!00002222"), None).await.unwrap();
+        let packet = sent.lock().unwrap()[0].clone();
+        assert_eq!((packet.id, packet.from, packet.to), (id, 0x1111, 0x2222));
+        assert!(packet.want_ack);
+        assert_eq!(manager.delivery_status(id), Some(DeliveryStatus::Pending { attempt: 1 }));
+
+        let ack = MeshPacket {
+            from: 0x2222,
+            request_id: id,
+            payload: Some(PayloadVariant::Routing(Routing {
+                variant: Some(RoutingVariant::ErrorReason(Routing_Error::NONE)),
+            })),
+            ..Default::default()
+        };
+        incoming.lock().unwrap().as_ref().unwrap().send(ack).unwrap();
+
+        loop {
+            let update = updates.recv().await.unwrap();
+            if update.status.is_final() {
+                assert_eq!(update.status, DeliveryStatus::Delivered);
+                break;
+            }
+        }
+        assert_eq!(manager.delivery_status(id), Some(DeliveryStatus::Delivered));
+    }
+
+    #[tokio::test]
+    async fn test_channel_url_updates_cached_channels() {
+        let manager = LoraCommsManager::new();
+        let device = LoopbackDevice::default();
+        let sent = device.sent.clone();
+        manager.add_device("radio".to_string(), Box::new(device)).await.unwrap();
+
+        let url = ChannelTable::from_channels(&[
+            Channel {
+                index: 0,
+                role: Channel_Role::PRIMARY,
+                settings: Some(ChannelSettings { psk: vec![1], ..Default::default() }),
+            },
+            Channel {
+                index: 1,
+                role: Channel_Role::SECONDARY,
+                settings: Some(ChannelSettings { name: "ops".to_string(), psk: vec![3; 16], ..Default::default() }),
+            },
+        ], 0).unwrap().channel_set(None).to_url();
+        assert!(matches!(
+            manager.send_message("radio", "hi", None, Some("ops")).await,
+            Err(LoraCommsError::ChannelNotFound(_))
+        ));
+
+        manager.set_channel_url("radio", &url).await.unwrap();
+        sent.lock().unwrap().clear();
+        manager.send_message("radio", "hi", None, Some("ops")).await.unwrap();
+        assert_eq!(sent.lock().unwrap()[0].channel, 1);
+    }
+
+    #[tokio::test]
+    async fn test_reaction_joins_parent_thread() {
+        let manager = LoraCommsManager::new();
+        let device = LoopbackDevice::default();
+        let (sent, incoming) = (device.sent.clone(), device.incoming.clone());
+        manager.add_device("radio".to_string(), Box::new(device)).await.unwrap();
+        let mut events = manager.subscribe_events();
+
+        let broadcast = MeshPacket { channel: 2, ..MeshPacket::new_text_message(0x2222, BROADCAST_ADDR, "lunch?") };
+        incoming.lock().unwrap().as_ref().unwrap().send(broadcast.clone()).unwrap();
+        events.recv().await.unwrap();
+
+        manager.send_reaction("radio", broadcast.id, "👍").await.unwrap();
+        let packet = sent.lock().unwrap()[0].clone();
+        assert_eq!((packet.to, packet.channel), (BROADCAST_ADDR, 2));
+        assert_eq!((packet.reply_id, packet.emoji), (broadcast.id, 1));
+
+        let page = manager.conversation_messages(ConversationId::Channel { index: 2 }, None, 10).unwrap();
+        assert_eq!(page.messages.len(), 1);
+        assert_eq!(page.messages[0].reactions[0].emoji, "👍");
+
+        assert!(matches!(
+            manager.send_reply("radio", broadcast.id.wrapping_add(1), "?").await,
+            Err(LoraCommsError::MessageNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_message_is_rejected_or_split() {
+        let manager = LoraCommsManager::new();
+        let device = LoopbackDevice::default();
+        let sent = device.sent.clone();
+        manager.add_device("radio".to_string(), Box::new(device)).await.unwrap();
+
+        let text = "é".repeat(200);
+        let size = manager.message_size(&text, None, false).unwrap();
+        assert_eq!((size.payload_bytes, size.available()), (400, 0));
+        assert!(matches!(
+            manager.send_message("radio", &text, None, None).await,
+            Err(LoraCommsError::Protocol(ProtocolError::PayloadTooLarge { size: 400, .. }))
+        ));
+        assert!(sent.lock().unwrap().is_empty());
+
+        let ids = manager.send_message_split("radio", &text, None, None).await.unwrap();
+        assert_eq!(ids.len(), 2);
+        let texts: Vec<String> = sent.lock().unwrap().iter()
+            .map(|packet| match &packet.payload {
+                Some(PayloadVariant::Text(text)) => text.clone(),
+                other => panic!("unexpected payload {:?}", other),
+            })
+            .collect();
+        assert_eq!(texts.concat(), text);
+    }
+
+    #[tokio::test]
+    async fn test_traceroute_reads_reply() {
+        use prost::Message;
+
+        let manager = LoraCommsManager::new();
+        let device = LoopbackDevice::default();
+        let (sent, incoming) = (device.sent.clone(), device.incoming.clone());
+        manager.add_device("radio".to_string(), Box::new(device)).await.unwrap();
+
+        // Answer the request as node 2222 would, through relay 3333
+        tokio::spawn(async move {
+            let request = loop {
+                if let Some(packet) = sent.lock().unwrap().first().cloned() {
+                    break packet;
+                }
+                tokio::task::yield_now().await;
+            };
+            let discovery = RouteDiscovery { route: vec![0x3333], snr_towards: vec![24, 40], route_back: vec![0x3333], snr_back: vec![20, 28] };
+            let reply = MeshPacket {
+                from: 0x2222,
+                to: 0x1111,
+                request_id: request.id,
+                payload: Some(PayloadVariant::App(PortNum::TRACEROUTE_APP, proto::RouteDiscovery::from(&discovery).encode_to_vec())),
+                ..Default::default()
+            };
+            incoming.lock().unwrap().as_ref().unwrap().send(reply).unwrap();
+        });
+
+        let trace = manager.traceroute("radio", "!00002222").await.unwrap();
+        assert_eq!((trace.origin, trace.destination), (0x1111, 0x2222));
+        assert_eq!(trace.forward.iter().map(|hop| hop.node).collect::<Vec<_>>(), [0x3333, 0x2222]);
+        assert_eq!(trace.back.last(), Some(&RouteHop { node: 0x1111, snr: Some(7.0) }));
+
+        assert!(matches!(
+            manager.traceroute_with_timeout("radio", "!00002222", std::time::Duration::from_millis(10)).await,
+            Err(LoraCommsError::Timeout)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_node_info_request_carries_our_owner() {
+        let manager = LoraCommsManager::new();
+        let device = LoopbackDevice::default();
+        let (sent, incoming) = (device.sent.clone(), device.incoming.clone());
+        manager.add_device("radio".to_string(), Box::new(device)).await.unwrap();
+
+        let owner = User { id: "!00001111".to_string(), long_name: "Base".to_string(), short_name: "BASE".to_string(), ..Default::default() };
+        let responder_owner = owner.clone();
+        let responder_sent = sent.clone();
+        tokio::spawn(async move {
+            let mut answered = 0;
+            loop {
+                let request = responder_sent.lock().unwrap().get(answered).cloned();
+                let Some(request) = request else {
+                    tokio::task::yield_now().await;
+                    continue;
+                };
+                answered += 1;
+                let reply = match request.payload {
+                    Some(PayloadVariant::Admin(_)) => {
+                        let admin = AdminMessage { variant: Some(admin_message::Variant::GetOwnerResponse(responder_owner.clone())) };
+                        MeshPacket { from: 0x1111, request_id: request.id, ..MeshPacket::new_admin_message(0x1111, admin) }
+                    }
+                    _ => {
+                        let user = User { id: "!00002222".to_string(), long_name: "Remote".to_string(), ..Default::default() };
+                        MeshPacket { to: 0x1111, request_id: request.id, ..MeshPacket::new_node_info(0x2222, user) }
+                    }
+                };
+                incoming.lock().unwrap().as_ref().unwrap().send(reply).unwrap();
+                if answered == 2 {
+                    break;
+                }
+            }
+        });
+
+        let user = manager.request_node_info("radio", "!00002222").await.unwrap();
+        assert_eq!(user.long_name, "Remote");
+
+        let request = sent.lock().unwrap()[1].clone();
+        assert_eq!((request.to, request.from), (0x2222, 0x1111));
+        assert!(request.want_response);
+        let Some(PayloadVariant::NodeInfo(sent_owner)) = request.payload else {
+            panic!("not a node info request: {:?}", request);
+        };
+        assert_eq!((sent_owner.id, sent_owner.long_name), (owner.id, owner.long_name));
+    }
+
+    #[tokio::test]
+    async fn test_get_config_reads_admin_response() {
+        let manager = LoraCommsManager::new();
+        let device = LoopbackDevice::default();
+        let (sent, incoming) = (device.sent.clone(), device.incoming.clone());
+        manager.add_device("radio".to_string(), Box::new(device)).await.unwrap();
+
+        tokio::spawn(async move {
+            let request = loop {
+                if let Some(packet) = sent.lock().unwrap().first().cloned() {
+                    break packet;
+                }
+                tokio::task::yield_now().await;
+            };
+            assert!(matches!(
+                request.payload,
+                Some(PayloadVariant::Admin(AdminMessage { variant: Some(admin_message::Variant::GetConfig(GetConfigRequest { config_type: 1 })) }))
+            ));
+            let position = PositionConfig { position_broadcast_secs: 900, gps_mode: GpsMode::GPS_ENABLED, ..Default::default() };
+            let admin = AdminMessage {
+                variant: Some(admin_message::Variant::GetConfigResponse(Config::new(config::Variant::Position(position)))),
+            };
+            let reply = MeshPacket { from: 0x1111, request_id: request.id, ..MeshPacket::new_admin_message(0x1111, admin) };
+            incoming.lock().unwrap().as_ref().unwrap().send(reply).unwrap();
+        });
+
+        let config = manager.get_config("radio", ConfigType::POSITION_CONFIG).await.unwrap();
+        let Some(config::Variant::Position(position)) = config.variant else {
+            panic!("not a position config: {:?}", config);
+        };
+        assert_eq!((position.position_broadcast_secs, position.gps_mode), (900, GpsMode::GPS_ENABLED));
+
+        assert!(manager.set_config("radio", Config::default()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_canned_messages_and_module_config() {
+        let manager = LoraCommsManager::new();
+        let device = LoopbackDevice::default();
+        let (sent, incoming) = (device.sent.clone(), device.incoming.clone());
+        manager.add_device("radio".to_string(), Box::new(device)).await.unwrap();
+
+        let messages = vec!["On my way".to_string(), "Need help".to_string()];
+        manager.set_canned_messages("radio", &messages).await.unwrap();
+        let set = sent.lock().unwrap().remove(0);
+        assert!(matches!(
+            set.payload,
+            Some(PayloadVariant::Admin(AdminMessage { variant: Some(admin_message::Variant::SetCannedMessageModuleMessages(ref list)) }))
+                if list == "On my way|Need help"
+        ));
+        assert!(manager.set_canned_messages("radio", &["a|b".to_string()]).await.is_err());
+        assert!(manager.set_canned_messages("radio", &["x".repeat(CANNED_MESSAGES_MAX_LEN + 1)]).await.is_err());
+
+        // Answer each admin request as the radio would
+        tokio::spawn(async move {
+            for _ in 0..2 {
+                let request = loop {
+                    if let Some(packet) = sent.lock().unwrap().pop() {
+                        break packet;
+                    }
+                    tokio::task::yield_now().await;
+                };
+                let answer = match request.payload {
+                    Some(PayloadVariant::Admin(AdminMessage { variant: Some(admin_message::Variant::GetCannedMessageModuleMessages(_)) })) => {
+                        admin_message::Variant::GetCannedMessageModuleMessagesResponse("On my way|Need help".to_string())
+                    }
+                    _ => {
+                        let mqtt = module_config::MqttConfig { enabled: true, root: "msh/EU_868".to_string(), ..Default::default() };
+                        admin_message::Variant::GetModuleConfigResponse(ModuleConfig::new(module_config::Variant::Mqtt(mqtt)))
+                    }
+                };
+                let admin = AdminMessage { variant: Some(answer) };
+                let reply = MeshPacket { from: 0x1111, request_id: request.id, ..MeshPacket::new_admin_message(0x1111, admin) };
+                incoming.lock().unwrap().as_ref().unwrap().send(reply).unwrap();
+            }
+        });
+
+        assert_eq!(manager.get_canned_messages("radio").await.unwrap(), messages);
+        let config = manager.get_module_config("radio", ModuleConfigType::MQTT_CONFIG).await.unwrap();
+        let Some(module_config::Variant::Mqtt(mqtt)) = config.variant else {
+            panic!("not an MQTT config: {:?}", config);
+        };
+        assert!(mqtt.enabled);
+        assert_eq!(mqtt.root, "msh/EU_868");
+    }
+
+    #[tokio::test]
+    async fn test_session_reports_each_operation() {
+        let manager = LoraCommsManager::new();
+        let device = LoopbackDevice::default();
+        let (sent, incoming) = (device.sent.clone(), device.incoming.clone());
+        manager.add_device("radio".to_string(), Box::new(device)).await.unwrap();
+
+        let invalid = ConfigSession::new().with_owner(User::default());
+        assert!(matches!(
+            manager.apply_session("radio", &invalid).await,
+            Err(LoraCommsError::Session(SessionError::Invalid(_)))
+        ));
+        assert!(sent.lock().unwrap().is_empty());
+
+        // Ack every admin message except the channel change
+        let answered = sent.clone();
+        let reboots = Arc::new(Mutex::new(Vec::new()));
+        let seen_reboots = reboots.clone();
+        tokio::spawn(async move {
+            for _ in 0..4 {
+                let request = loop {
+                    if let Some(packet) = answered.lock().unwrap().pop() {
+                        break packet;
+                    }
+                    tokio::task::yield_now().await;
+                };
+                let reason = match request.payload {
+                    Some(PayloadVariant::Admin(AdminMessage { variant: Some(admin_message::Variant::SetChannel(_)) })) => Routing_Error::BAD_REQUEST,
+                    Some(PayloadVariant::Admin(AdminMessage { variant: Some(admin_message::Variant::Reboot(seconds)) })) => {
+                        seen_reboots.lock().unwrap().push(seconds);
+                        Routing_Error::NONE
+                    }
+                    _ => Routing_Error::NONE,
+                };
+                let answer = MeshPacket {
+                    from: 0x1111,
+                    request_id: request.id,
+                    payload: Some(PayloadVariant::Routing(Routing { variant: Some(RoutingVariant::ErrorReason(reason)) })),
+                    ..Default::default()
+                };
+                incoming.lock().unwrap().as_ref().unwrap().send(answer).unwrap();
+            }
+        });
+
+        let session = ConfigSession::new()
+            .with_config(Config::new(config::Variant::Position(PositionConfig { position_broadcast_secs: 300, ..Default::default() })))
+            .with_channel(Channel {
+                index: 1,
+                role: Channel_Role::SECONDARY,
+                settings: Some(ChannelSettings { name: "ops".to_string(), psk: vec![1], ..Default::default() }),
+            })
+            .with_owner(User { long_name: "Base camp".to_string(), short_name: "BASE".to_string(), ..Default::default() });
+        let report = manager.apply_session("radio", &session).await.unwrap();
+        assert_eq!(report.begin, DeliveryStatus::Delivered);
+        assert!(report.operations[0].accepted());
+        assert_eq!(report.operations[1].status, DeliveryStatus::Failed(Routing_Error::BAD_REQUEST));
+        // The owner change and the commit are never sent
+        assert_eq!(report.operations.len(), 2);
+        assert_eq!(report.aborted_at, Some(1));
+        assert_eq!(report.commit, None);
+        // A reboot closes the edit transaction left open by the abort
+        assert_eq!(report.abort_reboot, Some(DeliveryStatus::Delivered));
+        assert_eq!(*reboots.lock().unwrap(), [protocol::session::ABORT_REBOOT_SECONDS]);
+        assert!(!report.all_accepted());
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(sent.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_lora_writes_refresh_cached_config() {
+        let manager = LoraCommsManager::new();
+        let device = LoopbackDevice::default();
+        let (sent, incoming) = (device.sent.clone(), device.incoming.clone());
+        manager.add_device("radio".to_string(), Box::new(device)).await.unwrap();
+        let cached_hop_limit = || async {
+            manager.device("radio").unwrap().lock().await.get_lora_config().map(|lora| lora.hop_limit)
+        };
+        let lora = |hop_limit| Config::new(config::Variant::Lora(RadioConfig { use_preset: true, region: 3, hop_limit, ..Default::default() }));
+
+        manager.set_config("radio", lora(4)).await.unwrap();
+        assert_eq!(cached_hop_limit().await, Some(4));
+        sent.lock().unwrap().clear();
+
+        // Ack the begin, the change and the commit
+        tokio::spawn(async move {
+            for _ in 0..3 {
+                let request = loop {
+                    if let Some(packet) = sent.lock().unwrap().pop() {
+                        break packet;
+                    }
+                    tokio::task::yield_now().await;
+                };
+                let answer = MeshPacket {
+                    from: 0x1111,
+                    request_id: request.id,
+                    payload: Some(PayloadVariant::Routing(Routing { variant: Some(RoutingVariant::ErrorReason(Routing_Error::NONE)) })),
+                    ..Default::default()
+                };
+                incoming.lock().unwrap().as_ref().unwrap().send(answer).unwrap();
+            }
+        });
+        let report = manager.apply_session("radio", &ConfigSession::new().with_config(lora(6))).await.unwrap();
+        assert!(report.all_accepted());
+        assert_eq!(cached_hop_limit().await, Some(6));
+    }
+
+    #[tokio::test]
+    async fn test_restore_profile_reports_changes() {
+        let manager = LoraCommsManager::new();
+        let device = LoopbackDevice::default();
+        let (sent, incoming) = (device.sent.clone(), device.incoming.clone());
+        manager.add_device("radio".to_string(), Box::new(device)).await.unwrap();
+
+        // A fresh radio: default settings everywhere
+        let mut radio = DeviceProfile::new();
+        for variant in [
+            config::Variant::Device(Default::default()),
+            config::Variant::Position(Default::default()),
+            config::Variant::Power(Default::default()),
+            config::Variant::Network(Default::default()),
+            config::Variant::Display(Default::default()),
+            config::Variant::Lora(RadioConfig { use_preset: true, hop_limit: 3, ..Default::default() }),
+            config::Variant::Bluetooth(Default::default()),
+        ] {
+            radio.config.insert(Config::new(variant));
+        }
+        for variant in [
+            module_config::Variant::Mqtt(Default::default()),
+            module_config::Variant::Serial(Default::default()),
+            module_config::Variant::ExternalNotification(Default::default()),
+            module_config::Variant::StoreForward(Default::default()),
+            module_config::Variant::RangeTest(Default::default()),
+            module_config::Variant::Telemetry(Default::default()),
+            module_config::Variant::CannedMessage(Default::default()),
+        ] {
+            radio.module_config.insert(ModuleConfig::new(variant));
+        }
+        radio.channels.push(Channel { index: 0, role: Channel_Role::PRIMARY, settings: Some(ChannelSettings::default()) });
+
+        // Answer reads from `radio` and ack every change
+        tokio::spawn(async move {
+            loop {
+                let Some(request) = sent.lock().unwrap().pop() else {
+                    tokio::task::yield_now().await;
+                    continue;
+                };
+                let Some(PayloadVariant::Admin(AdminMessage { variant: Some(variant) })) = request.payload else {
+                    continue;
+                };
+                let answer = match variant {
+                    admin_message::Variant::GetOwner(_) => admin_message::Variant::GetOwnerResponse(User {
+                        id: "!00001111".to_string(),
+                        long_name: "Meshtastic 1111".to_string(),
+                        short_name: "1111".to_string(),
+                        ..Default::default()
+                    }),
+                    admin_message::Variant::GetChannel(get) => admin_message::Variant::GetChannelResponse(
+                        radio.channels.iter().find(|channel| channel.index == get.index).cloned()
+                            .unwrap_or(Channel { index: get.index, ..Default::default() }),
+                    ),
+                    admin_message::Variant::GetConfig(get) => admin_message::Variant::GetConfigResponse(
+                        radio.config.sections().into_iter().find(|config| config.config_type().map(|t| i32::from(t) as u32) == Some(get.config_type)).unwrap(),
+                    ),
+                    admin_message::Variant::GetModuleConfig(get) => admin_message::Variant::GetModuleConfigResponse(
+                        radio.module_config.sections().into_iter().find(|config| config.config_type().map(|t| i32::from(t) as u32) == Some(get.config_type)).unwrap(),
+                    ),
+                    _ => {
+                        let ack = MeshPacket {
+                            from: 0x1111,
+                            request_id: request.id,
+                            payload: Some(PayloadVariant::Routing(Routing { variant: Some(RoutingVariant::ErrorReason(Routing_Error::NONE)) })),
+                            ..Default::default()
+                        };
+                        incoming.lock().unwrap().as_ref().unwrap().send(ack).unwrap();
+                        continue;
+                    }
+                };
+                let admin = AdminMessage { variant: Some(answer) };
+                let reply = MeshPacket { from: 0x1111, request_id: request.id, ..MeshPacket::new_admin_message(0x1111, admin) };
+                incoming.lock().unwrap().as_ref().unwrap().send(reply).unwrap();
+            }
+        });
+
+        let backup = manager.backup_profile("radio", false).await.unwrap();
+        assert!(backup.redacted);
+        assert_eq!(backup.channels.len(), 1);
+        assert!(backup.owner.as_ref().unwrap().id.is_empty());
+        assert_eq!((backup.config.sections().len(), backup.module_config.sections().len()), (7, 7));
+
+        let mut golden = backup.clone().with_owner(User { long_name: "Relay North".to_string(), short_name: "RLYN".to_string(), ..Default::default() });
+        golden.config.lora.as_mut().unwrap().hop_limit = 5;
+        golden.channels[0].settings = Some(ChannelSettings { name: "ops".to_string(), psk: vec![1], ..Default::default() });
+        let golden = DeviceProfile::parse(&golden.to_yaml().unwrap()).unwrap();
+
+        let report = manager.restore_profile("radio", &golden, false).await.unwrap();
+        let changed: Vec<&str> = report.changes.iter().map(|change| change.section.as_str()).collect();
+        assert_eq!(changed, ["owner", "channel 0", "config LORA_CONFIG"]);
+        assert_eq!(report.changes[2].current.as_ref().unwrap()["variant"]["Lora"]["hop_limit"], 3);
+        let session = report.session.unwrap();
+        assert_eq!(session.operations.len(), 3);
+        assert!(session.all_accepted());
+
+        let full = manager.restore_profile("radio", &golden, true).await.unwrap().session.unwrap();
+        assert_eq!(full.operations.len(), 1 + protocol::channels::MAX_CHANNELS + 7 + 7);
+
+        // Restoring the radio's own settings sends nothing
+        assert!(manager.restore_profile("radio", &backup, false).await.unwrap().session.is_none());
+
+        // The responder still reports the old settings, so the plan finds the same drift
+        let plan = manager.plan_profile("radio", &golden).await.unwrap();
+        let lora = plan.changes.iter().find(|change| change.section == "config LORA_CONFIG").unwrap();
+        assert_eq!((lora.field.as_str(), lora.current.clone(), lora.desired.clone()), ("hop_limit", Some(3.into()), 5.into()));
+        assert_eq!(plan.session.operations.len(), 3);
+        // Channels are read from the radio, not the table cached at connect (empty here)
+        let channel = plan.changes.iter().find(|change| change.section == "channel 0" && change.field == "settings.name").unwrap();
+        assert_eq!(channel.current, Some("".into()));
+
+        let radio_config = manager.get_radio_config("radio").await.unwrap();
+        assert!(matches!(radio_config.preset, Some(radio::RadioPreset::LongFast)));
+        assert!(manager.apply_plan("radio", &plan).await.unwrap().unwrap().all_accepted());
+        assert!(manager.apply_plan("radio", &ProfilePlan::default()).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_long_message_is_reassembled_as_one() {
+        let sender = LoraCommsManager::new();
+        let sender_device = LoopbackDevice::default();
+        let sent = sender_device.sent.clone();
+        sender.add_device("radio".to_string(), Box::new(sender_device)).await.unwrap();
+
+        let mut receiver = LoraCommsManager::new();
+        let mut messages = receiver.get_message_receiver().unwrap();
+        let receiver_device = LoopbackDevice::default();
+        let incoming = receiver_device.incoming.clone();
+        receiver.add_device("radio".to_string(), Box::new(receiver_device)).await.unwrap();
+
+        let text = "a long story, ".repeat(40);
+        let message_id = sender.send_long_message("radio", &text, Some("!00002222"), None).await.unwrap();
+        let chunks = sent.lock().unwrap().clone();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(sender.message_history(None).unwrap()[0].text, text);
+
+        // Chunks may arrive out of order
+        let incoming = incoming.lock().unwrap().clone().unwrap();
+        for chunk in chunks.into_iter().rev() {
+            incoming.send(chunk).unwrap();
+        }
+        let message = tokio::time::timeout(std::time::Duration::from_secs(1), messages.recv()).await.unwrap().unwrap();
+        assert_eq!((message.packet_id, message.text), (Some(message_id), text));
+        assert_eq!(receiver.message_history(None).unwrap().len(), 1);
+    }
+
     #[tokio::test]
     async fn test_device_scanning() {
         let manager = LoraCommsManager::new();
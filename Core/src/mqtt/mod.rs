@@ -3,7 +3,7 @@ use rumqttc::{AsyncClient, Event, EventLoop, MqttOptions, Packet, QoS};
 #[cfg(feature = "mqtt")]
 use url::Url;
 
-use crate::protocol::{MeshMessage, MeshPacket, MessageProcessor, MessageType, PayloadVariant};
+use crate::protocol::{proto, Channel, ChannelSettings, ChannelTable, Channel_Role, DedupStore, MeshMessage, MeshPacket, MessageProcessor, PayloadVariant};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -47,6 +47,13 @@ pub struct MqttConfig {
     pub qos: u8,
     /// Whether to retain messages
     pub retain: bool,
+    /// PSK of the primary channel when no channel table is supplied
+    #[serde(default = "default_channel_psk")]
+    pub channel_psk: Vec<u8>,
+}
+
+fn default_channel_psk() -> Vec<u8> {
+    vec![1]
 }
 
 impl Default for MqttConfig {
@@ -61,6 +68,7 @@ impl Default for MqttConfig {
             keep_alive: 60,
             qos: 1,
             retain: false,
+            channel_psk: default_channel_psk(),
         }
     }
 }
@@ -99,7 +107,13 @@ pub struct MqttGateway {
     #[cfg(feature = "mqtt")]
     eventloop: Option<EventLoop>,
     message_processor: Arc<MessageProcessor>,
+    /// Channels bridged to the broker; one topic per channel name
+    channels: ChannelTable,
+    dedup: DedupStore,
+    /// Packets from the broker, re-encrypted and ready to hand to a radio
+    downlink_tx: Option<mpsc::UnboundedSender<MeshPacket>>,
     node_database: Arc<RwLock<HashMap<u32, crate::protocol::User>>>,
+    #[allow(dead_code)]
     message_tx: Option<mpsc::UnboundedSender<MeshMessage>>,
     stats: Arc<RwLock<GatewayStats>>,
 }
@@ -118,19 +132,79 @@ pub struct GatewayStats {
 #[cfg(feature = "mqtt")]
 impl MqttGateway {
     pub fn new(config: MqttConfig) -> Result<Self, MqttError> {
-        let message_processor = Arc::new(MessageProcessor::new());
-        
+        let primary = Channel {
+            index: 0,
+            role: Channel_Role::PRIMARY,
+            settings: Some(ChannelSettings {
+                psk: config.channel_psk.clone(),
+                ..Default::default()
+            }),
+        };
+        let channels = ChannelTable::from_channels(&[primary], 0)
+            .map_err(|e| MqttError::Configuration(format!("Invalid channel PSK: {}", e)))?;
+
         Ok(Self {
             config,
             client: None,
             eventloop: None,
-            message_processor,
+            message_processor: Arc::new(MessageProcessor::new().with_channels(channels.clone())),
+            channels,
+            dedup: DedupStore::default(),
+            downlink_tx: None,
             node_database: Arc::new(RwLock::new(HashMap::new())),
             message_tx: None,
             stats: Arc::new(RwLock::new(GatewayStats::default())),
         })
     }
 
+    /// Forward packets received from the broker to `tx`, e.g. a radio's send queue
+    pub fn with_downlink_channel(mut self, tx: mpsc::UnboundedSender<MeshPacket>) -> Self {
+        self.downlink_tx = Some(tx);
+        self
+    }
+
+    /// Bridge these channels instead of the single primary built from
+    /// `channel_psk`, typically a radio's own table
+    pub fn with_channels(mut self, channels: ChannelTable) -> Self {
+        self.channels = channels;
+        self.rebuild_processor();
+        self
+    }
+
+    /// Share duplicate suppression with the radios attached to this app, so
+    /// packets the broker echoes back are not processed twice
+    pub fn with_dedup(mut self, dedup: DedupStore) -> Self {
+        self.dedup = dedup;
+        self.rebuild_processor();
+        self
+    }
+
+    fn rebuild_processor(&mut self) {
+        self.message_processor = Arc::new(
+            MessageProcessor::new()
+                .with_channels(self.channels.clone())
+                .with_dedup(self.dedup.clone()),
+        );
+    }
+
+    /// Re-encrypt a packet with its channel's key before it goes back onto
+    /// the mesh. Packets that are still encrypted are passed through untouched.
+    pub fn encrypt_downlink(&self, packet: &MeshPacket) -> Result<MeshPacket, MqttError> {
+        encrypt_downlink(&self.channels, packet)
+    }
+
+    /// Channel name a packet is published under: the channel it was heard on,
+    /// falling back to the primary
+    fn channel_name(&self, packet: &MeshPacket) -> &str {
+        let entry = match packet.payload {
+            Some(PayloadVariant::Encrypted(_)) => self.channels.by_hash(packet.channel).next(),
+            _ => self.channels.by_index(packet.channel as u32),
+        };
+        entry.or_else(|| self.channels.primary())
+            .map(|entry| entry.name.as_str())
+            .unwrap_or("LongFast")
+    }
+
     pub async fn connect(&mut self) -> Result<(), MqttError> {
         let url = Url::parse(&self.config.broker_url)
             .map_err(|e| MqttError::InvalidUrl(format!("Invalid broker URL: {}", e)))?;
@@ -155,12 +229,13 @@ impl MqttGateway {
 
         let (client, eventloop) = AsyncClient::new(mqttoptions, 100);
         
-        // Subscribe to incoming message topics
-        let subscribe_topics = vec![
-            format!("{}/2/c/LongFast/+/+", self.config.topic_prefix),
-            format!("{}/2/e/LongFast/+/+", self.config.topic_prefix),
-            format!("{}/2/stat/+", self.config.topic_prefix),
-        ];
+        // Subscribe to incoming message topics for every bridged channel
+        let mut subscribe_topics = Vec::new();
+        for entry in self.channels.entries() {
+            subscribe_topics.push(format!("{}/2/c/{}/+/+", self.config.topic_prefix, entry.name));
+            subscribe_topics.push(format!("{}/2/e/{}/+/+", self.config.topic_prefix, entry.name));
+        }
+        subscribe_topics.push(format!("{}/2/stat/+", self.config.topic_prefix));
 
         for topic in subscribe_topics {
             client.subscribe(&topic, QoS::AtMostOnce).await
@@ -200,12 +275,36 @@ impl MqttGateway {
 
     pub async fn start_event_loop(&mut self) -> Result<(), MqttError> {
         if let Some(mut eventloop) = self.eventloop.take() {
+            let processor = Arc::clone(&self.message_processor);
+            let channels = self.channels.clone();
+            let downlink_tx = self.downlink_tx.clone();
+            let dedup = self.dedup.clone();
+            let gateway_id = self.config.client_id.clone();
+
             tokio::spawn(async move {
                 loop {
                     match eventloop.poll().await {
                         Ok(Event::Incoming(Packet::Publish(publish))) => {
                             println!("Received MQTT message on topic: {}", publish.topic);
                             
+                            // Encrypted feeds carry protobuf ServiceEnvelopes
+                            if publish.topic.contains("/2/e/") {
+                                match accept_service_envelope(&publish.payload, &channels, &dedup, &gateway_id) {
+                                    Ok(Some(inbound)) => {
+                                        if let (Some(tx), Some(downlink)) = (&downlink_tx, inbound.downlink) {
+                                            let _ = tx.send(downlink);
+                                        }
+                                        if let Err(e) = processor.process_new_packet(inbound.packet).await {
+                                            eprintln!("Failed to process MQTT packet: {}", e);
+                                        }
+                                    }
+                                    // Our own uplink or a packet already heard
+                                    Ok(None) => {}
+                                    Err(e) => eprintln!("Invalid ServiceEnvelope on {}: {}", publish.topic, e),
+                                }
+                                continue;
+                            }
+
                             // Parse and process incoming MQTT messages
                             if let Ok(mqtt_msg) = serde_json::from_slice::<MqttMeshtasticMessage>(&publish.payload) {
                                 println!("Parsed Meshtastic message from MQTT: {:?}", mqtt_msg);
@@ -234,18 +333,21 @@ impl MqttGateway {
     pub async fn publish_mesh_message(&self, packet: &MeshPacket) -> Result<(), MqttError> {
         if let Some(client) = &self.client {
             let mqtt_message = self.convert_to_mqtt_message(packet).await;
+            let channel_name = self.channel_name(packet);
             
             let topic = match &packet.payload {
                 Some(PayloadVariant::Text(_)) => {
-                    format!("{}/2/c/LongFast/{}/{}", 
+                    format!("{}/2/c/{}/{}/{}", 
                         self.config.topic_prefix, 
+                        channel_name,
                         self.config.client_id,
                         packet.from
                     )
                 }
                 Some(PayloadVariant::NodeInfo(_)) => {
-                    format!("{}/2/e/LongFast/{}/{}", 
+                    format!("{}/2/e/{}/{}/{}", 
                         self.config.topic_prefix, 
+                        channel_name,
                         self.config.client_id,
                         packet.from
                     )
@@ -257,8 +359,9 @@ impl MqttGateway {
                     )
                 }
                 _ => {
-                    format!("{}/2/c/LongFast/{}/{}", 
+                    format!("{}/2/c/{}/{}/{}", 
                         self.config.topic_prefix, 
+                        channel_name,
                         self.config.client_id,
                         packet.from
                     )
@@ -307,8 +410,11 @@ impl MqttGateway {
             Some(PayloadVariant::Routing(routing)) => {
                 ("ROUTING_APP".to_string(), serde_json::to_value(routing).unwrap_or_default())
             }
-            Some(PayloadVariant::Raw(data)) => {
-                ("RAW".to_string(), serde_json::json!({"data": base64::prelude::BASE64_STANDARD.encode(data)}))
+            Some(PayloadVariant::App(portnum, data)) => {
+                ("RAW".to_string(), serde_json::json!({"portnum": portnum.0, "data": base64::prelude::BASE64_STANDARD.encode(data)}))
+            }
+            Some(PayloadVariant::Encrypted(data)) => {
+                ("ENCRYPTED".to_string(), serde_json::json!({"data": base64::prelude::BASE64_STANDARD.encode(data)}))
             }
             None => {
                 ("UNKNOWN".to_string(), serde_json::json!({}))
@@ -430,6 +536,80 @@ impl MqttGateway {
     }
 }
 
+/// Encrypt a decoded packet with the key of the channel at `packet.channel`;
+/// already-encrypted packets pass through
+fn encrypt_downlink(channels: &ChannelTable, packet: &MeshPacket) -> Result<MeshPacket, MqttError> {
+    match packet.payload {
+        Some(PayloadVariant::Encrypted(_)) => Ok(packet.clone()),
+        _ => channels.encrypt(packet)
+            .map_err(|e| MqttError::Configuration(format!("Failed to encrypt packet: {}", e))),
+    }
+}
+
+/// A packet taken off the broker that this gateway has not seen before
+#[cfg(feature = "mqtt")]
+struct InboundPacket {
+    packet: MeshPacket,
+    /// Copy to put on the air; `None` unless the channel allows downlink
+    downlink: Option<MeshPacket>,
+}
+
+/// Decide what to do with a ServiceEnvelope from the broker. Our own
+/// uplinks echoed back and packets already heard (over the air or from
+/// another gateway) yield `None`; everything else is recorded in `dedup`.
+#[cfg(feature = "mqtt")]
+fn accept_service_envelope(
+    payload: &[u8],
+    channels: &ChannelTable,
+    dedup: &DedupStore,
+    gateway_id: &str,
+) -> Result<Option<InboundPacket>, crate::protocol::ProtocolError> {
+    let (packet, envelope) = decode_service_envelope(payload, channels)?;
+    if envelope.gateway_id == gateway_id || !dedup.first_seen(packet.from, packet.id) {
+        return Ok(None);
+    }
+
+    let downlink_enabled = channels.by_name(&envelope.channel_id)
+        .is_some_and(|entry| entry.settings.downlink_enabled);
+    let downlink = if downlink_enabled {
+        match encrypt_downlink(channels, &packet) {
+            Ok(downlink) => Some(downlink),
+            Err(e) => {
+                eprintln!("Failed to encrypt downlink: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    Ok(Some(InboundPacket { packet, downlink }))
+}
+
+/// Pull the MeshPacket out of a ServiceEnvelope published by a Meshtastic
+/// gateway, returning the rest of the envelope alongside it. Decoded packets
+/// get `channel` set to the local index of the envelope's channel name.
+#[cfg(feature = "mqtt")]
+fn decode_service_envelope(
+    payload: &[u8],
+    channels: &ChannelTable,
+) -> Result<(MeshPacket, proto::ServiceEnvelope), crate::protocol::ProtocolError> {
+    use prost::Message;
+
+    let mut envelope = proto::ServiceEnvelope::decode(payload)
+        .map_err(|e| crate::protocol::ProtocolError::Protobuf(e.to_string()))?;
+    let mut packet: MeshPacket = envelope.packet.take()
+        .ok_or(crate::protocol::ProtocolError::InvalidFormat)?
+        .try_into()?;
+
+    if !matches!(packet.payload, Some(PayloadVariant::Encrypted(_))) {
+        if let Some(entry) = channels.by_name(&envelope.channel_id) {
+            packet.channel = entry.index as u8;
+        }
+    }
+    Ok((packet, envelope))
+}
+
 /// MQTT Gateway manager for handling multiple gateway instances
 pub struct MqttGatewayManager {
     gateways: Arc<RwLock<HashMap<String, MqttGateway>>>,
@@ -521,7 +701,83 @@ mod tests {
             ..Default::default()
         };
         
-        assert!(manager.add_gateway("test".to_string(), config).is_ok());
+        assert!(manager.add_gateway("test".to_string(), config).await.is_ok());
         assert_eq!(manager.list_gateways().await, vec!["test"]);
     }
+
+    #[test]
+    fn test_downlink_uses_channel_table() {
+        let channels = ChannelTable::from_channels(&[
+            Channel {
+                index: 0,
+                role: Channel_Role::PRIMARY,
+                settings: Some(ChannelSettings { psk: vec![1], ..Default::default() }),
+            },
+            Channel {
+                index: 1,
+                role: Channel_Role::SECONDARY,
+                settings: Some(ChannelSettings { name: "ops".to_string(), psk: vec![3; 16], ..Default::default() }),
+            },
+        ], 0).unwrap();
+        let gateway = MqttGateway::new(MqttConfig::default()).unwrap().with_channels(channels.clone());
+
+        let packet = MeshPacket {
+            channel: 1,
+            ..MeshPacket::new_text_message(0x12345678, 0xFFFFFFFF, "downlink")
+        };
+        let encrypted = gateway.encrypt_downlink(&packet).unwrap();
+        assert_eq!(encrypted.channel, channels.by_name("ops").unwrap().hash);
+        assert_eq!(gateway.channel_name(&encrypted), "ops");
+        assert_eq!(gateway.channel_name(&packet), "ops");
+    }
+
+    #[cfg(feature = "mqtt")]
+    #[test]
+    fn test_envelopes_are_deduped_and_gated_on_downlink() {
+        use prost::Message;
+
+        let channels = ChannelTable::from_channels(&[
+            Channel {
+                index: 0,
+                role: Channel_Role::PRIMARY,
+                settings: Some(ChannelSettings { psk: vec![1], ..Default::default() }),
+            },
+            Channel {
+                index: 1,
+                role: Channel_Role::SECONDARY,
+                settings: Some(ChannelSettings {
+                    name: "ops".to_string(),
+                    psk: vec![3; 16],
+                    downlink_enabled: true,
+                    ..Default::default()
+                }),
+            },
+        ], 0).unwrap();
+        let dedup = DedupStore::default();
+        let envelope = |id: u32, channel: &str, gateway: &str| {
+            let packet = MeshPacket { id, ..MeshPacket::new_text_message(0x12345678, 0xFFFFFFFF, "hi") };
+            proto::ServiceEnvelope {
+                packet: Some(proto::MeshPacket::try_from(&packet).unwrap()),
+                channel_id: channel.to_string(),
+                gateway_id: gateway.to_string(),
+            }.encode_to_vec()
+        };
+
+        // Our own uplink coming back is neither processed nor recorded
+        let echo = accept_service_envelope(&envelope(1, "ops", "me"), &channels, &dedup, "me").unwrap();
+        assert!(echo.is_none());
+        assert!(dedup.is_empty());
+
+        let inbound = accept_service_envelope(&envelope(1, "ops", "other"), &channels, &dedup, "me").unwrap().unwrap();
+        assert_eq!(inbound.packet.channel, 1);
+        assert_eq!(inbound.downlink.unwrap().channel, channels.by_name("ops").unwrap().hash);
+
+        // The same packet from another gateway is dropped
+        let again = accept_service_envelope(&envelope(1, "ops", "third"), &channels, &dedup, "me").unwrap();
+        assert!(again.is_none());
+
+        // The primary channel does not allow downlink
+        let inbound = accept_service_envelope(&envelope(2, "LongFast", "other"), &channels, &dedup, "me").unwrap().unwrap();
+        assert!(inbound.downlink.is_none());
+    }
 }
@@ -0,0 +1,324 @@
+//! Per-device channel table.
+//!
+//! On the air a packet's `channel` field carries an 8-bit hash of the channel
+//! name and key rather than the channel index, so incoming traffic has to be
+//! matched against every configured channel. Towards the radio over the
+//! stream API the field is the plain index.
+
+use super::{
+    decrypt_packet, encrypt_packet, proto, Channel, ChannelKey, ChannelSet, ChannelSettings,
+    Channel_Role, MeshPacket, PayloadVariant, ProtocolError, RadioConfig,
+};
+use base64::prelude::*;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of channels a Meshtastic radio supports
+pub const MAX_CHANNELS: usize = 8;
+
+/// Prefix of the channel URLs produced by the official apps
+pub const CHANNEL_URL_PREFIX: &str = "https://meshtastic.org/e/#";
+
+/// Name the firmware shows for a channel whose name is left empty: the
+/// modem preset's display name
+pub fn preset_name(modem_preset: i32) -> &'static str {
+    match modem_preset {
+        1 => "LongSlow",
+        2 => "VLongSlow",
+        3 => "MediumSlow",
+        4 => "MediumFast",
+        5 => "ShortSlow",
+        6 => "ShortFast",
+        7 => "LongMod",
+        8 => "ShortTurbo",
+        _ => "LongFast",
+    }
+}
+
+/// Meshtastic channel hash: xor of the name bytes, xored with the xor of the
+/// expanded key bytes
+pub fn channel_hash(name: &str, key: &ChannelKey) -> u8 {
+    let xor = |bytes: &[u8]| bytes.iter().fold(0u8, |acc, b| acc ^ b);
+    let key_hash = match key {
+        ChannelKey::None => 0,
+        ChannelKey::Aes128(key) => xor(key),
+        ChannelKey::Aes256(key) => xor(key),
+    };
+    xor(name.as_bytes()) ^ key_hash
+}
+
+/// A configured channel with its key expanded and hash precomputed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelEntry {
+    pub index: u32,
+    pub role: Channel_Role,
+    /// Effective name; the preset name when the settings leave it empty
+    pub name: String,
+    pub settings: ChannelSettings,
+    #[serde(skip, default = "no_key")]
+    pub key: ChannelKey,
+    pub hash: u8,
+}
+
+fn no_key() -> ChannelKey {
+    ChannelKey::None
+}
+
+/// Channel table for one device, ordered by index. Disabled slots are not
+/// stored.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChannelTable {
+    entries: Vec<ChannelEntry>,
+    modem_preset: i32,
+}
+
+impl ChannelTable {
+    pub fn new(modem_preset: i32) -> Self {
+        Self {
+            entries: Vec::new(),
+            modem_preset,
+        }
+    }
+
+    /// Build a table from the channels a radio reported
+    pub fn from_channels(channels: &[Channel], modem_preset: i32) -> Result<Self, ProtocolError> {
+        let mut table = Self::new(modem_preset);
+        for channel in channels {
+            table.upsert(channel)?;
+        }
+        Ok(table)
+    }
+
+    /// Add or replace the channel at `channel.index`. A DISABLED role removes it.
+    pub fn upsert(&mut self, channel: &Channel) -> Result<(), ProtocolError> {
+        if channel.index as usize >= MAX_CHANNELS {
+            return Err(ProtocolError::Encoding(format!(
+                "Channel index {} is out of range (max {})", channel.index, MAX_CHANNELS - 1
+            )));
+        }
+
+        self.entries.retain(|entry| entry.index != channel.index);
+        if channel.role == Channel_Role::DISABLED {
+            return Ok(());
+        }
+
+        let settings = channel.settings.clone().unwrap_or_default();
+        let key = ChannelKey::from_psk(&settings.psk)?;
+        let name = if settings.name.is_empty() {
+            preset_name(self.modem_preset).to_string()
+        } else {
+            settings.name.clone()
+        };
+
+        self.entries.push(ChannelEntry {
+            index: channel.index,
+            role: channel.role,
+            hash: channel_hash(&name, &key),
+            name,
+            settings,
+            key,
+        });
+        self.entries.sort_by_key(|entry| entry.index);
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[ChannelEntry] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn primary(&self) -> Option<&ChannelEntry> {
+        self.entries.iter().find(|entry| entry.role == Channel_Role::PRIMARY)
+    }
+
+    pub fn by_index(&self, index: u32) -> Option<&ChannelEntry> {
+        self.entries.iter().find(|entry| entry.index == index)
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&ChannelEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// Channels whose hash matches; more than one is possible
+    pub fn by_hash(&self, hash: u8) -> impl Iterator<Item = &ChannelEntry> {
+        self.entries.iter().filter(move |entry| entry.hash == hash)
+    }
+
+    /// Channel set for sharing as a URL: the primary channel first, then the
+    /// secondaries in index order
+    pub fn channel_set(&self, lora_config: Option<RadioConfig>) -> ChannelSet {
+        let primary = self.entries.iter().filter(|entry| entry.role == Channel_Role::PRIMARY);
+        let secondary = self.entries.iter().filter(|entry| entry.role != Channel_Role::PRIMARY);
+        ChannelSet {
+            settings: primary.chain(secondary).map(|entry| entry.settings.clone()).collect(),
+            lora_config,
+        }
+    }
+
+    /// Encrypt a decoded packet for the air. `packet.channel` selects the
+    /// channel by index and is replaced with that channel's hash.
+    pub fn encrypt(&self, packet: &MeshPacket) -> Result<MeshPacket, ProtocolError> {
+        let entry = self.by_index(packet.channel as u32).ok_or_else(|| {
+            ProtocolError::Crypto(format!("No channel at index {}", packet.channel))
+        })?;
+
+        let mut encrypted = encrypt_packet(packet, &entry.key)?;
+        encrypted.channel = entry.hash;
+        Ok(encrypted)
+    }
+
+    /// Decrypt a packet heard on the air or from an MQTT feed, trying every
+    /// channel whose hash matches. On success `packet.channel` is rewritten
+    /// to the local channel index.
+    pub fn decrypt(&self, packet: &MeshPacket) -> Option<MeshPacket> {
+        if !matches!(packet.payload, Some(PayloadVariant::Encrypted(_))) {
+            return None;
+        }
+
+        self.by_hash(packet.channel).find_map(|entry| {
+            let mut decrypted = decrypt_packet(packet, &entry.key).ok()?;
+            decrypted.channel = entry.index as u8;
+            Some(decrypted)
+        })
+    }
+}
+
+impl ChannelSet {
+    /// Encode as a `https://meshtastic.org/e/#…` URL
+    pub fn to_url(&self) -> String {
+        let encoded = proto::ChannelSet::from(self).encode_to_vec();
+        format!("{}{}", CHANNEL_URL_PREFIX, BASE64_URL_SAFE_NO_PAD.encode(encoded))
+    }
+
+    /// Parse a channel URL. Also accepts the `/e/?add=true#` form, padded
+    /// base64 and the standard base64 alphabet.
+    pub fn from_url(url: &str) -> Result<Self, ProtocolError> {
+        let (base, fragment) = url.trim().split_once('#')
+            .ok_or_else(|| ProtocolError::InvalidChannelUrl("missing '#' fragment".to_string()))?;
+        if !base.contains("meshtastic.org/e/") {
+            return Err(ProtocolError::InvalidChannelUrl(format!("unexpected location {}", base)));
+        }
+
+        let fragment = fragment.trim_end_matches('=').replace('+', "-").replace('/', "_");
+        let bytes = BASE64_URL_SAFE_NO_PAD.decode(fragment)
+            .map_err(|e| ProtocolError::InvalidChannelUrl(e.to_string()))?;
+        let set = proto::ChannelSet::decode(bytes.as_slice())
+            .map_err(|e| ProtocolError::InvalidChannelUrl(e.to_string()))?;
+
+        if set.settings.is_empty() {
+            return Err(ProtocolError::InvalidChannelUrl("no channels".to_string()));
+        }
+        if set.settings.len() > MAX_CHANNELS {
+            return Err(ProtocolError::InvalidChannelUrl(format!(
+                "{} channels, at most {} are supported", set.settings.len(), MAX_CHANNELS
+            )));
+        }
+        Ok(set.into())
+    }
+
+    /// Channels to write to a radio: index 0 is PRIMARY, the rest SECONDARY
+    pub fn channels(&self) -> Vec<Channel> {
+        self.settings.iter().enumerate()
+            .map(|(index, settings)| Channel {
+                index: index as u32,
+                role: if index == 0 { Channel_Role::PRIMARY } else { Channel_Role::SECONDARY },
+                settings: Some(settings.clone()),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(index: u32, role: Channel_Role, name: &str, psk: &[u8]) -> Channel {
+        Channel {
+            index,
+            role,
+            settings: Some(ChannelSettings {
+                name: name.to_string(),
+                psk: psk.to_vec(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_known_channel_hashes() {
+        let default_key = ChannelKey::from_psk(&[1]).unwrap();
+        assert_eq!(channel_hash("LongFast", &default_key), 8);
+        assert_eq!(channel_hash("MediumFast", &default_key), 31);
+    }
+
+    #[test]
+    fn test_table_roles_and_lookup() {
+        let table = ChannelTable::from_channels(&[
+            channel(0, Channel_Role::PRIMARY, "", &[1]),
+            channel(1, Channel_Role::SECONDARY, "admin", &(0u8..16).collect::<Vec<_>>()),
+            channel(2, Channel_Role::DISABLED, "", &[]),
+        ], 0).unwrap();
+
+        assert_eq!(table.entries().len(), 2);
+        assert_eq!(table.primary().unwrap().name, "LongFast");
+        assert_eq!(table.primary().unwrap().hash, 8);
+        assert_eq!(table.by_name("admin").unwrap().index, 1);
+        assert_eq!(table.by_name("admin").unwrap().hash, 111);
+        assert!(table.by_index(2).is_none());
+    }
+
+    #[test]
+    fn test_incoming_packet_maps_to_channel() {
+        let table = ChannelTable::from_channels(&[
+            channel(0, Channel_Role::PRIMARY, "", &[1]),
+            channel(1, Channel_Role::SECONDARY, "admin", &(0u8..16).collect::<Vec<_>>()),
+        ], 0).unwrap();
+
+        let packet = MeshPacket {
+            channel: 1,
+            ..MeshPacket::new_text_message(0x12345678, 0xFFFFFFFF, "secret")
+        };
+        let on_air = table.encrypt(&packet).unwrap();
+        assert_eq!(on_air.channel, 111);
+
+        let received = table.decrypt(&on_air).unwrap();
+        assert_eq!(received.channel, 1);
+        assert!(matches!(received.payload, Some(PayloadVariant::Text(ref t)) if t == "secret"));
+    }
+
+    #[test]
+    fn test_channel_url_round_trip() {
+        // Default LongFast channel, US region, 3 hops
+        let url = "https://meshtastic.org/e/#CgMSAQESCAgBOAFAA0gB";
+        let set = ChannelSet::from_url(url).unwrap();
+        assert_eq!(set.settings.len(), 1);
+        assert_eq!(set.settings[0].psk, vec![1]);
+        let lora = set.lora_config.as_ref().unwrap();
+        assert!(lora.use_preset);
+        assert_eq!(lora.region, 1);
+        assert_eq!(lora.hop_limit, 3);
+        assert_eq!(set.to_url(), url);
+
+        assert!(ChannelSet::from_url("https://meshtastic.org/e/?add=true#CgMSAQESCAgBOAFAA0gB").is_ok());
+        assert!(ChannelSet::from_url("https://example.com/e/#CgMSAQESCAgBOAFAA0gB").is_err());
+        assert!(ChannelSet::from_url("https://meshtastic.org/e/#").is_err());
+    }
+
+    #[test]
+    fn test_channel_set_puts_primary_first() {
+        let table = ChannelTable::from_channels(&[
+            channel(1, Channel_Role::SECONDARY, "ops", &[2]),
+            channel(3, Channel_Role::PRIMARY, "main", &[1]),
+        ], 0).unwrap();
+
+        let channels = ChannelSet::from_url(&table.channel_set(None).to_url()).unwrap().channels();
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].role, Channel_Role::PRIMARY);
+        assert_eq!(channels[0].settings.as_ref().unwrap().name, "main");
+        assert_eq!(channels[1].role, Channel_Role::SECONDARY);
+        assert_eq!(channels[1].settings.as_ref().unwrap().name, "ops");
+    }
+}
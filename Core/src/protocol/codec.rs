@@ -0,0 +1,197 @@
+//! Meshtastic stream-API framing shared by every byte-stream transport.
+//!
+//! Each frame is `0x94 0xC3 <len_hi> <len_lo>` followed by `len` bytes of
+//! protobuf. There is no escaping and no checksum; the firmware interleaves
+//! plain-text debug output between frames, which the decoder skips.
+
+use bytes::{Buf, BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// First byte of the frame header
+pub const START1: u8 = 0x94;
+/// Second byte of the frame header
+pub const START2: u8 = 0xC3;
+/// START1, START2 and a big-endian u16 payload length
+pub const HEADER_LEN: usize = 4;
+/// Largest payload the firmware will accept or send in a single frame
+pub const MAX_FRAME_PAYLOAD: usize = 512;
+
+#[derive(Debug, thiserror::Error)]
+pub enum FrameError {
+    /// Bytes outside any frame were dropped while looking for a header.
+    /// The codec has already resynced; decoding can simply continue.
+    #[error("Discarded {discarded} bytes while searching for a frame header")]
+    Garbage { discarded: usize },
+    /// A header (or outgoing payload) exceeded [`MAX_FRAME_PAYLOAD`]
+    #[error("Frame length {len} exceeds the {MAX_FRAME_PAYLOAD} byte limit")]
+    Oversized { len: usize },
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Encoder/decoder for the Meshtastic stream protocol.
+///
+/// Decode errors are reported per frame and never leave the codec in a bad
+/// state, so callers driving [`Decoder::decode`] directly can log the error
+/// and keep going.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FrameCodec;
+
+impl FrameCodec {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Frame a single payload into a fresh buffer
+    pub fn frame(data: &[u8]) -> Result<Vec<u8>, FrameError> {
+        let mut buffer = BytesMut::with_capacity(HEADER_LEN + data.len());
+        FrameCodec.encode(data, &mut buffer)?;
+        Ok(buffer.to_vec())
+    }
+}
+
+impl Decoder for FrameCodec {
+    type Item = Vec<u8>;
+    type Error = FrameError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        // Drop everything before the next possible header
+        let start = src.iter().position(|&b| b == START1).unwrap_or(src.len());
+        if start > 0 {
+            src.advance(start);
+            return Err(FrameError::Garbage { discarded: start });
+        }
+
+        if src.len() < 2 {
+            return Ok(None);
+        }
+        if src[1] != START2 {
+            src.advance(1);
+            return Err(FrameError::Garbage { discarded: 1 });
+        }
+
+        if src.len() < HEADER_LEN {
+            return Ok(None);
+        }
+        let len = u16::from_be_bytes([src[2], src[3]]) as usize;
+        if len > MAX_FRAME_PAYLOAD {
+            // Treat it as a false start so we resync on the next header
+            src.advance(1);
+            return Err(FrameError::Oversized { len });
+        }
+
+        if src.len() < HEADER_LEN + len {
+            src.reserve(HEADER_LEN + len - src.len());
+            return Ok(None);
+        }
+
+        src.advance(HEADER_LEN);
+        Ok(Some(src.split_to(len).to_vec()))
+    }
+}
+
+impl Encoder<&[u8]> for FrameCodec {
+    type Error = FrameError;
+
+    fn encode(&mut self, item: &[u8], dst: &mut BytesMut) -> Result<(), Self::Error> {
+        if item.len() > MAX_FRAME_PAYLOAD {
+            return Err(FrameError::Oversized { len: item.len() });
+        }
+
+        dst.reserve(HEADER_LEN + item.len());
+        dst.put_u8(START1);
+        dst.put_u8(START2);
+        dst.put_u16(item.len() as u16);
+        dst.put_slice(item);
+        Ok(())
+    }
+}
+
+impl Encoder<Vec<u8>> for FrameCodec {
+    type Error = FrameError;
+
+    fn encode(&mut self, item: Vec<u8>, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode(item.as_slice(), dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decode every frame in `buffer`, collecting payloads and errors
+    fn drain(buffer: &mut BytesMut) -> (Vec<Vec<u8>>, Vec<FrameError>) {
+        let mut codec = FrameCodec::new();
+        let (mut frames, mut errors) = (Vec::new(), Vec::new());
+        loop {
+            match codec.decode(buffer) {
+                Ok(Some(frame)) => frames.push(frame),
+                Ok(None) => break,
+                Err(e) => errors.push(e),
+            }
+        }
+        (frames, errors)
+    }
+
+    #[test]
+    fn test_frame_header() {
+        let framed = FrameCodec::frame(&[0x01, 0x02, 0x03]).unwrap();
+        assert_eq!(framed, vec![0x94, 0xC3, 0x00, 0x03, 0x01, 0x02, 0x03]);
+
+        // Payload bytes that look like framing are not escaped
+        let framed = FrameCodec::frame(&[0x94, 0x7E, 0x7D]).unwrap();
+        assert_eq!(&framed[HEADER_LEN..], &[0x94, 0x7E, 0x7D]);
+    }
+
+    #[test]
+    fn test_frame_too_large() {
+        assert!(FrameCodec::frame(&[0u8; MAX_FRAME_PAYLOAD]).is_ok());
+        assert!(matches!(
+            FrameCodec::frame(&[0u8; MAX_FRAME_PAYLOAD + 1]),
+            Err(FrameError::Oversized { len }) if len == MAX_FRAME_PAYLOAD + 1
+        ));
+    }
+
+    #[test]
+    fn test_decode_waits_for_complete_frame() {
+        let framed = FrameCodec::frame(b"hello").unwrap();
+        let mut buffer = BytesMut::from(&framed[..6]);
+        let mut codec = FrameCodec::new();
+        assert!(codec.decode(&mut buffer).unwrap().is_none());
+
+        buffer.extend_from_slice(&framed[6..]);
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(b"hello".to_vec()));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_decode_resyncs_and_reports_errors() {
+        let mut buffer = BytesMut::new();
+        buffer.extend_from_slice(b"INFO | boot\r\n");
+        // Stray START1 not followed by START2
+        buffer.extend_from_slice(&[0x94, 0x00]);
+        // Header claiming an oversized frame
+        buffer.extend_from_slice(&[0x94, 0xC3, 0xFF, 0xFF]);
+        buffer.extend_from_slice(&FrameCodec::frame(b"one").unwrap());
+        buffer.extend_from_slice(&FrameCodec::frame(b"two").unwrap());
+
+        let (frames, errors) = drain(&mut buffer);
+        assert_eq!(frames, vec![b"one".to_vec(), b"two".to_vec()]);
+        assert!(errors.iter().any(|e| matches!(e, FrameError::Garbage { discarded: 13 })));
+        assert!(errors.iter().any(|e| matches!(e, FrameError::Oversized { len: 0xFFFF })));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_round_trips_a_packet() {
+        use crate::protocol::{decode_packet, encode_packet, MeshPacket, PayloadVariant};
+
+        let packet = MeshPacket::new_text_message(1, 2, "framed");
+        let encoded = encode_packet(&packet).unwrap();
+        let mut buffer = BytesMut::from(&FrameCodec::frame(&encoded).unwrap()[..]);
+
+        let frame = FrameCodec::new().decode(&mut buffer).unwrap().unwrap();
+        let decoded = decode_packet(&frame).unwrap();
+        assert!(matches!(decoded.payload, Some(PayloadVariant::Text(ref t)) if t == "framed"));
+    }
+}
@@ -0,0 +1,1925 @@
+//! Lossless conversions between the prost-generated wire types in
+//! [`super::proto`] and the hand-written protocol models.
+
+use super::proto;
+use super::*;
+
+fn decode_error(e: prost::DecodeError) -> ProtocolError {
+    ProtocolError::Protobuf(e.to_string())
+}
+
+// =============================================================================
+// ENUMS
+// =============================================================================
+
+/// Map a protocol enum to and from its wire value. Values this build does not
+/// know land in the enum's `Unknown` variant instead of a default, so packets
+/// from newer firmware re-encode unchanged.
+macro_rules! wire_enum {
+    ($ty:ty { $($variant:ident = $value:literal),+ $(,)? }) => {
+        impl From<i32> for $ty {
+            fn from(value: i32) -> Self {
+                match value {
+                    $($value => Self::$variant,)+
+                    other => Self::Unknown(other),
+                }
+            }
+        }
+
+        impl From<$ty> for i32 {
+            fn from(value: $ty) -> Self {
+                type Enum = $ty;
+                match value {
+                    $(Enum::$variant => $value,)+
+                    Enum::Unknown(other) => other,
+                }
+            }
+        }
+    };
+}
+
+wire_enum!(HardwareModel {
+    UNSET = 0,
+    TLORA_V2 = 1,
+    TLORA_V1 = 2,
+    TLORA_V2_1_1P6 = 3,
+    TBEAM = 4,
+    HELTEC_V2_0 = 5,
+    TBEAM_V0_7 = 6,
+    T_ECHO = 7,
+    TLORA_V1_1P3 = 8,
+    RAK4631 = 9,
+    HELTEC_V2_1 = 10,
+    HELTEC_V1 = 11,
+    LILYGO_TBEAM_S3_CORE = 12,
+    RAK11200 = 13,
+    NANO_G1 = 14,
+    TLORA_V2_1_1P8 = 15,
+    TLORA_T3_S3 = 16,
+    NANO_G1_EXPLORER = 17,
+    NANO_G2_ULTRA = 18,
+    LORA_TYPE = 19,
+    WIPHONE = 20,
+    WIO_WM1110 = 21,
+    RAK2560 = 22,
+    HELTEC_HRU_3601 = 23,
+    STATION_G1 = 25,
+    RAK11310 = 26,
+    SENSELORA_RP2040 = 27,
+    SENSELORA_S3 = 28,
+    CANARYONE = 29,
+    RP2040_LORA = 30,
+    STATION_G2 = 31,
+    LORA_RELAY_V1 = 32,
+    NRF52840DK = 33,
+    PPR = 34,
+    GENIEBLOCKS = 35,
+    NRF52_UNKNOWN = 36,
+    PORTDUINO = 37,
+    ANDROID_SIM = 38,
+    DIY_V1 = 39,
+    NRF52840_PCA10059 = 40,
+    DR_DEV = 41,
+    M5STACK = 42,
+    HELTEC_V3 = 43,
+    HELTEC_WSL_V3 = 44,
+    BETAFPV_2400_TX = 45,
+    BETAFPV_900_NANO_TX = 46,
+    RPI_PICO = 47,
+    HELTEC_WIRELESS_TRACKER = 48,
+    HELTEC_WIRELESS_PAPER = 49,
+    T_DECK = 50,
+    T_WATCH_S3 = 51,
+    PICOMPUTER_S3 = 52,
+    HELTEC_HT62 = 53,
+    EBYTE_ESP32_S3 = 54,
+    ESP32_S3_PICO = 55,
+    CHATTER_2 = 56,
+    HELTEC_WIRELESS_PAPER_V1_0 = 57,
+    HELTEC_WIRELESS_TRACKER_V1_0 = 58,
+    UNPHONE = 59,
+    TD_LORAC = 60,
+    CDEBYTE_EORA_S3 = 61,
+    TWC_MESH_V4 = 62,
+    NRF52_PROMICRO_DIY = 63,
+    RADIOMASTER_900_BANDIT_NANO = 64,
+    HELTEC_CAPSULE_SENSOR_V3 = 65,
+    HELTEC_VISION_MASTER_T190 = 66,
+    HELTEC_VISION_MASTER_E213 = 67,
+    HELTEC_VISION_MASTER_E290 = 68,
+    HELTEC_MESH_NODE_T114 = 69,
+    SENSECAP_INDICATOR = 70,
+    TRACKER_T1000_E = 71,
+    RAK3172 = 72,
+    WIO_E5 = 73,
+    RADIOMASTER_900_BANDIT = 74,
+    ME25LS01_4Y10TD = 75,
+    PRIVATE_HW = 255,
+});
+
+wire_enum!(Role {
+    CLIENT = 0,
+    CLIENT_MUTE = 1,
+    ROUTER = 2,
+    ROUTER_CLIENT = 3,
+    REPEATER = 4,
+    TRACKER = 5,
+    SENSOR = 6,
+    TAK = 7,
+    CLIENT_HIDDEN = 8,
+    LOST_AND_FOUND = 9,
+    TAK_TRACKER = 10,
+    ROUTER_LATE = 11,
+});
+
+wire_enum!(ConfigType {
+    DEVICE_CONFIG = 0,
+    POSITION_CONFIG = 1,
+    POWER_CONFIG = 2,
+    NETWORK_CONFIG = 3,
+    DISPLAY_CONFIG = 4,
+    LORA_CONFIG = 5,
+    BLUETOOTH_CONFIG = 6,
+});
+
+wire_enum!(RebroadcastMode {
+    ALL = 0,
+    ALL_SKIP_DECODING = 1,
+    LOCAL_ONLY = 2,
+    KNOWN_ONLY = 3,
+});
+
+wire_enum!(GpsMode {
+    GPS_DISABLED = 0,
+    GPS_ENABLED = 1,
+    GPS_NOT_PRESENT = 2,
+});
+
+wire_enum!(AddressMode {
+    DHCP = 0,
+    STATIC = 1,
+});
+
+wire_enum!(GpsCoordinateFormat {
+    DEC = 0,
+    DMS = 1,
+    UTM = 2,
+    MGRS = 3,
+    OLC = 4,
+    OSGR = 5,
+});
+
+wire_enum!(DisplayUnits {
+    METRIC = 0,
+    IMPERIAL = 1,
+});
+
+wire_enum!(OledType {
+    OLED_AUTO = 0,
+    OLED_SSD1306 = 1,
+    OLED_SH1106 = 2,
+    OLED_SH1107 = 3,
+});
+
+wire_enum!(DisplayMode {
+    DEFAULT = 0,
+    TWOCOLOR = 1,
+    INVERTED = 2,
+    COLOR = 3,
+});
+
+wire_enum!(PairingMode {
+    RANDOM_PIN = 0,
+    FIXED_PIN = 1,
+    NO_PIN = 2,
+});
+
+wire_enum!(ModuleConfigType {
+    MQTT_CONFIG = 0,
+    SERIAL_CONFIG = 1,
+    EXTNOTIF_CONFIG = 2,
+    STOREFORWARD_CONFIG = 3,
+    RANGETEST_CONFIG = 4,
+    TELEMETRY_CONFIG = 5,
+    CANNEDMSG_CONFIG = 6,
+});
+
+wire_enum!(module_config::Serial_Baud {
+    BAUD_DEFAULT = 0,
+    BAUD_110 = 1,
+    BAUD_300 = 2,
+    BAUD_600 = 3,
+    BAUD_1200 = 4,
+    BAUD_2400 = 5,
+    BAUD_4800 = 6,
+    BAUD_9600 = 7,
+    BAUD_19200 = 8,
+    BAUD_38400 = 9,
+    BAUD_57600 = 10,
+    BAUD_115200 = 11,
+    BAUD_230400 = 12,
+    BAUD_460800 = 13,
+    BAUD_576000 = 14,
+    BAUD_921600 = 15,
+});
+
+wire_enum!(module_config::Serial_Mode {
+    DEFAULT = 0,
+    SIMPLE = 1,
+    PROTO = 2,
+    TEXTMSG = 3,
+    NMEA = 4,
+    CALTOPO = 5,
+});
+
+wire_enum!(module_config::InputEventChar {
+    NONE = 0,
+    UP = 17,
+    DOWN = 18,
+    LEFT = 19,
+    RIGHT = 20,
+    SELECT = 10,
+    BACK = 27,
+    CANCEL = 24,
+});
+
+wire_enum!(MeshPacket_Priority {
+    UNSET = 0,
+    DEFAULT = 64,
+    MIN = 1,
+    BACKGROUND = 10,
+    RELIABLE = 70,
+    RESPONSE = 80,
+    HIGH = 100,
+    ALERT = 110,
+    ACK = 120,
+    MAX = 127,
+});
+
+wire_enum!(Routing_Error {
+    NONE = 0,
+    NO_ROUTE = 1,
+    GOT_NAK = 2,
+    TIMEOUT = 3,
+    NO_INTERFACE = 4,
+    MAX_RETRANSMIT = 5,
+    NO_CHANNEL = 6,
+    TOO_LARGE = 7,
+    NO_RESPONSE = 8,
+    DUTY_CYCLE_LIMIT = 9,
+    BAD_REQUEST = 32,
+    NOT_AUTHORIZED = 33,
+});
+
+wire_enum!(Channel_Role {
+    DISABLED = 0,
+    PRIMARY = 1,
+    SECONDARY = 2,
+});
+
+// =============================================================================
+// APPLICATION PAYLOADS
+// =============================================================================
+
+impl From<&User> for proto::User {
+    fn from(user: &User) -> Self {
+        Self {
+            id: user.id.clone(),
+            long_name: user.long_name.clone(),
+            short_name: user.short_name.clone(),
+            macaddr: user.macaddr.clone(),
+            hw_model: user.hw_model.into(),
+            is_licensed: user.is_licensed,
+            role: user.role.into(),
+        }
+    }
+}
+
+impl From<proto::User> for User {
+    fn from(user: proto::User) -> Self {
+        Self {
+            id: user.id,
+            long_name: user.long_name,
+            short_name: user.short_name,
+            macaddr: user.macaddr,
+            hw_model: HardwareModel::from(user.hw_model),
+            is_licensed: user.is_licensed,
+            role: Role::from(user.role),
+        }
+    }
+}
+
+impl From<&Position> for proto::Position {
+    fn from(position: &Position) -> Self {
+        Self {
+            latitude_i: position.latitude_i,
+            longitude_i: position.longitude_i,
+            altitude: position.altitude,
+            time: position.time,
+            location_source: position.location_source,
+            altitude_source: position.altitude_source,
+            timestamp: position.timestamp,
+            timestamp_millis_adjust: position.timestamp_millis_adjust,
+            altitude_hae: position.altitude_hae,
+            altitude_geoidal_separation: position.altitude_geoidal_separation,
+            pdop: position.PDOP,
+            hdop: position.HDOP,
+            vdop: position.VDOP,
+            gps_accuracy: position.gps_accuracy,
+            ground_speed: position.ground_speed,
+            ground_track: position.ground_track,
+            fix_quality: position.fix_quality,
+            fix_type: position.fix_type,
+            sats_in_view: position.sats_in_view,
+            sensor_id: position.sensor_id,
+            next_update: position.next_update,
+            seq_number: position.seq_number,
+            precision_bits: position.precision_bits,
+        }
+    }
+}
+
+impl From<proto::Position> for Position {
+    fn from(position: proto::Position) -> Self {
+        Self {
+            latitude_i: position.latitude_i,
+            longitude_i: position.longitude_i,
+            altitude: position.altitude,
+            time: position.time,
+            location_source: position.location_source,
+            altitude_source: position.altitude_source,
+            timestamp: position.timestamp,
+            timestamp_millis_adjust: position.timestamp_millis_adjust,
+            altitude_hae: position.altitude_hae,
+            altitude_geoidal_separation: position.altitude_geoidal_separation,
+            PDOP: position.pdop,
+            HDOP: position.hdop,
+            VDOP: position.vdop,
+            gps_accuracy: position.gps_accuracy,
+            ground_speed: position.ground_speed,
+            ground_track: position.ground_track,
+            fix_quality: position.fix_quality,
+            fix_type: position.fix_type,
+            sats_in_view: position.sats_in_view,
+            sensor_id: position.sensor_id,
+            next_update: position.next_update,
+            seq_number: position.seq_number,
+            precision_bits: position.precision_bits,
+        }
+    }
+}
+
+impl From<&DeviceMetrics> for proto::DeviceMetrics {
+    fn from(metrics: &DeviceMetrics) -> Self {
+        Self {
+            battery_level: metrics.battery_level,
+            voltage: metrics.voltage,
+            channel_utilization: metrics.channel_utilization,
+            air_util_tx: metrics.air_util_tx,
+            uptime_seconds: metrics.uptime_seconds,
+        }
+    }
+}
+
+impl From<proto::DeviceMetrics> for DeviceMetrics {
+    fn from(metrics: proto::DeviceMetrics) -> Self {
+        Self {
+            battery_level: metrics.battery_level,
+            voltage: metrics.voltage,
+            channel_utilization: metrics.channel_utilization,
+            air_util_tx: metrics.air_util_tx,
+            uptime_seconds: metrics.uptime_seconds,
+        }
+    }
+}
+
+impl From<&EnvironmentMetrics> for proto::EnvironmentMetrics {
+    fn from(metrics: &EnvironmentMetrics) -> Self {
+        Self {
+            temperature: metrics.temperature,
+            relative_humidity: metrics.relative_humidity,
+            barometric_pressure: metrics.barometric_pressure,
+            gas_resistance: metrics.gas_resistance,
+            voltage: metrics.voltage,
+            current: metrics.current,
+        }
+    }
+}
+
+impl From<proto::EnvironmentMetrics> for EnvironmentMetrics {
+    fn from(metrics: proto::EnvironmentMetrics) -> Self {
+        Self {
+            temperature: metrics.temperature,
+            relative_humidity: metrics.relative_humidity,
+            barometric_pressure: metrics.barometric_pressure,
+            gas_resistance: metrics.gas_resistance,
+            voltage: metrics.voltage,
+            current: metrics.current,
+        }
+    }
+}
+
+impl From<&PowerMetrics> for proto::PowerMetrics {
+    fn from(metrics: &PowerMetrics) -> Self {
+        Self {
+            ch1_voltage: metrics.ch1_voltage,
+            ch1_current: metrics.ch1_current,
+            ch2_voltage: metrics.ch2_voltage,
+            ch2_current: metrics.ch2_current,
+            ch3_voltage: metrics.ch3_voltage,
+            ch3_current: metrics.ch3_current,
+        }
+    }
+}
+
+impl From<proto::PowerMetrics> for PowerMetrics {
+    fn from(metrics: proto::PowerMetrics) -> Self {
+        Self {
+            ch1_voltage: metrics.ch1_voltage,
+            ch1_current: metrics.ch1_current,
+            ch2_voltage: metrics.ch2_voltage,
+            ch2_current: metrics.ch2_current,
+            ch3_voltage: metrics.ch3_voltage,
+            ch3_current: metrics.ch3_current,
+        }
+    }
+}
+
+impl From<&TelemetryData> for proto::Telemetry {
+    fn from(telemetry: &TelemetryData) -> Self {
+        Self {
+            time: telemetry.time,
+            variant: telemetry.variant.as_ref().map(|variant| match variant {
+                TelemetryVariant::DeviceMetrics(m) => proto::telemetry::Variant::DeviceMetrics(m.into()),
+                TelemetryVariant::EnvironmentMetrics(m) => proto::telemetry::Variant::EnvironmentMetrics(m.into()),
+                TelemetryVariant::PowerMetrics(m) => proto::telemetry::Variant::PowerMetrics(m.into()),
+            }),
+        }
+    }
+}
+
+impl From<proto::Telemetry> for TelemetryData {
+    fn from(telemetry: proto::Telemetry) -> Self {
+        Self {
+            time: telemetry.time,
+            variant: telemetry.variant.map(|variant| match variant {
+                proto::telemetry::Variant::DeviceMetrics(m) => TelemetryVariant::DeviceMetrics(m.into()),
+                proto::telemetry::Variant::EnvironmentMetrics(m) => TelemetryVariant::EnvironmentMetrics(m.into()),
+                proto::telemetry::Variant::PowerMetrics(m) => TelemetryVariant::PowerMetrics(m.into()),
+            }),
+        }
+    }
+}
+
+impl From<&RouteDiscovery> for proto::RouteDiscovery {
+    fn from(route: &RouteDiscovery) -> Self {
+        Self {
+            route: route.route.clone(),
+            snr_towards: route.snr_towards.clone(),
+            route_back: route.route_back.clone(),
+            snr_back: route.snr_back.clone(),
+        }
+    }
+}
+
+impl From<proto::RouteDiscovery> for RouteDiscovery {
+    fn from(route: proto::RouteDiscovery) -> Self {
+        Self {
+            route: route.route,
+            snr_towards: route.snr_towards,
+            route_back: route.route_back,
+            snr_back: route.snr_back,
+        }
+    }
+}
+
+impl From<&Routing> for proto::Routing {
+    fn from(routing: &Routing) -> Self {
+        Self {
+            variant: routing.variant.as_ref().map(|variant| match variant {
+                RoutingVariant::RouteRequest(r) => proto::routing::Variant::RouteRequest(r.into()),
+                RoutingVariant::RouteReply(r) => proto::routing::Variant::RouteReply(r.into()),
+                RoutingVariant::ErrorReason(e) => proto::routing::Variant::ErrorReason((*e).into()),
+            }),
+        }
+    }
+}
+
+impl From<proto::Routing> for Routing {
+    fn from(routing: proto::Routing) -> Self {
+        Self {
+            variant: routing.variant.map(|variant| match variant {
+                proto::routing::Variant::RouteRequest(r) => RoutingVariant::RouteRequest(r.into()),
+                proto::routing::Variant::RouteReply(r) => RoutingVariant::RouteReply(r.into()),
+                proto::routing::Variant::ErrorReason(e) => RoutingVariant::ErrorReason(Routing_Error::from(e)),
+            }),
+        }
+    }
+}
+
+// =============================================================================
+// CHANNELS, CONFIG AND ADMIN
+// =============================================================================
+
+impl From<&ModuleSettings> for proto::ModuleSettings {
+    fn from(settings: &ModuleSettings) -> Self {
+        Self {
+            position_precision: settings.position_precision,
+            is_client_muted: settings.is_client_muted,
+        }
+    }
+}
+
+impl From<proto::ModuleSettings> for ModuleSettings {
+    fn from(settings: proto::ModuleSettings) -> Self {
+        Self {
+            position_precision: settings.position_precision,
+            is_client_muted: settings.is_client_muted,
+        }
+    }
+}
+
+impl From<&ChannelSettings> for proto::ChannelSettings {
+    fn from(settings: &ChannelSettings) -> Self {
+        Self {
+            psk: settings.psk.clone(),
+            name: settings.name.clone(),
+            id: settings.id,
+            uplink_enabled: settings.uplink_enabled,
+            downlink_enabled: settings.downlink_enabled,
+            module_settings: settings.module_settings.as_ref().map(Into::into),
+        }
+    }
+}
+
+impl From<proto::ChannelSettings> for ChannelSettings {
+    fn from(settings: proto::ChannelSettings) -> Self {
+        Self {
+            psk: settings.psk,
+            name: settings.name,
+            id: settings.id,
+            uplink_enabled: settings.uplink_enabled,
+            downlink_enabled: settings.downlink_enabled,
+            module_settings: settings.module_settings.map(Into::into),
+        }
+    }
+}
+
+impl From<&Channel> for proto::Channel {
+    fn from(channel: &Channel) -> Self {
+        Self {
+            index: channel.index as i32,
+            settings: channel.settings.as_ref().map(Into::into),
+            role: channel.role.into(),
+        }
+    }
+}
+
+impl From<proto::Channel> for Channel {
+    fn from(channel: proto::Channel) -> Self {
+        Self {
+            index: channel.index.max(0) as u32,
+            settings: channel.settings.map(Into::into),
+            role: Channel_Role::from(channel.role),
+        }
+    }
+}
+
+impl From<&ChannelSet> for proto::ChannelSet {
+    fn from(set: &ChannelSet) -> Self {
+        Self {
+            settings: set.settings.iter().map(Into::into).collect(),
+            lora_config: set.lora_config.as_ref().map(Into::into),
+        }
+    }
+}
+
+impl From<proto::ChannelSet> for ChannelSet {
+    fn from(set: proto::ChannelSet) -> Self {
+        Self {
+            settings: set.settings.into_iter().map(Into::into).collect(),
+            lora_config: set.lora_config.map(Into::into),
+        }
+    }
+}
+
+impl From<&RadioConfig> for proto::LoRaConfig {
+    fn from(config: &RadioConfig) -> Self {
+        Self {
+            use_preset: config.use_preset,
+            modem_preset: config.modem_preset,
+            bandwidth: config.bandwidth,
+            spread_factor: config.spread_factor,
+            coding_rate: config.coding_rate,
+            frequency_offset: config.frequency_offset,
+            region: config.region,
+            hop_limit: config.hop_limit,
+            tx_enabled: config.tx_enabled,
+            tx_power: config.tx_power,
+            channel_num: config.channel_num,
+            override_duty_cycle: config.override_duty_cycle,
+            sx126x_rx_boosted_gain: config.sx126x_rx_boosted_gain,
+            override_frequency: config.override_frequency,
+            pa_fan_disabled: config.pa_fan_disabled,
+            ignore_mqtt: config.ignore_mqtt,
+        }
+    }
+}
+
+impl From<proto::LoRaConfig> for RadioConfig {
+    fn from(config: proto::LoRaConfig) -> Self {
+        Self {
+            use_preset: config.use_preset,
+            modem_preset: config.modem_preset,
+            bandwidth: config.bandwidth,
+            spread_factor: config.spread_factor,
+            coding_rate: config.coding_rate,
+            frequency_offset: config.frequency_offset,
+            region: config.region,
+            hop_limit: config.hop_limit,
+            tx_enabled: config.tx_enabled,
+            tx_power: config.tx_power,
+            channel_num: config.channel_num,
+            override_duty_cycle: config.override_duty_cycle,
+            sx126x_rx_boosted_gain: config.sx126x_rx_boosted_gain,
+            override_frequency: config.override_frequency,
+            pa_fan_disabled: config.pa_fan_disabled,
+            ignore_mqtt: config.ignore_mqtt,
+        }
+    }
+}
+
+impl From<&DeviceConfig> for proto::DeviceConfig {
+    fn from(config: &DeviceConfig) -> Self {
+        Self {
+            role: config.role.into(),
+            serial_enabled: config.serial_enabled,
+            debug_log_enabled: config.debug_log_enabled,
+            button_gpio: config.button_gpio,
+            buzzer_gpio: config.buzzer_gpio,
+            rebroadcast_mode: config.rebroadcast_mode.into(),
+            node_info_broadcast_secs: config.node_info_broadcast_secs,
+            double_tap_as_button_press: config.double_tap_as_button_press,
+            is_managed: config.is_managed,
+            disable_triple_click: config.disable_triple_click,
+            tzdef: config.tzdef.clone(),
+            led_heartbeat_disabled: config.led_heartbeat_disabled,
+        }
+    }
+}
+
+impl From<proto::DeviceConfig> for DeviceConfig {
+    fn from(config: proto::DeviceConfig) -> Self {
+        Self {
+            role: Role::from(config.role),
+            serial_enabled: config.serial_enabled,
+            debug_log_enabled: config.debug_log_enabled,
+            button_gpio: config.button_gpio,
+            buzzer_gpio: config.buzzer_gpio,
+            rebroadcast_mode: RebroadcastMode::from(config.rebroadcast_mode),
+            node_info_broadcast_secs: config.node_info_broadcast_secs,
+            double_tap_as_button_press: config.double_tap_as_button_press,
+            is_managed: config.is_managed,
+            disable_triple_click: config.disable_triple_click,
+            tzdef: config.tzdef,
+            led_heartbeat_disabled: config.led_heartbeat_disabled,
+        }
+    }
+}
+
+impl From<&PositionConfig> for proto::PositionConfig {
+    fn from(config: &PositionConfig) -> Self {
+        Self {
+            position_broadcast_secs: config.position_broadcast_secs,
+            position_broadcast_smart_enabled: config.position_broadcast_smart_enabled,
+            fixed_position: config.fixed_position,
+            gps_enabled: config.gps_enabled,
+            gps_update_interval: config.gps_update_interval,
+            gps_attempt_time: config.gps_attempt_time,
+            position_flags: config.position_flags,
+            rx_gpio: config.rx_gpio,
+            tx_gpio: config.tx_gpio,
+            broadcast_smart_minimum_distance: config.broadcast_smart_minimum_distance,
+            broadcast_smart_minimum_interval_secs: config.broadcast_smart_minimum_interval_secs,
+            gps_en_gpio: config.gps_en_gpio,
+            gps_mode: config.gps_mode.into(),
+        }
+    }
+}
+
+impl From<proto::PositionConfig> for PositionConfig {
+    fn from(config: proto::PositionConfig) -> Self {
+        Self {
+            position_broadcast_secs: config.position_broadcast_secs,
+            position_broadcast_smart_enabled: config.position_broadcast_smart_enabled,
+            fixed_position: config.fixed_position,
+            gps_enabled: config.gps_enabled,
+            gps_update_interval: config.gps_update_interval,
+            gps_attempt_time: config.gps_attempt_time,
+            position_flags: config.position_flags,
+            rx_gpio: config.rx_gpio,
+            tx_gpio: config.tx_gpio,
+            broadcast_smart_minimum_distance: config.broadcast_smart_minimum_distance,
+            broadcast_smart_minimum_interval_secs: config.broadcast_smart_minimum_interval_secs,
+            gps_en_gpio: config.gps_en_gpio,
+            gps_mode: GpsMode::from(config.gps_mode),
+        }
+    }
+}
+
+impl From<&PowerConfig> for proto::PowerConfig {
+    fn from(config: &PowerConfig) -> Self {
+        Self {
+            is_power_saving: config.is_power_saving,
+            on_battery_shutdown_after_secs: config.on_battery_shutdown_after_secs,
+            adc_multiplier_override: config.adc_multiplier_override,
+            wait_bluetooth_secs: config.wait_bluetooth_secs,
+            sds_secs: config.sds_secs,
+            ls_secs: config.ls_secs,
+            min_wake_secs: config.min_wake_secs,
+            device_battery_ina_address: config.device_battery_ina_address,
+        }
+    }
+}
+
+impl From<proto::PowerConfig> for PowerConfig {
+    fn from(config: proto::PowerConfig) -> Self {
+        Self {
+            is_power_saving: config.is_power_saving,
+            on_battery_shutdown_after_secs: config.on_battery_shutdown_after_secs,
+            adc_multiplier_override: config.adc_multiplier_override,
+            wait_bluetooth_secs: config.wait_bluetooth_secs,
+            sds_secs: config.sds_secs,
+            ls_secs: config.ls_secs,
+            min_wake_secs: config.min_wake_secs,
+            device_battery_ina_address: config.device_battery_ina_address,
+        }
+    }
+}
+
+impl From<&IpV4Config> for proto::IpV4Config {
+    fn from(config: &IpV4Config) -> Self {
+        Self {
+            ip: config.ip,
+            gateway: config.gateway,
+            subnet: config.subnet,
+            dns: config.dns,
+        }
+    }
+}
+
+impl From<proto::IpV4Config> for IpV4Config {
+    fn from(config: proto::IpV4Config) -> Self {
+        Self {
+            ip: config.ip,
+            gateway: config.gateway,
+            subnet: config.subnet,
+            dns: config.dns,
+        }
+    }
+}
+
+impl From<&NetworkConfig> for proto::NetworkConfig {
+    fn from(config: &NetworkConfig) -> Self {
+        Self {
+            wifi_enabled: config.wifi_enabled,
+            wifi_ssid: config.wifi_ssid.clone(),
+            wifi_psk: config.wifi_psk.clone(),
+            ntp_server: config.ntp_server.clone(),
+            eth_enabled: config.eth_enabled,
+            address_mode: config.address_mode.into(),
+            ipv4_config: config.ipv4_config.as_ref().map(Into::into),
+            rsyslog_server: config.rsyslog_server.clone(),
+        }
+    }
+}
+
+impl From<proto::NetworkConfig> for NetworkConfig {
+    fn from(config: proto::NetworkConfig) -> Self {
+        Self {
+            wifi_enabled: config.wifi_enabled,
+            wifi_ssid: config.wifi_ssid,
+            wifi_psk: config.wifi_psk,
+            ntp_server: config.ntp_server,
+            eth_enabled: config.eth_enabled,
+            address_mode: AddressMode::from(config.address_mode),
+            ipv4_config: config.ipv4_config.map(Into::into),
+            rsyslog_server: config.rsyslog_server,
+        }
+    }
+}
+
+impl From<&DisplayConfig> for proto::DisplayConfig {
+    fn from(config: &DisplayConfig) -> Self {
+        Self {
+            screen_on_secs: config.screen_on_secs,
+            gps_format: config.gps_format.into(),
+            auto_screen_carousel_secs: config.auto_screen_carousel_secs,
+            compass_north_top: config.compass_north_top,
+            flip_screen: config.flip_screen,
+            units: config.units.into(),
+            oled: config.oled.into(),
+            displaymode: config.displaymode.into(),
+            heading_bold: config.heading_bold,
+            wake_on_tap_or_motion: config.wake_on_tap_or_motion,
+        }
+    }
+}
+
+impl From<proto::DisplayConfig> for DisplayConfig {
+    fn from(config: proto::DisplayConfig) -> Self {
+        Self {
+            screen_on_secs: config.screen_on_secs,
+            gps_format: GpsCoordinateFormat::from(config.gps_format),
+            auto_screen_carousel_secs: config.auto_screen_carousel_secs,
+            compass_north_top: config.compass_north_top,
+            flip_screen: config.flip_screen,
+            units: DisplayUnits::from(config.units),
+            oled: OledType::from(config.oled),
+            displaymode: DisplayMode::from(config.displaymode),
+            heading_bold: config.heading_bold,
+            wake_on_tap_or_motion: config.wake_on_tap_or_motion,
+        }
+    }
+}
+
+impl From<&BluetoothConfig> for proto::BluetoothConfig {
+    fn from(config: &BluetoothConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            mode: config.mode.into(),
+            fixed_pin: config.fixed_pin,
+        }
+    }
+}
+
+impl From<proto::BluetoothConfig> for BluetoothConfig {
+    fn from(config: proto::BluetoothConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            mode: PairingMode::from(config.mode),
+            fixed_pin: config.fixed_pin,
+        }
+    }
+}
+
+impl From<&Config> for proto::Config {
+    fn from(config: &Config) -> Self {
+        use proto::config::PayloadVariant as Wire;
+
+        Self {
+            payload_variant: config.variant.as_ref().map(|variant| match variant {
+                config::Variant::Device(device) => Wire::Device(device.into()),
+                config::Variant::Position(position) => Wire::Position(position.into()),
+                config::Variant::Power(power) => Wire::Power(power.into()),
+                config::Variant::Network(network) => Wire::Network(network.into()),
+                config::Variant::Display(display) => Wire::Display(display.into()),
+                config::Variant::Lora(lora) => Wire::Lora(lora.into()),
+                config::Variant::Bluetooth(bluetooth) => Wire::Bluetooth(bluetooth.into()),
+            }),
+        }
+    }
+}
+
+impl From<proto::Config> for Config {
+    fn from(config: proto::Config) -> Self {
+        use proto::config::PayloadVariant as Wire;
+
+        Self {
+            variant: config.payload_variant.map(|variant| match variant {
+                Wire::Device(device) => config::Variant::Device(device.into()),
+                Wire::Position(position) => config::Variant::Position(position.into()),
+                Wire::Power(power) => config::Variant::Power(power.into()),
+                Wire::Network(network) => config::Variant::Network(network.into()),
+                Wire::Display(display) => config::Variant::Display(display.into()),
+                Wire::Lora(lora) => config::Variant::Lora(lora.into()),
+                Wire::Bluetooth(bluetooth) => config::Variant::Bluetooth(bluetooth.into()),
+            }),
+        }
+    }
+}
+
+impl From<&module_config::MqttConfig> for proto::module_config::MqttConfig {
+    fn from(config: &module_config::MqttConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            address: config.address.clone(),
+            username: config.username.clone(),
+            password: config.password.clone(),
+            encryption_enabled: config.encryption_enabled,
+            json_enabled: config.json_enabled,
+            tls_enabled: config.tls_enabled,
+            root: config.root.clone(),
+            proxy_to_client_enabled: config.proxy_to_client_enabled,
+            map_reporting_enabled: config.map_reporting_enabled,
+            map_report_settings: config.map_report_settings.map(|settings| proto::module_config::MapReportSettings {
+                publish_interval_secs: settings.publish_interval_secs,
+                position_precision: settings.position_precision,
+            }),
+        }
+    }
+}
+
+impl From<proto::module_config::MqttConfig> for module_config::MqttConfig {
+    fn from(config: proto::module_config::MqttConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            address: config.address,
+            username: config.username,
+            password: config.password,
+            encryption_enabled: config.encryption_enabled,
+            json_enabled: config.json_enabled,
+            tls_enabled: config.tls_enabled,
+            root: config.root,
+            proxy_to_client_enabled: config.proxy_to_client_enabled,
+            map_reporting_enabled: config.map_reporting_enabled,
+            map_report_settings: config.map_report_settings.map(|settings| module_config::MapReportSettings {
+                publish_interval_secs: settings.publish_interval_secs,
+                position_precision: settings.position_precision,
+            }),
+        }
+    }
+}
+
+impl From<&module_config::SerialConfig> for proto::module_config::SerialConfig {
+    fn from(config: &module_config::SerialConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            echo: config.echo,
+            rxd: config.rxd,
+            txd: config.txd,
+            baud: config.baud.into(),
+            timeout: config.timeout,
+            mode: config.mode.into(),
+            override_console_serial_port: config.override_console_serial_port,
+        }
+    }
+}
+
+impl From<proto::module_config::SerialConfig> for module_config::SerialConfig {
+    fn from(config: proto::module_config::SerialConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            echo: config.echo,
+            rxd: config.rxd,
+            txd: config.txd,
+            baud: module_config::Serial_Baud::from(config.baud),
+            timeout: config.timeout,
+            mode: module_config::Serial_Mode::from(config.mode),
+            override_console_serial_port: config.override_console_serial_port,
+        }
+    }
+}
+
+impl From<&module_config::ExternalNotificationConfig> for proto::module_config::ExternalNotificationConfig {
+    fn from(config: &module_config::ExternalNotificationConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            output_ms: config.output_ms,
+            output: config.output,
+            active: config.active,
+            alert_message: config.alert_message,
+            alert_bell: config.alert_bell,
+            use_pwm: config.use_pwm,
+            output_vibra: config.output_vibra,
+            output_buzzer: config.output_buzzer,
+            alert_message_vibra: config.alert_message_vibra,
+            alert_message_buzzer: config.alert_message_buzzer,
+            alert_bell_vibra: config.alert_bell_vibra,
+            alert_bell_buzzer: config.alert_bell_buzzer,
+            nag_timeout: config.nag_timeout,
+            use_i2s_as_buzzer: config.use_i2s_as_buzzer,
+        }
+    }
+}
+
+impl From<proto::module_config::ExternalNotificationConfig> for module_config::ExternalNotificationConfig {
+    fn from(config: proto::module_config::ExternalNotificationConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            output_ms: config.output_ms,
+            output: config.output,
+            active: config.active,
+            alert_message: config.alert_message,
+            alert_bell: config.alert_bell,
+            use_pwm: config.use_pwm,
+            output_vibra: config.output_vibra,
+            output_buzzer: config.output_buzzer,
+            alert_message_vibra: config.alert_message_vibra,
+            alert_message_buzzer: config.alert_message_buzzer,
+            alert_bell_vibra: config.alert_bell_vibra,
+            alert_bell_buzzer: config.alert_bell_buzzer,
+            nag_timeout: config.nag_timeout,
+            use_i2s_as_buzzer: config.use_i2s_as_buzzer,
+        }
+    }
+}
+
+impl From<&module_config::StoreForwardConfig> for proto::module_config::StoreForwardConfig {
+    fn from(config: &module_config::StoreForwardConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            heartbeat: config.heartbeat,
+            records: config.records,
+            history_return_max: config.history_return_max,
+            history_return_window: config.history_return_window,
+            is_server: config.is_server,
+        }
+    }
+}
+
+impl From<proto::module_config::StoreForwardConfig> for module_config::StoreForwardConfig {
+    fn from(config: proto::module_config::StoreForwardConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            heartbeat: config.heartbeat,
+            records: config.records,
+            history_return_max: config.history_return_max,
+            history_return_window: config.history_return_window,
+            is_server: config.is_server,
+        }
+    }
+}
+
+impl From<&module_config::RangeTestConfig> for proto::module_config::RangeTestConfig {
+    fn from(config: &module_config::RangeTestConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            sender: config.sender,
+            save: config.save,
+        }
+    }
+}
+
+impl From<proto::module_config::RangeTestConfig> for module_config::RangeTestConfig {
+    fn from(config: proto::module_config::RangeTestConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            sender: config.sender,
+            save: config.save,
+        }
+    }
+}
+
+impl From<&module_config::TelemetryConfig> for proto::module_config::TelemetryConfig {
+    fn from(config: &module_config::TelemetryConfig) -> Self {
+        Self {
+            device_update_interval: config.device_update_interval,
+            environment_update_interval: config.environment_update_interval,
+            environment_measurement_enabled: config.environment_measurement_enabled,
+            environment_screen_enabled: config.environment_screen_enabled,
+            environment_display_fahrenheit: config.environment_display_fahrenheit,
+            air_quality_enabled: config.air_quality_enabled,
+            air_quality_interval: config.air_quality_interval,
+            power_measurement_enabled: config.power_measurement_enabled,
+            power_update_interval: config.power_update_interval,
+            power_screen_enabled: config.power_screen_enabled,
+        }
+    }
+}
+
+impl From<proto::module_config::TelemetryConfig> for module_config::TelemetryConfig {
+    fn from(config: proto::module_config::TelemetryConfig) -> Self {
+        Self {
+            device_update_interval: config.device_update_interval,
+            environment_update_interval: config.environment_update_interval,
+            environment_measurement_enabled: config.environment_measurement_enabled,
+            environment_screen_enabled: config.environment_screen_enabled,
+            environment_display_fahrenheit: config.environment_display_fahrenheit,
+            air_quality_enabled: config.air_quality_enabled,
+            air_quality_interval: config.air_quality_interval,
+            power_measurement_enabled: config.power_measurement_enabled,
+            power_update_interval: config.power_update_interval,
+            power_screen_enabled: config.power_screen_enabled,
+        }
+    }
+}
+
+impl From<&module_config::CannedMessageConfig> for proto::module_config::CannedMessageConfig {
+    fn from(config: &module_config::CannedMessageConfig) -> Self {
+        Self {
+            rotary1_enabled: config.rotary1_enabled,
+            inputbroker_pin_a: config.inputbroker_pin_a,
+            inputbroker_pin_b: config.inputbroker_pin_b,
+            inputbroker_pin_press: config.inputbroker_pin_press,
+            inputbroker_event_cw: config.inputbroker_event_cw.into(),
+            inputbroker_event_ccw: config.inputbroker_event_ccw.into(),
+            inputbroker_event_press: config.inputbroker_event_press.into(),
+            updown1_enabled: config.updown1_enabled,
+            enabled: config.enabled,
+            allow_input_source: config.allow_input_source.clone(),
+            send_bell: config.send_bell,
+        }
+    }
+}
+
+impl From<proto::module_config::CannedMessageConfig> for module_config::CannedMessageConfig {
+    fn from(config: proto::module_config::CannedMessageConfig) -> Self {
+        Self {
+            rotary1_enabled: config.rotary1_enabled,
+            inputbroker_pin_a: config.inputbroker_pin_a,
+            inputbroker_pin_b: config.inputbroker_pin_b,
+            inputbroker_pin_press: config.inputbroker_pin_press,
+            inputbroker_event_cw: module_config::InputEventChar::from(config.inputbroker_event_cw),
+            inputbroker_event_ccw: module_config::InputEventChar::from(config.inputbroker_event_ccw),
+            inputbroker_event_press: module_config::InputEventChar::from(config.inputbroker_event_press),
+            updown1_enabled: config.updown1_enabled,
+            enabled: config.enabled,
+            allow_input_source: config.allow_input_source,
+            send_bell: config.send_bell,
+        }
+    }
+}
+
+impl From<&ModuleConfig> for proto::ModuleConfig {
+    fn from(config: &ModuleConfig) -> Self {
+        use module_config::Variant;
+        use proto::module_config::PayloadVariant as Wire;
+
+        Self {
+            payload_variant: config.variant.as_ref().map(|variant| match variant {
+                Variant::Mqtt(mqtt) => Wire::Mqtt(mqtt.into()),
+                Variant::Serial(serial) => Wire::Serial(serial.into()),
+                Variant::ExternalNotification(notification) => Wire::ExternalNotification(notification.into()),
+                Variant::StoreForward(store_forward) => Wire::StoreForward(store_forward.into()),
+                Variant::RangeTest(range_test) => Wire::RangeTest(range_test.into()),
+                Variant::Telemetry(telemetry) => Wire::Telemetry(telemetry.into()),
+                Variant::CannedMessage(canned) => Wire::CannedMessage(canned.into()),
+            }),
+        }
+    }
+}
+
+impl From<proto::ModuleConfig> for ModuleConfig {
+    fn from(config: proto::ModuleConfig) -> Self {
+        use module_config::Variant;
+        use proto::module_config::PayloadVariant as Wire;
+
+        Self {
+            variant: config.payload_variant.map(|variant| match variant {
+                Wire::Mqtt(mqtt) => Variant::Mqtt(mqtt.into()),
+                Wire::Serial(serial) => Variant::Serial(serial.into()),
+                Wire::ExternalNotification(notification) => Variant::ExternalNotification(notification.into()),
+                Wire::StoreForward(store_forward) => Variant::StoreForward(store_forward.into()),
+                Wire::RangeTest(range_test) => Variant::RangeTest(range_test.into()),
+                Wire::Telemetry(telemetry) => Variant::Telemetry(telemetry.into()),
+                Wire::CannedMessage(canned) => Variant::CannedMessage(canned.into()),
+            }),
+        }
+    }
+}
+
+impl From<&DeviceMetadata> for proto::DeviceMetadata {
+    fn from(metadata: &DeviceMetadata) -> Self {
+        Self {
+            firmware_version: metadata.firmware_version.clone(),
+            device_state_version: metadata.device_state_version,
+            can_shutdown: metadata.can_shutdown,
+            has_wifi: metadata.has_wifi,
+            has_bluetooth: metadata.has_bluetooth,
+            has_ethernet: metadata.has_ethernet,
+            role: metadata.role.into(),
+            position_flags: metadata.position_flags,
+            hw_model: metadata.hw_model.into(),
+            has_remote_hardware: metadata.has_remote_hardware,
+        }
+    }
+}
+
+impl From<proto::DeviceMetadata> for DeviceMetadata {
+    fn from(metadata: proto::DeviceMetadata) -> Self {
+        Self {
+            firmware_version: metadata.firmware_version,
+            device_state_version: metadata.device_state_version,
+            can_shutdown: metadata.can_shutdown,
+            has_wifi: metadata.has_wifi,
+            has_bluetooth: metadata.has_bluetooth,
+            has_ethernet: metadata.has_ethernet,
+            role: Role::from(metadata.role),
+            position_flags: metadata.position_flags,
+            hw_model: HardwareModel::from(metadata.hw_model),
+            has_remote_hardware: metadata.has_remote_hardware,
+        }
+    }
+}
+
+impl TryFrom<&AdminMessage> for proto::AdminMessage {
+    type Error = ProtocolError;
+
+    fn try_from(admin: &AdminMessage) -> Result<Self, Self::Error> {
+        use admin_message::Variant;
+        use proto::admin_message::PayloadVariant as Wire;
+
+        let payload_variant = match &admin.variant {
+            None => None,
+            Some(variant) => Some(match variant {
+                // The firmware expects index + 1 so that channel 0 is not
+                // indistinguishable from an unset field
+                Variant::GetChannel(req) => Wire::GetChannelRequest(req.index + 1),
+                Variant::GetChannelResponse(channel) => Wire::GetChannelResponse(channel.into()),
+                Variant::GetOwner(_) => Wire::GetOwnerRequest(true),
+                Variant::GetOwnerResponse(user) => Wire::GetOwnerResponse(user.into()),
+                Variant::GetConfig(req) => Wire::GetConfigRequest(req.config_type as i32),
+                Variant::GetConfigResponse(config) => Wire::GetConfigResponse(config.into()),
+                Variant::GetModuleConfig(req) => Wire::GetModuleConfigRequest(req.config_type as i32),
+                Variant::GetModuleConfigResponse(config) => Wire::GetModuleConfigResponse(config.into()),
+                Variant::GetCannedMessageModuleMessages(_) => Wire::GetCannedMessageModuleMessagesRequest(true),
+                Variant::GetCannedMessageModuleMessagesResponse(messages) => {
+                    Wire::GetCannedMessageModuleMessagesResponse(messages.clone())
+                }
+                Variant::GetDeviceMetadata(_) => Wire::GetDeviceMetadataRequest(true),
+                Variant::GetDeviceMetadataResponse(metadata) => Wire::GetDeviceMetadataResponse(metadata.into()),
+                Variant::SetOwner(user) => Wire::SetOwner(user.into()),
+                Variant::SetChannel(channel) => Wire::SetChannel(channel.into()),
+                Variant::SetConfig(config) => Wire::SetConfig(config.into()),
+                Variant::SetModuleConfig(config) => Wire::SetModuleConfig(config.into()),
+                Variant::SetCannedMessageModuleMessages(messages) => Wire::SetCannedMessageModuleMessages(messages.clone()),
+                Variant::SetRingtone(ringtone) => Wire::SetRingtoneMessage(ringtone.clone()),
+                Variant::RemoveByNodenum(node) => Wire::RemoveByNodenum(*node),
+                Variant::SetFavoriteNode(node) => Wire::SetFavoriteNode(*node),
+                Variant::RemoveFavoriteNode(node) => Wire::RemoveFavoriteNode(*node),
+                Variant::SetFixedPosition(position) => Wire::SetFixedPosition(position.into()),
+                Variant::RemoveFixedPosition(remove) => Wire::RemoveFixedPosition(*remove),
+                Variant::SetTime(time) => Wire::SetTimeOnly(*time),
+                Variant::Shutdown(seconds) => Wire::ShutdownSeconds(*seconds as i32),
+                Variant::Reboot(seconds) => Wire::RebootSeconds(*seconds as i32),
+                Variant::RebootOta(seconds) => Wire::RebootOtaSeconds(*seconds as i32),
+                Variant::ExitSimulator(exit) => Wire::ExitSimulator(*exit),
+                Variant::LoadUrl(_) => {
+                    return Err(ProtocolError::Encoding(
+                        "LoadUrl has no wire representation; apply the URL's channels and config instead".to_string(),
+                    ))
+                }
+                Variant::FactoryReset(value) => Wire::FactoryResetConfig(*value as i32),
+                Variant::NodedbReset(value) => Wire::NodedbReset(*value as i32),
+                Variant::BeginEditSettings(begin) => Wire::BeginEditSettings(*begin),
+                Variant::CommitEditSettings(commit) => Wire::CommitEditSettings(*commit),
+                Variant::SetRadio(config) => Wire::SetConfig(proto::Config {
+                    payload_variant: Some(proto::config::PayloadVariant::Lora(config.into())),
+                }),
+            }),
+        };
+
+        Ok(Self { payload_variant })
+    }
+}
+
+impl From<proto::AdminMessage> for AdminMessage {
+    fn from(admin: proto::AdminMessage) -> Self {
+        use admin_message::Variant;
+        use proto::admin_message::PayloadVariant as Wire;
+
+        let variant = admin.payload_variant.map(|variant| match variant {
+            Wire::GetChannelRequest(index) => Variant::GetChannel(GetChannelRequest {
+                index: index.saturating_sub(1),
+            }),
+            Wire::GetChannelResponse(channel) => Variant::GetChannelResponse(channel.into()),
+            Wire::GetOwnerRequest(_) => Variant::GetOwner(GetOwnerRequest {}),
+            Wire::GetOwnerResponse(user) => Variant::GetOwnerResponse(user.into()),
+            Wire::GetConfigRequest(config_type) => Variant::GetConfig(GetConfigRequest {
+                config_type: config_type.max(0) as u32,
+            }),
+            Wire::GetConfigResponse(config) => Variant::GetConfigResponse(config.into()),
+            Wire::GetModuleConfigRequest(config_type) => Variant::GetModuleConfig(GetModuleConfigRequest {
+                config_type: config_type.max(0) as u32,
+            }),
+            Wire::GetModuleConfigResponse(config) => Variant::GetModuleConfigResponse(config.into()),
+            Wire::GetCannedMessageModuleMessagesRequest(_) => {
+                Variant::GetCannedMessageModuleMessages(GetCannedMessageModuleMessagesRequest {})
+            }
+            Wire::GetCannedMessageModuleMessagesResponse(messages) => {
+                Variant::GetCannedMessageModuleMessagesResponse(messages)
+            }
+            Wire::GetDeviceMetadataRequest(_) => Variant::GetDeviceMetadata(GetDeviceMetadataRequest {}),
+            Wire::GetDeviceMetadataResponse(metadata) => Variant::GetDeviceMetadataResponse(metadata.into()),
+            Wire::SetOwner(user) => Variant::SetOwner(user.into()),
+            Wire::SetChannel(channel) => Variant::SetChannel(channel.into()),
+            Wire::SetConfig(config) => Variant::SetConfig(config.into()),
+            Wire::SetModuleConfig(config) => Variant::SetModuleConfig(config.into()),
+            Wire::SetCannedMessageModuleMessages(messages) => Variant::SetCannedMessageModuleMessages(messages),
+            Wire::SetRingtoneMessage(ringtone) => Variant::SetRingtone(ringtone),
+            Wire::RemoveByNodenum(node) => Variant::RemoveByNodenum(node),
+            Wire::SetFavoriteNode(node) => Variant::SetFavoriteNode(node),
+            Wire::RemoveFavoriteNode(node) => Variant::RemoveFavoriteNode(node),
+            Wire::SetFixedPosition(position) => Variant::SetFixedPosition(position.into()),
+            Wire::RemoveFixedPosition(remove) => Variant::RemoveFixedPosition(remove),
+            Wire::SetTimeOnly(time) => Variant::SetTime(time),
+            Wire::BeginEditSettings(begin) => Variant::BeginEditSettings(begin),
+            Wire::CommitEditSettings(commit) => Variant::CommitEditSettings(commit),
+            Wire::RebootOtaSeconds(seconds) => Variant::RebootOta(seconds.max(0) as u32),
+            Wire::ExitSimulator(exit) => Variant::ExitSimulator(exit),
+            Wire::RebootSeconds(seconds) => Variant::Reboot(seconds.max(0) as u32),
+            Wire::ShutdownSeconds(seconds) => Variant::Shutdown(seconds.max(0) as u32),
+            Wire::FactoryResetConfig(value) => Variant::FactoryReset(value.max(0) as u32),
+            Wire::NodedbReset(value) => Variant::NodedbReset(value.max(0) as u32),
+        });
+
+        Self { variant }
+    }
+}
+
+// =============================================================================
+// MESH PACKETS
+// =============================================================================
+
+impl From<&Data> for proto::Data {
+    fn from(data: &Data) -> Self {
+        Self {
+            portnum: data.portnum.0 as i32,
+            payload: data.payload.clone(),
+            want_response: data.want_response,
+            dest: data.dest,
+            source: data.source,
+            request_id: data.request_id,
+            reply_id: data.reply_id,
+            emoji: data.emoji,
+        }
+    }
+}
+
+impl From<proto::Data> for Data {
+    fn from(data: proto::Data) -> Self {
+        Self {
+            portnum: PortNum(data.portnum.max(0) as u32),
+            payload: data.payload,
+            want_response: data.want_response,
+            dest: data.dest,
+            source: data.source,
+            request_id: data.request_id,
+            reply_id: data.reply_id,
+            emoji: data.emoji,
+        }
+    }
+}
+
+impl TryFrom<&PayloadVariant> for (PortNum, Vec<u8>) {
+    type Error = ProtocolError;
+
+    fn try_from(payload: &PayloadVariant) -> Result<Self, Self::Error> {
+        Ok(match payload {
+            PayloadVariant::Encrypted(_) => {
+                return Err(ProtocolError::Encoding("Encrypted payload has no port number".to_string()))
+            }
+            PayloadVariant::Text(text) => (PortNum::TEXT_MESSAGE_APP, text.as_bytes().to_vec()),
+            PayloadVariant::Position(position) => {
+                (PortNum::POSITION_APP, proto::Position::from(position).encode_to_vec())
+            }
+            PayloadVariant::NodeInfo(user) => (PortNum::NODEINFO_APP, proto::User::from(user).encode_to_vec()),
+            PayloadVariant::Telemetry(telemetry) => {
+                (PortNum::TELEMETRY_APP, proto::Telemetry::from(telemetry).encode_to_vec())
+            }
+            PayloadVariant::Routing(routing) => {
+                (PortNum::ROUTING_APP, proto::Routing::from(routing).encode_to_vec())
+            }
+            PayloadVariant::Admin(admin) => {
+                (PortNum::ADMIN_APP, proto::AdminMessage::try_from(admin)?.encode_to_vec())
+            }
+            PayloadVariant::App(portnum, bytes) => (*portnum, bytes.clone()),
+        })
+    }
+}
+
+impl TryFrom<&Data> for PayloadVariant {
+    type Error = ProtocolError;
+
+    fn try_from(data: &Data) -> Result<Self, Self::Error> {
+        let bytes = data.payload.as_slice();
+        Ok(match data.portnum {
+            PortNum::TEXT_MESSAGE_APP => PayloadVariant::Text(
+                String::from_utf8(data.payload.clone())
+                    .map_err(|e| ProtocolError::Decoding(format!("Text payload is not UTF-8: {}", e)))?,
+            ),
+            PortNum::POSITION_APP => {
+                PayloadVariant::Position(proto::Position::decode(bytes).map_err(decode_error)?.into())
+            }
+            PortNum::NODEINFO_APP => {
+                PayloadVariant::NodeInfo(proto::User::decode(bytes).map_err(decode_error)?.into())
+            }
+            PortNum::TELEMETRY_APP => {
+                PayloadVariant::Telemetry(proto::Telemetry::decode(bytes).map_err(decode_error)?.into())
+            }
+            PortNum::ROUTING_APP => {
+                PayloadVariant::Routing(proto::Routing::decode(bytes).map_err(decode_error)?.into())
+            }
+            PortNum::ADMIN_APP => {
+                PayloadVariant::Admin(proto::AdminMessage::decode(bytes).map_err(decode_error)?.into())
+            }
+            portnum => PayloadVariant::App(portnum, data.payload.clone()),
+        })
+    }
+}
+
+impl MeshPacket {
+    /// The decoded payload as a wire-level `Data` message, or `None` if the
+    /// packet is empty or still encrypted
+    pub fn data(&self) -> Result<Option<Data>, ProtocolError> {
+        let payload = match &self.payload {
+            None | Some(PayloadVariant::Encrypted(_)) => return Ok(None),
+            Some(payload) => payload,
+        };
+        let (portnum, payload) = payload.try_into()?;
+
+        Ok(Some(Data {
+            portnum,
+            payload,
+            want_response: self.want_response,
+            dest: self.dest,
+            source: self.source,
+            request_id: self.request_id,
+            reply_id: self.reply_id,
+            emoji: self.emoji,
+        }))
+    }
+
+    /// Replace the payload and `Data` header fields with those of `data`
+    pub fn set_data(&mut self, data: &Data) -> Result<(), ProtocolError> {
+        self.payload = Some(data.try_into()?);
+        self.want_response = data.want_response;
+        self.dest = data.dest;
+        self.source = data.source;
+        self.request_id = data.request_id;
+        self.reply_id = data.reply_id;
+        self.emoji = data.emoji;
+        Ok(())
+    }
+}
+
+impl TryFrom<&MeshPacket> for proto::MeshPacket {
+    type Error = ProtocolError;
+
+    fn try_from(packet: &MeshPacket) -> Result<Self, Self::Error> {
+        let payload_variant = match &packet.payload {
+            Some(PayloadVariant::Encrypted(bytes)) => Some(proto::mesh_packet::PayloadVariant::Encrypted(bytes.clone())),
+            _ => packet.data()?.map(|data| proto::mesh_packet::PayloadVariant::Decoded((&data).into())),
+        };
+
+        Ok(Self {
+            from: packet.from,
+            to: packet.to,
+            channel: packet.channel as u32,
+            id: packet.id,
+            rx_time: packet.rx_time,
+            rx_snr: packet.rx_snr,
+            hop_limit: packet.hop_limit as u32,
+            want_ack: packet.want_ack,
+            priority: packet.priority.into(),
+            rx_rssi: packet.rx_rssi,
+            hop_start: packet.hop_start as u32,
+            payload_variant,
+        })
+    }
+}
+
+impl TryFrom<proto::MeshPacket> for MeshPacket {
+    type Error = ProtocolError;
+
+    fn try_from(packet: proto::MeshPacket) -> Result<Self, Self::Error> {
+        let mut decoded = Self {
+            from: packet.from,
+            to: packet.to,
+            id: packet.id,
+            payload: None,
+            hop_limit: packet.hop_limit.min(u8::MAX as u32) as u8,
+            want_ack: packet.want_ack,
+            priority: MeshPacket_Priority::from(packet.priority),
+            rx_time: packet.rx_time,
+            rx_snr: packet.rx_snr,
+            rx_rssi: packet.rx_rssi,
+            channel: packet.channel.min(u8::MAX as u32) as u8,
+            hop_start: packet.hop_start.min(u8::MAX as u32) as u8,
+            want_response: false,
+            dest: 0,
+            source: 0,
+            request_id: 0,
+            reply_id: 0,
+            emoji: 0,
+        };
+
+        match packet.payload_variant {
+            Some(proto::mesh_packet::PayloadVariant::Encrypted(bytes)) => {
+                decoded.payload = Some(PayloadVariant::Encrypted(bytes));
+            }
+            Some(proto::mesh_packet::PayloadVariant::Decoded(data)) => decoded.set_data(&data.into())?,
+            None => {}
+        }
+        Ok(decoded)
+    }
+}
+
+// =============================================================================
+// STREAM API ENVELOPES
+// =============================================================================
+
+impl From<&MyNodeInfo> for proto::MyNodeInfo {
+    fn from(info: &MyNodeInfo) -> Self {
+        Self {
+            my_node_num: info.my_node_num,
+            reboot_count: info.reboot_count,
+            min_app_version: info.min_app_version,
+        }
+    }
+}
+
+impl From<proto::MyNodeInfo> for MyNodeInfo {
+    fn from(info: proto::MyNodeInfo) -> Self {
+        Self {
+            my_node_num: info.my_node_num,
+            reboot_count: info.reboot_count,
+            min_app_version: info.min_app_version,
+        }
+    }
+}
+
+impl From<&MeshNodeInfo> for proto::NodeInfo {
+    fn from(node: &MeshNodeInfo) -> Self {
+        Self {
+            num: node.num,
+            user: node.user.as_ref().map(Into::into),
+            position: node.position.as_ref().map(Into::into),
+            snr: node.snr,
+            last_heard: node.last_heard,
+            device_metrics: node.device_metrics.as_ref().map(Into::into),
+            channel: node.channel,
+            via_mqtt: node.via_mqtt,
+            hops_away: node.hops_away,
+            is_favorite: node.is_favorite,
+        }
+    }
+}
+
+impl From<proto::NodeInfo> for MeshNodeInfo {
+    fn from(node: proto::NodeInfo) -> Self {
+        Self {
+            num: node.num,
+            user: node.user.map(Into::into),
+            position: node.position.map(Into::into),
+            snr: node.snr,
+            last_heard: node.last_heard,
+            device_metrics: node.device_metrics.map(Into::into),
+            channel: node.channel,
+            via_mqtt: node.via_mqtt,
+            hops_away: node.hops_away,
+            is_favorite: node.is_favorite,
+        }
+    }
+}
+
+impl From<&LogRecord> for proto::LogRecord {
+    fn from(record: &LogRecord) -> Self {
+        Self {
+            message: record.message.clone(),
+            time: record.time,
+            source: record.source.clone(),
+            level: record.level,
+        }
+    }
+}
+
+impl From<proto::LogRecord> for LogRecord {
+    fn from(record: proto::LogRecord) -> Self {
+        Self {
+            message: record.message,
+            time: record.time,
+            source: record.source,
+            level: record.level,
+        }
+    }
+}
+
+impl From<&QueueStatus> for proto::QueueStatus {
+    fn from(status: &QueueStatus) -> Self {
+        Self {
+            res: status.res,
+            free: status.free,
+            maxlen: status.maxlen,
+            mesh_packet_id: status.mesh_packet_id,
+        }
+    }
+}
+
+impl From<proto::QueueStatus> for QueueStatus {
+    fn from(status: proto::QueueStatus) -> Self {
+        Self {
+            res: status.res,
+            free: status.free,
+            maxlen: status.maxlen,
+            mesh_packet_id: status.mesh_packet_id,
+        }
+    }
+}
+
+impl TryFrom<&ToRadio> for proto::ToRadio {
+    type Error = ProtocolError;
+
+    fn try_from(message: &ToRadio) -> Result<Self, Self::Error> {
+        use proto::to_radio::PayloadVariant as Wire;
+
+        let payload_variant = match &message.variant {
+            None => None,
+            Some(variant) => Some(match variant {
+                to_radio::Variant::Packet(packet) => Wire::Packet(packet.try_into()?),
+                to_radio::Variant::WantConfigId(id) => Wire::WantConfigId(*id),
+                to_radio::Variant::Disconnect(disconnect) => Wire::Disconnect(*disconnect),
+                to_radio::Variant::Heartbeat => Wire::Heartbeat(proto::Heartbeat {}),
+            }),
+        };
+
+        Ok(Self { payload_variant })
+    }
+}
+
+impl TryFrom<proto::ToRadio> for ToRadio {
+    type Error = ProtocolError;
+
+    fn try_from(message: proto::ToRadio) -> Result<Self, Self::Error> {
+        use proto::to_radio::PayloadVariant as Wire;
+
+        let variant = match message.payload_variant {
+            None => None,
+            Some(variant) => Some(match variant {
+                Wire::Packet(packet) => to_radio::Variant::Packet(packet.try_into()?),
+                Wire::WantConfigId(id) => to_radio::Variant::WantConfigId(id),
+                Wire::Disconnect(disconnect) => to_radio::Variant::Disconnect(disconnect),
+                Wire::Heartbeat(_) => to_radio::Variant::Heartbeat,
+            }),
+        };
+
+        Ok(Self { variant })
+    }
+}
+
+impl TryFrom<&FromRadio> for proto::FromRadio {
+    type Error = ProtocolError;
+
+    fn try_from(message: &FromRadio) -> Result<Self, Self::Error> {
+        use from_radio::Variant;
+        use proto::from_radio::PayloadVariant as Wire;
+
+        let payload_variant = match &message.variant {
+            None => None,
+            Some(variant) => Some(match variant {
+                Variant::Packet(packet) => Wire::Packet(packet.try_into()?),
+                Variant::MyInfo(info) => Wire::MyInfo(info.into()),
+                Variant::NodeInfo(node) => Wire::NodeInfo(node.into()),
+                Variant::Config(config) => Wire::Config(config.into()),
+                Variant::LogRecord(record) => Wire::LogRecord(record.into()),
+                Variant::ConfigCompleteId(id) => Wire::ConfigCompleteId(*id),
+                Variant::Rebooted(rebooted) => Wire::Rebooted(*rebooted),
+                Variant::ModuleConfig(config) => Wire::ModuleConfig(config.into()),
+                Variant::Channel(channel) => Wire::Channel(channel.into()),
+                Variant::QueueStatus(status) => Wire::QueueStatus(status.into()),
+                Variant::Metadata(metadata) => Wire::Metadata(metadata.into()),
+            }),
+        };
+
+        Ok(Self { id: message.id, payload_variant })
+    }
+}
+
+impl TryFrom<proto::FromRadio> for FromRadio {
+    type Error = ProtocolError;
+
+    fn try_from(message: proto::FromRadio) -> Result<Self, Self::Error> {
+        use from_radio::Variant;
+        use proto::from_radio::PayloadVariant as Wire;
+
+        let variant = match message.payload_variant {
+            None => None,
+            Some(variant) => Some(match variant {
+                Wire::Packet(packet) => Variant::Packet(packet.try_into()?),
+                Wire::MyInfo(info) => Variant::MyInfo(info.into()),
+                Wire::NodeInfo(node) => Variant::NodeInfo(node.into()),
+                Wire::Config(config) => Variant::Config(config.into()),
+                Wire::LogRecord(record) => Variant::LogRecord(record.into()),
+                Wire::ConfigCompleteId(id) => Variant::ConfigCompleteId(id),
+                Wire::Rebooted(rebooted) => Variant::Rebooted(rebooted),
+                Wire::ModuleConfig(config) => Variant::ModuleConfig(config.into()),
+                Wire::Channel(channel) => Variant::Channel(channel.into()),
+                Wire::QueueStatus(status) => Variant::QueueStatus(status.into()),
+                Wire::Metadata(metadata) => Variant::Metadata(metadata.into()),
+            }),
+        };
+
+        Ok(Self { id: message.id, variant })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Broadcast "hi" from !12345678 as delivered by the firmware over serial
+    const TEXT_PACKET: &[u8] = &[
+        0x0d, 0x78, 0x56, 0x34, 0x12, 0x15, 0xff, 0xff, 0xff, 0xff, 0x22, 0x06, 0x08, 0x01, 0x12, 0x02,
+        0x68, 0x69, 0x35, 0x0d, 0x0c, 0x0b, 0x0a, 0x3d, 0x00, 0xf1, 0x53, 0x65, 0x45, 0x00, 0x00, 0xc8,
+        0x40, 0x48, 0x03, 0x60, 0xd3, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01, 0x78, 0x03,
+    ];
+
+    /// POSITION_APP broadcast with a 32-bit precision fix
+    const POSITION_PACKET: &[u8] = &[
+        0x0d, 0xef, 0xbe, 0xad, 0xde, 0x15, 0xff, 0xff, 0xff, 0xff, 0x22, 0x1d, 0x08, 0x03, 0x12, 0x19,
+        0x0d, 0x08, 0xfe, 0x83, 0x16, 0x15, 0x30, 0x48, 0x08, 0xb7, 0x18, 0x0c, 0x25, 0x00, 0xf1, 0x53,
+        0x65, 0x28, 0x02, 0x98, 0x01, 0x07, 0xb8, 0x01, 0x20, 0x35, 0x44, 0x33, 0x22, 0x11, 0x48, 0x02,
+        0x78, 0x03,
+    ];
+
+    /// ROUTING_APP ack (error_reason NONE) for packet 0x0a0b0c0d
+    const ROUTING_ACK_PACKET: &[u8] = &[
+        0x0d, 0x78, 0x56, 0x34, 0x12, 0x15, 0x21, 0x43, 0x65, 0x87, 0x22, 0x0b, 0x08, 0x05, 0x12, 0x02,
+        0x18, 0x00, 0x35, 0x0d, 0x0c, 0x0b, 0x0a, 0x35, 0x88, 0x77, 0x66, 0x55, 0x48, 0x03, 0x58, 0x78,
+        0x78, 0x03,
+    ];
+
+    /// TELEMETRY_APP device metrics broadcast
+    const TELEMETRY_PACKET: &[u8] = &[
+        0x0d, 0xef, 0xbe, 0xad, 0xde, 0x15, 0xff, 0xff, 0xff, 0xff, 0x22, 0x1f, 0x08, 0x43, 0x12, 0x1b,
+        0x0d, 0x00, 0xf1, 0x53, 0x65, 0x12, 0x14, 0x08, 0x57, 0x15, 0x00, 0x00, 0x80, 0x40, 0x1d, 0x00,
+        0x00, 0x48, 0x41, 0x25, 0x00, 0x00, 0xc0, 0x3f, 0x28, 0x90, 0x1c, 0x35, 0x04, 0x03, 0x02, 0x01,
+        0x48, 0x03, 0x58, 0x0a, 0x78, 0x03,
+    ];
+
+    /// Still-encrypted packet as seen on an MQTT feed (channel hash 8)
+    const ENCRYPTED_PACKET: &[u8] = &[
+        0x0d, 0xef, 0xbe, 0xad, 0xde, 0x15, 0xff, 0xff, 0xff, 0xff, 0x18, 0x08, 0x2a, 0x05, 0x9a, 0x1f,
+        0x33, 0x07, 0xc2, 0x35, 0x04, 0x03, 0x02, 0x01, 0x48, 0x03, 0x78, 0x03,
+    ];
+
+    #[test]
+    fn test_decode_text_vector() {
+        let packet = decode_packet(TEXT_PACKET).unwrap();
+        assert_eq!(packet.from, 0x12345678);
+        assert!(packet.is_broadcast());
+        assert_eq!(packet.id, 0x0a0b0c0d);
+        assert_eq!(packet.rx_time, 1_700_000_000);
+        assert_eq!(packet.rx_snr, 6.25);
+        assert_eq!(packet.rx_rssi, -45);
+        assert_eq!(packet.hop_limit, 3);
+        assert_eq!(packet.hop_start, 3);
+        assert!(matches!(packet.payload, Some(PayloadVariant::Text(ref text)) if text == "hi"));
+    }
+
+    #[test]
+    fn test_decode_position_vector() {
+        let packet = decode_packet(POSITION_PACKET).unwrap();
+        let Some(PayloadVariant::Position(position)) = packet.payload else {
+            panic!("expected a position payload");
+        };
+        assert_eq!(position.latitude_i, 377_749_000);
+        assert_eq!(position.longitude_i, -1_224_194_000);
+        assert_eq!(position.altitude, 12);
+        assert_eq!(position.location_source, 2);
+        assert_eq!(position.sats_in_view, 7);
+        assert_eq!(position.precision_bits, 32);
+    }
+
+    #[test]
+    fn test_decode_routing_ack_vector() {
+        let packet = decode_packet(ROUTING_ACK_PACKET).unwrap();
+        assert_eq!(packet.priority, MeshPacket_Priority::ACK);
+        assert!(matches!(
+            packet.payload,
+            Some(PayloadVariant::Routing(Routing { variant: Some(RoutingVariant::ErrorReason(Routing_Error::NONE)) }))
+        ));
+        assert_eq!(packet.request_id, 0x0a0b0c0d);
+    }
+
+    #[test]
+    fn test_decode_telemetry_vector() {
+        let packet = decode_packet(TELEMETRY_PACKET).unwrap();
+        let Some(PayloadVariant::Telemetry(telemetry)) = packet.payload else {
+            panic!("expected a telemetry payload");
+        };
+        assert_eq!(telemetry.time, 1_700_000_000);
+        let Some(TelemetryVariant::DeviceMetrics(metrics)) = telemetry.variant else {
+            panic!("expected device metrics");
+        };
+        assert_eq!(metrics.battery_level, 87);
+        assert_eq!(metrics.voltage, 4.0);
+        assert_eq!(metrics.uptime_seconds, 3600);
+    }
+
+    #[test]
+    fn test_private_port_payload_keeps_data_fields() {
+        let mut packet = MeshPacket::default();
+        packet.set_data(&Data {
+            portnum: PortNum(300),
+            payload: vec![1, 2, 3],
+            want_response: true,
+            reply_id: 42,
+            ..Default::default()
+        }).unwrap();
+        assert!(matches!(packet.payload, Some(PayloadVariant::App(PortNum(300), _))));
+
+        let decoded = decode_packet(&encode_packet(&packet).unwrap()).unwrap();
+        let data = decoded.data().unwrap().unwrap();
+        assert_eq!(data.portnum, PortNum(300));
+        assert!(data.portnum.is_private());
+        assert_eq!(data.payload, vec![1, 2, 3]);
+        assert!(data.want_response);
+        assert_eq!(data.reply_id, 42);
+    }
+
+    #[test]
+    fn test_vectors_round_trip_byte_for_byte() {
+        for vector in [TEXT_PACKET, POSITION_PACKET, ROUTING_ACK_PACKET, TELEMETRY_PACKET, ENCRYPTED_PACKET] {
+            let packet = decode_packet(vector).unwrap();
+            assert_eq!(encode_packet(&packet).unwrap(), vector);
+        }
+    }
+
+    #[test]
+    fn test_unknown_values_and_store_forward_fields_round_trip() {
+        let user = User { hw_model: HardwareModel::from(9999), role: Role::from(99), ..Default::default() };
+        let mut packet = MeshPacket::new_node_info(0xdeadbeef, user);
+        packet.priority = MeshPacket_Priority::from(42);
+        packet.dest = 0x11223344;
+        packet.source = 0x55667788;
+
+        let bytes = encode_packet(&packet).unwrap();
+        let decoded = decode_packet(&bytes).unwrap();
+        assert_eq!(decoded.priority, MeshPacket_Priority::Unknown(42));
+        assert_eq!(decoded.dest, 0x11223344);
+        assert_eq!(decoded.source, 0x55667788);
+        let Some(PayloadVariant::NodeInfo(ref user)) = decoded.payload else {
+            panic!("expected a node info payload");
+        };
+        assert_eq!(user.hw_model, HardwareModel::Unknown(9999));
+        assert_eq!(user.role, Role::Unknown(99));
+        assert_eq!(encode_packet(&decoded).unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_encrypted_payload_is_preserved() {
+        let packet = decode_packet(ENCRYPTED_PACKET).unwrap();
+        assert_eq!(packet.channel, 8);
+        assert!(matches!(packet.payload, Some(PayloadVariant::Encrypted(ref b)) if b == &[0x9a, 0x1f, 0x33, 0x07, 0xc2]));
+    }
+
+    #[test]
+    fn test_node_info_round_trip() {
+        let user = User {
+            id: "!deadbeef".to_string(),
+            long_name: "Base Camp".to_string(),
+            short_name: "BASE".to_string(),
+            macaddr: vec![0xde, 0xad, 0xbe, 0xef, 0x00, 0x01],
+            hw_model: HardwareModel::HELTEC_V3,
+            is_licensed: true,
+            role: Role::ROUTER_LATE,
+        };
+        let packet = MeshPacket::new_node_info(0xdeadbeef, user);
+        let decoded = decode_packet(&encode_packet(&packet).unwrap()).unwrap();
+        let Some(PayloadVariant::NodeInfo(user)) = decoded.payload else {
+            panic!("expected a node info payload");
+        };
+        assert_eq!(user.long_name, "Base Camp");
+        assert_eq!(user.hw_model, HardwareModel::HELTEC_V3);
+        assert_eq!(user.role, Role::ROUTER_LATE);
+        assert!(user.is_licensed);
+    }
+
+    #[test]
+    fn test_admin_get_channel_uses_one_based_index() {
+        let admin = AdminMessage {
+            variant: Some(admin_message::Variant::GetChannel(GetChannelRequest { index: 0 })),
+        };
+        let wire = proto::AdminMessage::try_from(&admin).unwrap();
+        assert_eq!(wire.encode_to_vec(), vec![0x08, 0x01]);
+        let back = AdminMessage::from(wire);
+        assert!(matches!(back.variant, Some(admin_message::Variant::GetChannel(GetChannelRequest { index: 0 }))));
+    }
+
+    #[test]
+    fn test_config_sections_round_trip() {
+        let network = NetworkConfig {
+            wifi_enabled: true,
+            wifi_ssid: "mesh".to_string(),
+            address_mode: AddressMode::STATIC,
+            ipv4_config: Some(IpV4Config { ip: IpV4Config::encode_address([192, 168, 1, 20].into()), ..Default::default() }),
+            ..Default::default()
+        };
+        let wire = proto::Config::from(&Config::new(config::Variant::Network(network)));
+        let Some(proto::config::PayloadVariant::Network(ref wire_network)) = wire.payload_variant else {
+            panic!("expected a network section");
+        };
+        assert_eq!(wire_network.address_mode, 1);
+        assert_eq!(wire_network.ipv4_config.as_ref().unwrap().ip.to_le_bytes(), [192, 168, 1, 20]);
+
+        let back = Config::from(proto::Config::decode(wire.encode_to_vec().as_slice()).unwrap());
+        assert_eq!(back.config_type(), Some(ConfigType::NETWORK_CONFIG));
+        let Some(config::Variant::Network(network)) = back.variant else { unreachable!() };
+        assert_eq!((network.wifi_ssid.as_str(), network.address_mode), ("mesh", AddressMode::STATIC));
+        assert_eq!(IpV4Config::decode_address(network.ipv4_config.unwrap().ip), std::net::Ipv4Addr::new(192, 168, 1, 20));
+
+        let display = DisplayConfig { units: DisplayUnits::IMPERIAL, oled: OledType::OLED_SH1107, ..Default::default() };
+        let back = Config::from(proto::Config::from(&Config::new(config::Variant::Display(display))));
+        assert!(matches!(
+            back.variant,
+            Some(config::Variant::Display(DisplayConfig { units: DisplayUnits::IMPERIAL, oled: OledType::OLED_SH1107, .. }))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_bytes_are_rejected() {
+        assert!(decode_packet(&[0x22, 0x10, 0x08]).is_err());
+    }
+}
@@ -0,0 +1,220 @@
+//! Channel payload encryption compatible with the Meshtastic firmware.
+//!
+//! Packets are encrypted with AES-CTR using the channel PSK. The 128-bit
+//! counter block starts as the packet id (u64, little-endian) followed by the
+//! sender's node number (u32, little-endian) and four zero bytes. What gets
+//! encrypted is the protobuf-encoded `Data` message.
+
+use super::proto;
+use super::{MeshPacket, PayloadVariant, ProtocolError};
+use aes::cipher::{KeyIvInit, StreamCipher};
+use prost::Message;
+
+type Aes128Ctr = ctr::Ctr128BE<aes::Aes128>;
+type Aes256Ctr = ctr::Ctr128BE<aes::Aes256>;
+
+/// The well-known key behind the 1-byte PSK `0x01` ("AQ==")
+pub const DEFAULT_KEY: [u8; 16] = [
+    0xd4, 0xf1, 0xbb, 0x3a, 0x20, 0x29, 0x07, 0x59, 0xf0, 0xbc, 0xff, 0xab, 0xcf, 0x4e, 0x69, 0x01,
+];
+
+/// An expanded channel key, ready to encrypt or decrypt payloads
+#[derive(Clone, PartialEq, Eq)]
+pub enum ChannelKey {
+    /// PSK of `[]` or `[0x00]`: payloads travel in the clear
+    None,
+    Aes128([u8; 16]),
+    Aes256([u8; 32]),
+}
+
+impl ChannelKey {
+    /// Expand a channel PSK the way the firmware does.
+    ///
+    /// A single byte `n` selects the default key with its last byte bumped by
+    /// `n - 1`; short keys are zero-padded up to the next AES key size.
+    pub fn from_psk(psk: &[u8]) -> Result<Self, ProtocolError> {
+        match psk.len() {
+            0 => Ok(ChannelKey::None),
+            1 if psk[0] == 0 => Ok(ChannelKey::None),
+            1 => {
+                let mut key = DEFAULT_KEY;
+                key[15] = key[15].wrapping_add(psk[0] - 1);
+                Ok(ChannelKey::Aes128(key))
+            }
+            2..=16 => {
+                let mut key = [0u8; 16];
+                key[..psk.len()].copy_from_slice(psk);
+                Ok(ChannelKey::Aes128(key))
+            }
+            17..=32 => {
+                let mut key = [0u8; 32];
+                key[..psk.len()].copy_from_slice(psk);
+                Ok(ChannelKey::Aes256(key))
+            }
+            len => Err(ProtocolError::Crypto(format!("PSK of {} bytes is longer than 32", len))),
+        }
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        !matches!(self, ChannelKey::None)
+    }
+
+    /// XOR `data` with the keystream for this packet. CTR mode is symmetric,
+    /// so this both encrypts and decrypts.
+    pub fn apply(&self, packet_id: u32, from: u32, data: &mut [u8]) {
+        let nonce = Self::nonce(packet_id, from);
+        match self {
+            ChannelKey::None => {}
+            ChannelKey::Aes128(key) => Aes128Ctr::new(key.into(), &nonce.into()).apply_keystream(data),
+            ChannelKey::Aes256(key) => Aes256Ctr::new(key.into(), &nonce.into()).apply_keystream(data),
+        }
+    }
+
+    fn nonce(packet_id: u32, from: u32) -> [u8; 16] {
+        let mut nonce = [0u8; 16];
+        nonce[..8].copy_from_slice(&(packet_id as u64).to_le_bytes());
+        nonce[8..12].copy_from_slice(&from.to_le_bytes());
+        nonce
+    }
+}
+
+impl std::fmt::Debug for ChannelKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never print key material
+        match self {
+            ChannelKey::None => write!(f, "ChannelKey::None"),
+            ChannelKey::Aes128(_) => write!(f, "ChannelKey::Aes128(..)"),
+            ChannelKey::Aes256(_) => write!(f, "ChannelKey::Aes256(..)"),
+        }
+    }
+}
+
+/// Return a copy of `packet` with its decoded payload replaced by the
+/// channel-encrypted `Data` bytes
+pub fn encrypt_packet(packet: &MeshPacket, key: &ChannelKey) -> Result<MeshPacket, ProtocolError> {
+    if matches!(packet.payload, Some(PayloadVariant::Encrypted(_))) {
+        return Err(ProtocolError::Crypto("Packet is already encrypted".to_string()));
+    }
+    let data = packet.data()?.ok_or(ProtocolError::InvalidFormat)?;
+    let mut bytes = proto::Data::from(&data).encode_to_vec();
+    key.apply(packet.id, packet.from, &mut bytes);
+
+    Ok(MeshPacket {
+        payload: Some(PayloadVariant::Encrypted(bytes)),
+        ..packet.clone()
+    })
+}
+
+/// Return a copy of `packet` with its encrypted payload decrypted and decoded.
+///
+/// Fails if the plaintext is not a valid `Data` message with a port number,
+/// which is how a wrong key shows up.
+pub fn decrypt_packet(packet: &MeshPacket, key: &ChannelKey) -> Result<MeshPacket, ProtocolError> {
+    let Some(PayloadVariant::Encrypted(encrypted)) = &packet.payload else {
+        return Err(ProtocolError::Crypto("Packet is not encrypted".to_string()));
+    };
+
+    let mut bytes = encrypted.clone();
+    key.apply(packet.id, packet.from, &mut bytes);
+
+    let data = proto::Data::decode(bytes.as_slice())
+        .map_err(|_| ProtocolError::Crypto("Payload did not decrypt to a Data message".to_string()))?;
+    if data.portnum == proto::PortNum::UnknownApp as i32 {
+        return Err(ProtocolError::Crypto("Payload did not decrypt to a Data message".to_string()));
+    }
+
+    let mut decrypted = packet.clone();
+    decrypted.set_data(&data.into())?;
+    Ok(decrypted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Data { portnum: TEXT_MESSAGE_APP, payload: "hi" } from !12345678, id
+    /// 0x0a0b0c0d, under the default LongFast key
+    const DEFAULT_KEY_HI: [u8; 6] = [0x90, 0xee, 0x2a, 0x3e, 0xad, 0x1f];
+
+    /// Same packet under PSK index 2 (default key with last byte + 1)
+    const KEY_INDEX_2_HI: [u8; 6] = [0x78, 0x20, 0xe4, 0x0b, 0x28, 0x26];
+
+    /// 47-byte text payload under a 256-bit key, spanning several counter blocks
+    const AES256_FOX: [u8; 47] = [
+        0x35, 0x2a, 0x42, 0x97, 0x12, 0x00, 0xd3, 0x9b, 0xdb, 0xe0, 0x85, 0x8f, 0x72, 0x11, 0xb2, 0x17,
+        0x20, 0x43, 0xd9, 0xb8, 0xe6, 0xa5, 0x79, 0xf8, 0xbd, 0x50, 0x61, 0x8a, 0x43, 0x0b, 0xe3, 0xf0,
+        0x0e, 0xea, 0xa1, 0x12, 0x13, 0x59, 0x31, 0xbd, 0x2c, 0x23, 0x56, 0x32, 0x00, 0x12, 0xac,
+    ];
+
+    fn text_packet(from: u32, id: u32, text: &str) -> MeshPacket {
+        MeshPacket {
+            id,
+            ..MeshPacket::new_text_message(from, 0xFFFFFFFF, text)
+        }
+    }
+
+    fn encrypted_bytes(packet: &MeshPacket) -> &[u8] {
+        match &packet.payload {
+            Some(PayloadVariant::Encrypted(bytes)) => bytes,
+            other => panic!("expected encrypted payload, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_psk_expansion() {
+        assert_eq!(ChannelKey::from_psk(&[]).unwrap(), ChannelKey::None);
+        assert_eq!(ChannelKey::from_psk(&[0]).unwrap(), ChannelKey::None);
+        assert_eq!(ChannelKey::from_psk(&[1]).unwrap(), ChannelKey::Aes128(DEFAULT_KEY));
+
+        let ChannelKey::Aes128(key) = ChannelKey::from_psk(&[5]).unwrap() else { panic!() };
+        assert_eq!(key[15], DEFAULT_KEY[15] + 4);
+        assert_eq!(key[..15], DEFAULT_KEY[..15]);
+
+        assert!(matches!(ChannelKey::from_psk(&[7; 10]).unwrap(), ChannelKey::Aes128(k) if k[9] == 7 && k[10] == 0));
+        assert!(matches!(ChannelKey::from_psk(&[7; 24]).unwrap(), ChannelKey::Aes256(k) if k[23] == 7 && k[24] == 0));
+        assert!(ChannelKey::from_psk(&[7; 33]).is_err());
+    }
+
+    #[test]
+    fn test_default_key_vector() {
+        let key = ChannelKey::from_psk(&[1]).unwrap();
+        let encrypted = encrypt_packet(&text_packet(0x12345678, 0x0a0b0c0d, "hi"), &key).unwrap();
+        assert_eq!(encrypted_bytes(&encrypted), DEFAULT_KEY_HI);
+
+        let key = ChannelKey::from_psk(&[2]).unwrap();
+        let encrypted = encrypt_packet(&text_packet(0x12345678, 0x0a0b0c0d, "hi"), &key).unwrap();
+        assert_eq!(encrypted_bytes(&encrypted), KEY_INDEX_2_HI);
+    }
+
+    #[test]
+    fn test_aes256_vector_decrypts() {
+        let key = ChannelKey::from_psk(&(0u8..32).collect::<Vec<_>>()).unwrap();
+        let packet = MeshPacket {
+            id: 0x01020304,
+            from: 0xdeadbeef,
+            payload: Some(PayloadVariant::Encrypted(AES256_FOX.to_vec())),
+            ..Default::default()
+        };
+
+        let decrypted = decrypt_packet(&packet, &key).unwrap();
+        assert!(matches!(
+            decrypted.payload,
+            Some(PayloadVariant::Text(ref t)) if t == "The quick brown fox jumps over the lazy dog"
+        ));
+    }
+
+    #[test]
+    fn test_no_crypto_psk_passes_data_through() {
+        let key = ChannelKey::from_psk(&[0]).unwrap();
+        let encrypted = encrypt_packet(&text_packet(1, 2, "hi"), &key).unwrap();
+        assert_eq!(encrypted_bytes(&encrypted), [0x08, 0x01, 0x12, 0x02, 0x68, 0x69]);
+        assert!(decrypt_packet(&encrypted, &key).is_ok());
+    }
+
+    #[test]
+    fn test_wrong_key_is_rejected() {
+        let packet = text_packet(0x12345678, 0x0a0b0c0d, "hello there");
+        let encrypted = encrypt_packet(&packet, &ChannelKey::from_psk(&[1]).unwrap()).unwrap();
+        assert!(decrypt_packet(&encrypted, &ChannelKey::from_psk(&[9; 16]).unwrap()).is_err());
+    }
+}
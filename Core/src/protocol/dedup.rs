@@ -0,0 +1,117 @@
+//! Duplicate suppression for packets heard more than once.
+//!
+//! A packet is identified by its sender and packet id; ids are only unique
+//! per sender. Entries expire after a time window rather than by count, so
+//! a burst of traffic cannot flush out entries that are still relevant.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a packet is remembered by default. Rebroadcasts of a packet
+/// arrive within seconds; this leaves room for slow multi-hop paths and
+/// MQTT round trips.
+pub const DEFAULT_DEDUP_WINDOW: Duration = Duration::from_secs(600);
+
+/// Upper bound on remembered packets, whatever their age
+const MAX_ENTRIES: usize = 20_000;
+
+#[derive(Debug, Default)]
+struct Seen {
+    first_seen: HashMap<(u32, u32), Instant>,
+    /// Keys in the order they were first seen, oldest first
+    order: VecDeque<((u32, u32), Instant)>,
+}
+
+impl Seen {
+    fn expire(&mut self, now: Instant, window: Duration) {
+        while let Some(&(key, seen_at)) = self.order.front() {
+            if now.duration_since(seen_at) < window && self.order.len() <= MAX_ENTRIES {
+                break;
+            }
+            self.order.pop_front();
+            self.first_seen.remove(&key);
+        }
+    }
+}
+
+/// Remembers which `(from, id)` pairs have been processed. Clones share
+/// state, so one store can sit in front of several devices and gateways.
+#[derive(Debug, Clone)]
+pub struct DedupStore {
+    window: Duration,
+    seen: Arc<Mutex<Seen>>,
+}
+
+impl DedupStore {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: Arc::new(Mutex::new(Seen::default())),
+        }
+    }
+
+    /// Record a packet. Returns true the first time `(from, id)` is seen
+    /// within the window and false for duplicates. Packets with id 0 carry no
+    /// identity and are never treated as duplicates.
+    pub fn first_seen(&self, from: u32, id: u32) -> bool {
+        if id == 0 {
+            return true;
+        }
+
+        let now = Instant::now();
+        let mut seen = self.seen.lock().unwrap();
+        seen.expire(now, self.window);
+
+        if seen.first_seen.contains_key(&(from, id)) {
+            return false;
+        }
+        seen.first_seen.insert((from, id), now);
+        seen.order.push_back(((from, id), now));
+        true
+    }
+
+    /// Number of packets currently remembered
+    pub fn len(&self) -> usize {
+        self.seen.lock().unwrap().first_seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for DedupStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_DEDUP_WINDOW)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_on_sender_and_id() {
+        let store = DedupStore::default();
+        assert!(store.first_seen(1, 100));
+        assert!(!store.first_seen(1, 100));
+        // Same id from another node is a different packet
+        assert!(store.first_seen(2, 100));
+        assert!(store.first_seen(1, 0));
+        assert!(store.first_seen(1, 0));
+        assert_eq!(store.len(), 2);
+    }
+
+    #[test]
+    fn test_entries_expire_by_age() {
+        let store = DedupStore::new(Duration::from_millis(20));
+        let shared = store.clone();
+        assert!(store.first_seen(1, 100));
+        assert!(!shared.first_seen(1, 100));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(shared.first_seen(1, 100));
+        assert_eq!(store.len(), 1);
+    }
+}
@@ -0,0 +1,334 @@
+//! Delivery tracking for packets sent with `want_ack`.
+//!
+//! The radio answers every acknowledged send with a ROUTING_APP packet whose
+//! `request_id` is the id of the original packet. An error reason of NONE
+//! from the destination is a real ack; NONE from anyone else means our radio
+//! heard a neighbour rebroadcast the packet (an implicit ack, and the only
+//! ack a broadcast gets). Any other reason is a NAK.
+
+use super::{MeshPacket, PayloadVariant, RoutingVariant, Routing_Error};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, oneshot};
+
+/// How many finished statuses are kept for `DeliveryTracker::status`
+const STATUS_HISTORY: usize = 1000;
+
+/// Delivery state of one sent packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeliveryStatus {
+    /// Waiting for an ack; `attempt` counts from 1
+    Pending { attempt: u32 },
+    /// Acked by the destination
+    Delivered,
+    /// A neighbour was heard rebroadcasting the packet
+    ImplicitAck,
+    /// NAKed, or no ack after the last retry (`MAX_RETRANSMIT`)
+    Failed(Routing_Error),
+    /// Could not be handed to the radio; the mesh never saw this attempt
+    SendFailed,
+}
+
+impl DeliveryStatus {
+    pub fn is_final(&self) -> bool {
+        !matches!(self, DeliveryStatus::Pending { .. })
+    }
+
+    /// NAKed, out of retries or never sent
+    pub fn is_failed(&self) -> bool {
+        matches!(self, DeliveryStatus::Failed(_) | DeliveryStatus::SendFailed)
+    }
+}
+
+/// Status change for a tracked packet
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DeliveryUpdate {
+    pub packet_id: u32,
+    pub status: DeliveryStatus,
+}
+
+/// When and how often to retransmit an unacknowledged packet
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Total number of transmissions, including the first
+    pub max_attempts: u32,
+    /// How long to wait for an ack after the first transmission
+    pub ack_timeout: Duration,
+    /// Factor applied to the timeout after every attempt
+    pub backoff: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            ack_timeout: Duration::from_secs(15),
+            backoff: 2,
+        }
+    }
+}
+
+struct Waiter {
+    to: u32,
+    tx: oneshot::Sender<DeliveryStatus>,
+}
+
+#[derive(Default)]
+struct TrackerState {
+    waiters: HashMap<u32, Waiter>,
+    statuses: HashMap<u32, DeliveryStatus>,
+    finished: VecDeque<u32>,
+}
+
+/// Tracks acknowledgements for sent packets and retransmits on timeout.
+/// Cheap to clone; clones share state.
+#[derive(Clone)]
+pub struct DeliveryTracker {
+    policy: RetryPolicy,
+    state: Arc<Mutex<TrackerState>>,
+    updates: broadcast::Sender<DeliveryUpdate>,
+}
+
+impl DeliveryTracker {
+    pub fn new() -> Self {
+        let (updates, _) = broadcast::channel(256);
+        Self {
+            policy: RetryPolicy::default(),
+            state: Arc::new(Mutex::new(TrackerState::default())),
+            updates,
+        }
+    }
+
+    pub fn with_policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Status updates for every tracked packet
+    pub fn subscribe(&self) -> broadcast::Receiver<DeliveryUpdate> {
+        self.updates.subscribe()
+    }
+
+    /// Latest known status of a tracked packet
+    pub fn status(&self, packet_id: u32) -> Option<DeliveryStatus> {
+        self.state.lock().unwrap().statuses.get(&packet_id).copied()
+    }
+
+    /// Send `packet` through `send` and keep retransmitting it in the
+    /// background until it is acked, NAKed or out of attempts. Only the first
+    /// transmission's error is returned; later failures are reported as
+    /// `SendFailed`.
+    pub async fn send<F, Fut, E>(&self, packet: MeshPacket, send: F) -> Result<u32, E>
+    where
+        F: Fn(MeshPacket) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), E>> + Send,
+        E: std::fmt::Display + Send + 'static,
+    {
+        let packet_id = packet.id;
+        let mut ack = self.register(&packet, 1);
+        if let Err(e) = send(packet.clone()).await {
+            self.finish(packet_id, DeliveryStatus::SendFailed);
+            return Err(e);
+        }
+
+        let tracker = self.clone();
+        tokio::spawn(async move {
+            let mut timeout = tracker.policy.ack_timeout;
+            let mut attempt = 1;
+            loop {
+                let outcome = tokio::time::timeout(timeout, ack).await;
+                let retry = match outcome {
+                    Ok(Ok(status)) => is_retryable(status),
+                    // Dropped by `finish` elsewhere; nothing left to do
+                    Ok(Err(_)) => return,
+                    Err(_) => true,
+                };
+
+                if !retry {
+                    return;
+                }
+                if attempt >= tracker.policy.max_attempts {
+                    tracker.finish(packet_id, DeliveryStatus::Failed(Routing_Error::MAX_RETRANSMIT));
+                    return;
+                }
+
+                attempt += 1;
+                timeout *= tracker.policy.backoff;
+                ack = tracker.register(&packet, attempt);
+                // Same id on purpose: a destination that already has the
+                // packet drops the copy as a duplicate and acks it again,
+                // instead of showing the message twice
+                if let Err(e) = send(packet.clone()).await {
+                    log::warn!("Retransmission of packet {:08x} failed: {}", packet_id, e);
+                    tracker.finish(packet_id, DeliveryStatus::SendFailed);
+                    return;
+                }
+            }
+        });
+
+        Ok(packet_id)
+    }
+
+    /// Feed an incoming packet to the tracker. Returns true if it was a
+    /// routing response for a tracked packet.
+    pub fn handle_packet(&self, packet: &MeshPacket) -> bool {
+        let Some(PayloadVariant::Routing(routing)) = &packet.payload else {
+            return false;
+        };
+        if packet.request_id == 0 {
+            return false;
+        }
+
+        let waiter = self.state.lock().unwrap().waiters.remove(&packet.request_id);
+        let Some(waiter) = waiter else {
+            return false;
+        };
+
+        let status = match routing.variant {
+            Some(RoutingVariant::ErrorReason(Routing_Error::NONE)) | None => {
+                if packet.from == waiter.to {
+                    DeliveryStatus::Delivered
+                } else {
+                    DeliveryStatus::ImplicitAck
+                }
+            }
+            Some(RoutingVariant::ErrorReason(error)) => DeliveryStatus::Failed(error),
+            // A route reply from the destination also proves it got the packet
+            Some(RoutingVariant::RouteReply(_)) | Some(RoutingVariant::RouteRequest(_)) => DeliveryStatus::Delivered,
+        };
+
+        if !is_retryable(status) {
+            self.finish(packet.request_id, status);
+        }
+        let _ = waiter.tx.send(status);
+        true
+    }
+
+    fn register(&self, packet: &MeshPacket, attempt: u32) -> oneshot::Receiver<DeliveryStatus> {
+        let (tx, rx) = oneshot::channel();
+        let status = DeliveryStatus::Pending { attempt };
+        {
+            let mut state = self.state.lock().unwrap();
+            state.waiters.insert(packet.id, Waiter { to: packet.to, tx });
+            state.statuses.insert(packet.id, status);
+        }
+        let _ = self.updates.send(DeliveryUpdate { packet_id: packet.id, status });
+        rx
+    }
+
+    fn finish(&self, packet_id: u32, status: DeliveryStatus) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.waiters.remove(&packet_id);
+            state.statuses.insert(packet_id, status);
+            state.finished.push_back(packet_id);
+            while state.finished.len() > STATUS_HISTORY {
+                if let Some(old) = state.finished.pop_front() {
+                    state.statuses.remove(&old);
+                }
+            }
+        }
+        let _ = self.updates.send(DeliveryUpdate { packet_id, status });
+    }
+}
+
+impl Default for DeliveryTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// NAKs that mean "try again later" rather than "this will never work"
+fn is_retryable(status: DeliveryStatus) -> bool {
+    matches!(
+        status,
+        DeliveryStatus::Failed(Routing_Error::TIMEOUT) | DeliveryStatus::Failed(Routing_Error::MAX_RETRANSMIT)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Routing;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn routing_reply(from: u32, request_id: u32, error: Routing_Error) -> MeshPacket {
+        MeshPacket {
+            from,
+            request_id,
+            payload: Some(PayloadVariant::Routing(Routing {
+                variant: Some(RoutingVariant::ErrorReason(error)),
+            })),
+            ..Default::default()
+        }
+    }
+
+    fn fast_tracker() -> DeliveryTracker {
+        DeliveryTracker::new().with_policy(RetryPolicy {
+            max_attempts: 3,
+            ack_timeout: Duration::from_millis(20),
+            backoff: 2,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_ack_from_destination_and_implicit_ack() {
+        let tracker = fast_tracker();
+        let dm = MeshPacket { to: 0x42, ..MeshPacket::new_text_message(1, 0x42, "hi") };
+        let id = tracker.send(dm, |_| async { Ok::<_, String>(()) }).await.unwrap();
+        assert_eq!(tracker.status(id), Some(DeliveryStatus::Pending { attempt: 1 }));
+
+        // Ack for an unknown packet is ignored
+        assert!(!tracker.handle_packet(&routing_reply(0x42, id.wrapping_add(1), Routing_Error::NONE)));
+        assert!(tracker.handle_packet(&routing_reply(0x42, id, Routing_Error::NONE)));
+        assert_eq!(tracker.status(id), Some(DeliveryStatus::Delivered));
+
+        let broadcast = MeshPacket::new_text_message(1, 0xFFFFFFFF, "all");
+        let id = tracker.send(broadcast, |_| async { Ok::<_, String>(()) }).await.unwrap();
+        assert!(tracker.handle_packet(&routing_reply(1, id, Routing_Error::NONE)));
+        assert_eq!(tracker.status(id), Some(DeliveryStatus::ImplicitAck));
+    }
+
+    #[tokio::test]
+    async fn test_nak_is_reported() {
+        let tracker = fast_tracker();
+        let id = tracker.send(MeshPacket::new_text_message(1, 0x42, "hi"), |_| async { Ok::<_, String>(()) })
+            .await.unwrap();
+        tracker.handle_packet(&routing_reply(1, id, Routing_Error::NO_CHANNEL));
+        assert_eq!(tracker.status(id), Some(DeliveryStatus::Failed(Routing_Error::NO_CHANNEL)));
+    }
+
+    #[tokio::test]
+    async fn test_local_send_failure_is_not_a_nak() {
+        let tracker = fast_tracker();
+        let packet = MeshPacket::new_text_message(1, 0x42, "hi");
+        let id = packet.id;
+        assert!(tracker.send(packet, |_| async { Err::<(), _>("port closed") }).await.is_err());
+        assert_eq!(tracker.status(id), Some(DeliveryStatus::SendFailed));
+    }
+
+    #[tokio::test]
+    async fn test_retries_then_fails() {
+        let tracker = fast_tracker();
+        let mut updates = tracker.subscribe();
+        let sends = Arc::new(AtomicU32::new(0));
+        let counter = sends.clone();
+        let id = tracker.send(MeshPacket::new_text_message(1, 0x42, "hi"), move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { Ok::<_, String>(()) }
+        }).await.unwrap();
+
+        loop {
+            let update = updates.recv().await.unwrap();
+            if update.status.is_final() {
+                assert_eq!(update.packet_id, id);
+                assert_eq!(update.status, DeliveryStatus::Failed(Routing_Error::MAX_RETRANSMIT));
+                break;
+            }
+        }
+        assert_eq!(sends.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,113 @@
+//! Typed events published by [`MessageProcessor`](super::MessageProcessor)
+//! for every packet it accepts.
+
+use super::{AdminMessage, MeshPacket, PortNum, Position, Routing, TelemetryData, User};
+use serde::{Deserialize, Serialize};
+
+/// Reception details shared by every event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct PacketMeta {
+    pub from: u32,
+    pub to: u32,
+    pub id: u32,
+    /// Local channel index
+    pub channel: u8,
+    /// Seconds since the epoch, as stamped by the receiving radio
+    pub rx_time: u32,
+    pub rx_snr: f32,
+    pub rx_rssi: i32,
+    pub hop_limit: u8,
+    pub hop_start: u8,
+    pub want_ack: bool,
+    pub want_response: bool,
+    pub request_id: u32,
+    pub reply_id: u32,
+    pub emoji: u32,
+}
+
+impl PacketMeta {
+    /// Hops the packet travelled, when the sender's firmware reports
+    /// `hop_start`
+    pub fn hops_away(&self) -> Option<u8> {
+        (self.hop_start > 0).then(|| self.hop_start.saturating_sub(self.hop_limit))
+    }
+
+    pub fn is_broadcast(&self) -> bool {
+        self.to == super::BROADCAST_ADDR
+    }
+}
+
+impl From<&MeshPacket> for PacketMeta {
+    fn from(packet: &MeshPacket) -> Self {
+        Self {
+            from: packet.from,
+            to: packet.to,
+            id: packet.id,
+            channel: packet.channel,
+            rx_time: packet.rx_time,
+            rx_snr: packet.rx_snr,
+            rx_rssi: packet.rx_rssi,
+            hop_limit: packet.hop_limit,
+            hop_start: packet.hop_start,
+            want_ack: packet.want_ack,
+            want_response: packet.want_response,
+            request_id: packet.request_id,
+            reply_id: packet.reply_id,
+            emoji: packet.emoji,
+        }
+    }
+}
+
+/// Something that happened on the mesh
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum MeshEvent {
+    TextReceived { meta: PacketMeta, text: String },
+    PositionUpdated { meta: PacketMeta, position: Position },
+    TelemetryReceived { meta: PacketMeta, telemetry: TelemetryData },
+    NodeUpdated { meta: PacketMeta, user: User },
+    /// Ack, NAK or route discovery result; `meta.request_id` names the
+    /// packet it answers
+    RoutingResult { meta: PacketMeta, routing: Routing },
+    AdminResponse { meta: PacketMeta, admin: AdminMessage },
+    /// Payload on a port without a built-in model
+    AppData { meta: PacketMeta, portnum: PortNum, payload: Vec<u8> },
+    /// Heard, but no configured channel could decrypt it
+    Undecryptable { meta: PacketMeta },
+}
+
+impl MeshEvent {
+    /// Build the event for a decoded packet; `None` for packets with no payload
+    pub fn from_packet(packet: &MeshPacket) -> Option<Self> {
+        use super::PayloadVariant;
+
+        let meta = PacketMeta::from(packet);
+        Some(match packet.payload.as_ref()? {
+            PayloadVariant::Text(text) => MeshEvent::TextReceived { meta, text: text.clone() },
+            PayloadVariant::Position(position) => MeshEvent::PositionUpdated { meta, position: position.clone() },
+            PayloadVariant::Telemetry(telemetry) => {
+                MeshEvent::TelemetryReceived { meta, telemetry: telemetry.clone() }
+            }
+            PayloadVariant::NodeInfo(user) => MeshEvent::NodeUpdated { meta, user: user.clone() },
+            PayloadVariant::Routing(routing) => MeshEvent::RoutingResult { meta, routing: routing.clone() },
+            PayloadVariant::Admin(admin) => MeshEvent::AdminResponse { meta, admin: admin.clone() },
+            PayloadVariant::App(portnum, payload) => {
+                MeshEvent::AppData { meta, portnum: *portnum, payload: payload.clone() }
+            }
+            PayloadVariant::Encrypted(_) => MeshEvent::Undecryptable { meta },
+        })
+    }
+
+    pub fn meta(&self) -> &PacketMeta {
+        match self {
+            MeshEvent::TextReceived { meta, .. }
+            | MeshEvent::PositionUpdated { meta, .. }
+            | MeshEvent::TelemetryReceived { meta, .. }
+            | MeshEvent::NodeUpdated { meta, .. }
+            | MeshEvent::RoutingResult { meta, .. }
+            | MeshEvent::AdminResponse { meta, .. }
+            | MeshEvent::AppData { meta, .. }
+            | MeshEvent::Undecryptable { meta } => meta,
+        }
+    }
+}
@@ -0,0 +1,502 @@
+//! Fragmentation and reassembly of payloads too large for one packet.
+//!
+//! Large payloads travel on [`PortNum::FRAGMENT_APP`] as numbered chunks,
+//! each behind a little-endian header:
+//!
+//! ```text
+//! chunk:   kind=0 (1) | message id (4) | index (2) | total (2) | portnum (2) | data
+//! request: kind=1 (1) | message id (4) | missing index (2) ...
+//! ```
+//!
+//! Chunks are reassembled per `(sender, message id)`. When a partial message
+//! has seen no new chunk for `idle_timeout`, the receiver sends the sender a
+//! request listing the chunks it is missing, and gives up after
+//! `max_requests` unanswered requests. A complete message is handed on as a
+//! packet of its original portnum, so a long text arrives as one message.
+//!
+//! Any node can open a partial message, so the receiver caps how many each
+//! sender may have open and how many bytes all of them may announce; chunks
+//! that would start a message past either limit are rejected.
+
+use super::{Data, MeshPacket, PayloadSize, PayloadVariant, PortHandler, PortNum, ProtocolError};
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+const KIND_CHUNK: u8 = 0;
+const KIND_REQUEST: u8 = 1;
+const CHUNK_HEADER_LEN: usize = 11;
+const REQUEST_HEADER_LEN: usize = 5;
+
+/// One frame on the fragment port
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FragmentFrame {
+    Chunk {
+        message_id: u32,
+        index: u16,
+        total: u16,
+        /// Port the reassembled payload belongs to
+        portnum: PortNum,
+        data: Vec<u8>,
+    },
+    /// Ask the sender to repeat the listed chunks
+    Request { message_id: u32, missing: Vec<u16> },
+}
+
+impl FragmentFrame {
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            FragmentFrame::Chunk { message_id, index, total, portnum, data } => {
+                let mut bytes = Vec::with_capacity(CHUNK_HEADER_LEN + data.len());
+                bytes.push(KIND_CHUNK);
+                bytes.extend_from_slice(&message_id.to_le_bytes());
+                bytes.extend_from_slice(&index.to_le_bytes());
+                bytes.extend_from_slice(&total.to_le_bytes());
+                bytes.extend_from_slice(&(portnum.0 as u16).to_le_bytes());
+                bytes.extend_from_slice(data);
+                bytes
+            }
+            FragmentFrame::Request { message_id, missing } => {
+                let mut bytes = Vec::with_capacity(REQUEST_HEADER_LEN + 2 * missing.len());
+                bytes.push(KIND_REQUEST);
+                bytes.extend_from_slice(&message_id.to_le_bytes());
+                for index in missing {
+                    bytes.extend_from_slice(&index.to_le_bytes());
+                }
+                bytes
+            }
+        }
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, ProtocolError> {
+        let u16_at = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+        match bytes.first() {
+            Some(&KIND_CHUNK) if bytes.len() >= CHUNK_HEADER_LEN => {
+                let (index, total) = (u16_at(5), u16_at(7));
+                if total == 0 || index >= total {
+                    return Err(ProtocolError::Decoding(format!("fragment {} of {}", index, total)));
+                }
+                Ok(FragmentFrame::Chunk {
+                    message_id: u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]),
+                    index,
+                    total,
+                    portnum: PortNum(u16_at(9) as u32),
+                    data: bytes[CHUNK_HEADER_LEN..].to_vec(),
+                })
+            }
+            Some(&KIND_REQUEST) if bytes.len() >= REQUEST_HEADER_LEN && (bytes.len() - REQUEST_HEADER_LEN).is_multiple_of(2) => {
+                Ok(FragmentFrame::Request {
+                    message_id: u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]),
+                    missing: (REQUEST_HEADER_LEN..bytes.len()).step_by(2).map(u16_at).collect(),
+                })
+            }
+            _ => Err(ProtocolError::Decoding("malformed fragment frame".to_string())),
+        }
+    }
+}
+
+/// Timing and limits of reassembly and re-requests
+#[derive(Debug, Clone, Copy)]
+pub struct FragmentConfig {
+    /// How long a partial message may go without a new chunk before the
+    /// missing ones are requested
+    pub idle_timeout: Duration,
+    /// Requests sent for one message before it is abandoned
+    pub max_requests: u32,
+    /// How long sent chunks are kept to answer requests, and completed
+    /// messages remembered to ignore late duplicates
+    pub retain: Duration,
+    /// Partial messages one sender may have open at a time
+    pub max_partials_per_sender: usize,
+    /// Bytes all open partial messages together may announce (`total`
+    /// chunks of full size each); also the largest message accepted
+    pub max_buffered_bytes: usize,
+}
+
+impl Default for FragmentConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(20),
+            max_requests: 3,
+            retain: Duration::from_secs(600),
+            max_partials_per_sender: 4,
+            max_buffered_bytes: 256 * 1024,
+        }
+    }
+}
+
+struct Partial {
+    portnum: PortNum,
+    total: u16,
+    chunks: BTreeMap<u16, Vec<u8>>,
+    /// Latest chunk packet; the reassembled packet takes its header
+    last_packet: MeshPacket,
+    last_activity: Instant,
+    requests: u32,
+}
+
+struct Sent {
+    to: u32,
+    packets: Vec<MeshPacket>,
+    sent_at: Instant,
+}
+
+#[derive(Default)]
+struct State {
+    incoming: HashMap<(u32, u32), Partial>,
+    completed: HashMap<(u32, u32), Instant>,
+    sent: HashMap<u32, Sent>,
+}
+
+/// Splits outgoing payloads into chunks and reassembles incoming ones.
+/// Register it as the [`PortHandler`] for `FRAGMENT_APP` and call
+/// [`tick`](Self::tick) periodically.
+///
+/// Requests and repeated chunks go to `outbound` with `from` left at 0 for
+/// the sending device to fill in; reassembled packets go to `completed`.
+pub struct FragmentLayer {
+    config: FragmentConfig,
+    state: Arc<Mutex<State>>,
+    outbound: mpsc::UnboundedSender<MeshPacket>,
+    completed: mpsc::UnboundedSender<MeshPacket>,
+}
+
+impl FragmentLayer {
+    pub fn new(outbound: mpsc::UnboundedSender<MeshPacket>, completed: mpsc::UnboundedSender<MeshPacket>) -> Self {
+        Self {
+            config: FragmentConfig::default(),
+            state: Arc::new(Mutex::new(State::default())),
+            outbound,
+            completed,
+        }
+    }
+
+    pub fn with_config(mut self, config: FragmentConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Payload bytes carried by each chunk
+    pub fn chunk_size() -> usize {
+        let empty = MeshPacket {
+            payload: Some(PayloadVariant::App(PortNum::FRAGMENT_APP, Vec::new())),
+            ..Default::default()
+        };
+        PayloadSize::of(&empty).map(|size| size.max_payload_bytes).unwrap_or(0) - CHUNK_HEADER_LEN
+    }
+
+    /// Split `payload` into chunk packets ready to send, and keep them to
+    /// answer requests for missing chunks. Returns the message id and the
+    /// packets in order.
+    pub fn fragment(
+        &self,
+        from: u32,
+        to: u32,
+        channel: u8,
+        portnum: PortNum,
+        payload: &[u8],
+    ) -> Result<(u32, Vec<MeshPacket>), ProtocolError> {
+        let chunk_size = Self::chunk_size();
+        let mut pieces: Vec<&[u8]> = payload.chunks(chunk_size).collect();
+        if pieces.is_empty() {
+            pieces.push(&[]);
+        }
+        let total = pieces.len();
+        if total > u16::MAX as usize {
+            return Err(ProtocolError::PayloadTooLarge {
+                size: payload.len(),
+                max: chunk_size * u16::MAX as usize,
+            });
+        }
+
+        let message_id: u32 = rand::random();
+        let packets: Vec<MeshPacket> = pieces.into_iter().enumerate()
+            .map(|(index, data)| {
+                let frame = FragmentFrame::Chunk {
+                    message_id,
+                    index: index as u16,
+                    total: total as u16,
+                    portnum,
+                    data: data.to_vec(),
+                };
+                fragment_packet(from, to, channel, &frame)
+            })
+            .collect();
+
+        self.state.lock().unwrap().sent.insert(message_id, Sent {
+            to,
+            packets: packets.clone(),
+            sent_at: Instant::now(),
+        });
+        Ok((message_id, packets))
+    }
+
+    /// Handle a frame received on the fragment port
+    pub fn handle_frame(&self, packet: &MeshPacket, payload: &[u8]) -> Result<(), ProtocolError> {
+        match FragmentFrame::decode(payload)? {
+            FragmentFrame::Chunk { message_id, index, total, portnum, data } => {
+                self.receive_chunk(packet, message_id, index, total, portnum, data)
+            }
+            FragmentFrame::Request { message_id, missing } => {
+                self.resend(packet.from, message_id, &missing);
+                Ok(())
+            }
+        }
+    }
+
+    fn receive_chunk(
+        &self,
+        packet: &MeshPacket,
+        message_id: u32,
+        index: u16,
+        total: u16,
+        portnum: PortNum,
+        data: Vec<u8>,
+    ) -> Result<(), ProtocolError> {
+        let key = (packet.from, message_id);
+        let mut state = self.state.lock().unwrap();
+        if state.completed.contains_key(&key) {
+            return Ok(());
+        }
+
+        let chunk_size = Self::chunk_size();
+        if data.len() > chunk_size {
+            return Err(ProtocolError::PayloadTooLarge { size: data.len(), max: chunk_size });
+        }
+        if !state.incoming.contains_key(&key) {
+            let open = state.incoming.keys().filter(|(from, _)| *from == packet.from).count();
+            if open >= self.config.max_partials_per_sender {
+                return Err(ProtocolError::Decoding(format!(
+                    "{:08x} already has {} fragmented messages in flight", packet.from, open
+                )));
+            }
+            let announced = total as usize * chunk_size;
+            let buffered: usize = state.incoming.values().map(|partial| partial.total as usize * chunk_size).sum();
+            if buffered + announced > self.config.max_buffered_bytes {
+                return Err(ProtocolError::PayloadTooLarge {
+                    size: announced,
+                    max: self.config.max_buffered_bytes.saturating_sub(buffered),
+                });
+            }
+        }
+
+        let partial = state.incoming.entry(key).or_insert_with(|| Partial {
+            portnum,
+            total,
+            chunks: BTreeMap::new(),
+            last_packet: packet.clone(),
+            last_activity: Instant::now(),
+            requests: 0,
+        });
+        if partial.total != total || partial.portnum != portnum {
+            return Err(ProtocolError::Decoding(format!("fragment {:08x} changed shape", message_id)));
+        }
+        partial.chunks.insert(index, data);
+        partial.last_packet = packet.clone();
+        partial.last_activity = Instant::now();
+
+        if partial.chunks.len() < total as usize {
+            return Ok(());
+        }
+
+        let partial = state.incoming.remove(&key).expect("partial message present");
+        state.completed.insert(key, Instant::now());
+        drop(state);
+
+        let mut reassembled = MeshPacket { id: message_id, ..partial.last_packet };
+        reassembled.set_data(&Data {
+            portnum: partial.portnum,
+            payload: partial.chunks.into_values().flatten().collect(),
+            ..Default::default()
+        })?;
+        let _ = self.completed.send(reassembled);
+        Ok(())
+    }
+
+    fn resend(&self, requester: u32, message_id: u32, missing: &[u16]) {
+        let mut state = self.state.lock().unwrap();
+        let Some(sent) = state.sent.get_mut(&message_id) else {
+            log::debug!("Request for unknown fragmented message {:08x}", message_id);
+            return;
+        };
+        if sent.to != requester && sent.to != super::BROADCAST_ADDR {
+            return;
+        }
+
+        sent.sent_at = Instant::now();
+        for &index in missing {
+            if let Some(packet) = sent.packets.get(index as usize) {
+                // Same id as the first send, like any retransmission; the
+                // receiver keys chunks by index, so a copy is harmless
+                let _ = self.outbound.send(packet.clone());
+            }
+        }
+    }
+
+    /// Request missing chunks of stalled messages, abandon those out of
+    /// requests, and forget sent and completed messages past `retain`
+    pub fn tick(&self) {
+        let now = Instant::now();
+        let config = self.config;
+        let max_missing = (Self::chunk_size() + CHUNK_HEADER_LEN - REQUEST_HEADER_LEN) / 2;
+        let mut state = self.state.lock().unwrap();
+
+        state.incoming.retain(|&(from, message_id), partial| {
+            if now.duration_since(partial.last_activity) < config.idle_timeout {
+                return true;
+            }
+            if partial.requests >= config.max_requests {
+                log::warn!(
+                    "Giving up on message {:08x} from {:08x}: {} of {} chunks",
+                    message_id, from, partial.chunks.len(), partial.total
+                );
+                return false;
+            }
+
+            let missing: Vec<u16> = (0..partial.total)
+                .filter(|index| !partial.chunks.contains_key(index))
+                .take(max_missing)
+                .collect();
+            let request = FragmentFrame::Request { message_id, missing };
+            let _ = self.outbound.send(fragment_packet(0, from, partial.last_packet.channel, &request));
+            partial.requests += 1;
+            partial.last_activity = now;
+            true
+        });
+        state.completed.retain(|_, at| now.duration_since(*at) < config.retain);
+        state.sent.retain(|_, sent| now.duration_since(sent.sent_at) < config.retain);
+    }
+
+    /// Messages currently being reassembled
+    pub fn pending(&self) -> usize {
+        self.state.lock().unwrap().incoming.len()
+    }
+}
+
+fn fragment_packet(from: u32, to: u32, channel: u8, frame: &FragmentFrame) -> MeshPacket {
+    MeshPacket {
+        from,
+        to,
+        channel,
+        payload: Some(PayloadVariant::App(PortNum::FRAGMENT_APP, frame.encode())),
+        ..Default::default()
+    }
+}
+
+#[async_trait]
+impl PortHandler for FragmentLayer {
+    async fn handle(&self, packet: &MeshPacket, data: &Data) -> Result<(), ProtocolError> {
+        self.handle_frame(packet, &data.payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload_of(packet: &MeshPacket) -> Vec<u8> {
+        match &packet.payload {
+            Some(PayloadVariant::App(PortNum::FRAGMENT_APP, bytes)) => bytes.clone(),
+            other => panic!("not a fragment: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_frame_round_trip() {
+        let chunk = FragmentFrame::Chunk {
+            message_id: 7,
+            index: 2,
+            total: 3,
+            portnum: PortNum::TEXT_MESSAGE_APP,
+            data: b"abc".to_vec(),
+        };
+        assert_eq!(FragmentFrame::decode(&chunk.encode()).unwrap(), chunk);
+        let request = FragmentFrame::Request { message_id: 7, missing: vec![0, 5] };
+        assert_eq!(FragmentFrame::decode(&request.encode()).unwrap(), request);
+        assert!(FragmentFrame::decode(&[KIND_CHUNK, 0, 0, 0, 0, 3, 0, 3, 0, 1, 0]).is_err());
+    }
+
+    #[test]
+    fn test_lost_chunk_is_requested_and_reassembled() {
+        let (sender_out, mut sender_rx) = mpsc::unbounded_channel();
+        let (receiver_out, mut receiver_rx) = mpsc::unbounded_channel();
+        let (done_tx, mut done_rx) = mpsc::unbounded_channel();
+        let config = FragmentConfig { idle_timeout: Duration::ZERO, ..Default::default() };
+        let sender = FragmentLayer::new(sender_out, mpsc::unbounded_channel().0);
+        let receiver = FragmentLayer::new(receiver_out, done_tx).with_config(config);
+
+        let text = "long message ".repeat(60);
+        let (message_id, packets) = sender.fragment(0xa, 0xb, 1, PortNum::TEXT_MESSAGE_APP, text.as_bytes()).unwrap();
+        assert_eq!(packets.len(), text.len().div_ceil(FragmentLayer::chunk_size()));
+        for packet in packets.iter().enumerate().filter(|&(index, _)| index != 1).map(|(_, packet)| packet) {
+            receiver.handle_frame(packet, &payload_of(packet)).unwrap();
+        }
+        assert_eq!(receiver.pending(), 1);
+
+        receiver.tick();
+        let request = receiver_rx.try_recv().unwrap();
+        assert_eq!((request.to, request.channel), (0xa, 1));
+        assert_eq!(
+            FragmentFrame::decode(&payload_of(&request)).unwrap(),
+            FragmentFrame::Request { message_id, missing: vec![1] }
+        );
+
+        let request_payload = payload_of(&request);
+        sender.handle_frame(&MeshPacket { from: 0xb, ..request }, &request_payload).unwrap();
+        let resent = sender_rx.try_recv().unwrap();
+        assert_eq!(resent.id, packets[1].id);
+        receiver.handle_frame(&resent, &payload_of(&resent)).unwrap();
+
+        let message = done_rx.try_recv().unwrap();
+        assert_eq!((message.id, message.from, message.to), (message_id, 0xa, 0xb));
+        assert!(matches!(message.payload, Some(PayloadVariant::Text(ref t)) if *t == text));
+        assert_eq!(receiver.pending(), 0);
+
+        // A late duplicate does not start the message over
+        receiver.handle_frame(&packets[0], &payload_of(&packets[0])).unwrap();
+        assert_eq!(receiver.pending(), 0);
+    }
+
+    #[test]
+    fn test_gives_up_after_max_requests() {
+        let (out, mut out_rx) = mpsc::unbounded_channel();
+        let config = FragmentConfig { idle_timeout: Duration::ZERO, max_requests: 2, ..Default::default() };
+        let receiver = FragmentLayer::new(out, mpsc::unbounded_channel().0).with_config(config);
+        let chunk = FragmentFrame::Chunk { message_id: 1, index: 0, total: 2, portnum: PortNum(301), data: vec![1] };
+        let packet = fragment_packet(0xa, 0xb, 0, &chunk);
+        receiver.handle_frame(&packet, &chunk.encode()).unwrap();
+
+        for _ in 0..3 {
+            receiver.tick();
+        }
+        assert_eq!(receiver.pending(), 0);
+        assert!(out_rx.try_recv().is_ok() && out_rx.try_recv().is_ok());
+        assert!(out_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_partials_are_capped_per_sender_and_in_bytes() {
+        let config = FragmentConfig {
+            max_partials_per_sender: 2,
+            max_buffered_bytes: 10 * FragmentLayer::chunk_size(),
+            ..Default::default()
+        };
+        let receiver = FragmentLayer::new(mpsc::unbounded_channel().0, mpsc::unbounded_channel().0).with_config(config);
+        let open = |from: u32, message_id: u32, total: u16| {
+            let chunk = FragmentFrame::Chunk { message_id, index: 0, total, portnum: PortNum(301), data: vec![1] };
+            receiver.handle_frame(&fragment_packet(from, 0xb, 0, &chunk), &chunk.encode())
+        };
+
+        assert!(open(0xa, 1, 2).is_ok());
+        assert!(open(0xa, 2, 2).is_ok());
+        assert!(open(0xa, 3, 2).is_err());
+        // More chunks of an open message are still accepted
+        assert!(open(0xa, 1, 2).is_ok());
+
+        assert!(matches!(open(0xc, 4, 7), Err(ProtocolError::PayloadTooLarge { .. })));
+        assert!(open(0xc, 4, 6).is_ok());
+        assert_eq!(receiver.pending(), 3);
+    }
+}
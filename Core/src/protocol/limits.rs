@@ -0,0 +1,141 @@
+//! Over-the-air size limits for outgoing packets.
+//!
+//! A LoRa frame holds 256 bytes, 16 of which are the Meshtastic header. The
+//! rest carries the encoded `Data` message, encrypted with the channel key.
+//! AES-CTR adds no bytes, so the limit applies to the encoded `Data`
+//! (portnum, payload and any reply/emoji fields) as a whole. The firmware
+//! drops anything larger without telling the sender.
+
+use super::{proto, MeshPacket, ProtocolError};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+
+/// Bytes available to the encrypted `Data` message in one LoRa frame
+pub const MAX_LORA_PAYLOAD_LEN: usize = 240;
+
+/// Firmware limit on `Data.payload` itself (`Constants.DATA_PAYLOAD_LEN`)
+pub const DATA_PAYLOAD_LEN: usize = 233;
+
+/// Size of a packet's payload against what fits in one frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PayloadSize {
+    /// Application payload bytes, e.g. the UTF-8 text of a message
+    pub payload_bytes: usize,
+    /// Encoded (and encrypted) `Data` message
+    pub encoded_bytes: usize,
+    /// Largest payload that fits alongside this packet's other `Data` fields
+    pub max_payload_bytes: usize,
+}
+
+impl PayloadSize {
+    /// Measure the payload of a decoded packet
+    pub fn of(packet: &MeshPacket) -> Result<Self, ProtocolError> {
+        let mut data = proto::Data::from(&packet.data()?.ok_or(ProtocolError::InvalidFormat)?);
+        let payload_bytes = data.payload.len();
+        let encoded_bytes = data.encoded_len();
+
+        data.payload.clear();
+        let overhead = data.encoded_len();
+        Ok(Self {
+            payload_bytes,
+            encoded_bytes,
+            max_payload_bytes: max_payload_len(overhead),
+        })
+    }
+
+    pub fn fits(&self) -> bool {
+        self.payload_bytes <= self.max_payload_bytes
+    }
+
+    /// Payload bytes still free; 0 when the payload is already too large
+    pub fn available(&self) -> usize {
+        self.max_payload_bytes.saturating_sub(self.payload_bytes)
+    }
+
+    /// `Ok` if the payload fits, otherwise `ProtocolError::PayloadTooLarge`
+    pub fn check(&self) -> Result<(), ProtocolError> {
+        if self.fits() {
+            Ok(())
+        } else {
+            Err(ProtocolError::PayloadTooLarge {
+                size: self.payload_bytes,
+                max: self.max_payload_bytes,
+            })
+        }
+    }
+}
+
+/// Largest payload that fits next to `overhead` bytes of other `Data` fields.
+/// The payload field costs a tag byte and a varint length on top of its
+/// contents.
+fn max_payload_len(overhead: usize) -> usize {
+    let room = MAX_LORA_PAYLOAD_LEN.saturating_sub(overhead + 1);
+    let mut len = room.min(DATA_PAYLOAD_LEN);
+    while len > 0 && prost::encoding::encoded_len_varint(len as u64) + len > room {
+        len -= 1;
+    }
+    len
+}
+
+/// Split `text` into pieces of at most `max_bytes` bytes without cutting a
+/// UTF-8 character in half. Characters are kept whole, though a multi-code-
+/// point emoji may still land across two pieces.
+pub fn split_utf8(text: &str, max_bytes: usize) -> Vec<&str> {
+    let max_bytes = max_bytes.max(4); // room for any single character
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while rest.len() > max_bytes {
+        let mut end = max_bytes;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (piece, tail) = rest.split_at(end);
+        pieces.push(piece);
+        rest = tail;
+    }
+    if !rest.is_empty() || pieces.is_empty() {
+        pieces.push(rest);
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_limit_accounts_for_data_fields() {
+        let size = PayloadSize::of(&MeshPacket::new_text_message(1, 2, "hi")).unwrap();
+        assert_eq!(size.payload_bytes, 2);
+        // portnum (2) + payload tag and length (2)
+        assert_eq!(size.encoded_bytes, 6);
+        assert_eq!(size.max_payload_bytes, DATA_PAYLOAD_LEN);
+
+        let reply = MeshPacket { reply_id: 0xdeadbeef, emoji: 1, ..MeshPacket::new_text_message(1, 2, "👍") };
+        let size = PayloadSize::of(&reply).unwrap();
+        assert!(size.max_payload_bytes < DATA_PAYLOAD_LEN);
+
+        let full = "x".repeat(size.max_payload_bytes);
+        let packet = MeshPacket { reply_id: 0xdeadbeef, emoji: 1, ..MeshPacket::new_text_message(1, 2, &full) };
+        let size = PayloadSize::of(&packet).unwrap();
+        assert!(size.fits());
+        assert_eq!(size.encoded_bytes, MAX_LORA_PAYLOAD_LEN);
+
+        let long = MeshPacket::new_text_message(1, 2, &"x".repeat(300));
+        assert!(matches!(
+            PayloadSize::of(&long).unwrap().check(),
+            Err(ProtocolError::PayloadTooLarge { size: 300, max: DATA_PAYLOAD_LEN })
+        ));
+    }
+
+    #[test]
+    fn test_split_keeps_characters_whole() {
+        let text = "añb€c😀d";
+        for max in 1..=text.len() {
+            let pieces = split_utf8(text, max);
+            assert_eq!(pieces.concat(), text);
+            assert!(pieces.iter().all(|piece| piece.len() <= max.max(4)));
+        }
+        assert_eq!(split_utf8("", 10), [""]);
+    }
+}
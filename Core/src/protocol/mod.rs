@@ -1,9 +1,47 @@
+#![allow(non_camel_case_types, non_snake_case)]
+
+pub mod channels;
+pub mod codec;
+pub mod convert;
+pub mod crypto;
+pub mod dedup;
+pub mod delivery;
+pub mod events;
+pub mod fragment;
+pub mod limits;
+pub mod nodedb;
+pub mod ports;
+pub mod profile;
+pub mod proto;
+pub mod rpc;
+pub mod session;
+pub mod traceroute;
+pub mod transfer;
+
+pub use channels::{ChannelEntry, ChannelTable};
+pub use codec::{FrameCodec, FrameError};
+pub use crypto::{decrypt_packet, encrypt_packet, ChannelKey};
+pub use dedup::DedupStore;
+pub use delivery::{DeliveryStatus, DeliveryTracker, DeliveryUpdate, RetryPolicy};
+pub use events::{MeshEvent, PacketMeta};
+pub use fragment::{FragmentConfig, FragmentLayer};
+pub use limits::{split_utf8, PayloadSize};
+pub use nodedb::NodeDb;
+pub use ports::{PortHandler, PortNum};
+pub use profile::{DeviceProfile, FieldChange, ProfileError, ProfilePlan, RestoreReport, SectionChange, PROFILE_VERSION};
+pub use rpc::{PendingRequest, PendingRequests, RequestError};
+pub use session::{ConfigSession, EditIssue, EditOperation, OperationReport, SessionError, SessionReport};
+pub use traceroute::{RouteHop, TraceRoute};
+pub use transfer::{TransferConfig, TransferDirection, TransferError, TransferInfo, TransferService, TransferStatus};
+
+use crate::storage::{ConversationId, SqliteStorage, Storage};
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
-use uuid::Uuid;
 use std::collections::HashMap;
-use tokio::sync::{mpsc, RwLock};
+use tokio::sync::{broadcast, mpsc, RwLock};
 use std::sync::Arc;
+use prost::Message;
+use tokio_util::codec::Decoder;
 
 #[derive(Debug, thiserror::Error)]
 pub enum ProtocolError {
@@ -19,8 +57,16 @@ pub enum ProtocolError {
     Decoding(String),
     #[error("Protobuf error: {0}")]
     Protobuf(String),
+    #[error("Framing error: {0}")]
+    Frame(#[from] FrameError),
+    #[error("Crypto error: {0}")]
+    Crypto(String),
     #[error("Invalid node ID")]
     InvalidNodeId,
+    #[error("Invalid channel URL: {0}")]
+    InvalidChannelUrl(String),
+    #[error("Payload of {size} bytes exceeds the {max} bytes that fit in one packet")]
+    PayloadTooLarge { size: usize, max: usize },
 }
 
 /// Represents a message in the mesh network
@@ -35,6 +81,12 @@ pub struct MeshMessage {
     pub hop_limit: Option<u8>,
     pub channel: Option<u8>,
     pub message_type: MessageType,
+    /// Packet id of the message this one replies or reacts to
+    #[serde(default)]
+    pub reply_id: Option<u32>,
+    /// `text` is an emoji tapback on `reply_id` rather than a message
+    #[serde(default)]
+    pub is_reaction: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -60,6 +112,25 @@ impl MeshMessage {
             hop_limit: Some(3),
             channel: Some(0),
             message_type: MessageType::Text,
+            reply_id: None,
+            is_reaction: false,
+        }
+    }
+
+    /// Text message carried by `packet`
+    pub fn from_packet(packet: &MeshPacket, text: &str) -> Self {
+        Self {
+            from: packet.from.to_string(),
+            to: if packet.is_broadcast() { "broadcast".to_string() } else { packet.to.to_string() },
+            text: text.to_string(),
+            timestamp: Utc::now(),
+            want_ack: Some(packet.want_ack),
+            packet_id: Some(packet.id),
+            hop_limit: Some(packet.hop_limit),
+            channel: Some(packet.channel),
+            message_type: MessageType::Text,
+            reply_id: (packet.reply_id != 0).then_some(packet.reply_id),
+            is_reaction: packet.emoji != 0,
         }
     }
 
@@ -77,12 +148,23 @@ impl MeshMessage {
 }
 
 /// Represents a node in the mesh network
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct NodeInfo {
     pub id: String,
     pub name: String,
     pub short_name: String,
     pub is_online: bool,
+    pub num: u32,
+    pub hw_model: HardwareModel,
+    pub role: Role,
+    pub battery_level: Option<u32>,
+    pub voltage: Option<f32>,
+    /// SNR of the last packet heard from the node
+    pub snr: f32,
+    pub hops_away: Option<u32>,
+    /// Seconds since the epoch; 0 if never heard
+    pub last_heard: u32,
+    pub position: Option<Position>,
 }
 
 impl NodeInfo {
@@ -91,7 +173,7 @@ impl NodeInfo {
             id,
             name,
             short_name,
-            is_online: false,
+            ..Default::default()
         }
     }
 
@@ -101,6 +183,8 @@ impl NodeInfo {
             name: "All Nodes".to_string(),
             short_name: "ALL".to_string(),
             is_online: true,
+            num: BROADCAST_ADDR,
+            ..Default::default()
         }
     }
 }
@@ -119,6 +203,36 @@ pub struct MeshPacket {
     pub rx_snr: f32,
     pub rx_rssi: i32,
     pub channel: u8,
+    pub hop_start: u8,
+    /// Sender expects an application-level reply
+    pub want_response: bool,
+    /// Ultimate destination, used by store-and-forward
+    pub dest: u32,
+    /// Original source, used by store-and-forward
+    pub source: u32,
+    /// Id of the request this packet answers
+    pub request_id: u32,
+    /// Id of the message this one replies to
+    pub reply_id: u32,
+    /// Non-zero when the payload is an emoji reaction
+    pub emoji: u32,
+}
+
+/// Decoded application payload as carried on the wire: the proto `Data`
+/// message. `MeshPacket` holds the same header fields next to a typed
+/// payload; see [`MeshPacket::data`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct Data {
+    pub portnum: PortNum,
+    pub payload: Vec<u8>,
+    pub want_response: bool,
+    /// Ultimate destination, used by store-and-forward
+    pub dest: u32,
+    /// Original source, used by store-and-forward
+    pub source: u32,
+    pub request_id: u32,
+    pub reply_id: u32,
+    pub emoji: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -129,18 +243,28 @@ pub enum PayloadVariant {
     Telemetry(TelemetryData),
     Routing(Routing),
     Admin(AdminMessage),
-    Raw(Vec<u8>),
+    /// Payload on a port without a built-in model, including private apps
+    App(PortNum, Vec<u8>),
+    /// Channel-encrypted payload that has not been decrypted yet
+    Encrypted(Vec<u8>),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[repr(i32)]
 pub enum MeshPacket_Priority {
+    UNSET = 0,
     #[default]
     DEFAULT = 64,
     MIN = 1,
     BACKGROUND = 10,
     RELIABLE = 70,
+    RESPONSE = 80,
+    HIGH = 100,
+    ALERT = 110,
     ACK = 120,
     MAX = 127,
+    /// A value this build does not know, kept as-is so it round-trips
+    Unknown(i32),
 }
 
 /// User information for node identification
@@ -155,20 +279,92 @@ pub struct User {
     pub role: Role,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[repr(i32)]
 pub enum HardwareModel {
     #[default]
     UNSET = 0,
     TLORA_V2 = 1,
     TLORA_V1 = 2,
-    TBEAM = 3,
-    HELTEC_V2_0 = 4,
-    TBEAM_V0_7 = 5,
-    T_ECHO = 6,
-    TLORA_V2_1_1P6 = 7,
+    TLORA_V2_1_1P6 = 3,
+    TBEAM = 4,
+    HELTEC_V2_0 = 5,
+    TBEAM_V0_7 = 6,
+    T_ECHO = 7,
+    TLORA_V1_1P3 = 8,
+    RAK4631 = 9,
+    HELTEC_V2_1 = 10,
+    HELTEC_V1 = 11,
+    LILYGO_TBEAM_S3_CORE = 12,
+    RAK11200 = 13,
+    NANO_G1 = 14,
+    TLORA_V2_1_1P8 = 15,
+    TLORA_T3_S3 = 16,
+    NANO_G1_EXPLORER = 17,
+    NANO_G2_ULTRA = 18,
+    LORA_TYPE = 19,
+    WIPHONE = 20,
+    WIO_WM1110 = 21,
+    RAK2560 = 22,
+    HELTEC_HRU_3601 = 23,
+    STATION_G1 = 25,
+    RAK11310 = 26,
+    SENSELORA_RP2040 = 27,
+    SENSELORA_S3 = 28,
+    CANARYONE = 29,
+    RP2040_LORA = 30,
+    STATION_G2 = 31,
+    LORA_RELAY_V1 = 32,
+    NRF52840DK = 33,
+    PPR = 34,
+    GENIEBLOCKS = 35,
+    NRF52_UNKNOWN = 36,
+    PORTDUINO = 37,
+    ANDROID_SIM = 38,
+    DIY_V1 = 39,
+    NRF52840_PCA10059 = 40,
+    DR_DEV = 41,
+    M5STACK = 42,
+    HELTEC_V3 = 43,
+    HELTEC_WSL_V3 = 44,
+    BETAFPV_2400_TX = 45,
+    BETAFPV_900_NANO_TX = 46,
+    RPI_PICO = 47,
+    HELTEC_WIRELESS_TRACKER = 48,
+    HELTEC_WIRELESS_PAPER = 49,
+    T_DECK = 50,
+    T_WATCH_S3 = 51,
+    PICOMPUTER_S3 = 52,
+    HELTEC_HT62 = 53,
+    EBYTE_ESP32_S3 = 54,
+    ESP32_S3_PICO = 55,
+    CHATTER_2 = 56,
+    HELTEC_WIRELESS_PAPER_V1_0 = 57,
+    HELTEC_WIRELESS_TRACKER_V1_0 = 58,
+    UNPHONE = 59,
+    TD_LORAC = 60,
+    CDEBYTE_EORA_S3 = 61,
+    TWC_MESH_V4 = 62,
+    NRF52_PROMICRO_DIY = 63,
+    RADIOMASTER_900_BANDIT_NANO = 64,
+    HELTEC_CAPSULE_SENSOR_V3 = 65,
+    HELTEC_VISION_MASTER_T190 = 66,
+    HELTEC_VISION_MASTER_E213 = 67,
+    HELTEC_VISION_MASTER_E290 = 68,
+    HELTEC_MESH_NODE_T114 = 69,
+    SENSECAP_INDICATOR = 70,
+    TRACKER_T1000_E = 71,
+    RAK3172 = 72,
+    WIO_E5 = 73,
+    RADIOMASTER_900_BANDIT = 74,
+    ME25LS01_4Y10TD = 75,
+    PRIVATE_HW = 255,
+    /// A value this build does not know, kept as-is so it round-trips
+    Unknown(i32),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[repr(i32)]
 pub enum Role {
     #[default]
     CLIENT = 0,
@@ -176,6 +372,15 @@ pub enum Role {
     ROUTER = 2,
     ROUTER_CLIENT = 3,
     REPEATER = 4,
+    TRACKER = 5,
+    SENSOR = 6,
+    TAK = 7,
+    CLIENT_HIDDEN = 8,
+    LOST_AND_FOUND = 9,
+    TAK_TRACKER = 10,
+    ROUTER_LATE = 11,
+    /// A value this build does not know, kept as-is so it round-trips
+    Unknown(i32),
 }
 
 /// Position information
@@ -184,12 +389,25 @@ pub struct Position {
     pub latitude_i: i32,
     pub longitude_i: i32,
     pub altitude: i32,
-    pub battery_level: u32,
     pub time: u32,
+    pub location_source: i32,
+    pub altitude_source: i32,
+    pub timestamp: u32,
+    pub timestamp_millis_adjust: i32,
+    pub altitude_hae: i32,
+    pub altitude_geoidal_separation: i32,
     pub PDOP: u32,
+    pub HDOP: u32,
+    pub VDOP: u32,
+    pub gps_accuracy: u32,
     pub ground_speed: u32,
     pub ground_track: u32,
+    pub fix_quality: u32,
+    pub fix_type: u32,
     pub sats_in_view: u32,
+    pub sensor_id: u32,
+    pub next_update: u32,
+    pub seq_number: u32,
     pub precision_bits: u32,
 }
 
@@ -253,9 +471,12 @@ pub enum RoutingVariant {
 pub struct RouteDiscovery {
     pub route: Vec<u32>,
     pub snr_towards: Vec<i32>,
+    pub route_back: Vec<u32>,
+    pub snr_back: Vec<i32>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[repr(i32)]
 pub enum Routing_Error {
     #[default]
     NONE = 0,
@@ -270,6 +491,8 @@ pub enum Routing_Error {
     DUTY_CYCLE_LIMIT = 9,
     BAD_REQUEST = 32,
     NOT_AUTHORIZED = 33,
+    /// A value this build does not know, kept as-is so it round-trips
+    Unknown(i32),
 }
 
 /// Administrative messages for device configuration
@@ -284,11 +507,17 @@ pub mod admin_message {
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub enum Variant {
         GetChannel(GetChannelRequest),
+        GetChannelResponse(Channel),
         GetOwner(GetOwnerRequest),
+        GetOwnerResponse(User),
         GetConfig(GetConfigRequest),
+        GetConfigResponse(Config),
         GetModuleConfig(GetModuleConfigRequest),
+        GetModuleConfigResponse(ModuleConfig),
         GetCannedMessageModuleMessages(GetCannedMessageModuleMessagesRequest),
+        GetCannedMessageModuleMessagesResponse(String),
         GetDeviceMetadata(GetDeviceMetadataRequest),
+        GetDeviceMetadataResponse(DeviceMetadata),
         SetOwner(User),
         SetChannel(Channel),
         SetConfig(Config),
@@ -340,12 +569,23 @@ pub struct Channel {
     pub role: Channel_Role,
 }
 
+/// Channels and LoRa settings shared through a channel URL. The first
+/// settings entry is the primary channel.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ChannelSet {
+    pub settings: Vec<ChannelSettings>,
+    pub lora_config: Option<RadioConfig>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[repr(i32)]
 pub enum Channel_Role {
     #[default]
     DISABLED = 0,
     PRIMARY = 1,
     SECONDARY = 2,
+    /// A value this build does not know, kept as-is so it round-trips
+    Unknown(i32),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -360,17 +600,630 @@ pub struct ChannelSettings {
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ModuleSettings {
-    // Placeholder for module-specific settings
+    pub position_precision: u32,
+    pub is_client_muted: bool,
 }
 
+/// Device configuration record: one section of the radio's config, as sent
+/// in the connect-time dump and by `GetConfig`/`SetConfig`
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Config {
-    // Configuration payload variant would go here
+    pub variant: Option<config::Variant>,
+}
+
+pub mod config {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub enum Variant {
+        Device(DeviceConfig),
+        Position(PositionConfig),
+        Power(PowerConfig),
+        Network(NetworkConfig),
+        Display(DisplayConfig),
+        Lora(RadioConfig),
+        Bluetooth(BluetoothConfig),
+    }
+}
+
+impl Config {
+    pub fn new(variant: config::Variant) -> Self {
+        Self { variant: Some(variant) }
+    }
+
+    /// Section this record holds; `None` for an empty or unknown section
+    pub fn config_type(&self) -> Option<ConfigType> {
+        self.variant.as_ref().map(|variant| match variant {
+            config::Variant::Device(_) => ConfigType::DEVICE_CONFIG,
+            config::Variant::Position(_) => ConfigType::POSITION_CONFIG,
+            config::Variant::Power(_) => ConfigType::POWER_CONFIG,
+            config::Variant::Network(_) => ConfigType::NETWORK_CONFIG,
+            config::Variant::Display(_) => ConfigType::DISPLAY_CONFIG,
+            config::Variant::Lora(_) => ConfigType::LORA_CONFIG,
+            config::Variant::Bluetooth(_) => ConfigType::BLUETOOTH_CONFIG,
+        })
+    }
+}
+
+/// Config section named in a `GetConfig` request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+#[repr(i32)]
+pub enum ConfigType {
+    #[default]
+    DEVICE_CONFIG = 0,
+    POSITION_CONFIG = 1,
+    POWER_CONFIG = 2,
+    NETWORK_CONFIG = 3,
+    DISPLAY_CONFIG = 4,
+    LORA_CONFIG = 5,
+    BLUETOOTH_CONFIG = 6,
+    /// A value this build does not know, kept as-is so it round-trips
+    Unknown(i32),
+}
+
+impl ConfigType {
+    pub const ALL: [ConfigType; 7] = [
+        ConfigType::DEVICE_CONFIG,
+        ConfigType::POSITION_CONFIG,
+        ConfigType::POWER_CONFIG,
+        ConfigType::NETWORK_CONFIG,
+        ConfigType::DISPLAY_CONFIG,
+        ConfigType::LORA_CONFIG,
+        ConfigType::BLUETOOTH_CONFIG,
+    ];
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct DeviceConfig {
+    pub role: Role,
+    pub serial_enabled: bool,
+    pub debug_log_enabled: bool,
+    pub button_gpio: u32,
+    pub buzzer_gpio: u32,
+    pub rebroadcast_mode: RebroadcastMode,
+    pub node_info_broadcast_secs: u32,
+    pub double_tap_as_button_press: bool,
+    /// Managed nodes only accept config changes over remote admin
+    pub is_managed: bool,
+    pub disable_triple_click: bool,
+    /// POSIX TZ string, e.g. `CET-1CEST,M3.5.0,M10.5.0/3`
+    pub tzdef: String,
+    pub led_heartbeat_disabled: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[repr(i32)]
+pub enum RebroadcastMode {
+    #[default]
+    ALL = 0,
+    ALL_SKIP_DECODING = 1,
+    LOCAL_ONLY = 2,
+    KNOWN_ONLY = 3,
+    /// A value this build does not know, kept as-is so it round-trips
+    Unknown(i32),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct PositionConfig {
+    pub position_broadcast_secs: u32,
+    pub position_broadcast_smart_enabled: bool,
+    /// Report the position set with `SetFixedPosition` instead of the GPS
+    pub fixed_position: bool,
+    /// Superseded by `gps_mode` in current firmware
+    pub gps_enabled: bool,
+    pub gps_update_interval: u32,
+    pub gps_attempt_time: u32,
+    /// Bit set of the optional position fields to include
+    pub position_flags: u32,
+    pub rx_gpio: u32,
+    pub tx_gpio: u32,
+    /// Metres
+    pub broadcast_smart_minimum_distance: u32,
+    pub broadcast_smart_minimum_interval_secs: u32,
+    pub gps_en_gpio: u32,
+    pub gps_mode: GpsMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[repr(i32)]
+pub enum GpsMode {
+    #[default]
+    GPS_DISABLED = 0,
+    GPS_ENABLED = 1,
+    GPS_NOT_PRESENT = 2,
+    /// A value this build does not know, kept as-is so it round-trips
+    Unknown(i32),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct PowerConfig {
+    pub is_power_saving: bool,
+    pub on_battery_shutdown_after_secs: u32,
+    pub adc_multiplier_override: f32,
+    pub wait_bluetooth_secs: u32,
+    /// Super deep sleep
+    pub sds_secs: u32,
+    /// Light sleep
+    pub ls_secs: u32,
+    pub min_wake_secs: u32,
+    pub device_battery_ina_address: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct NetworkConfig {
+    pub wifi_enabled: bool,
+    pub wifi_ssid: String,
+    pub wifi_psk: String,
+    pub ntp_server: String,
+    pub eth_enabled: bool,
+    pub address_mode: AddressMode,
+    /// Used when `address_mode` is `STATIC`
+    pub ipv4_config: Option<IpV4Config>,
+    pub rsyslog_server: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[repr(i32)]
+pub enum AddressMode {
+    #[default]
+    DHCP = 0,
+    STATIC = 1,
+    /// A value this build does not know, kept as-is so it round-trips
+    Unknown(i32),
+}
+
+/// Static IPv4 settings. Addresses are as the firmware stores them: the
+/// first octet in the lowest byte, so `192.168.1.1` is `0x0101a8c0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct IpV4Config {
+    pub ip: u32,
+    pub gateway: u32,
+    pub subnet: u32,
+    pub dns: u32,
+}
+
+impl IpV4Config {
+    /// Firmware representation of an address
+    pub fn encode_address(ip: std::net::Ipv4Addr) -> u32 {
+        u32::from_le_bytes(ip.octets())
+    }
+
+    /// Address from its firmware representation
+    pub fn decode_address(address: u32) -> std::net::Ipv4Addr {
+        std::net::Ipv4Addr::from(address.to_le_bytes())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct DisplayConfig {
+    pub screen_on_secs: u32,
+    pub gps_format: GpsCoordinateFormat,
+    pub auto_screen_carousel_secs: u32,
+    pub compass_north_top: bool,
+    pub flip_screen: bool,
+    pub units: DisplayUnits,
+    pub oled: OledType,
+    pub displaymode: DisplayMode,
+    pub heading_bold: bool,
+    pub wake_on_tap_or_motion: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[repr(i32)]
+pub enum GpsCoordinateFormat {
+    #[default]
+    DEC = 0,
+    DMS = 1,
+    UTM = 2,
+    MGRS = 3,
+    OLC = 4,
+    OSGR = 5,
+    /// A value this build does not know, kept as-is so it round-trips
+    Unknown(i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[repr(i32)]
+pub enum DisplayUnits {
+    #[default]
+    METRIC = 0,
+    IMPERIAL = 1,
+    /// A value this build does not know, kept as-is so it round-trips
+    Unknown(i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[repr(i32)]
+pub enum OledType {
+    #[default]
+    OLED_AUTO = 0,
+    OLED_SSD1306 = 1,
+    OLED_SH1106 = 2,
+    OLED_SH1107 = 3,
+    /// A value this build does not know, kept as-is so it round-trips
+    Unknown(i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[repr(i32)]
+pub enum DisplayMode {
+    #[default]
+    DEFAULT = 0,
+    TWOCOLOR = 1,
+    INVERTED = 2,
+    COLOR = 3,
+    /// A value this build does not know, kept as-is so it round-trips
+    Unknown(i32),
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct BluetoothConfig {
+    pub enabled: bool,
+    pub mode: PairingMode,
+    /// Six-digit PIN used with `FIXED_PIN`
+    pub fixed_pin: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[repr(i32)]
+pub enum PairingMode {
+    #[default]
+    RANDOM_PIN = 0,
+    FIXED_PIN = 1,
+    NO_PIN = 2,
+    /// A value this build does not know, kept as-is so it round-trips
+    Unknown(i32),
+}
+
+/// Module configuration record: one module's settings, as sent in the
+/// connect-time dump and by `GetModuleConfig`/`SetModuleConfig`
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ModuleConfig {
-    // Module configuration payload variant would go here
+    pub variant: Option<module_config::Variant>,
+}
+
+impl ModuleConfig {
+    pub fn new(variant: module_config::Variant) -> Self {
+        Self { variant: Some(variant) }
+    }
+
+    /// Module this record configures; `None` for an empty or unknown module
+    pub fn config_type(&self) -> Option<ModuleConfigType> {
+        use module_config::Variant;
+
+        self.variant.as_ref().map(|variant| match variant {
+            Variant::Mqtt(_) => ModuleConfigType::MQTT_CONFIG,
+            Variant::Serial(_) => ModuleConfigType::SERIAL_CONFIG,
+            Variant::ExternalNotification(_) => ModuleConfigType::EXTNOTIF_CONFIG,
+            Variant::StoreForward(_) => ModuleConfigType::STOREFORWARD_CONFIG,
+            Variant::RangeTest(_) => ModuleConfigType::RANGETEST_CONFIG,
+            Variant::Telemetry(_) => ModuleConfigType::TELEMETRY_CONFIG,
+            Variant::CannedMessage(_) => ModuleConfigType::CANNEDMSG_CONFIG,
+        })
+    }
+}
+
+/// Module named in a `GetModuleConfig` request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+#[repr(i32)]
+pub enum ModuleConfigType {
+    #[default]
+    MQTT_CONFIG = 0,
+    SERIAL_CONFIG = 1,
+    EXTNOTIF_CONFIG = 2,
+    STOREFORWARD_CONFIG = 3,
+    RANGETEST_CONFIG = 4,
+    TELEMETRY_CONFIG = 5,
+    CANNEDMSG_CONFIG = 6,
+    /// A value this build does not know, kept as-is so it round-trips
+    Unknown(i32),
+}
+
+impl ModuleConfigType {
+    pub const ALL: [ModuleConfigType; 7] = [
+        ModuleConfigType::MQTT_CONFIG,
+        ModuleConfigType::SERIAL_CONFIG,
+        ModuleConfigType::EXTNOTIF_CONFIG,
+        ModuleConfigType::STOREFORWARD_CONFIG,
+        ModuleConfigType::RANGETEST_CONFIG,
+        ModuleConfigType::TELEMETRY_CONFIG,
+        ModuleConfigType::CANNEDMSG_CONFIG,
+    ];
+}
+
+pub mod module_config {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub enum Variant {
+        Mqtt(MqttConfig),
+        Serial(SerialConfig),
+        ExternalNotification(ExternalNotificationConfig),
+        StoreForward(StoreForwardConfig),
+        RangeTest(RangeTestConfig),
+        Telemetry(TelemetryConfig),
+        CannedMessage(CannedMessageConfig),
+    }
+
+    /// The radio's own MQTT uplink, as opposed to this library's gateway
+    #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+    pub struct MqttConfig {
+        pub enabled: bool,
+        /// Broker host, optionally with `:port`; empty for the public broker
+        pub address: String,
+        pub username: String,
+        pub password: String,
+        pub encryption_enabled: bool,
+        pub json_enabled: bool,
+        pub tls_enabled: bool,
+        /// Topic root, `msh` by default
+        pub root: String,
+        /// Publish through the connected client instead of the radio's own
+        /// network connection
+        pub proxy_to_client_enabled: bool,
+        pub map_reporting_enabled: bool,
+        pub map_report_settings: Option<MapReportSettings>,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+    pub struct MapReportSettings {
+        pub publish_interval_secs: u32,
+        pub position_precision: u32,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+    pub struct SerialConfig {
+        pub enabled: bool,
+        pub echo: bool,
+        pub rxd: u32,
+        pub txd: u32,
+        pub baud: Serial_Baud,
+        pub timeout: u32,
+        pub mode: Serial_Mode,
+        pub override_console_serial_port: bool,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+    #[repr(i32)]
+    pub enum Serial_Baud {
+        #[default]
+        BAUD_DEFAULT = 0,
+        BAUD_110 = 1,
+        BAUD_300 = 2,
+        BAUD_600 = 3,
+        BAUD_1200 = 4,
+        BAUD_2400 = 5,
+        BAUD_4800 = 6,
+        BAUD_9600 = 7,
+        BAUD_19200 = 8,
+        BAUD_38400 = 9,
+        BAUD_57600 = 10,
+        BAUD_115200 = 11,
+        BAUD_230400 = 12,
+        BAUD_460800 = 13,
+        BAUD_576000 = 14,
+        BAUD_921600 = 15,
+        /// A value this build does not know, kept as-is so it round-trips
+        Unknown(i32),
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+    #[repr(i32)]
+    pub enum Serial_Mode {
+        #[default]
+        DEFAULT = 0,
+        SIMPLE = 1,
+        PROTO = 2,
+        TEXTMSG = 3,
+        NMEA = 4,
+        CALTOPO = 5,
+        /// A value this build does not know, kept as-is so it round-trips
+        Unknown(i32),
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+    pub struct ExternalNotificationConfig {
+        pub enabled: bool,
+        pub output_ms: u32,
+        pub output: u32,
+        /// Output is active high
+        pub active: bool,
+        pub alert_message: bool,
+        pub alert_bell: bool,
+        pub use_pwm: bool,
+        pub output_vibra: u32,
+        pub output_buzzer: u32,
+        pub alert_message_vibra: bool,
+        pub alert_message_buzzer: bool,
+        pub alert_bell_vibra: bool,
+        pub alert_bell_buzzer: bool,
+        pub nag_timeout: u32,
+        pub use_i2s_as_buzzer: bool,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+    pub struct StoreForwardConfig {
+        pub enabled: bool,
+        pub heartbeat: bool,
+        pub records: u32,
+        pub history_return_max: u32,
+        pub history_return_window: u32,
+        pub is_server: bool,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+    pub struct RangeTestConfig {
+        pub enabled: bool,
+        /// Seconds between test packets; 0 to only receive
+        pub sender: u32,
+        pub save: bool,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+    pub struct TelemetryConfig {
+        pub device_update_interval: u32,
+        pub environment_update_interval: u32,
+        pub environment_measurement_enabled: bool,
+        pub environment_screen_enabled: bool,
+        pub environment_display_fahrenheit: bool,
+        pub air_quality_enabled: bool,
+        pub air_quality_interval: u32,
+        pub power_measurement_enabled: bool,
+        pub power_update_interval: u32,
+        pub power_screen_enabled: bool,
+    }
+
+    /// Input settings of the canned message module. The messages themselves
+    /// are read and written separately.
+    #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+    pub struct CannedMessageConfig {
+        pub rotary1_enabled: bool,
+        pub inputbroker_pin_a: u32,
+        pub inputbroker_pin_b: u32,
+        pub inputbroker_pin_press: u32,
+        pub inputbroker_event_cw: InputEventChar,
+        pub inputbroker_event_ccw: InputEventChar,
+        pub inputbroker_event_press: InputEventChar,
+        pub updown1_enabled: bool,
+        pub enabled: bool,
+        /// Input source name, e.g. `rotEnc1`, `upDown1` or `_any`
+        pub allow_input_source: String,
+        pub send_bell: bool,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+    #[repr(i32)]
+    pub enum InputEventChar {
+        #[default]
+        NONE = 0,
+        UP = 17,
+        DOWN = 18,
+        LEFT = 19,
+        RIGHT = 20,
+        SELECT = 10,
+        BACK = 27,
+        CANCEL = 24,
+        /// A value this build does not know, kept as-is so it round-trips
+        Unknown(i32),
+    }
+}
+
+/// Firmware-reported device metadata
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct DeviceMetadata {
+    pub firmware_version: String,
+    pub device_state_version: u32,
+    pub can_shutdown: bool,
+    pub has_wifi: bool,
+    pub has_bluetooth: bool,
+    pub has_ethernet: bool,
+    pub role: Role,
+    pub position_flags: u32,
+    pub hw_model: HardwareModel,
+    pub has_remote_hardware: bool,
+}
+
+/// Information about the locally attached radio
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct MyNodeInfo {
+    pub my_node_num: u32,
+    pub reboot_count: u32,
+    pub min_app_version: u32,
+}
+
+/// A node database entry as reported by the radio
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct MeshNodeInfo {
+    pub num: u32,
+    pub user: Option<User>,
+    pub position: Option<Position>,
+    pub snr: f32,
+    pub last_heard: u32,
+    pub device_metrics: Option<DeviceMetrics>,
+    pub channel: u32,
+    pub via_mqtt: bool,
+    pub hops_away: u32,
+    pub is_favorite: bool,
+}
+
+/// Firmware debug output forwarded over the API
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct LogRecord {
+    pub message: String,
+    pub time: u32,
+    pub source: String,
+    pub level: i32,
+}
+
+/// Transmit queue state reported after each packet handed to the radio
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct QueueStatus {
+    pub res: i32,
+    pub free: u32,
+    pub maxlen: u32,
+    pub mesh_packet_id: u32,
+}
+
+/// Envelope for everything the radio sends to us
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct FromRadio {
+    pub id: u32,
+    pub variant: Option<from_radio::Variant>,
+}
+
+pub mod from_radio {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub enum Variant {
+        Packet(MeshPacket),
+        MyInfo(MyNodeInfo),
+        NodeInfo(MeshNodeInfo),
+        Config(Config),
+        LogRecord(LogRecord),
+        ConfigCompleteId(u32),
+        Rebooted(bool),
+        ModuleConfig(ModuleConfig),
+        Channel(Channel),
+        QueueStatus(QueueStatus),
+        Metadata(DeviceMetadata),
+    }
+}
+
+/// Envelope for everything we send to the radio
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ToRadio {
+    pub variant: Option<to_radio::Variant>,
+}
+
+pub mod to_radio {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub enum Variant {
+        Packet(MeshPacket),
+        WantConfigId(u32),
+        Disconnect(bool),
+        Heartbeat,
+    }
+}
+
+impl ToRadio {
+    pub fn packet(packet: MeshPacket) -> Self {
+        Self { variant: Some(to_radio::Variant::Packet(packet)) }
+    }
+
+    pub fn want_config(config_id: u32) -> Self {
+        Self { variant: Some(to_radio::Variant::WantConfigId(config_id)) }
+    }
+
+    pub fn disconnect() -> Self {
+        Self { variant: Some(to_radio::Variant::Disconnect(true)) }
+    }
+
+    pub fn heartbeat() -> Self {
+        Self { variant: Some(to_radio::Variant::Heartbeat) }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -385,8 +1238,12 @@ pub struct RadioConfig {
     pub hop_limit: u32,
     pub tx_enabled: bool,
     pub tx_power: i32,
-    pub sx126x_rx_boosted_gain: bool,
+    pub channel_num: u32,
     pub override_duty_cycle: bool,
+    pub sx126x_rx_boosted_gain: bool,
+    pub override_frequency: f32,
+    pub pa_fan_disabled: bool,
+    pub ignore_mqtt: bool,
 }
 
 // Additional enums for configuration
@@ -440,6 +1297,13 @@ impl Default for MeshPacket {
             rx_snr: 0.0,
             rx_rssi: 0,
             channel: 0,
+            hop_start: 3,
+            want_response: false,
+            dest: 0,
+            source: 0,
+            request_id: 0,
+            reply_id: 0,
+            emoji: 0,
         }
     }
 }
@@ -461,6 +1325,13 @@ impl MeshPacket {
             rx_snr: 0.0,
             rx_rssi: 0,
             channel: 0,
+            hop_start: 3,
+            want_response: false,
+            dest: 0,
+            source: 0,
+            request_id: 0,
+            reply_id: 0,
+            emoji: 0,
         }
     }
 
@@ -480,6 +1351,13 @@ impl MeshPacket {
             rx_snr: 0.0,
             rx_rssi: 0,
             channel: 0,
+            hop_start: 3,
+            want_response: false,
+            dest: 0,
+            source: 0,
+            request_id: 0,
+            reply_id: 0,
+            emoji: 0,
         }
     }
 
@@ -499,6 +1377,41 @@ impl MeshPacket {
             rx_snr: 0.0,
             rx_rssi: 0,
             channel: 0,
+            hop_start: 3,
+            want_response: false,
+            dest: 0,
+            source: 0,
+            request_id: 0,
+            reply_id: 0,
+            emoji: 0,
+        }
+    }
+
+    /// Admin request addressed to `to`; for the locally attached radio that
+    /// is its own node number
+    pub fn new_admin_message(to: u32, admin: AdminMessage) -> Self {
+        Self {
+            from: 0,
+            to,
+            id: rand::random(),
+            payload: Some(PayloadVariant::Admin(admin)),
+            hop_limit: 3,
+            want_ack: true,
+            priority: MeshPacket_Priority::RELIABLE,
+            rx_time: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs() as u32,
+            rx_snr: 0.0,
+            rx_rssi: 0,
+            channel: 0,
+            hop_start: 3,
+            want_response: false,
+            dest: 0,
+            source: 0,
+            request_id: 0,
+            reply_id: 0,
+            emoji: 0,
         }
     }
 
@@ -518,6 +1431,13 @@ impl MeshPacket {
             rx_snr: 0.0,
             rx_rssi: 0,
             channel: 0,
+            hop_start: 3,
+            want_response: false,
+            dest: 0,
+            source: 0,
+            request_id: 0,
+            reply_id: 0,
+            emoji: 0,
         }
     }
 
@@ -550,6 +1470,7 @@ impl ProtocolHandler {
         }
     }
 
+    /// Frame a text message as a `ToRadio` packet for a stream transport
     pub fn encode_text_message(&self, message: &MeshMessage) -> Result<Vec<u8>, ProtocolError> {
         let to_node = if message.to == "broadcast" { 0xFFFFFFFF } else {
             message.to.parse().unwrap_or(0xFFFFFFFF)
@@ -561,79 +1482,27 @@ impl ProtocolHandler {
             &message.text,
         );
         
-        // For now, use JSON encoding (in production, this would be protobuf)
-        let json_data = serde_json::to_vec(&packet).map_err(ProtocolError::from)?;
-        
-        // Add simple framing: [START][LENGTH][DATA][CHECKSUM]
-        let mut framed = Vec::new();
-        framed.push(0x94); // Start byte 1
-        framed.push(0xC3); // Start byte 2
-        
-        let len = json_data.len() as u16;
-        framed.extend_from_slice(&len.to_le_bytes());
-        framed.extend_from_slice(&json_data);
-        
-        // Simple XOR checksum
-        let checksum = json_data.iter().fold(0u8, |acc, &b| acc ^ b);
-        framed.push(checksum);
-        
-        Ok(framed)
+        let data = encode_to_radio(&ToRadio::packet(packet))?;
+        Ok(FrameCodec::frame(&data)?)
     }
 
+    /// Decode one stream frame holding a `FromRadio` text packet
     pub fn decode_message(&self, data: &[u8]) -> Result<MeshMessage, ProtocolError> {
-        // Remove framing
-        let json_data = self.unframe_data(data)?;
-        
-        // Parse JSON (in production, this would be protobuf)
-        let packet: MeshPacket = serde_json::from_slice(&json_data)?;
-        
+        let mut buffer = bytes::BytesMut::from(data);
+        let payload = FrameCodec::new()
+            .decode(&mut buffer)?
+            .ok_or(ProtocolError::InvalidFormat)?;
+        let packet = match decode_from_radio(&payload)?.variant {
+            Some(from_radio::Variant::Packet(packet)) => packet,
+            _ => return Err(ProtocolError::UnsupportedType),
+        };
+
         match &packet.payload {
-            Some(crate::protocol::PayloadVariant::Text(text)) => {
-                Ok(MeshMessage {
-                    from: packet.from.to_string(),
-                    to: if packet.to == 0xFFFFFFFF { "broadcast".to_string() } else { packet.to.to_string() },
-                    text: text.clone(),
-                    timestamp: chrono::Utc::now(),
-                    want_ack: Some(packet.want_ack),
-                    packet_id: Some(packet.id),
-                    hop_limit: Some(packet.hop_limit),
-                    channel: Some(packet.channel),
-                    message_type: MessageType::Text,
-                })
-            }
+            Some(crate::protocol::PayloadVariant::Text(text)) => Ok(MeshMessage::from_packet(&packet, text)),
             _ => Err(ProtocolError::UnsupportedType),
         }
     }
 
-    fn unframe_data(&self, data: &[u8]) -> Result<Vec<u8>, ProtocolError> {
-        if data.len() < 5 {
-            return Err(ProtocolError::InvalidFormat);
-        }
-
-        // Check start bytes
-        if data[0] != 0x94 || data[1] != 0xC3 {
-            return Err(ProtocolError::InvalidFormat);
-        }
-
-        // Get length
-        let len = u16::from_le_bytes([data[2], data[3]]) as usize;
-        
-        if data.len() < 4 + len + 1 {
-            return Err(ProtocolError::InvalidFormat);
-        }
-
-        let json_data = &data[4..4 + len];
-        let received_checksum = data[4 + len];
-        
-        // Verify checksum
-        let calculated_checksum = json_data.iter().fold(0u8, |acc, &b| acc ^ b);
-        if received_checksum != calculated_checksum {
-            return Err(ProtocolError::InvalidFormat);
-        }
-
-        Ok(json_data.to_vec())
-    }
-
     pub fn get_local_node_id(&self) -> u32 {
         self.local_node_id
     }
@@ -645,86 +1514,104 @@ impl Default for ProtocolHandler {
     }
 }
 
-/// Encode a MeshPacket to bytes (placeholder for protobuf encoding)
-pub fn encode_packet(packet: &MeshPacket) -> Result<Vec<u8>, ProtocolError> {
-    // In a real implementation, this would use protobuf encoding
-    // For now, use JSON as a placeholder
-    serde_json::to_vec(packet)
-        .map_err(|e| ProtocolError::Encoding(format!("JSON encoding failed: {}", e)))
+/// Node number of the broadcast address
+pub const BROADCAST_ADDR: u32 = 0xFFFFFFFF;
+
+/// Parse a node id as shown by Meshtastic clients (`!12345678`), a decimal
+/// node number, or `broadcast`
+pub fn parse_node_id(id: &str) -> Result<u32, ProtocolError> {
+    let id = id.trim();
+    if id == "broadcast" {
+        return Ok(BROADCAST_ADDR);
+    }
+    match id.strip_prefix('!') {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => id.parse(),
+    }
+    .map_err(|_| ProtocolError::InvalidNodeId)
 }
 
-/// Decode bytes to a MeshPacket (placeholder for protobuf decoding)
-pub fn decode_packet(data: &[u8]) -> Result<MeshPacket, ProtocolError> {
-    // In a real implementation, this would use protobuf decoding
-    // For now, use JSON as a placeholder
-    serde_json::from_slice(data)
-        .map_err(|e| ProtocolError::Decoding(format!("JSON decoding failed: {}", e)))
-}
-
-/// Extract complete frame from buffer (helper function for serial processing)
-pub fn extract_frame_from_buffer(buffer: &mut bytes::BytesMut) -> Option<Vec<u8>> {
-    const FRAME_START: u8 = 0x94;
-    const FRAME_END: u8 = 0x7E;
-    const ESCAPE: u8 = 0x7D;
-    const ESCAPE_XOR: u8 = 0x20;
-    
-    // Find frame boundaries
-    let start_pos = buffer.iter().position(|&b| b == FRAME_START)?;
-    let end_pos = buffer[start_pos + 1..].iter().position(|&b| b == FRAME_END)? + start_pos + 1;
-    
-    // Extract and remove the frame from buffer
-    let frame_data = buffer[start_pos + 1..end_pos].to_vec();
-    let _ = buffer.split_to(end_pos + 1);
-    
-    // Unescape the frame
-    let mut unescaped = Vec::new();
-    let mut i = 0;
-    while i < frame_data.len() {
-        if frame_data[i] == ESCAPE && i + 1 < frame_data.len() {
-            unescaped.push(frame_data[i + 1] ^ ESCAPE_XOR);
-            i += 2;
-        } else {
-            unescaped.push(frame_data[i]);
-            i += 1;
-        }
+/// Longest canned message list the firmware stores, in bytes with the `|`
+/// separators
+pub const CANNED_MESSAGES_MAX_LEN: usize = 200;
+
+/// Join canned messages into the `|`-separated list the firmware stores
+pub fn join_canned_messages(messages: &[String]) -> Result<String, ProtocolError> {
+    if let Some(message) = messages.iter().find(|message| message.is_empty() || message.contains('|')) {
+        return Err(ProtocolError::Encoding(format!("invalid canned message {:?}", message)));
     }
-    
-    // Verify CRC and return payload (without CRC)
-    if unescaped.len() >= 2 {
-        let payload_len = unescaped.len() - 2;
-        let payload = &unescaped[..payload_len];
-        let received_crc = u16::from_le_bytes([unescaped[payload_len], unescaped[payload_len + 1]]);
-        
-        // Simple CRC check (in real implementation would use proper CRC16)
-        let calculated_crc = payload.iter().fold(0u16, |acc, &b| acc.wrapping_add(b as u16));
-        
-        if received_crc == calculated_crc {
-            Some(payload.to_vec())
-        } else {
-            eprintln!("CRC mismatch: received {:04x}, calculated {:04x}", received_crc, calculated_crc);
-            None
-        }
-    } else {
-        None
+    let joined = messages.join("|");
+    if joined.len() > CANNED_MESSAGES_MAX_LEN {
+        return Err(ProtocolError::Encoding(format!(
+            "canned messages take {} bytes; the radio stores {}",
+            joined.len(),
+            CANNED_MESSAGES_MAX_LEN
+        )));
     }
+    Ok(joined)
+}
+
+/// Split the firmware's canned message list
+pub fn split_canned_messages(list: &str) -> Vec<String> {
+    list.split('|').filter(|message| !message.is_empty()).map(str::to_string).collect()
+}
+
+/// Encode a MeshPacket to its Meshtastic protobuf wire form
+pub fn encode_packet(packet: &MeshPacket) -> Result<Vec<u8>, ProtocolError> {
+    Ok(proto::MeshPacket::try_from(packet)?.encode_to_vec())
+}
+
+/// Decode a Meshtastic protobuf MeshPacket
+pub fn decode_packet(data: &[u8]) -> Result<MeshPacket, ProtocolError> {
+    proto::MeshPacket::decode(data)
+        .map_err(|e| ProtocolError::Protobuf(e.to_string()))?
+        .try_into()
+}
+
+/// Encode a ToRadio envelope for a stream transport
+pub fn encode_to_radio(message: &ToRadio) -> Result<Vec<u8>, ProtocolError> {
+    Ok(proto::ToRadio::try_from(message)?.encode_to_vec())
+}
+
+/// Decode a ToRadio envelope (the radio's side of the stream API)
+pub fn decode_to_radio(data: &[u8]) -> Result<ToRadio, ProtocolError> {
+    proto::ToRadio::decode(data)
+        .map_err(|e| ProtocolError::Protobuf(e.to_string()))?
+        .try_into()
+}
+
+/// Encode a FromRadio envelope (the radio's side of the stream API)
+pub fn encode_from_radio(message: &FromRadio) -> Result<Vec<u8>, ProtocolError> {
+    Ok(proto::FromRadio::try_from(message)?.encode_to_vec())
+}
+
+/// Decode a FromRadio envelope received from a stream transport
+pub fn decode_from_radio(data: &[u8]) -> Result<FromRadio, ProtocolError> {
+    proto::FromRadio::decode(data)
+        .map_err(|e| ProtocolError::Protobuf(e.to_string()))?
+        .try_into()
 }
 
 /// Message processor for handling incoming packets
 #[derive(Debug)]
 pub struct MessageProcessor {
-    node_database: Arc<RwLock<HashMap<u32, User>>>,
-    message_history: Arc<RwLock<Vec<MeshMessage>>>,
-    packet_cache: Arc<RwLock<HashMap<u32, MeshPacket>>>, // For deduplication
+    storage: Arc<dyn Storage>,
+    dedup: DedupStore,
     message_tx: Option<mpsc::UnboundedSender<MeshMessage>>,
+    channels: ChannelTable,
+    handlers: Arc<RwLock<HashMap<PortNum, Arc<dyn PortHandler>>>>,
+    events: broadcast::Sender<MeshEvent>,
 }
 
 impl MessageProcessor {
     pub fn new() -> Self {
         Self {
-            node_database: Arc::new(RwLock::new(HashMap::new())),
-            message_history: Arc::new(RwLock::new(Vec::new())),
-            packet_cache: Arc::new(RwLock::new(HashMap::new())),
+            storage: Arc::new(SqliteStorage::in_memory().expect("in-memory SQLite database")),
+            dedup: DedupStore::default(),
             message_tx: None,
+            channels: ChannelTable::default(),
+            handlers: Arc::new(RwLock::new(HashMap::new())),
+            events: broadcast::channel(1024).0,
         }
     }
 
@@ -733,182 +1620,157 @@ impl MessageProcessor {
         self
     }
 
+    /// Persist history and node records in `storage` instead of memory
+    pub fn with_storage(mut self, storage: Arc<dyn Storage>) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    pub fn storage(&self) -> Arc<dyn Storage> {
+        Arc::clone(&self.storage)
+    }
+
+    /// Share a dedup store with other processors, e.g. one per device or
+    /// gateway, so a packet heard through several of them is handled once
+    pub fn with_dedup(mut self, dedup: DedupStore) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Channels used to decrypt packets that arrive still encrypted
+    pub fn with_channels(mut self, channels: ChannelTable) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// Typed events for every packet processed from now on
+    pub fn subscribe_events(&self) -> broadcast::Receiver<MeshEvent> {
+        self.events.subscribe()
+    }
+
+    /// Handler for `portnum` installed at construction
+    pub fn with_handler(mut self, portnum: PortNum, handler: Arc<dyn PortHandler>) -> Self {
+        Arc::get_mut(&mut self.handlers)
+            .expect("handler table not yet shared")
+            .get_mut()
+            .insert(portnum, handler);
+        self
+    }
+
+    /// Register the handler for `portnum`, replacing any previous one
+    pub async fn register_handler(&self, portnum: PortNum, handler: Arc<dyn PortHandler>) {
+        self.handlers.write().await.insert(portnum, handler);
+    }
+
+    /// Remove the handler for `portnum`, returning it if there was one
+    pub async fn unregister_handler(&self, portnum: PortNum) -> Option<Arc<dyn PortHandler>> {
+        self.handlers.write().await.remove(&portnum)
+    }
+
+    /// Decrypt `packet` with the channel whose hash and key match, mapping
+    /// its `channel` to the local index. Packets that are already decoded, or
+    /// that no channel opens, are returned unchanged.
+    pub fn decrypt(&self, packet: MeshPacket) -> MeshPacket {
+        self.channels.decrypt(&packet).unwrap_or(packet)
+    }
+
     /// Process incoming packet and extract relevant information
     pub async fn process_packet(&self, packet: MeshPacket) -> Result<(), ProtocolError> {
-        // Check for duplicate packets
-        {
-            let mut cache = self.packet_cache.write().await;
-            if cache.contains_key(&packet.id) {
-                // Duplicate packet, ignore
-                return Ok(());
-            }
-            cache.insert(packet.id, packet.clone());
-            
-            // Keep cache size reasonable
-            if cache.len() > 1000 {
-                cache.clear();
-            }
+        // Duplicate packet, ignore
+        if !self.dedup.first_seen(packet.from, packet.id) {
+            return Ok(());
         }
 
+        self.process_new_packet(packet).await
+    }
+
+    /// Process a packet the caller has already recorded in the dedup store
+    pub async fn process_new_packet(&self, packet: MeshPacket) -> Result<(), ProtocolError> {
+        let packet = self.decrypt(packet);
+        self.update_node(&packet);
+
         match &packet.payload {
             Some(PayloadVariant::Text(text)) => {
-                let message = MeshMessage {
-                    from: packet.from.to_string(),
-                    to: if packet.is_broadcast() { "broadcast".to_string() } else { packet.to.to_string() },
-                    text: text.clone(),
-                    timestamp: Utc::now(),
-                    want_ack: Some(packet.want_ack),
-                    packet_id: Some(packet.id),
-                    hop_limit: Some(packet.hop_limit),
-                    channel: Some(packet.channel),
-                    message_type: MessageType::Text,
-                };
-                
-                self.store_message(message.clone()).await;
+                let message = MeshMessage::from_packet(&packet, text);
                 
-                if let Some(tx) = &self.message_tx {
-                    let _ = tx.send(message);
+                if let Err(e) = self.storage.save_message(ConversationId::incoming(&packet), false, &message) {
+                    log::warn!("Failed to store message {:08x}: {}", packet.id, e);
                 }
-            }
-            Some(PayloadVariant::NodeInfo(user)) => {
-                self.update_node_info(packet.from, user.clone()).await;
-                
-                let message = MeshMessage {
-                    from: packet.from.to_string(),
-                    to: "broadcast".to_string(),
-                    text: format!("Node info: {} ({})", user.long_name, user.short_name),
-                    timestamp: Utc::now(),
-                    want_ack: Some(false),
-                    packet_id: Some(packet.id),
-                    hop_limit: Some(packet.hop_limit),
-                    channel: Some(packet.channel),
-                    message_type: MessageType::NodeInfo,
-                };
                 
                 if let Some(tx) = &self.message_tx {
                     let _ = tx.send(message);
                 }
             }
             Some(PayloadVariant::Position(position)) => {
-                let lat = position.latitude_i as f64 / 1e7;
-                let lon = position.longitude_i as f64 / 1e7;
-                
-                let message = MeshMessage {
-                    from: packet.from.to_string(),
-                    to: "broadcast".to_string(),
-                    text: format!("Position: {:.6}, {:.6} (alt: {}m)", lat, lon, position.altitude),
-                    timestamp: Utc::now(),
-                    want_ack: Some(false),
-                    packet_id: Some(packet.id),
-                    hop_limit: Some(packet.hop_limit),
-                    channel: Some(packet.channel),
-                    message_type: MessageType::Position,
-                };
-                
-                if let Some(tx) = &self.message_tx {
-                    let _ = tx.send(message);
+                if let Err(e) = self.storage.save_position(packet.from, position) {
+                    log::warn!("Failed to store position from {:08x}: {}", packet.from, e);
                 }
             }
             Some(PayloadVariant::Telemetry(telemetry)) => {
-                let telemetry_text = match &telemetry.variant {
-                    Some(TelemetryVariant::DeviceMetrics(metrics)) => {
-                        format!("Battery: {}%, Voltage: {:.2}V, Uptime: {}s", 
-                            metrics.battery_level, metrics.voltage, metrics.uptime_seconds)
-                    }
-                    Some(TelemetryVariant::EnvironmentMetrics(metrics)) => {
-                        format!("Temp: {:.1}°C, Humidity: {:.1}%, Pressure: {:.1}hPa", 
-                            metrics.temperature, metrics.relative_humidity, metrics.barometric_pressure)
-                    }
-                    Some(TelemetryVariant::PowerMetrics(metrics)) => {
-                        format!("Power: {:.2}V/{:.2}A", metrics.ch1_voltage, metrics.ch1_current)
-                    }
-                    None => "Telemetry data".to_string(),
-                };
-                
-                let message = MeshMessage {
-                    from: packet.from.to_string(),
-                    to: "broadcast".to_string(),
-                    text: telemetry_text,
-                    timestamp: Utc::now(),
-                    want_ack: Some(false),
-                    packet_id: Some(packet.id),
-                    hop_limit: Some(packet.hop_limit),
-                    channel: Some(packet.channel),
-                    message_type: MessageType::Telemetry,
-                };
-                
-                if let Some(tx) = &self.message_tx {
-                    let _ = tx.send(message);
-                }
-            }
-            Some(PayloadVariant::Admin(_admin)) => {
-                // Handle admin messages (configuration, etc.)
-                let message = MeshMessage {
-                    from: packet.from.to_string(),
-                    to: packet.to.to_string(),
-                    text: "Admin message".to_string(),
-                    timestamp: Utc::now(),
-                    want_ack: Some(packet.want_ack),
-                    packet_id: Some(packet.id),
-                    hop_limit: Some(packet.hop_limit),
-                    channel: Some(packet.channel),
-                    message_type: MessageType::Admin,
-                };
-                
-                if let Some(tx) = &self.message_tx {
-                    let _ = tx.send(message);
+                if let Err(e) = self.storage.save_telemetry(packet.from, telemetry) {
+                    log::warn!("Failed to store telemetry from {:08x}: {}", packet.from, e);
                 }
             }
-            Some(PayloadVariant::Routing(_routing)) => {
-                // Handle routing messages
-                println!("Received routing message from node {}", packet.from);
-            }
-            Some(PayloadVariant::Raw(_data)) => {
-                // Handle raw data
-                println!("Received raw data from node {}", packet.from);
-            }
-            None => {
-                println!("Received packet with no payload from node {}", packet.from);
+            Some(PayloadVariant::Encrypted(_)) => {
+                // Channel key unknown, nothing more we can do with it
+                log::debug!("No channel key for packet {:08x} from {:08x}", packet.id, packet.from);
             }
+            _ => {}
+        }
+
+        if let Some(event) = MeshEvent::from_packet(&packet) {
+            // Nobody listening is fine
+            let _ = self.events.send(event);
         }
 
-        Ok(())
+        self.dispatch(&packet).await
     }
 
-    async fn store_message(&self, message: MeshMessage) {
-        let mut history = self.message_history.write().await;
-        history.push(message);
-        
-        // Keep history size reasonable
-        if history.len() > 10000 {
-            history.remove(0);
+    /// Hand a decoded packet to the handler registered for its port
+    async fn dispatch(&self, packet: &MeshPacket) -> Result<(), ProtocolError> {
+        let Some(data) = packet.data()? else {
+            return Ok(());
+        };
+        let handler = self.handlers.read().await.get(&data.portnum).cloned();
+
+        match handler {
+            Some(handler) => handler.handle(packet, &data).await,
+            None => Ok(()),
         }
     }
 
-    async fn update_node_info(&self, node_id: u32, user: User) {
-        let mut db = self.node_database.write().await;
-        db.insert(node_id, user);
+    /// Fold the packet into the stored record of the node that sent it
+    fn update_node(&self, packet: &MeshPacket) {
+        if packet.from == 0 {
+            return;
+        }
+
+        let result = self.storage.node(packet.from).and_then(|node| {
+            let mut node = node.unwrap_or(MeshNodeInfo { num: packet.from, ..Default::default() });
+            nodedb::apply_packet(&mut node, packet);
+            self.storage.save_node(&node)
+        });
+        if let Err(e) = result {
+            log::warn!("Failed to update node {:08x}: {}", packet.from, e);
+        }
     }
 
     pub async fn get_node_info(&self, node_id: u32) -> Option<User> {
-        let db = self.node_database.read().await;
-        db.get(&node_id).cloned()
+        self.storage.node(node_id).ok().flatten().and_then(|node| node.user)
     }
 
     pub async fn get_all_nodes(&self) -> Vec<(u32, User)> {
-        let db = self.node_database.read().await;
-        db.iter().map(|(&id, user)| (id, user.clone())).collect()
+        self.storage.nodes().unwrap_or_default().into_iter()
+            .filter_map(|node| Some((node.num, node.user?)))
+            .collect()
     }
 
     pub async fn get_message_history(&self) -> Vec<MeshMessage> {
-        let history = self.message_history.read().await;
-        history.clone()
+        self.storage.messages(None).unwrap_or_default()
     }
 
     pub async fn get_recent_messages(&self, limit: usize) -> Vec<MeshMessage> {
-        let history = self.message_history.read().await;
-        let start = if history.len() > limit { history.len() - limit } else { 0 };
-        history[start..].to_vec()
+        self.storage.messages(Some(limit)).unwrap_or_default()
     }
 }
 
@@ -917,3 +1779,143 @@ impl Default for MessageProcessor {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_node_id() {
+        assert_eq!(parse_node_id("!12345678").unwrap(), 0x12345678);
+        assert_eq!(parse_node_id("305419896").unwrap(), 0x12345678);
+        assert_eq!(parse_node_id("broadcast").unwrap(), BROADCAST_ADDR);
+        assert!(parse_node_id("!xyz").is_err());
+    }
+
+    #[test]
+    fn test_text_messages_use_stream_envelopes() {
+        let handler = ProtocolHandler::new();
+        let message = MeshMessage::from_packet(&MeshPacket::new_text_message(1, BROADCAST_ADDR, "hi"), "hi");
+        let framed = handler.encode_text_message(&message).unwrap();
+
+        let mut buffer = bytes::BytesMut::from(&framed[..]);
+        let payload = FrameCodec::new().decode(&mut buffer).unwrap().unwrap();
+        let Some(to_radio::Variant::Packet(packet)) = decode_to_radio(&payload).unwrap().variant else {
+            panic!("expected a ToRadio packet");
+        };
+        assert!(matches!(packet.payload, Some(PayloadVariant::Text(ref text)) if text == "hi"));
+
+        let from_radio = FromRadio { id: 1, variant: Some(from_radio::Variant::Packet(packet)) };
+        let framed = FrameCodec::frame(&encode_from_radio(&from_radio).unwrap()).unwrap();
+        assert_eq!(handler.decode_message(&framed).unwrap().text, "hi");
+    }
+
+    #[tokio::test]
+    async fn test_process_packet_decrypts_with_channel_table() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let channels = ChannelTable::from_channels(&[
+            Channel {
+                index: 0,
+                role: Channel_Role::PRIMARY,
+                settings: Some(ChannelSettings { psk: vec![1], ..Default::default() }),
+            },
+            Channel {
+                index: 1,
+                role: Channel_Role::SECONDARY,
+                settings: Some(ChannelSettings { name: "ops".to_string(), psk: vec![3; 16], ..Default::default() }),
+            },
+        ], 0).unwrap();
+        let processor = MessageProcessor::new()
+            .with_channels(channels.clone())
+            .with_message_channel(tx);
+
+        let packet = MeshPacket {
+            channel: 1,
+            ..MeshPacket::new_text_message(0x12345678, 0xFFFFFFFF, "over the air")
+        };
+        processor.process_packet(channels.encrypt(&packet).unwrap()).await.unwrap();
+
+        let message = rx.try_recv().unwrap();
+        assert_eq!(message.text, "over the air");
+        assert_eq!(message.channel, Some(1));
+        assert_eq!(processor.get_message_history().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_registered_handler_receives_private_port() {
+        let processor = MessageProcessor::new();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        processor.register_handler(PortNum(287), Arc::new(move |packet: &MeshPacket, data: &Data| {
+            let _ = tx.send((packet.from, data.payload.clone()));
+            Ok(())
+        })).await;
+
+        let packet = MeshPacket {
+            from: 0x1234,
+            payload: Some(PayloadVariant::App(PortNum(287), b"ping".to_vec())),
+            ..Default::default()
+        };
+        processor.process_packet(packet).await.unwrap();
+        assert_eq!(rx.try_recv().unwrap(), (0x1234, b"ping".to_vec()));
+
+        processor.unregister_handler(PortNum(287)).await;
+        let packet = MeshPacket {
+            payload: Some(PayloadVariant::App(PortNum(287), b"ping".to_vec())),
+            ..Default::default()
+        };
+        processor.process_packet(packet).await.unwrap();
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_processors_share_dedup_store() {
+        let dedup = DedupStore::default();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let first = MessageProcessor::new().with_dedup(dedup.clone()).with_message_channel(tx.clone());
+        let second = MessageProcessor::new().with_dedup(dedup).with_message_channel(tx);
+
+        let packet = MeshPacket { id: 7, ..MeshPacket::new_text_message(1, BROADCAST_ADDR, "hi") };
+        first.process_packet(packet.clone()).await.unwrap();
+        second.process_packet(packet).await.unwrap();
+        // Same id from another sender is not a duplicate
+        let other = MeshPacket { id: 7, ..MeshPacket::new_text_message(2, BROADCAST_ADDR, "hi") };
+        second.process_packet(other).await.unwrap();
+
+        assert_eq!(rx.try_recv().unwrap().from, "1");
+        assert_eq!(rx.try_recv().unwrap().from, "2");
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_position_publishes_typed_event() {
+        let processor = MessageProcessor::new();
+        let mut events = processor.subscribe_events();
+
+        let packet = MeshPacket {
+            rx_snr: 6.25,
+            rx_rssi: -97,
+            rx_time: 1_700_000_000,
+            hop_start: 3,
+            hop_limit: 1,
+            ..MeshPacket::new_position(0xdeadbeef, Position {
+                latitude_i: 374_221_234,
+                longitude_i: -1_220_841_234,
+                ..Default::default()
+            })
+        };
+        processor.process_packet(packet).await.unwrap();
+
+        let MeshEvent::PositionUpdated { meta, position } = events.try_recv().unwrap() else {
+            panic!("expected a position event");
+        };
+        assert_eq!(meta.from, 0xdeadbeef);
+        assert_eq!((meta.rx_snr, meta.rx_rssi, meta.rx_time), (6.25, -97, 1_700_000_000));
+        assert_eq!(meta.hops_away(), Some(2));
+        assert_eq!(position.latitude_i, 374_221_234);
+
+        let storage = processor.storage();
+        assert_eq!(storage.positions(0xdeadbeef, 10).unwrap().len(), 1);
+        let node = storage.node(0xdeadbeef).unwrap().unwrap();
+        assert_eq!((node.last_heard, node.hops_away), (1_700_000_000, 2));
+    }
+}
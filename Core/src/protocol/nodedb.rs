@@ -0,0 +1,258 @@
+//! Per-node state merged from the radio's NodeDB dump and live traffic.
+//!
+//! The radio sends its NodeDB during the connect-time config exchange and
+//! pushes `NodeInfo` updates afterwards. Between those, every packet heard
+//! refreshes the sender's last-heard time and signal, and NodeInfo, Position
+//! and DeviceMetrics payloads fill in the rest of the record.
+
+use super::{MeshNodeInfo, MeshPacket, NodeInfo, PayloadVariant, TelemetryVariant};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How recently a node must have been heard to count as online. Matches the
+/// official clients.
+pub const DEFAULT_ONLINE_THRESHOLD: Duration = Duration::from_secs(2 * 60 * 60);
+
+fn now_secs() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or(0)
+}
+
+/// Fold what a packet says about its sender into the sender's record
+pub fn apply_packet(record: &mut MeshNodeInfo, packet: &MeshPacket) {
+    record.last_heard = if packet.rx_time > 0 { packet.rx_time } else { now_secs() };
+    record.snr = packet.rx_snr;
+    record.channel = packet.channel as u32;
+    if packet.hop_start > 0 {
+        record.hops_away = packet.hop_start.saturating_sub(packet.hop_limit) as u32;
+    }
+
+    match &packet.payload {
+        Some(PayloadVariant::NodeInfo(user)) => record.user = Some(user.clone()),
+        Some(PayloadVariant::Position(position)) => record.position = Some(position.clone()),
+        Some(PayloadVariant::Telemetry(telemetry)) => {
+            if let Some(TelemetryVariant::DeviceMetrics(metrics)) = &telemetry.variant {
+                record.device_metrics = Some(metrics.clone());
+            }
+        }
+        _ => {}
+    }
+}
+
+/// One record per node number, in the radio's own `NodeInfo` shape
+#[derive(Debug, Clone)]
+pub struct NodeDb {
+    nodes: HashMap<u32, MeshNodeInfo>,
+    online_threshold: Duration,
+}
+
+impl NodeDb {
+    pub fn new() -> Self {
+        Self {
+            nodes: HashMap::new(),
+            online_threshold: DEFAULT_ONLINE_THRESHOLD,
+        }
+    }
+
+    pub fn with_online_threshold(mut self, threshold: Duration) -> Self {
+        self.online_threshold = threshold;
+        self
+    }
+
+    pub fn online_threshold(&self) -> Duration {
+        self.online_threshold
+    }
+
+    pub fn set_online_threshold(&mut self, threshold: Duration) {
+        self.online_threshold = threshold;
+    }
+
+    /// Merge a record from the radio's NodeDB. Sections the dump leaves out
+    /// are kept, and signal details are only taken if the dump is at least
+    /// as recent as what we heard ourselves.
+    pub fn merge_dump(&mut self, node: &MeshNodeInfo) {
+        let record = self.nodes.entry(node.num).or_insert_with(|| MeshNodeInfo {
+            num: node.num,
+            ..Default::default()
+        });
+
+        if node.user.is_some() {
+            record.user = node.user.clone();
+        }
+        if node.position.is_some() {
+            record.position = node.position.clone();
+        }
+        if node.device_metrics.is_some() {
+            record.device_metrics = node.device_metrics.clone();
+        }
+        if node.last_heard >= record.last_heard {
+            record.last_heard = node.last_heard;
+            record.snr = node.snr;
+            record.hops_away = node.hops_away;
+            record.channel = node.channel;
+            record.via_mqtt = node.via_mqtt;
+        }
+        record.is_favorite = node.is_favorite;
+    }
+
+    /// Update the sender's record from a received packet. Packets that could
+    /// not be decrypted still count as heard.
+    pub fn update_from_packet(&mut self, packet: &MeshPacket) {
+        if packet.from == 0 {
+            return;
+        }
+
+        let record = self.nodes.entry(packet.from).or_insert_with(|| MeshNodeInfo {
+            num: packet.from,
+            ..Default::default()
+        });
+        apply_packet(record, packet);
+    }
+
+    pub fn get(&self, num: u32) -> Option<&MeshNodeInfo> {
+        self.nodes.get(&num)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Whether a node was heard within the online threshold
+    pub fn is_online(&self, num: u32) -> bool {
+        self.get(num).is_some_and(|node| self.heard_recently(node, now_secs()))
+    }
+
+    /// All known nodes, most recently heard first
+    pub fn nodes(&self) -> Vec<NodeInfo> {
+        let now = now_secs();
+        let mut nodes: Vec<&MeshNodeInfo> = self.nodes.values().collect();
+        nodes.sort_by(|a, b| b.last_heard.cmp(&a.last_heard).then(a.num.cmp(&b.num)));
+        nodes.into_iter().map(|node| self.node_info(node, now)).collect()
+    }
+
+    fn heard_recently(&self, node: &MeshNodeInfo, now: u32) -> bool {
+        node.last_heard > 0 && now.saturating_sub(node.last_heard) as u64 <= self.online_threshold.as_secs()
+    }
+
+    fn node_info(&self, node: &MeshNodeInfo, now: u32) -> NodeInfo {
+        let fallback_id = format!("!{:08x}", node.num);
+        let user = node.user.clone().unwrap_or_default();
+        let id = if user.id.is_empty() { fallback_id.clone() } else { user.id.clone() };
+        let name = if user.long_name.is_empty() { fallback_id.clone() } else { user.long_name.clone() };
+        let short_name = if user.short_name.is_empty() {
+            fallback_id[fallback_id.len() - 4..].to_string()
+        } else {
+            user.short_name.clone()
+        };
+
+        NodeInfo {
+            id,
+            name,
+            short_name,
+            is_online: self.heard_recently(node, now),
+            num: node.num,
+            hw_model: user.hw_model,
+            role: user.role,
+            battery_level: node.device_metrics.as_ref().map(|m| m.battery_level),
+            voltage: node.device_metrics.as_ref().map(|m| m.voltage),
+            snr: node.snr,
+            // Direct neighbours report 0; without hop_start we cannot tell
+            hops_away: (node.last_heard > 0).then_some(node.hops_away),
+            last_heard: node.last_heard,
+            position: node.position.clone(),
+        }
+    }
+}
+
+impl Default for NodeDb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{DeviceMetrics, HardwareModel, Position, TelemetryData, User};
+
+    fn heard(from: u32, payload: PayloadVariant) -> MeshPacket {
+        MeshPacket {
+            from,
+            rx_time: now_secs(),
+            rx_snr: 6.5,
+            hop_start: 3,
+            hop_limit: 1,
+            payload: Some(payload),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_merges_dump_and_packets() {
+        let mut db = NodeDb::new();
+        db.merge_dump(&MeshNodeInfo {
+            num: 0x1234abcd,
+            user: Some(User {
+                id: "!1234abcd".into(),
+                long_name: "Base".into(),
+                short_name: "BS".into(),
+                hw_model: HardwareModel::TBEAM,
+                ..Default::default()
+            }),
+            last_heard: 1,
+            ..Default::default()
+        });
+
+        db.update_from_packet(&heard(0x1234abcd, PayloadVariant::Position(Position {
+            latitude_i: 515_000_000,
+            ..Default::default()
+        })));
+        db.update_from_packet(&heard(0x1234abcd, PayloadVariant::Telemetry(TelemetryData {
+            time: 0,
+            variant: Some(TelemetryVariant::DeviceMetrics(DeviceMetrics {
+                battery_level: 87,
+                voltage: 4.01,
+                ..Default::default()
+            })),
+        })));
+        // An older dump must not roll back what we heard live
+        db.merge_dump(&MeshNodeInfo { num: 0x1234abcd, last_heard: 1, snr: -20.0, ..Default::default() });
+
+        let nodes = db.nodes();
+        assert_eq!(nodes.len(), 1);
+        let node = &nodes[0];
+        assert_eq!(node.name, "Base");
+        assert_eq!(node.hw_model, HardwareModel::TBEAM);
+        assert_eq!(node.battery_level, Some(87));
+        assert_eq!(node.snr, 6.5);
+        assert_eq!(node.hops_away, Some(2));
+        assert_eq!(node.position.as_ref().map(|p| p.latitude_i), Some(515_000_000));
+        assert!(node.is_online);
+    }
+
+    #[test]
+    fn test_online_threshold() {
+        let mut db = NodeDb::new().with_online_threshold(Duration::from_secs(60));
+        db.merge_dump(&MeshNodeInfo { num: 1, last_heard: now_secs() - 120, ..Default::default() });
+        db.merge_dump(&MeshNodeInfo { num: 2, ..Default::default() });
+        db.update_from_packet(&heard(3, PayloadVariant::Encrypted(vec![1, 2, 3])));
+
+        assert!(!db.is_online(1));
+        assert!(!db.is_online(2));
+        assert!(db.is_online(3));
+
+        db.set_online_threshold(Duration::from_secs(600));
+        assert!(db.is_online(1));
+
+        let unnamed = db.nodes().into_iter().find(|n| n.num == 2).unwrap();
+        assert_eq!(unnamed.id, "!00000002");
+        assert_eq!(unnamed.short_name, "0002");
+        assert_eq!(unnamed.hops_away, None);
+    }
+}
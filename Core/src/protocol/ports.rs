@@ -0,0 +1,75 @@
+//! Application port numbers and the handlers registered for them.
+//!
+//! Every decoded packet carries a port number that says which application
+//! its payload belongs to. Numbers 256-511 are reserved for private apps, so
+//! `PortNum` is an open set rather than a closed enum.
+
+use super::{Data, MeshPacket, ProtocolError};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+/// Meshtastic application port number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, Default)]
+pub struct PortNum(pub u32);
+
+impl PortNum {
+    pub const UNKNOWN_APP: PortNum = PortNum(0);
+    pub const TEXT_MESSAGE_APP: PortNum = PortNum(1);
+    pub const REMOTE_HARDWARE_APP: PortNum = PortNum(2);
+    pub const POSITION_APP: PortNum = PortNum(3);
+    pub const NODEINFO_APP: PortNum = PortNum(4);
+    pub const ROUTING_APP: PortNum = PortNum(5);
+    pub const ADMIN_APP: PortNum = PortNum(6);
+    pub const TEXT_MESSAGE_COMPRESSED_APP: PortNum = PortNum(7);
+    pub const WAYPOINT_APP: PortNum = PortNum(8);
+    pub const AUDIO_APP: PortNum = PortNum(9);
+    pub const DETECTION_SENSOR_APP: PortNum = PortNum(10);
+    pub const REPLY_APP: PortNum = PortNum(32);
+    pub const IP_TUNNEL_APP: PortNum = PortNum(33);
+    pub const PAXCOUNTER_APP: PortNum = PortNum(34);
+    pub const SERIAL_APP: PortNum = PortNum(64);
+    pub const STORE_FORWARD_APP: PortNum = PortNum(65);
+    pub const RANGE_TEST_APP: PortNum = PortNum(66);
+    pub const TELEMETRY_APP: PortNum = PortNum(67);
+    pub const ZPS_APP: PortNum = PortNum(68);
+    pub const SIMULATOR_APP: PortNum = PortNum(69);
+    pub const TRACEROUTE_APP: PortNum = PortNum(70);
+    pub const NEIGHBORINFO_APP: PortNum = PortNum(71);
+    pub const ATAK_PLUGIN: PortNum = PortNum(72);
+    pub const MAP_REPORT_APP: PortNum = PortNum(73);
+    pub const PRIVATE_APP: PortNum = PortNum(256);
+    pub const ATAK_FORWARDER: PortNum = PortNum(257);
+    /// Chunks of payloads too large for one packet; see `protocol::fragment`
+    pub const FRAGMENT_APP: PortNum = PortNum(300);
+    /// File transfers; see `protocol::transfer`
+    pub const FILE_TRANSFER_APP: PortNum = PortNum(301);
+    pub const MAX: PortNum = PortNum(511);
+
+    /// Whether the port lies in the range set aside for private apps
+    pub fn is_private(self) -> bool {
+        (Self::PRIVATE_APP.0..=Self::MAX.0).contains(&self.0)
+    }
+}
+
+/// Application handler for one port. Handlers see every decoded packet on
+/// their port after the built-in processing has run.
+#[async_trait]
+pub trait PortHandler: Send + Sync {
+    async fn handle(&self, packet: &MeshPacket, data: &Data) -> Result<(), ProtocolError>;
+}
+
+#[async_trait]
+impl<F> PortHandler for F
+where
+    F: Fn(&MeshPacket, &Data) -> Result<(), ProtocolError> + Send + Sync,
+{
+    async fn handle(&self, packet: &MeshPacket, data: &Data) -> Result<(), ProtocolError> {
+        self(packet, data)
+    }
+}
+
+impl std::fmt::Debug for dyn PortHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PortHandler")
+    }
+}
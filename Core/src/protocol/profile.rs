@@ -0,0 +1,546 @@
+//! Device profiles: a radio's settings as a versioned YAML or JSON document.
+//!
+//! A [`DeviceProfile`] holds the owner, channels, every config and module
+//! config section and the fixed position. Backing up a radio fills in every
+//! part; a hand-written profile may leave sections out, and restoring it
+//! leaves those sections alone. A profile without channels leaves the
+//! radio's channels alone too; one that lists any channel owns every slot,
+//! and slots it does not list are disabled, as with a channel URL.
+//!
+//! Comparing a radio's profile with a desired one gives a [`ProfilePlan`]:
+//! every field that differs, and a session that writes only the sections
+//! holding those fields.
+//!
+//! Backups are meant to be copied around, so by default they leave out the
+//! Wi-Fi PSK and MQTT password and are marked `redacted`. Comparing with or
+//! restoring a redacted profile keeps the radio's own values for those.
+
+use super::{
+    channels::MAX_CHANNELS, config, module_config, BluetoothConfig, Channel, Config, ConfigSession, DeviceConfig,
+    DisplayConfig, EditOperation, ModuleConfig, NetworkConfig, Position, PositionConfig, PowerConfig, RadioConfig,
+    SessionReport, User,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Version written by this library. Profiles with another version are rejected.
+pub const PROFILE_VERSION: u32 = 1;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProfileError {
+    #[error("Unsupported profile version {0} (expected {PROFILE_VERSION})")]
+    UnsupportedVersion(u32),
+    #[error("Invalid JSON profile: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid YAML profile: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+}
+
+/// Config sections of a profile, one field per section
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfileConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<DeviceConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<PositionConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power: Option<PowerConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display: Option<DisplayConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lora: Option<RadioConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bluetooth: Option<BluetoothConfig>,
+}
+
+impl ProfileConfig {
+    /// Store a section read from a radio, replacing any earlier copy
+    pub fn insert(&mut self, config: Config) {
+        match config.variant {
+            Some(config::Variant::Device(section)) => self.device = Some(section),
+            Some(config::Variant::Position(section)) => self.position = Some(section),
+            Some(config::Variant::Power(section)) => self.power = Some(section),
+            Some(config::Variant::Network(section)) => self.network = Some(section),
+            Some(config::Variant::Display(section)) => self.display = Some(section),
+            Some(config::Variant::Lora(section)) => self.lora = Some(section),
+            Some(config::Variant::Bluetooth(section)) => self.bluetooth = Some(section),
+            None => {}
+        }
+    }
+
+    /// The sections present, in `ConfigType` order
+    pub fn sections(&self) -> Vec<Config> {
+        use config::Variant;
+
+        [
+            self.device.clone().map(Variant::Device),
+            self.position.clone().map(Variant::Position),
+            self.power.clone().map(Variant::Power),
+            self.network.clone().map(Variant::Network),
+            self.display.clone().map(Variant::Display),
+            self.lora.clone().map(Variant::Lora),
+            self.bluetooth.clone().map(Variant::Bluetooth),
+        ]
+        .into_iter()
+        .flatten()
+        .map(Config::new)
+        .collect()
+    }
+}
+
+/// Module config sections of a profile, one field per module
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfileModuleConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mqtt: Option<module_config::MqttConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serial: Option<module_config::SerialConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_notification: Option<module_config::ExternalNotificationConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store_forward: Option<module_config::StoreForwardConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range_test: Option<module_config::RangeTestConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub telemetry: Option<module_config::TelemetryConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub canned_message: Option<module_config::CannedMessageConfig>,
+}
+
+impl ProfileModuleConfig {
+    /// Store a module's settings read from a radio, replacing any earlier copy
+    pub fn insert(&mut self, config: ModuleConfig) {
+        use module_config::Variant;
+
+        match config.variant {
+            Some(Variant::Mqtt(section)) => self.mqtt = Some(section),
+            Some(Variant::Serial(section)) => self.serial = Some(section),
+            Some(Variant::ExternalNotification(section)) => self.external_notification = Some(section),
+            Some(Variant::StoreForward(section)) => self.store_forward = Some(section),
+            Some(Variant::RangeTest(section)) => self.range_test = Some(section),
+            Some(Variant::Telemetry(section)) => self.telemetry = Some(section),
+            Some(Variant::CannedMessage(section)) => self.canned_message = Some(section),
+            None => {}
+        }
+    }
+
+    /// The modules present, in `ModuleConfigType` order
+    pub fn sections(&self) -> Vec<ModuleConfig> {
+        use module_config::Variant;
+
+        [
+            self.mqtt.clone().map(Variant::Mqtt),
+            self.serial.clone().map(Variant::Serial),
+            self.external_notification.clone().map(Variant::ExternalNotification),
+            self.store_forward.clone().map(Variant::StoreForward),
+            self.range_test.clone().map(Variant::RangeTest),
+            self.telemetry.clone().map(Variant::Telemetry),
+            self.canned_message.clone().map(Variant::CannedMessage),
+        ]
+        .into_iter()
+        .flatten()
+        .map(ModuleConfig::new)
+        .collect()
+    }
+}
+
+/// A radio's settings, ready to save or to apply to another radio
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceProfile {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<User>,
+    /// Enabled channels. Empty leaves the radio's channels alone; otherwise
+    /// slots not listed are disabled on restore.
+    #[serde(default)]
+    pub channels: Vec<Channel>,
+    #[serde(default)]
+    pub config: ProfileConfig,
+    #[serde(default)]
+    pub module_config: ProfileModuleConfig,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fixed_position: Option<Position>,
+    /// The Wi-Fi PSK and MQTT password were left out; see [`Self::redact_secrets`]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub redacted: bool,
+}
+
+impl Default for DeviceProfile {
+    fn default() -> Self {
+        Self {
+            version: PROFILE_VERSION,
+            owner: None,
+            channels: Vec::new(),
+            config: ProfileConfig::default(),
+            module_config: ProfileModuleConfig::default(),
+            fixed_position: None,
+            redacted: false,
+        }
+    }
+}
+
+impl DeviceProfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep only the owner fields a radio accepts in `SetOwner`; the node id,
+    /// MAC address and hardware model belong to the source radio
+    pub fn with_owner(mut self, owner: User) -> Self {
+        self.owner = Some(User { long_name: owner.long_name, short_name: owner.short_name, is_licensed: owner.is_licensed, ..Default::default() });
+        self
+    }
+
+    /// Blank the Wi-Fi PSK and MQTT password and mark the profile redacted,
+    /// so restoring it leaves a radio's own credentials in place
+    pub fn redact_secrets(mut self) -> Self {
+        if let Some(network) = self.config.network.as_mut() {
+            network.wifi_psk.clear();
+        }
+        if let Some(mqtt) = self.module_config.mqtt.as_mut() {
+            mqtt.password.clear();
+        }
+        self.redacted = true;
+        self
+    }
+
+    pub fn to_json(&self) -> Result<String, ProfileError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_yaml(&self) -> Result<String, ProfileError> {
+        Ok(serde_yaml::to_string(self)?)
+    }
+
+    /// Read a profile written as JSON or YAML
+    pub fn parse(text: &str) -> Result<Self, ProfileError> {
+        let profile: Self = if text.trim_start().starts_with('{') {
+            serde_json::from_str(text)?
+        } else {
+            serde_yaml::from_str(text)?
+        };
+        if profile.version != PROFILE_VERSION {
+            return Err(ProfileError::UnsupportedVersion(profile.version));
+        }
+        Ok(profile)
+    }
+
+    /// Session that writes the profile to a radio: owner, every channel
+    /// slot if the profile lists any channel, the config and module sections
+    /// present, then the fixed position
+    pub fn to_session(&self) -> ConfigSession {
+        let mut session = ConfigSession::new();
+        if let Some(owner) = &self.owner {
+            session = session.with_owner(owner.clone());
+        }
+        let channel_slots = if self.channels.is_empty() { 0 } else { MAX_CHANNELS as u32 };
+        for index in 0..channel_slots {
+            let channel = self.channels.iter().find(|channel| channel.index == index).cloned();
+            session = session.with_channel(channel.unwrap_or(Channel { index, ..Default::default() }));
+        }
+        for config in self.config.sections() {
+            session = session.with_config(config);
+        }
+        for config in self.module_config.sections() {
+            session = session.with_module_config(config);
+        }
+        if let Some(position) = &self.fixed_position {
+            session = session.with_fixed_position(position.clone());
+        }
+        session
+    }
+
+    /// Sections whose settings differ between `self`, read from a radio,
+    /// and `desired`. Sections `desired` leaves out are not compared.
+    pub fn changes_to(&self, desired: &DeviceProfile) -> Vec<SectionChange> {
+        let current: Vec<(String, Value)> = self.to_session().operations.iter().map(section_value).collect();
+        self.resolve(desired)
+            .to_session()
+            .operations
+            .iter()
+            .map(section_value)
+            .filter_map(|(section, desired)| {
+                let current = current.iter().find(|(name, _)| *name == section).map(|(_, value)| value.clone());
+                (current.as_ref() != Some(&desired)).then_some(SectionChange { section, current, desired })
+            })
+            .collect()
+    }
+
+    /// What it takes to bring a radio whose settings are `self` in line
+    /// with `desired`
+    pub fn plan(&self, desired: &DeviceProfile) -> ProfilePlan {
+        let sections = self.changes_to(desired);
+        let operations = self
+            .resolve(desired)
+            .to_session()
+            .operations
+            .into_iter()
+            .filter(|operation| sections.iter().any(|change| change.section == operation.describe()))
+            .collect();
+        ProfilePlan {
+            changes: sections.iter().flat_map(SectionChange::fields).collect(),
+            session: ConfigSession { operations },
+        }
+    }
+
+    /// Session that restores `desired` onto a radio whose settings are
+    /// `self`: only the sections that differ, or every section if `full`
+    pub fn restore_session(&self, desired: &DeviceProfile, full: bool) -> ConfigSession {
+        if full {
+            self.resolve(desired).to_session()
+        } else {
+            self.plan(desired).session
+        }
+    }
+
+    /// `desired` as it applies to this radio: a redacted profile takes the
+    /// radio's own secrets
+    fn resolve(&self, desired: &DeviceProfile) -> DeviceProfile {
+        let mut resolved = desired.clone();
+        if !desired.redacted {
+            return resolved;
+        }
+        if let (Some(network), Some(current)) = (resolved.config.network.as_mut(), &self.config.network) {
+            network.wifi_psk = current.wifi_psk.clone();
+        }
+        if let (Some(mqtt), Some(current)) = (resolved.module_config.mqtt.as_mut(), &self.module_config.mqtt) {
+            mqtt.password = current.password.clone();
+        }
+        resolved
+    }
+}
+
+/// One section a restore changes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SectionChange {
+    /// Section name as in session reports, e.g. `config LORA_CONFIG`
+    pub section: String,
+    /// Settings on the radio before the restore; `None` if it did not report them
+    pub current: Option<Value>,
+    pub desired: Value,
+}
+
+impl SectionChange {
+    /// The individual settings that differ, by dotted path within the section
+    pub fn fields(&self) -> Vec<FieldChange> {
+        let mut current = Vec::new();
+        if let Some(value) = &self.current {
+            leaves(unwrap_variant(value), String::new(), &mut current);
+        }
+        let mut desired = Vec::new();
+        leaves(unwrap_variant(&self.desired), String::new(), &mut desired);
+
+        desired
+            .into_iter()
+            .filter_map(|(field, desired)| {
+                let current = current.iter().find(|(name, _)| *name == field).map(|(_, value)| value.clone());
+                (current.as_ref() != Some(&desired)).then(|| FieldChange {
+                    section: self.section.clone(),
+                    field,
+                    current,
+                    desired,
+                })
+            })
+            .collect()
+    }
+}
+
+/// One setting that differs between a radio and a desired profile
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub section: String,
+    /// Path within the section, e.g. `hop_limit` or `settings.name`
+    pub field: String,
+    /// Value on the radio; `None` if it did not report the field
+    pub current: Option<Value>,
+    pub desired: Value,
+}
+
+/// Fields that differ between a radio and a desired profile, and the
+/// session that reconciles them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProfilePlan {
+    pub changes: Vec<FieldChange>,
+    /// Writes only the sections with changes
+    pub session: ConfigSession,
+}
+
+impl ProfilePlan {
+    /// True if the radio already matches the profile
+    pub fn is_empty(&self) -> bool {
+        self.session.is_empty()
+    }
+}
+
+/// Settings inside a `Config` or `ModuleConfig` wrapper, so paths read
+/// `hop_limit` rather than `variant.Lora.hop_limit`
+fn unwrap_variant(value: &Value) -> &Value {
+    match value.get("variant").and_then(Value::as_object) {
+        Some(variant) if variant.len() == 1 => variant.values().next().expect("one variant"),
+        _ => value,
+    }
+}
+
+/// Flatten nested objects into dotted paths. Lists, such as a PSK, are
+/// compared whole.
+fn leaves(value: &Value, path: String, out: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Object(fields) => {
+            for (name, field) in fields {
+                let path = if path.is_empty() { name.clone() } else { format!("{}.{}", path, name) };
+                leaves(field, path, out);
+            }
+        }
+        _ => out.push((path, value.clone())),
+    }
+}
+
+fn section_value(operation: &EditOperation) -> (String, Value) {
+    let value = match operation {
+        EditOperation::SetConfig(config) => serde_json::to_value(config),
+        EditOperation::SetModuleConfig(config) => serde_json::to_value(config),
+        EditOperation::SetChannel(channel) => serde_json::to_value(channel),
+        EditOperation::SetOwner(owner) => serde_json::to_value(owner),
+        EditOperation::SetFixedPosition(position) => serde_json::to_value(position),
+    };
+    (operation.describe(), value.expect("settings serialize to JSON"))
+}
+
+/// Outcome of restoring a profile onto a radio
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RestoreReport {
+    /// What the restore changed, compared with the radio's settings before it
+    pub changes: Vec<SectionChange>,
+    /// `None` when the radio already matched and nothing was sent
+    pub session: Option<SessionReport>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{ChannelSettings, Channel_Role, Role};
+
+    fn golden() -> DeviceProfile {
+        let mut profile = DeviceProfile::new().with_owner(User {
+            id: "!a1b2c3d4".to_string(),
+            long_name: "Relay North".to_string(),
+            short_name: "RLYN".to_string(),
+            ..Default::default()
+        });
+        profile.channels.push(Channel {
+            index: 0,
+            role: Channel_Role::PRIMARY,
+            settings: Some(ChannelSettings { name: "ops".to_string(), psk: vec![1], ..Default::default() }),
+        });
+        profile.config.insert(Config::new(config::Variant::Device(DeviceConfig { role: Role::ROUTER, ..Default::default() })));
+        profile.config.insert(Config::new(config::Variant::Lora(RadioConfig { use_preset: true, region: 3, hop_limit: 5, ..Default::default() })));
+        profile.module_config.insert(ModuleConfig::new(module_config::Variant::Mqtt(module_config::MqttConfig {
+            enabled: true,
+            ..Default::default()
+        })));
+        profile.fixed_position = Some(Position { latitude_i: 515_000_000, longitude_i: -1_200_000, ..Default::default() });
+        profile
+    }
+
+    #[test]
+    fn test_profile_round_trips_and_checks_version() {
+        let profile = golden();
+        assert!(profile.owner.as_ref().unwrap().id.is_empty());
+
+        for text in [profile.to_yaml().unwrap(), profile.to_json().unwrap()] {
+            let parsed = DeviceProfile::parse(&text).unwrap();
+            assert!(parsed.changes_to(&profile).is_empty());
+            assert_eq!(parsed.config.lora.as_ref().unwrap().hop_limit, 5);
+        }
+        assert!(profile.to_yaml().unwrap().contains("role: ROUTER"));
+
+        let future = profile.to_yaml().unwrap().replace("version: 1", "version: 2");
+        assert!(matches!(DeviceProfile::parse(&future), Err(ProfileError::UnsupportedVersion(2))));
+    }
+
+    #[test]
+    fn test_changes_cover_only_differing_sections() {
+        let desired = golden();
+        let mut current = golden();
+        current.config.lora.as_mut().unwrap().hop_limit = 3;
+        current.module_config.mqtt = None;
+        current.channels.push(Channel { index: 2, role: Channel_Role::SECONDARY, settings: Some(ChannelSettings::default()) });
+
+        let session = desired.to_session();
+        assert!(session.validate().is_ok());
+        assert_eq!(session.operations.len(), 1 + MAX_CHANNELS + 2 + 1 + 1);
+
+        let changes = current.changes_to(&desired);
+        let changed: Vec<(&str, bool)> = changes
+            .iter()
+            .map(|change| (change.section.as_str(), change.current.is_some()))
+            .collect();
+        assert_eq!(changed, [("channel 2", true), ("config LORA_CONFIG", true), ("module config MQTT_CONFIG", false)]);
+    }
+
+    #[test]
+    fn test_plan_lists_fields_and_writes_changed_sections() {
+        let desired = golden();
+        assert!(golden().plan(&desired).is_empty());
+
+        let mut current = golden();
+        current.config.lora.as_mut().unwrap().hop_limit = 3;
+        current.channels[0].settings.as_mut().unwrap().name = "old".to_string();
+
+        let plan = current.plan(&desired);
+        let fields: Vec<(&str, &str, Option<&Value>, &Value)> = plan
+            .changes
+            .iter()
+            .map(|change| (change.section.as_str(), change.field.as_str(), change.current.as_ref(), &change.desired))
+            .collect();
+        assert_eq!(fields, [
+            ("channel 0", "settings.name", Some(&Value::from("old")), &Value::from("ops")),
+            ("config LORA_CONFIG", "hop_limit", Some(&Value::from(3)), &Value::from(5)),
+        ]);
+        let sections: Vec<String> = plan.session.operations.iter().map(EditOperation::describe).collect();
+        assert_eq!(sections, ["channel 0", "config LORA_CONFIG"]);
+        assert!(plan.session.validate().is_ok());
+    }
+
+    #[test]
+    fn test_profile_without_channels_leaves_them_alone() {
+        let mut desired = DeviceProfile::new();
+        desired.config.insert(Config::new(config::Variant::Lora(RadioConfig { use_preset: true, region: 3, hop_limit: 7, ..Default::default() })));
+        assert_eq!(desired.to_session().operations.len(), 1);
+
+        let plan = golden().plan(&desired);
+        let sections: Vec<String> = plan.session.operations.iter().map(EditOperation::describe).collect();
+        assert_eq!(sections, ["config LORA_CONFIG"]);
+        assert!(plan.changes.iter().all(|change| change.section == "config LORA_CONFIG"));
+        assert_eq!(golden().restore_session(&desired, true).operations.len(), 1);
+    }
+
+    #[test]
+    fn test_redacted_profile_keeps_radio_secrets() {
+        let mut radio = golden();
+        radio.config.insert(Config::new(config::Variant::Network(NetworkConfig {
+            wifi_ssid: "base".to_string(),
+            wifi_psk: "hunter22".to_string(),
+            ..Default::default()
+        })));
+        radio.module_config.mqtt.as_mut().unwrap().password = "s3cret".to_string();
+
+        let backup = radio.clone().redact_secrets();
+        let yaml = backup.to_yaml().unwrap();
+        assert!(!yaml.contains("hunter22") && !yaml.contains("s3cret"));
+        assert!(yaml.contains("redacted: true"));
+
+        let backup = DeviceProfile::parse(&yaml).unwrap();
+        assert!(radio.changes_to(&backup).is_empty());
+        assert!(radio.restore_session(&backup, false).is_empty());
+        let full = radio.restore_session(&backup, true);
+        assert!(full.operations.iter().any(|operation| matches!(
+            operation,
+            EditOperation::SetConfig(Config { variant: Some(config::Variant::Network(network)) }) if network.wifi_psk == "hunter22"
+        )));
+    }
+}
@@ -0,0 +1,9 @@
+//! Types generated by prost-build from `proto/meshtastic.proto`.
+//!
+//! These mirror the firmware's wire format exactly; application code should
+//! work with the hand-written models in [`crate::protocol`] and go through
+//! [`crate::protocol::encode_packet`] / [`crate::protocol::decode_packet`].
+
+#![allow(clippy::all)]
+
+include!(concat!(env!("OUT_DIR"), "/meshtastic.rs"));
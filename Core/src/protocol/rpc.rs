@@ -0,0 +1,172 @@
+//! Request/response over the mesh.
+//!
+//! A request is a packet sent with `want_response`; the answer carries the
+//! request's packet id in `request_id`. [`PendingRequests`] holds one entry
+//! per request in flight and resolves it from the receive path. A routing
+//! error naming the request (e.g. `NO_RESPONSE` or `NO_ROUTE`) fails it;
+//! plain acks are ignored since the response is still to come.
+
+use super::{MeshPacket, PayloadVariant, RoutingVariant, Routing_Error, BROADCAST_ADDR};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// How long the manager's request helpers wait for a response
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum RequestError {
+    #[error("No response in time")]
+    Timeout,
+    #[error("Request cancelled")]
+    Cancelled,
+    #[error("Request failed: {0:?}")]
+    Failed(Routing_Error),
+}
+
+struct Waiter {
+    to: u32,
+    tx: oneshot::Sender<Result<MeshPacket, RequestError>>,
+}
+
+/// Requests waiting for their response, keyed by packet id. Cheap to clone;
+/// clones share the table.
+#[derive(Clone, Default)]
+pub struct PendingRequests {
+    waiters: Arc<Mutex<HashMap<u32, Waiter>>>,
+}
+
+impl PendingRequests {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start waiting for the response to `request`. Register before sending
+    /// so a fast response is not missed.
+    pub fn register(&self, request: &MeshPacket) -> PendingRequest {
+        let (tx, rx) = oneshot::channel();
+        self.waiters.lock().unwrap().insert(request.id, Waiter { to: request.to, tx });
+        PendingRequest { id: request.id, rx, table: self.clone() }
+    }
+
+    /// Feed an incoming packet to the table. Returns true if it answered a
+    /// pending request.
+    pub fn handle_packet(&self, packet: &MeshPacket) -> bool {
+        if packet.request_id == 0 {
+            return false;
+        }
+
+        let outcome = match &packet.payload {
+            Some(PayloadVariant::Routing(routing)) => match routing.variant {
+                Some(RoutingVariant::ErrorReason(Routing_Error::NONE)) | None => return false,
+                Some(RoutingVariant::ErrorReason(error)) => Err(RequestError::Failed(error)),
+                Some(_) => Ok(packet.clone()),
+            },
+            _ => Ok(packet.clone()),
+        };
+
+        let mut waiters = self.waiters.lock().unwrap();
+        let answers = waiters.get(&packet.request_id).is_some_and(|waiter| {
+            // Errors may come from our own radio or a relay
+            outcome.is_err() || waiter.to == packet.from || waiter.to == BROADCAST_ADDR
+        });
+        if !answers {
+            return false;
+        }
+
+        let waiter = waiters.remove(&packet.request_id).expect("waiter present");
+        let _ = waiter.tx.send(outcome);
+        true
+    }
+
+    /// Fail a pending request with `RequestError::Cancelled`. Returns false
+    /// if it was not pending.
+    pub fn cancel(&self, request_id: u32) -> bool {
+        match self.waiters.lock().unwrap().remove(&request_id) {
+            Some(waiter) => {
+                let _ = waiter.tx.send(Err(RequestError::Cancelled));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Ids of the requests still waiting for a response
+    pub fn pending(&self) -> Vec<u32> {
+        self.waiters.lock().unwrap().keys().copied().collect()
+    }
+}
+
+/// One request in flight. Dropping it withdraws the request.
+pub struct PendingRequest {
+    id: u32,
+    rx: oneshot::Receiver<Result<MeshPacket, RequestError>>,
+    table: PendingRequests,
+}
+
+impl PendingRequest {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Wait up to `timeout` for the response packet
+    pub async fn wait(mut self, timeout: Duration) -> Result<MeshPacket, RequestError> {
+        match tokio::time::timeout(timeout, &mut self.rx).await {
+            Ok(Ok(outcome)) => outcome,
+            Ok(Err(_)) => Err(RequestError::Cancelled),
+            Err(_) => Err(RequestError::Timeout),
+        }
+    }
+}
+
+impl Drop for PendingRequest {
+    fn drop(&mut self) {
+        self.table.waiters.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{Position, Routing};
+
+    #[tokio::test]
+    async fn test_response_resolves_matching_request() {
+        let table = PendingRequests::new();
+        let request = MeshPacket { to: 0x2222, want_response: true, ..MeshPacket::new_position(0x1111, Position::default()) };
+        let pending = table.register(&request);
+
+        let ack = MeshPacket {
+            from: 0x2222,
+            request_id: request.id,
+            payload: Some(PayloadVariant::Routing(Routing { variant: Some(RoutingVariant::ErrorReason(Routing_Error::NONE)) })),
+            ..Default::default()
+        };
+        let stranger = MeshPacket { from: 0x3333, request_id: request.id, ..MeshPacket::new_position(0x3333, Position::default()) };
+        let response = MeshPacket { request_id: request.id, ..MeshPacket::new_position(0x2222, Position::default()) };
+        assert!(!table.handle_packet(&ack));
+        assert!(!table.handle_packet(&stranger));
+        assert!(table.handle_packet(&response));
+
+        assert_eq!(pending.wait(Duration::from_secs(1)).await.unwrap().id, response.id);
+        assert!(table.pending().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_timeout_cancel_and_drop_clear_the_table() {
+        let table = PendingRequests::new();
+        let request = MeshPacket::new_position(0x1111, Position::default());
+
+        let pending = table.register(&request);
+        assert_eq!(pending.wait(Duration::from_millis(10)).await.unwrap_err(), RequestError::Timeout);
+        assert!(table.pending().is_empty());
+
+        let pending = table.register(&request);
+        assert!(table.cancel(request.id));
+        assert_eq!(pending.wait(Duration::from_secs(1)).await.unwrap_err(), RequestError::Cancelled);
+
+        drop(table.register(&request));
+        assert!(table.pending().is_empty());
+    }
+}
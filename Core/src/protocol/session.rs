@@ -0,0 +1,350 @@
+//! Batched settings changes.
+//!
+//! Every `SetConfig`, `SetChannel` or `SetOwner` applied on its own makes
+//! the radio save its settings, and most make it reboot. A
+//! [`ConfigSession`] collects several changes and sends them between
+//! `BeginEditSettings` and `CommitEditSettings`, so the radio saves and
+//! reboots once at the commit. The batch is checked locally before
+//! anything is sent.
+
+use super::{
+    admin_message, channels::MAX_CHANNELS, AdminMessage, Channel, Channel_Role, Config,
+    DeliveryStatus, ModuleConfig, PairingMode, Position, User,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Firmware field limits, in bytes without the terminating NUL
+const MAX_LONG_NAME_LEN: usize = 39;
+const MAX_SHORT_NAME_LEN: usize = 4;
+const MAX_CHANNEL_NAME_LEN: usize = 11;
+const MAX_TZDEF_LEN: usize = 64;
+const MAX_WIFI_SSID_LEN: usize = 32;
+const MAX_WIFI_PSK_LEN: usize = 63;
+const MAX_HOP_LIMIT: u32 = 7;
+/// `LoRaConfig.bandwidth` values in kHz, sub-GHz and 2.4 GHz
+const LORA_BANDWIDTHS: [u32; 14] = [7, 10, 15, 20, 31, 41, 62, 125, 250, 500, 203, 406, 812, 1625];
+
+/// Delay before the reboot that closes an aborted session. The firmware
+/// has no message to cancel an edit transaction, and while one is open it
+/// does not save any other settings change.
+pub const ABORT_REBOOT_SECONDS: u32 = 1;
+
+/// One change in a session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum EditOperation {
+    SetConfig(Config),
+    SetModuleConfig(ModuleConfig),
+    SetChannel(Channel),
+    SetOwner(User),
+    SetFixedPosition(Position),
+}
+
+impl EditOperation {
+    /// Short name for reports, e.g. `config LORA_CONFIG` or `channel 1`
+    pub fn describe(&self) -> String {
+        match self {
+            EditOperation::SetConfig(config) => match config.config_type() {
+                Some(config_type) => format!("config {:?}", config_type),
+                None => "config".to_string(),
+            },
+            EditOperation::SetModuleConfig(config) => match config.config_type() {
+                Some(config_type) => format!("module config {:?}", config_type),
+                None => "module config".to_string(),
+            },
+            EditOperation::SetChannel(channel) => format!("channel {}", channel.index),
+            EditOperation::SetOwner(_) => "owner".to_string(),
+            EditOperation::SetFixedPosition(_) => "fixed position".to_string(),
+        }
+    }
+
+    pub fn to_admin_message(&self) -> AdminMessage {
+        let variant = match self {
+            EditOperation::SetConfig(config) => admin_message::Variant::SetConfig(config.clone()),
+            EditOperation::SetModuleConfig(config) => admin_message::Variant::SetModuleConfig(config.clone()),
+            EditOperation::SetChannel(channel) => admin_message::Variant::SetChannel(channel.clone()),
+            EditOperation::SetOwner(user) => admin_message::Variant::SetOwner(user.clone()),
+            EditOperation::SetFixedPosition(position) => admin_message::Variant::SetFixedPosition(position.clone()),
+        };
+        AdminMessage { variant: Some(variant) }
+    }
+}
+
+/// Problem with one operation of a session
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EditIssue {
+    /// Position of the operation in the session
+    pub index: usize,
+    pub operation: String,
+    pub reason: String,
+}
+
+impl std::fmt::Display for EditIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{} ({}): {}", self.index, self.operation, self.reason)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SessionError {
+    #[error("Session has no operations")]
+    Empty,
+    #[error("Invalid settings: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    Invalid(Vec<EditIssue>),
+}
+
+/// Settings changes to apply to a radio in one edit transaction
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConfigSession {
+    pub operations: Vec<EditOperation>,
+}
+
+impl ConfigSession {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.operations.push(EditOperation::SetConfig(config));
+        self
+    }
+
+    pub fn with_module_config(mut self, config: ModuleConfig) -> Self {
+        self.operations.push(EditOperation::SetModuleConfig(config));
+        self
+    }
+
+    pub fn with_channel(mut self, channel: Channel) -> Self {
+        self.operations.push(EditOperation::SetChannel(channel));
+        self
+    }
+
+    pub fn with_owner(mut self, owner: User) -> Self {
+        self.operations.push(EditOperation::SetOwner(owner));
+        self
+    }
+
+    pub fn with_fixed_position(mut self, position: Position) -> Self {
+        self.operations.push(EditOperation::SetFixedPosition(position));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Check every operation against the firmware's limits. Reports all
+    /// problems, not just the first.
+    pub fn validate(&self) -> Result<(), SessionError> {
+        if self.operations.is_empty() {
+            return Err(SessionError::Empty);
+        }
+
+        let mut seen = HashSet::new();
+        let mut issues = Vec::new();
+        for (index, operation) in self.operations.iter().enumerate() {
+            let mut reasons = Vec::new();
+            // Two changes to the same target would leave the result to ordering
+            if !seen.insert(operation.describe()) {
+                reasons.push("changed more than once in the session".to_string());
+            }
+            match operation {
+                EditOperation::SetConfig(config) => check_config(config, &mut reasons),
+                EditOperation::SetModuleConfig(config) => {
+                    if config.variant.is_none() {
+                        reasons.push("no module settings".to_string());
+                    }
+                }
+                EditOperation::SetChannel(channel) => check_channel(channel, &mut reasons),
+                EditOperation::SetOwner(owner) => check_owner(owner, &mut reasons),
+                EditOperation::SetFixedPosition(position) => check_position(position, &mut reasons),
+            }
+            issues.extend(reasons.into_iter().map(|reason| EditIssue {
+                index,
+                operation: operation.describe(),
+                reason,
+            }));
+        }
+
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(SessionError::Invalid(issues))
+        }
+    }
+}
+
+fn check_config(config: &Config, reasons: &mut Vec<String>) {
+    use super::config::Variant;
+
+    match &config.variant {
+        None => reasons.push("no config section".to_string()),
+        Some(Variant::Device(device)) => {
+            if device.tzdef.len() > MAX_TZDEF_LEN {
+                reasons.push(format!("tzdef is longer than {} bytes", MAX_TZDEF_LEN));
+            }
+        }
+        Some(Variant::Network(network)) => {
+            if network.wifi_ssid.len() > MAX_WIFI_SSID_LEN {
+                reasons.push(format!("Wi-Fi SSID is longer than {} bytes", MAX_WIFI_SSID_LEN));
+            }
+            if !network.wifi_psk.is_empty() && !(8..=MAX_WIFI_PSK_LEN).contains(&network.wifi_psk.len()) {
+                reasons.push(format!("Wi-Fi password must be 8 to {} bytes", MAX_WIFI_PSK_LEN));
+            }
+        }
+        Some(Variant::Lora(lora)) => {
+            if lora.hop_limit > MAX_HOP_LIMIT {
+                reasons.push(format!("hop limit {} is above {}", lora.hop_limit, MAX_HOP_LIMIT));
+            }
+            if !(0..=30).contains(&lora.tx_power) {
+                reasons.push(format!("TX power {} dBm is outside 0-30", lora.tx_power));
+            }
+            if lora.use_preset && !(0..=8).contains(&lora.modem_preset) {
+                reasons.push(format!("unknown modem preset {}", lora.modem_preset));
+            }
+            if !lora.use_preset {
+                if !(7..=12).contains(&lora.spread_factor) {
+                    reasons.push(format!("spreading factor {} is outside 7-12", lora.spread_factor));
+                }
+                if !(5..=8).contains(&lora.coding_rate) {
+                    reasons.push(format!("coding rate {} is outside 5-8", lora.coding_rate));
+                }
+                if !LORA_BANDWIDTHS.contains(&lora.bandwidth) {
+                    reasons.push(format!("bandwidth {} kHz is not supported", lora.bandwidth));
+                }
+            }
+        }
+        Some(Variant::Bluetooth(bluetooth)) => {
+            if bluetooth.mode == PairingMode::FIXED_PIN && !(100_000..=999_999).contains(&bluetooth.fixed_pin) {
+                reasons.push("fixed PIN must have six digits".to_string());
+            }
+        }
+        Some(Variant::Position(_)) | Some(Variant::Power(_)) | Some(Variant::Display(_)) => {}
+    }
+}
+
+fn check_channel(channel: &Channel, reasons: &mut Vec<String>) {
+    if channel.index as usize >= MAX_CHANNELS {
+        reasons.push(format!("index is above {}", MAX_CHANNELS - 1));
+    }
+    match (channel.index, channel.role) {
+        (0, Channel_Role::PRIMARY) => {}
+        (0, _) => reasons.push("channel 0 must stay primary".to_string()),
+        (_, Channel_Role::PRIMARY) => reasons.push("only channel 0 can be primary".to_string()),
+        _ => {}
+    }
+    if channel.role == Channel_Role::DISABLED {
+        return;
+    }
+    let Some(settings) = &channel.settings else {
+        reasons.push("no channel settings".to_string());
+        return;
+    };
+    if settings.name.len() > MAX_CHANNEL_NAME_LEN {
+        reasons.push(format!("name is longer than {} bytes", MAX_CHANNEL_NAME_LEN));
+    }
+    // Other lengths get zero-padded into a key, which is rarely intended
+    if !matches!(settings.psk.len(), 0 | 1 | 16 | 32) {
+        reasons.push(format!("PSK is {} bytes; expected 0, 1, 16 or 32", settings.psk.len()));
+    }
+}
+
+fn check_owner(owner: &User, reasons: &mut Vec<String>) {
+    if owner.long_name.is_empty() || owner.long_name.len() > MAX_LONG_NAME_LEN {
+        reasons.push(format!("long name must be 1 to {} bytes", MAX_LONG_NAME_LEN));
+    }
+    if owner.short_name.is_empty() || owner.short_name.len() > MAX_SHORT_NAME_LEN {
+        reasons.push(format!("short name must be 1 to {} bytes", MAX_SHORT_NAME_LEN));
+    }
+}
+
+fn check_position(position: &Position, reasons: &mut Vec<String>) {
+    if !(-900_000_000..=900_000_000).contains(&position.latitude_i) {
+        reasons.push("latitude is outside -90 to 90 degrees".to_string());
+    }
+    if !(-1_800_000_000..=1_800_000_000).contains(&position.longitude_i) {
+        reasons.push("longitude is outside -180 to 180 degrees".to_string());
+    }
+}
+
+/// What the radio made of one operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OperationReport {
+    pub index: usize,
+    pub operation: String,
+    /// `Delivered` once the radio acked the change; `Failed` with the
+    /// radio's reason otherwise
+    pub status: DeliveryStatus,
+}
+
+impl OperationReport {
+    pub fn accepted(&self) -> bool {
+        matches!(self.status, DeliveryStatus::Delivered | DeliveryStatus::ImplicitAck)
+    }
+}
+
+/// Outcome of applying a session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionReport {
+    /// Answer to `BeginEditSettings`. When it failed no operations were sent.
+    pub begin: DeliveryStatus,
+    /// Operations sent, in order. Ends at the first one the radio rejected.
+    pub operations: Vec<OperationReport>,
+    /// Index of the rejected operation that stopped the session; nothing
+    /// after it was sent and the commit was skipped
+    pub aborted_at: Option<usize>,
+    /// Answer to the reboot sent after an abort, which closes the edit
+    /// transaction and drops the changes sent before the rejected one
+    pub abort_reboot: Option<DeliveryStatus>,
+    /// Answer to `CommitEditSettings`; `None` when the session never got
+    /// that far. The radio may reboot before acking the commit.
+    pub commit: Option<DeliveryStatus>,
+}
+
+impl SessionReport {
+    /// True if the radio acked the begin, every operation and the commit
+    pub fn all_accepted(&self) -> bool {
+        let acked = |status: &DeliveryStatus| matches!(status, DeliveryStatus::Delivered | DeliveryStatus::ImplicitAck);
+        acked(&self.begin)
+            && self.operations.iter().all(OperationReport::accepted)
+            && self.commit.as_ref().is_some_and(acked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{config, BluetoothConfig, ChannelSettings, RadioConfig};
+
+    #[test]
+    fn test_validate_reports_every_issue() {
+        let session = ConfigSession::new()
+            .with_owner(User { long_name: "Base camp".to_string(), short_name: "BASE1".to_string(), ..Default::default() })
+            .with_channel(Channel {
+                index: 1,
+                role: Channel_Role::SECONDARY,
+                settings: Some(ChannelSettings { name: "ops".to_string(), psk: vec![7; 5], ..Default::default() }),
+            })
+            .with_config(Config::new(config::Variant::Lora(RadioConfig { use_preset: true, hop_limit: 9, ..Default::default() })))
+            .with_config(Config::new(config::Variant::Bluetooth(BluetoothConfig { enabled: true, ..Default::default() })))
+            .with_config(Config::new(config::Variant::Bluetooth(BluetoothConfig::default())));
+
+        let Err(SessionError::Invalid(issues)) = session.validate() else {
+            panic!("session should be invalid");
+        };
+        let found: Vec<(usize, &str)> = issues.iter().map(|issue| (issue.index, issue.operation.as_str())).collect();
+        assert_eq!(found, [
+            (0, "owner"),
+            (1, "channel 1"),
+            (2, "config LORA_CONFIG"),
+            (4, "config BLUETOOTH_CONFIG"),
+        ]);
+        assert!(issues[3].reason.contains("more than once"));
+
+        assert_eq!(ConfigSession::new().validate(), Err(SessionError::Empty));
+        let valid = ConfigSession::new()
+            .with_owner(User { long_name: "Base camp".to_string(), short_name: "BASE".to_string(), ..Default::default() })
+            .with_channel(Channel { index: 2, role: Channel_Role::DISABLED, settings: None });
+        assert!(valid.validate().is_ok());
+    }
+}
@@ -0,0 +1,118 @@
+//! Traceroute on `TRACEROUTE_APP`.
+//!
+//! The request is an empty `RouteDiscovery` sent with `want_response`. Every
+//! node that relays it appends itself to `route` and the SNR it heard the
+//! packet at to `snr_towards`; the destination adds its own SNR and answers
+//! with the record, which collects `route_back`/`snr_back` the same way.
+//! SNRs are in quarter dB, with `i8::MIN` for a hop whose SNR is unknown
+//! (e.g. relayed through MQTT).
+
+use super::{proto, MeshPacket, PayloadVariant, PortNum, ProtocolError, RouteDiscovery};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How long `LoraCommsManager::traceroute` waits for the reply
+pub const TRACEROUTE_TIMEOUT: Duration = Duration::from_secs(60);
+
+const UNKNOWN_SNR: i32 = i8::MIN as i32;
+
+/// One hop of a route: the node that received the packet and the SNR it
+/// heard it at
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RouteHop {
+    pub node: u32,
+    /// dB; `None` when the hop did not report it
+    pub snr: Option<f32>,
+}
+
+/// Result of a traceroute
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceRoute {
+    /// Node that started the traceroute
+    pub origin: u32,
+    pub destination: u32,
+    /// Hops towards the destination, ending with the destination itself
+    pub forward: Vec<RouteHop>,
+    /// Hops of the reply, ending with the origin. Empty when the
+    /// destination's firmware does not record the way back.
+    pub back: Vec<RouteHop>,
+}
+
+impl TraceRoute {
+    /// Read the route from the destination's reply
+    pub fn from_reply(origin: u32, destination: u32, discovery: &RouteDiscovery) -> Self {
+        let back = if discovery.snr_back.is_empty() {
+            Vec::new()
+        } else {
+            hops(&discovery.route_back, origin, &discovery.snr_back)
+        };
+        Self {
+            origin,
+            destination,
+            forward: hops(&discovery.route, destination, &discovery.snr_towards),
+            back,
+        }
+    }
+}
+
+fn hops(route: &[u32], last: u32, snrs: &[i32]) -> Vec<RouteHop> {
+    route.iter().copied().chain(std::iter::once(last))
+        .enumerate()
+        .map(|(i, node)| RouteHop {
+            node,
+            snr: snrs.get(i).filter(|&&snr| snr != UNKNOWN_SNR).map(|&snr| snr as f32 / 4.0),
+        })
+        .collect()
+}
+
+/// Traceroute request for node `to`
+pub fn traceroute_request(from: u32, to: u32, channel: u8) -> MeshPacket {
+    let discovery = proto::RouteDiscovery::from(&RouteDiscovery::default());
+    MeshPacket {
+        from,
+        to,
+        channel,
+        want_response: true,
+        payload: Some(PayloadVariant::App(PortNum::TRACEROUTE_APP, discovery.encode_to_vec())),
+        ..Default::default()
+    }
+}
+
+/// Decode the route record carried on `TRACEROUTE_APP`
+pub fn decode_route(payload: &[u8]) -> Result<RouteDiscovery, ProtocolError> {
+    proto::RouteDiscovery::decode(payload)
+        .map(Into::into)
+        .map_err(|e| ProtocolError::Decoding(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hops_include_endpoints_and_snr() {
+        let discovery = RouteDiscovery {
+            route: vec![0x3333],
+            snr_towards: vec![24, UNKNOWN_SNR],
+            route_back: vec![0x4444],
+            snr_back: vec![-10, 28],
+        };
+        let trace = TraceRoute::from_reply(0x1111, 0x2222, &discovery);
+        assert_eq!(trace.forward, [
+            RouteHop { node: 0x3333, snr: Some(6.0) },
+            RouteHop { node: 0x2222, snr: None },
+        ]);
+        assert_eq!(trace.back, [
+            RouteHop { node: 0x4444, snr: Some(-2.5) },
+            RouteHop { node: 0x1111, snr: Some(7.0) },
+        ]);
+
+        let request = traceroute_request(0x1111, 0x2222, 0);
+        assert!(request.want_response);
+        let Some(PayloadVariant::App(PortNum::TRACEROUTE_APP, payload)) = request.payload else {
+            panic!("not a traceroute request");
+        };
+        assert!(decode_route(&payload).unwrap().route.is_empty());
+    }
+}
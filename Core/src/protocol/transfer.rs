@@ -0,0 +1,767 @@
+//! File transfers between nodes on [`PortNum::FILE_TRANSFER_APP`].
+//!
+//! The sender offers a file with its name, size and SHA-256 digest. Once the
+//! receiver accepts, chunks go out one at a time, each waiting for the
+//! receiver's ack, and spaced so the transfer stays within the region's duty
+//! cycle as computed by [`RadioManager::calculate_air_time_ms`]. Acks and
+//! accepts carry the number of chunks received so far, so a transfer that
+//! stalled (e.g. the radio was disconnected) picks up where it stopped when
+//! the sender offers it again. The receiver checks the digest before the file
+//! is written.
+//!
+//! Frames start with a kind byte and the little-endian transfer id:
+//!
+//! ```text
+//! offer:    0 | id (4) | size (4) | chunk size (2) | sha256 (32) | name
+//! accept:   1 | id (4) | chunks received (4)
+//! reject:   2 | id (4)
+//! chunk:    3 | id (4) | index (4) | data
+//! ack:      4 | id (4) | chunks received (4)
+//! complete: 5 | id (4) | outcome (1)
+//! cancel:   6 | id (4)
+//! ```
+
+use super::{split_utf8, Data, MeshPacket, PayloadSize, PayloadVariant, PortHandler, PortNum, ProtocolError};
+use crate::radio::{RadioConfig, RadioManager};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, Notify};
+use tokio::time::Instant;
+
+const OFFER_HEADER_LEN: usize = 43;
+const CHUNK_HEADER_LEN: usize = 9;
+/// Meshtastic header in front of the encrypted `Data` on air
+const LORA_HEADER_LEN: usize = 16;
+/// Floor for `max_channel_share`, so pacing never divides by zero
+const MIN_CHANNEL_SHARE: f32 = 0.1;
+
+const OUTCOME_OK: u8 = 0;
+const OUTCOME_CORRUPTED: u8 = 1;
+const OUTCOME_NOT_SAVED: u8 = 2;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TransferError {
+    #[error("No file transfer {0:08x}")]
+    NotFound(u32),
+    #[error("File transfer {id:08x} is {status:?}")]
+    InvalidState { id: u32, status: TransferStatus },
+    #[error("File of {size} bytes exceeds the {max} byte limit")]
+    TooLarge { size: usize, max: usize },
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// One frame on the file transfer port
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferFrame {
+    Offer { id: u32, size: u32, chunk_size: u16, sha256: [u8; 32], name: String },
+    Accept { id: u32, received: u32 },
+    Reject { id: u32 },
+    Chunk { id: u32, index: u32, data: Vec<u8> },
+    Ack { id: u32, received: u32 },
+    /// Sent by the receiver once every chunk is in; `outcome` is 0 when the
+    /// file was verified and saved, 1 on a digest mismatch, 2 when it could
+    /// not be saved
+    Complete { id: u32, outcome: u8 },
+    Cancel { id: u32 },
+}
+
+impl TransferFrame {
+    pub fn encode(&self) -> Vec<u8> {
+        let (kind, id) = match self {
+            TransferFrame::Offer { id, .. } => (0u8, id),
+            TransferFrame::Accept { id, .. } => (1, id),
+            TransferFrame::Reject { id } => (2, id),
+            TransferFrame::Chunk { id, .. } => (3, id),
+            TransferFrame::Ack { id, .. } => (4, id),
+            TransferFrame::Complete { id, .. } => (5, id),
+            TransferFrame::Cancel { id } => (6, id),
+        };
+        let mut bytes = vec![kind];
+        bytes.extend_from_slice(&id.to_le_bytes());
+
+        match self {
+            TransferFrame::Offer { size, chunk_size, sha256, name, .. } => {
+                bytes.extend_from_slice(&size.to_le_bytes());
+                bytes.extend_from_slice(&chunk_size.to_le_bytes());
+                bytes.extend_from_slice(sha256);
+                bytes.extend_from_slice(name.as_bytes());
+            }
+            TransferFrame::Accept { received, .. } | TransferFrame::Ack { received, .. } => {
+                bytes.extend_from_slice(&received.to_le_bytes());
+            }
+            TransferFrame::Chunk { index, data, .. } => {
+                bytes.extend_from_slice(&index.to_le_bytes());
+                bytes.extend_from_slice(data);
+            }
+            TransferFrame::Complete { outcome, .. } => bytes.push(*outcome),
+            TransferFrame::Reject { .. } | TransferFrame::Cancel { .. } => {}
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, ProtocolError> {
+        let malformed = || ProtocolError::Decoding("malformed file transfer frame".to_string());
+        let u32_at = |at: usize| -> Result<u32, ProtocolError> {
+            let field = bytes.get(at..at + 4).ok_or_else(malformed)?;
+            Ok(u32::from_le_bytes(field.try_into().unwrap()))
+        };
+        let id = u32_at(1)?;
+
+        let frame = match bytes[0] {
+            0 => {
+                let header = bytes.get(..OFFER_HEADER_LEN).ok_or_else(malformed)?;
+                TransferFrame::Offer {
+                    id,
+                    size: u32_at(5)?,
+                    chunk_size: u16::from_le_bytes([header[9], header[10]]),
+                    sha256: header[11..43].try_into().unwrap(),
+                    name: String::from_utf8_lossy(&bytes[OFFER_HEADER_LEN..]).into_owned(),
+                }
+            }
+            1 => TransferFrame::Accept { id, received: u32_at(5)? },
+            2 => TransferFrame::Reject { id },
+            3 => TransferFrame::Chunk {
+                id,
+                index: u32_at(5)?,
+                data: bytes[CHUNK_HEADER_LEN..].to_vec(),
+            },
+            4 => TransferFrame::Ack { id, received: u32_at(5)? },
+            5 => TransferFrame::Complete { id, outcome: *bytes.get(5).ok_or_else(malformed)? },
+            6 => TransferFrame::Cancel { id },
+            _ => return Err(malformed()),
+        };
+        Ok(frame)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransferDirection {
+    Outgoing,
+    Incoming,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransferStatus {
+    /// Offer sent or received, waiting for the receiver to accept
+    Offered,
+    InProgress,
+    /// The peer stopped answering; resuming continues from the last acked
+    /// chunk
+    Paused,
+    /// Received in full, verified and saved
+    Completed,
+    /// Received in full but the digest did not match; nothing was saved
+    Corrupted,
+    Rejected,
+    Cancelled,
+    /// Received and verified, but the file could not be saved
+    Failed(String),
+}
+
+impl TransferStatus {
+    pub fn is_final(&self) -> bool {
+        !matches!(self, TransferStatus::Offered | TransferStatus::InProgress | TransferStatus::Paused)
+    }
+}
+
+/// State of one transfer; also the progress event sent on every change
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransferInfo {
+    pub transfer_id: u32,
+    pub direction: TransferDirection,
+    /// Node the file goes to or comes from
+    pub peer: u32,
+    pub name: String,
+    pub size: usize,
+    /// Bytes acked by the receiver (outgoing) or received (incoming)
+    pub transferred: usize,
+    pub status: TransferStatus,
+    /// Where an accepted incoming file is written
+    pub path: Option<PathBuf>,
+}
+
+/// Timing and limits of file transfers
+#[derive(Debug, Clone, Copy)]
+pub struct TransferConfig {
+    /// How long to wait for an answer to an offer or chunk before sending it
+    /// again
+    pub ack_timeout: Duration,
+    /// Transmissions of one offer or chunk before the transfer is paused
+    pub max_attempts: u32,
+    /// Share of airtime, in percent, a transfer may use even where the
+    /// region sets no duty cycle limit. Anything below 0.1 counts as 0.1.
+    pub max_channel_share: f32,
+    /// Largest file sent or accepted
+    pub max_file_size: usize,
+}
+
+impl Default for TransferConfig {
+    fn default() -> Self {
+        Self {
+            ack_timeout: Duration::from_secs(30),
+            max_attempts: 4,
+            max_channel_share: 10.0,
+            max_file_size: 256 * 1024,
+        }
+    }
+}
+
+struct Outgoing {
+    info: TransferInfo,
+    channel: u8,
+    data: Vec<u8>,
+    sha256: [u8; 32],
+    accepted: bool,
+    /// Chunks the receiver has confirmed
+    acked: u32,
+    running: bool,
+    /// Wakes the sending task when the receiver answers
+    notify: Arc<Notify>,
+}
+
+struct Incoming {
+    info: TransferInfo,
+    channel: u8,
+    chunk_size: usize,
+    sha256: [u8; 32],
+    data: Vec<u8>,
+    received: u32,
+    outcome: Option<u8>,
+}
+
+impl Incoming {
+    fn total_chunks(&self) -> u32 {
+        self.info.size.div_ceil(self.chunk_size) as u32
+    }
+}
+
+#[derive(Default)]
+struct State {
+    outgoing: HashMap<u32, Outgoing>,
+    incoming: HashMap<u32, Incoming>,
+}
+
+/// Sends and receives files. Register it as the [`PortHandler`] for
+/// `FILE_TRANSFER_APP`. Packets go to `outbound` with `from` left at 0 for
+/// the sending device to fill in. Cheap to clone; clones share state.
+#[derive(Clone)]
+pub struct TransferService {
+    config: TransferConfig,
+    radio: Arc<Mutex<RadioConfig>>,
+    state: Arc<Mutex<State>>,
+    outbound: mpsc::UnboundedSender<MeshPacket>,
+    updates: broadcast::Sender<TransferInfo>,
+}
+
+impl TransferService {
+    pub fn new(outbound: mpsc::UnboundedSender<MeshPacket>) -> Self {
+        Self {
+            config: TransferConfig::default(),
+            radio: Arc::new(Mutex::new(RadioConfig::default())),
+            state: Arc::new(Mutex::new(State::default())),
+            outbound,
+            updates: broadcast::channel(256).0,
+        }
+    }
+
+    pub fn with_config(mut self, config: TransferConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Radio settings used to pace chunks
+    pub fn set_radio_config(&self, radio: RadioConfig) {
+        *self.radio.lock().unwrap() = radio;
+    }
+
+    /// Progress of every transfer, sent whenever one changes
+    pub fn subscribe(&self) -> broadcast::Receiver<TransferInfo> {
+        self.updates.subscribe()
+    }
+
+    /// All transfers this session, outgoing and incoming
+    pub fn transfers(&self) -> Vec<TransferInfo> {
+        let state = self.state.lock().unwrap();
+        state.outgoing.values().map(|t| t.info.clone())
+            .chain(state.incoming.values().map(|t| t.info.clone()))
+            .collect()
+    }
+
+    /// File bytes carried by each chunk
+    pub fn chunk_size() -> usize {
+        let empty = MeshPacket {
+            payload: Some(PayloadVariant::App(PortNum::FILE_TRANSFER_APP, Vec::new())),
+            ..Default::default()
+        };
+        PayloadSize::of(&empty).map(|size| size.max_payload_bytes).unwrap_or(0) - CHUNK_HEADER_LEN
+    }
+
+    /// Offer `data` to node `to` as a file called `name` and keep sending in
+    /// the background once it is accepted. Returns the transfer id.
+    pub fn send(&self, to: u32, channel: u8, name: &str, data: Vec<u8>) -> Result<u32, TransferError> {
+        if data.len() > self.config.max_file_size {
+            return Err(TransferError::TooLarge { size: data.len(), max: self.config.max_file_size });
+        }
+
+        // The offer carries the name, so it gets whatever room is left
+        let name = split_utf8(name, Self::chunk_size() + CHUNK_HEADER_LEN - OFFER_HEADER_LEN)[0];
+        let id: u32 = rand::random();
+        let transfer = Outgoing {
+            info: TransferInfo {
+                transfer_id: id,
+                direction: TransferDirection::Outgoing,
+                peer: to,
+                name: name.to_string(),
+                size: data.len(),
+                transferred: 0,
+                status: TransferStatus::Offered,
+                path: None,
+            },
+            channel,
+            sha256: Sha256::digest(&data).into(),
+            data,
+            accepted: false,
+            acked: 0,
+            running: false,
+            notify: Arc::new(Notify::new()),
+        };
+
+        let _ = self.updates.send(transfer.info.clone());
+        self.state.lock().unwrap().outgoing.insert(id, transfer);
+        self.start(id);
+        Ok(id)
+    }
+
+    /// Accept an offered file, to be written to `path` once verified
+    pub fn accept(&self, id: u32, path: PathBuf) -> Result<(), TransferError> {
+        let mut state = self.state.lock().unwrap();
+        let transfer = state.incoming.get_mut(&id).ok_or(TransferError::NotFound(id))?;
+        if transfer.info.status != TransferStatus::Offered {
+            return Err(TransferError::InvalidState { id, status: transfer.info.status.clone() });
+        }
+
+        transfer.info.path = Some(path);
+        self.update(&mut transfer.info, TransferStatus::InProgress);
+        if transfer.total_chunks() == 0 {
+            self.finish(transfer);
+        } else {
+            self.reply(transfer.info.peer, transfer.channel, TransferFrame::Accept { id, received: 0 });
+        }
+        Ok(())
+    }
+
+    /// Turn down an offered file, or stop receiving one
+    pub fn reject(&self, id: u32) -> Result<(), TransferError> {
+        let mut state = self.state.lock().unwrap();
+        let transfer = state.incoming.get_mut(&id).ok_or(TransferError::NotFound(id))?;
+        if transfer.info.status.is_final() {
+            return Err(TransferError::InvalidState { id, status: transfer.info.status.clone() });
+        }
+
+        transfer.data = Vec::new();
+        self.update(&mut transfer.info, TransferStatus::Rejected);
+        self.reply(transfer.info.peer, transfer.channel, TransferFrame::Reject { id });
+        Ok(())
+    }
+
+    /// Stop sending a file and tell the receiver
+    pub fn cancel(&self, id: u32) -> Result<(), TransferError> {
+        let mut state = self.state.lock().unwrap();
+        let transfer = state.outgoing.get_mut(&id).ok_or(TransferError::NotFound(id))?;
+        if transfer.info.status.is_final() {
+            return Err(TransferError::InvalidState { id, status: transfer.info.status.clone() });
+        }
+
+        self.update(&mut transfer.info, TransferStatus::Cancelled);
+        self.reply(transfer.info.peer, transfer.channel, TransferFrame::Cancel { id });
+        transfer.notify.notify_one();
+        Ok(())
+    }
+
+    /// Offer a paused transfer again; the receiver answers with the chunks
+    /// it already has
+    pub fn resume(&self, id: u32) -> Result<(), TransferError> {
+        {
+            let mut state = self.state.lock().unwrap();
+            let transfer = state.outgoing.get_mut(&id).ok_or(TransferError::NotFound(id))?;
+            if transfer.info.status != TransferStatus::Paused {
+                return Err(TransferError::InvalidState { id, status: transfer.info.status.clone() });
+            }
+            transfer.accepted = false;
+            self.update(&mut transfer.info, TransferStatus::Offered);
+        }
+        self.start(id);
+        Ok(())
+    }
+
+    /// Resume every paused outgoing transfer, e.g. after reconnecting
+    pub fn resume_paused(&self) {
+        let paused: Vec<u32> = self.state.lock().unwrap().outgoing.values()
+            .filter(|t| t.info.status == TransferStatus::Paused)
+            .map(|t| t.info.transfer_id)
+            .collect();
+        for id in paused {
+            let _ = self.resume(id);
+        }
+    }
+
+    /// Handle a frame received on the file transfer port
+    pub fn handle_frame(&self, packet: &MeshPacket, payload: &[u8]) -> Result<(), ProtocolError> {
+        let frame = TransferFrame::decode(payload)?;
+        let mut state = self.state.lock().unwrap();
+
+        match frame {
+            TransferFrame::Offer { id, size, chunk_size, sha256, name } => {
+                match state.incoming.get_mut(&id) {
+                    Some(transfer) if transfer.info.peer == packet.from => self.reoffered(transfer),
+                    Some(_) => {}
+                    None => {
+                        if size as usize > self.config.max_file_size || chunk_size == 0 {
+                            self.reply(packet.from, packet.channel, TransferFrame::Reject { id });
+                            return Ok(());
+                        }
+                        let info = TransferInfo {
+                            transfer_id: id,
+                            direction: TransferDirection::Incoming,
+                            peer: packet.from,
+                            name,
+                            size: size as usize,
+                            transferred: 0,
+                            status: TransferStatus::Offered,
+                            path: None,
+                        };
+                        let _ = self.updates.send(info.clone());
+                        state.incoming.insert(id, Incoming {
+                            info,
+                            channel: packet.channel,
+                            chunk_size: chunk_size as usize,
+                            sha256,
+                            data: Vec::new(),
+                            received: 0,
+                            outcome: None,
+                        });
+                    }
+                }
+            }
+            TransferFrame::Chunk { id, index, data } => {
+                if let Some(transfer) = state.incoming.get_mut(&id).filter(|t| t.info.peer == packet.from) {
+                    self.receive_chunk(transfer, index, data);
+                }
+            }
+            TransferFrame::Cancel { id } => {
+                if let Some(transfer) = state.incoming.get_mut(&id).filter(|t| t.info.peer == packet.from) {
+                    if !transfer.info.status.is_final() {
+                        transfer.data = Vec::new();
+                        self.update(&mut transfer.info, TransferStatus::Cancelled);
+                    }
+                }
+            }
+            TransferFrame::Accept { id, received } | TransferFrame::Ack { id, received } => {
+                if let Some(transfer) = self.outgoing_from(&mut state, id, packet.from) {
+                    let total = transfer.data.len().div_ceil(Self::chunk_size()) as u32;
+                    transfer.accepted = true;
+                    transfer.acked = transfer.acked.max(received.min(total));
+                    transfer.info.transferred = (transfer.acked as usize * Self::chunk_size()).min(transfer.info.size);
+                    self.update(&mut transfer.info, TransferStatus::InProgress);
+                    transfer.notify.notify_one();
+                }
+            }
+            TransferFrame::Reject { id } => {
+                if let Some(transfer) = self.outgoing_from(&mut state, id, packet.from) {
+                    self.update(&mut transfer.info, TransferStatus::Rejected);
+                    transfer.notify.notify_one();
+                }
+            }
+            TransferFrame::Complete { id, outcome } => {
+                if let Some(transfer) = self.outgoing_from(&mut state, id, packet.from) {
+                    let status = match outcome {
+                        OUTCOME_OK => TransferStatus::Completed,
+                        OUTCOME_CORRUPTED => TransferStatus::Corrupted,
+                        _ => TransferStatus::Failed("receiver could not save the file".to_string()),
+                    };
+                    if status == TransferStatus::Completed {
+                        transfer.info.transferred = transfer.info.size;
+                    }
+                    self.update(&mut transfer.info, status);
+                    transfer.notify.notify_one();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Outgoing transfer `id` if `from` is its receiver and it is still going
+    fn outgoing_from<'a>(&self, state: &'a mut State, id: u32, from: u32) -> Option<&'a mut Outgoing> {
+        state.outgoing.get_mut(&id)
+            .filter(|t| t.info.peer == from && !t.info.status.is_final())
+    }
+
+    /// Answer an offer for a transfer we already know, which the sender
+    /// repeats when resuming or when our answer was lost
+    fn reoffered(&self, transfer: &mut Incoming) {
+        let id = transfer.info.transfer_id;
+        let frame = match (&transfer.info.status, transfer.outcome) {
+            (_, Some(outcome)) => TransferFrame::Complete { id, outcome },
+            (TransferStatus::InProgress | TransferStatus::Paused, _) => {
+                TransferFrame::Accept { id, received: transfer.received }
+            }
+            (TransferStatus::Offered, _) => return,
+            _ => TransferFrame::Reject { id },
+        };
+        self.reply(transfer.info.peer, transfer.channel, frame);
+    }
+
+    fn receive_chunk(&self, transfer: &mut Incoming, index: u32, data: Vec<u8>) {
+        let id = transfer.info.transfer_id;
+        if let Some(outcome) = transfer.outcome {
+            self.reply(transfer.info.peer, transfer.channel, TransferFrame::Complete { id, outcome });
+            return;
+        }
+        if transfer.info.status != TransferStatus::InProgress {
+            return;
+        }
+
+        // Chunks arrive in order; anything else is a repeat or a gap, and the
+        // ack tells the sender where we are
+        if index == transfer.received && transfer.data.len() + data.len() <= transfer.info.size {
+            transfer.data.extend_from_slice(&data);
+            transfer.received += 1;
+            transfer.info.transferred = transfer.data.len();
+            self.update(&mut transfer.info, TransferStatus::InProgress);
+            if transfer.received == transfer.total_chunks() {
+                self.finish(transfer);
+                return;
+            }
+        }
+        self.reply(transfer.info.peer, transfer.channel, TransferFrame::Ack { id, received: transfer.received });
+    }
+
+    /// Verify a fully received file, save it and tell the sender
+    fn finish(&self, transfer: &mut Incoming) {
+        let data = std::mem::take(&mut transfer.data);
+        let (outcome, status) = if <[u8; 32]>::from(Sha256::digest(&data)) != transfer.sha256 {
+            (OUTCOME_CORRUPTED, TransferStatus::Corrupted)
+        } else {
+            let path = transfer.info.path.clone().unwrap_or_default();
+            match std::fs::write(&path, &data) {
+                Ok(()) => (OUTCOME_OK, TransferStatus::Completed),
+                Err(e) => {
+                    log::warn!("Failed to save {} to {}: {}", transfer.info.name, path.display(), e);
+                    (OUTCOME_NOT_SAVED, TransferStatus::Failed(e.to_string()))
+                }
+            }
+        };
+
+        transfer.outcome = Some(outcome);
+        self.update(&mut transfer.info, status);
+        let id = transfer.info.transfer_id;
+        self.reply(transfer.info.peer, transfer.channel, TransferFrame::Complete { id, outcome });
+    }
+
+    fn update(&self, info: &mut TransferInfo, status: TransferStatus) {
+        info.status = status;
+        let _ = self.updates.send(info.clone());
+    }
+
+    fn reply(&self, to: u32, channel: u8, frame: TransferFrame) {
+        let _ = self.outbound.send(transfer_packet(to, channel, &frame));
+    }
+
+    fn start(&self, id: u32) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(transfer) = state.outgoing.get_mut(&id).filter(|t| !t.running) {
+            transfer.running = true;
+            tokio::spawn(self.clone().run(id));
+        }
+    }
+
+    /// Sending side of a transfer: repeat the offer until it is accepted,
+    /// then send each chunk until it is acked, pausing the transfer when the
+    /// receiver stops answering
+    async fn run(self, id: u32) {
+        let mut attempts = 0;
+        let mut next_send = Instant::now();
+        loop {
+            let (packet, notify, progress) = {
+                let mut state = self.state.lock().unwrap();
+                let Some(transfer) = state.outgoing.get_mut(&id) else {
+                    return;
+                };
+                if transfer.info.status.is_final() || attempts >= self.config.max_attempts {
+                    if !transfer.info.status.is_final() {
+                        log::info!("Pausing file transfer {:08x}: no answer from {:08x}", id, transfer.info.peer);
+                        self.update(&mut transfer.info, TransferStatus::Paused);
+                    }
+                    transfer.running = false;
+                    return;
+                }
+
+                let total = transfer.data.len().div_ceil(Self::chunk_size()) as u32;
+                let frame = if !transfer.accepted || total == 0 {
+                    TransferFrame::Offer {
+                        id,
+                        size: transfer.data.len() as u32,
+                        chunk_size: Self::chunk_size() as u16,
+                        sha256: transfer.sha256,
+                        name: transfer.info.name.clone(),
+                    }
+                } else {
+                    // Past the last ack we are waiting for `Complete`; the
+                    // last chunk again prompts the receiver to repeat it
+                    let index = transfer.acked.min(total - 1);
+                    let data = transfer.data.chunks(Self::chunk_size()).nth(index as usize).unwrap().to_vec();
+                    TransferFrame::Chunk { id, index, data }
+                };
+                let packet = transfer_packet(transfer.info.peer, transfer.channel, &frame);
+                (packet, Arc::clone(&transfer.notify), (transfer.accepted, transfer.acked))
+            };
+
+            tokio::time::sleep_until(next_send).await;
+            let _ = self.outbound.send(packet.clone());
+            next_send = Instant::now() + self.interval(&packet);
+            attempts += 1;
+
+            let deadline = Instant::now() + self.config.ack_timeout;
+            while tokio::time::timeout_at(deadline, notify.notified()).await.is_ok() {
+                let state = self.state.lock().unwrap();
+                match state.outgoing.get(&id) {
+                    Some(t) if !t.info.status.is_final() && (t.accepted, t.acked) == progress => continue,
+                    _ => {
+                        attempts = 0;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Time to leave between transmissions so the transfer uses no more than
+    /// the duty cycle allows (or `max_channel_share` where there is no limit)
+    fn interval(&self, packet: &MeshPacket) -> Duration {
+        let encoded = PayloadSize::of(packet).map(|size| size.encoded_bytes).unwrap_or(0);
+        let mut radio = RadioManager::new();
+        radio.set_config(self.radio.lock().unwrap().clone());
+
+        let air_time_ms = radio.calculate_air_time_ms(encoded + LORA_HEADER_LEN);
+        let share = radio.get_config().duty_cycle_percent()
+            .min(self.config.max_channel_share)
+            .max(MIN_CHANNEL_SHARE);
+        Duration::from_secs_f32(air_time_ms / 1000.0 * 100.0 / share)
+    }
+}
+
+fn transfer_packet(to: u32, channel: u8, frame: &TransferFrame) -> MeshPacket {
+    MeshPacket {
+        to,
+        channel,
+        payload: Some(PayloadVariant::App(PortNum::FILE_TRANSFER_APP, frame.encode())),
+        ..Default::default()
+    }
+}
+
+#[async_trait]
+impl PortHandler for TransferService {
+    async fn handle(&self, packet: &MeshPacket, data: &Data) -> Result<(), ProtocolError> {
+        self.handle_frame(packet, &data.payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    fn fast_service(outbound: mpsc::UnboundedSender<MeshPacket>) -> TransferService {
+        let service = TransferService::new(outbound).with_config(TransferConfig {
+            ack_timeout: Duration::from_millis(200),
+            max_attempts: 2,
+            max_channel_share: 100.0,
+            ..Default::default()
+        });
+        service.set_radio_config(RadioConfig { spreading_factor: 7, bandwidth: 500_000, ..Default::default() });
+        service
+    }
+
+    /// Deliver everything `from` sends to `to` while `link` is up
+    fn relay(mut rx: mpsc::UnboundedReceiver<MeshPacket>, from: u32, to: TransferService, link: Arc<AtomicBool>) {
+        tokio::spawn(async move {
+            while let Some(packet) = rx.recv().await {
+                if let (true, Some(PayloadVariant::App(_, payload))) = (link.load(Ordering::SeqCst), &packet.payload) {
+                    to.handle_frame(&MeshPacket { from, ..packet.clone() }, payload).unwrap();
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn test_frame_round_trip() {
+        let frames = [
+            TransferFrame::Offer { id: 1, size: 1000, chunk_size: 220, sha256: [7; 32], name: "track.gpx".to_string() },
+            TransferFrame::Accept { id: 1, received: 3 },
+            TransferFrame::Chunk { id: 1, index: 4, data: vec![1, 2, 3] },
+            TransferFrame::Complete { id: 1, outcome: OUTCOME_CORRUPTED },
+            TransferFrame::Cancel { id: 1 },
+        ];
+        for frame in frames {
+            assert_eq!(TransferFrame::decode(&frame.encode()).unwrap(), frame);
+        }
+        assert!(TransferFrame::decode(&[4, 1, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_zero_channel_share_is_clamped() {
+        let service = TransferService::new(mpsc::unbounded_channel().0)
+            .with_config(TransferConfig { max_channel_share: 0.0, ..Default::default() });
+        let packet = transfer_packet(1, 0, &TransferFrame::Cancel { id: 1 });
+        assert!(service.interval(&packet) > Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_resumes_after_link_loss() {
+        let (a_tx, a_rx) = mpsc::unbounded_channel();
+        let (b_tx, b_rx) = mpsc::unbounded_channel();
+        let sender = fast_service(a_tx);
+        let receiver = fast_service(b_tx);
+        let link = Arc::new(AtomicBool::new(true));
+        relay(a_rx, 0xa, receiver.clone(), Arc::clone(&link));
+        relay(b_rx, 0xb, sender.clone(), Arc::clone(&link));
+
+        let path = std::env::temp_dir().join(format!("lora-transfer-{}.bin", rand::random::<u32>()));
+        let file: Vec<u8> = (0..TransferService::chunk_size() * 3 + 10).map(|i| i as u8).collect();
+        let mut incoming = receiver.subscribe();
+        let mut outgoing = sender.subscribe();
+        let id = sender.send(0xb, 0, "track.gpx", file.clone()).unwrap();
+
+        let offer = incoming.recv().await.unwrap();
+        assert_eq!((offer.transfer_id, offer.name.as_str(), offer.size), (id, "track.gpx", file.len()));
+        receiver.accept(id, path.clone()).unwrap();
+
+        // Drop the link once the first chunk is in, until the sender gives up
+        loop {
+            let update = incoming.recv().await.unwrap();
+            if update.transferred > 0 {
+                link.store(false, Ordering::SeqCst);
+                break;
+            }
+        }
+        while outgoing.recv().await.unwrap().status != TransferStatus::Paused {}
+
+        link.store(true, Ordering::SeqCst);
+        sender.resume_paused();
+        let done = loop {
+            let update = outgoing.recv().await.unwrap();
+            if update.status.is_final() {
+                break update;
+            }
+        };
+        assert_eq!((done.status, done.transferred), (TransferStatus::Completed, file.len()));
+        assert_eq!(std::fs::read(&path).unwrap(), file);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -294,6 +294,53 @@ impl RadioConfig {
     }
 }
 
+/// Settings of a radio as it reports its LoRa config. Region and modem
+/// preset codes follow `Config.LoRaConfig`; bandwidth there is in kHz.
+impl From<&crate::protocol::RadioConfig> for RadioConfig {
+    fn from(lora: &crate::protocol::RadioConfig) -> Self {
+        let region = match lora.region {
+            2 => Region::EU433,
+            3 => Region::EU868,
+            4 => Region::CN,
+            5 => Region::JP,
+            6 => Region::ANZ,
+            7 => Region::KR,
+            8 => Region::TW,
+            9 => Region::RU,
+            10 => Region::IN,
+            11 => Region::NZ865,
+            12 => Region::TH,
+            14 => Region::UA433,
+            15 => Region::UA868,
+            16 => Region::MY433,
+            17 => Region::MY919,
+            18 => Region::SG923,
+            _ => Region::US,
+        };
+        let mut config = Self::for_region(region);
+        if lora.tx_power > 0 {
+            config.tx_power = lora.tx_power as u8;
+        }
+
+        if !lora.use_preset {
+            config.bandwidth = lora.bandwidth * 1000;
+            config.spreading_factor = lora.spread_factor as u8;
+            config.coding_rate = lora.coding_rate as u8;
+            config.preset = None;
+            return config;
+        }
+        config.with_preset(match lora.modem_preset {
+            1 => RadioPreset::LongSlow,
+            2 => RadioPreset::VeryLongSlow,
+            3 => RadioPreset::MediumSlow,
+            4 => RadioPreset::MediumFast,
+            5 => RadioPreset::ShortSlow,
+            6 => RadioPreset::ShortFast,
+            _ => RadioPreset::LongFast,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -312,6 +359,19 @@ mod tests {
         assert_eq!(config.bandwidth, 125000);
     }
 
+    #[test]
+    fn test_from_device_lora_config() {
+        let lora = crate::protocol::RadioConfig { use_preset: true, modem_preset: 6, region: 3, ..Default::default() };
+        let config = RadioConfig::from(&lora);
+        assert!(matches!(config.region, Region::EU868));
+        assert_eq!((config.spreading_factor, config.bandwidth), (7, 250000));
+
+        let custom = crate::protocol::RadioConfig { bandwidth: 62, spread_factor: 12, coding_rate: 8, ..Default::default() };
+        let config = RadioConfig::from(&custom);
+        assert_eq!((config.spreading_factor, config.bandwidth, config.coding_rate), (12, 62000, 8));
+        assert!(config.preset.is_none());
+    }
+
     #[test]
     fn test_range_estimation() {
         let short_config = RadioConfig::default().with_preset(RadioPreset::ShortFast);
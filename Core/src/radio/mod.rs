@@ -2,7 +2,7 @@ pub mod config;
 
 pub use config::{RadioConfig, Region, RadioPreset};
 
-use crate::protocol::{MeshPacket, AdminMessage};
+use crate::protocol::MeshPacket;
 use crate::device::{Device, DeviceError};
 
 /// Advanced radio configuration and management
@@ -68,6 +68,8 @@ impl RadioManager {
                 hop_limit: Some(1),
                 channel: Some(0),
                 message_type: crate::protocol::MessageType::Admin,
+                reply_id: None,
+                is_reaction: false,
             };
 
             device.send_message(&message).await
@@ -101,7 +103,7 @@ impl RadioManager {
     }
 
     /// Set device-specific radio configuration
-    pub async fn set_device_config(&self, device_id: &str, config: RadioConfig) -> Result<(), DeviceError> {
+    pub async fn set_device_config(&self, _device_id: &str, config: RadioConfig) -> Result<(), DeviceError> {
         // In a real implementation, this would store the config per device
         // For now, just validate the config
         let mut temp_manager = RadioManager::new();
@@ -110,19 +112,21 @@ impl RadioManager {
         Ok(())
     }
 
-    /// Get device-specific radio configuration
-    pub async fn get_device_config(&self, device_id: &str) -> Result<Option<RadioConfig>, DeviceError> {
-        // In a real implementation, this would retrieve the config for the specific device
-        // For now, return the current config if device_id is valid
-        if !device_id.is_empty() {
-            Ok(Some(self.config.clone()))
-        } else {
-            Ok(None)
+    /// Radio settings the attached device last reported, converted from its
+    /// LoRa config; `None` until it has reported them. This is the device's
+    /// cached copy, which `LoraCommsManager` refreshes on every LoRa read and
+    /// write; `LoraCommsManager::get_radio_config` reads the radio.
+    pub async fn get_device_config(&self) -> Result<Option<RadioConfig>, DeviceError> {
+        match &self.device {
+            Some(device) => Ok(device.get_lora_config().as_ref().map(RadioConfig::from)),
+            None => Err(DeviceError::ConnectionFailed {
+                message: "No device connected".to_string(),
+            }),
         }
     }
 
     /// Get available presets for a region
-    pub fn get_region_presets(region: &Region) -> Vec<RadioPreset> {
+    pub fn get_region_presets(_region: &Region) -> Vec<RadioPreset> {
         // All presets are generally available for all regions
         // Some regions might have restrictions, but for now return all
         vec![
@@ -281,6 +285,12 @@ mod tests {
         assert!(manager.validate_config().is_err());
     }
 
+    #[tokio::test]
+    async fn test_device_config_needs_a_device() {
+        let manager = RadioManager::new();
+        assert!(manager.get_device_config().await.is_err());
+    }
+
     #[test]
     fn test_air_time_calculation() {
         let manager = RadioManager::new();
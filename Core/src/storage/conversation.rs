@@ -0,0 +1,141 @@
+//! Conversations: stored messages grouped into one direct-message thread
+//! per peer node and one broadcast thread per channel.
+
+use crate::protocol::{MeshMessage, MeshPacket};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Thread a message belongs to. Serialized as its key, `direct:<node num>`
+/// or `channel:<index>`, which is also what the FFI takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum ConversationId {
+    /// Direct messages exchanged with one peer
+    Direct { peer: u32 },
+    /// Broadcasts on one local channel
+    Channel { index: u8 },
+}
+
+impl ConversationId {
+    /// Thread for a message we received
+    pub fn incoming(packet: &MeshPacket) -> Self {
+        if packet.is_broadcast() {
+            ConversationId::Channel { index: packet.channel }
+        } else {
+            ConversationId::Direct { peer: packet.from }
+        }
+    }
+
+    /// Thread for a message we sent
+    pub fn outgoing(packet: &MeshPacket) -> Self {
+        if packet.is_broadcast() {
+            ConversationId::Channel { index: packet.channel }
+        } else {
+            ConversationId::Direct { peer: packet.to }
+        }
+    }
+}
+
+impl fmt::Display for ConversationId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConversationId::Direct { peer } => write!(f, "direct:{}", peer),
+            ConversationId::Channel { index } => write!(f, "channel:{}", index),
+        }
+    }
+}
+
+impl FromStr for ConversationId {
+    type Err = String;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid conversation key: {}", key);
+        match key.split_once(':') {
+            Some(("direct", peer)) => Ok(ConversationId::Direct { peer: peer.parse().map_err(|_| invalid())? }),
+            Some(("channel", index)) => Ok(ConversationId::Channel { index: index.parse().map_err(|_| invalid())? }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl From<ConversationId> for String {
+    fn from(id: ConversationId) -> Self {
+        id.to_string()
+    }
+}
+
+impl TryFrom<String> for ConversationId {
+    type Error = String;
+
+    fn try_from(key: String) -> Result<Self, Self::Error> {
+        key.parse()
+    }
+}
+
+/// A message as stored, with its place in the conversation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredMessage {
+    /// Storage id; increases with arrival order and serves as the page cursor
+    pub id: i64,
+    pub conversation: ConversationId,
+    /// Sent by us rather than received
+    pub outgoing: bool,
+    /// Outgoing messages are always read
+    pub read: bool,
+    /// Storage id of the message this one replies to, if it is in history
+    pub reply_to: Option<i64>,
+    /// Emoji tapbacks on this message, oldest first. Reactions are listed
+    /// here rather than as messages of their own.
+    pub reactions: Vec<Reaction>,
+    pub message: MeshMessage,
+}
+
+/// An emoji tapback attached to a message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reaction {
+    pub id: i64,
+    pub from: String,
+    pub emoji: String,
+    pub outgoing: bool,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// One thread in the conversation list
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationSummary {
+    pub id: ConversationId,
+    pub last_activity: DateTime<Utc>,
+    /// Received messages newer than the read marker
+    pub unread_count: u32,
+    pub last_message: Option<StoredMessage>,
+}
+
+/// A page of a conversation, oldest message first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MessagePage {
+    pub messages: Vec<StoredMessage>,
+    /// Pass as `before` to fetch the next older page; `None` at the start of
+    /// the conversation
+    pub next_cursor: Option<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversation_keys() {
+        let dm = ConversationId::Direct { peer: 0x12345678 };
+        assert_eq!(dm.to_string(), "direct:305419896");
+        assert_eq!("direct:305419896".parse::<ConversationId>().unwrap(), dm);
+        assert_eq!(serde_json::to_string(&ConversationId::Channel { index: 2 }).unwrap(), "\"channel:2\"");
+        assert!("channel:300".parse::<ConversationId>().is_err());
+        assert!("group:1".parse::<ConversationId>().is_err());
+
+        let packet = MeshPacket::new_text_message(1, 2, "hi");
+        assert_eq!(ConversationId::incoming(&packet), ConversationId::Direct { peer: 1 });
+        assert_eq!(ConversationId::outgoing(&packet), ConversationId::Direct { peer: 2 });
+    }
+}
@@ -0,0 +1,93 @@
+//! Persistent storage for message history, node records, position tracks
+//! and telemetry samples.
+//!
+//! The manager and message processor only talk to the [`Storage`] trait;
+//! [`SqliteStorage`] is the file-backed implementation.
+
+pub mod conversation;
+pub mod sqlite;
+
+pub use conversation::{ConversationId, ConversationSummary, MessagePage, Reaction, StoredMessage};
+pub use sqlite::SqliteStorage;
+
+use crate::protocol::{MeshMessage, MeshNodeInfo, Position, TelemetryData};
+
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("Migration error: {0}")]
+    Migration(String),
+    #[error("Invalid stored record: {0}")]
+    InvalidRecord(String),
+}
+
+/// Backing store for everything that should survive a restart. Calls are
+/// short local I/O and are made directly from async code.
+pub trait Storage: Send + Sync {
+    /// Append a sent or received message to `conversation`, returning its
+    /// storage id. Bumps the conversation's last activity. A message with a
+    /// `reply_id` is linked to the message it answers in the same thread.
+    fn save_message(
+        &self,
+        conversation: ConversationId,
+        outgoing: bool,
+        message: &MeshMessage,
+    ) -> Result<i64, StorageError>;
+
+    /// Message history, oldest first. With a `limit`, only the most recent
+    /// `limit` messages are returned.
+    fn messages(&self, limit: Option<usize>) -> Result<Vec<MeshMessage>, StorageError>;
+
+    /// Delete the whole message history and every conversation, returning
+    /// how many messages it held
+    fn clear_messages(&self) -> Result<usize, StorageError>;
+
+    /// All conversations, most recently active first
+    fn conversations(&self) -> Result<Vec<ConversationSummary>, StorageError>;
+
+    /// Up to `limit` messages of `conversation` older than the `before`
+    /// cursor, or the newest ones without a cursor
+    fn conversation_messages(
+        &self,
+        conversation: ConversationId,
+        before: Option<i64>,
+        limit: usize,
+    ) -> Result<MessagePage, StorageError>;
+
+    /// Most recent stored message with this packet id
+    fn message_by_packet_id(&self, packet_id: u32) -> Result<Option<StoredMessage>, StorageError>;
+
+    /// Mark received messages up to and including `up_to` as read, or the
+    /// whole conversation without an id. The read marker never moves back.
+    fn mark_read(&self, conversation: ConversationId, up_to: Option<i64>) -> Result<(), StorageError>;
+
+    /// Insert or replace a node record. The record's position and device
+    /// metrics are kept with it; use `save_position`/`save_telemetry` for
+    /// history.
+    fn save_node(&self, node: &MeshNodeInfo) -> Result<(), StorageError>;
+
+    fn node(&self, num: u32) -> Result<Option<MeshNodeInfo>, StorageError>;
+
+    fn nodes(&self) -> Result<Vec<MeshNodeInfo>, StorageError>;
+
+    /// Record a position report from `node_num`
+    fn save_position(&self, node_num: u32, position: &Position) -> Result<(), StorageError>;
+
+    /// Position reports from `node_num`, newest first
+    fn positions(&self, node_num: u32, limit: usize) -> Result<Vec<Position>, StorageError>;
+
+    /// Record a telemetry sample from `node_num`
+    fn save_telemetry(&self, node_num: u32, telemetry: &TelemetryData) -> Result<(), StorageError>;
+
+    /// Telemetry samples from `node_num`, newest first
+    fn telemetry(&self, node_num: u32, limit: usize) -> Result<Vec<TelemetryData>, StorageError>;
+}
+
+impl std::fmt::Debug for dyn Storage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Storage")
+    }
+}
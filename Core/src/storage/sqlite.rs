@@ -0,0 +1,705 @@
+//! SQLite implementation of [`Storage`].
+//!
+//! The schema is versioned with `PRAGMA user_version`; opening a database
+//! applies every migration newer than its version, each in its own
+//! transaction. Records that have no queryable structure beyond a few
+//! columns (users, telemetry variants) are kept as JSON.
+
+use super::{ConversationId, ConversationSummary, MessagePage, Reaction, Storage, StorageError, StoredMessage};
+use crate::protocol::{MeshMessage, MeshNodeInfo, MessageType, Position, TelemetryData, TelemetryVariant};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Schema migrations, in order. Entry `n` moves the database from version
+/// `n` to `n + 1`; never edit one that has shipped, append a new one.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE messages (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        packet_id INTEGER,
+        from_node TEXT NOT NULL,
+        to_node TEXT NOT NULL,
+        channel INTEGER,
+        text TEXT NOT NULL,
+        timestamp_ms INTEGER NOT NULL,
+        want_ack INTEGER,
+        hop_limit INTEGER,
+        message_type TEXT NOT NULL
+    );
+    CREATE INDEX messages_timestamp ON messages (timestamp_ms);
+
+    CREATE TABLE nodes (
+        num INTEGER PRIMARY KEY,
+        user TEXT,
+        position TEXT,
+        device_metrics TEXT,
+        snr REAL NOT NULL,
+        last_heard INTEGER NOT NULL,
+        channel INTEGER NOT NULL,
+        via_mqtt INTEGER NOT NULL,
+        hops_away INTEGER NOT NULL,
+        is_favorite INTEGER NOT NULL
+    );
+
+    CREATE TABLE positions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        node_num INTEGER NOT NULL,
+        latitude_i INTEGER NOT NULL,
+        longitude_i INTEGER NOT NULL,
+        altitude INTEGER NOT NULL,
+        time INTEGER NOT NULL,
+        recorded_at INTEGER NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX positions_node ON positions (node_num, id);
+
+    CREATE TABLE telemetry (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        node_num INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        time INTEGER NOT NULL,
+        recorded_at INTEGER NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX telemetry_node ON telemetry (node_num, id);",
+    // Conversations. Messages from before this version were all received,
+    // so they are filed by sender (or channel) and count as read.
+    "ALTER TABLE messages ADD COLUMN conversation TEXT NOT NULL DEFAULT '';
+    ALTER TABLE messages ADD COLUMN outgoing INTEGER NOT NULL DEFAULT 0;
+    UPDATE messages SET conversation = CASE
+        WHEN to_node IN ('broadcast', '^all') THEN 'channel:' || COALESCE(channel, 0)
+        ELSE 'direct:' || from_node
+    END;
+    CREATE INDEX messages_conversation ON messages (conversation, id);
+
+    CREATE TABLE conversations (
+        key TEXT PRIMARY KEY,
+        last_activity_ms INTEGER NOT NULL,
+        last_read_id INTEGER NOT NULL DEFAULT 0
+    );
+    INSERT INTO conversations (key, last_activity_ms, last_read_id)
+        SELECT conversation, MAX(timestamp_ms), MAX(id) FROM messages GROUP BY conversation;",
+    // Replies and reactions; parent_id links to the message they answer
+    "ALTER TABLE messages ADD COLUMN reply_id INTEGER;
+    ALTER TABLE messages ADD COLUMN is_reaction INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE messages ADD COLUMN parent_id INTEGER;
+    CREATE INDEX messages_packet ON messages (packet_id);
+    CREATE INDEX messages_parent ON messages (parent_id);",
+];
+
+/// File-backed store. One connection, serialised behind a mutex.
+pub struct SqliteStorage {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    /// Open or create the database at `path` and bring its schema up to date
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Database that lives only as long as this value; used when no path is
+    /// configured
+    pub fn in_memory() -> Result<Self, StorageError> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut conn: Connection) -> Result<Self, StorageError> {
+        migrate(&mut conn)?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    /// Schema version of the open database
+    pub fn schema_version(&self) -> Result<u32, StorageError> {
+        Ok(self.conn.lock().unwrap().query_row("PRAGMA user_version", [], |row| row.get(0))?)
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), StorageError> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        return Err(StorageError::Migration(format!(
+            "database is at schema version {}, this build only knows {}",
+            version,
+            MIGRATIONS.len()
+        )));
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+        log::info!("Storage migrated to schema version {}", index + 1);
+    }
+    Ok(())
+}
+
+fn now_ms() -> i64 {
+    Utc::now().timestamp_millis()
+}
+
+fn to_json<T: serde::Serialize>(value: &Option<T>) -> Result<Option<String>, StorageError> {
+    Ok(value.as_ref().map(serde_json::to_string).transpose()?)
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(value: Option<String>) -> Result<Option<T>, StorageError> {
+    Ok(value.as_deref().map(serde_json::from_str).transpose()?)
+}
+
+fn message_type_name(message_type: &MessageType) -> Result<String, StorageError> {
+    match serde_json::to_value(message_type)? {
+        serde_json::Value::String(name) => Ok(name),
+        other => Ok(other.to_string()),
+    }
+}
+
+fn telemetry_kind(telemetry: &TelemetryData) -> &'static str {
+    match telemetry.variant {
+        Some(TelemetryVariant::DeviceMetrics(_)) => "device",
+        Some(TelemetryVariant::EnvironmentMetrics(_)) => "environment",
+        Some(TelemetryVariant::PowerMetrics(_)) => "power",
+        None => "none",
+    }
+}
+
+const MESSAGE_COLUMNS: &str = "packet_id, from_node, to_node, channel, text, timestamp_ms, want_ack, hop_limit, \
+     message_type, id, conversation, outgoing, reply_id, is_reaction, parent_id";
+
+/// Reactions attached to a parent are shown on it, not as messages
+const NOT_ATTACHED_REACTION: &str = "NOT (is_reaction = 1 AND parent_id IS NOT NULL)";
+
+/// Raw message columns, decoded outside the rusqlite row callback
+struct MessageRow {
+    id: i64,
+    conversation: String,
+    outgoing: bool,
+    reply_id: Option<u32>,
+    is_reaction: bool,
+    parent_id: Option<i64>,
+    packet_id: Option<u32>,
+    from: String,
+    to: String,
+    channel: Option<u8>,
+    text: String,
+    timestamp_ms: i64,
+    want_ack: Option<bool>,
+    hop_limit: Option<u8>,
+    message_type: String,
+}
+
+impl MessageRow {
+    fn read(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            packet_id: row.get(0)?,
+            from: row.get(1)?,
+            to: row.get(2)?,
+            channel: row.get(3)?,
+            text: row.get(4)?,
+            timestamp_ms: row.get(5)?,
+            want_ack: row.get(6)?,
+            hop_limit: row.get(7)?,
+            message_type: row.get(8)?,
+            id: row.get(9)?,
+            conversation: row.get(10)?,
+            outgoing: row.get(11)?,
+            reply_id: row.get(12)?,
+            is_reaction: row.get(13)?,
+            parent_id: row.get(14)?,
+        })
+    }
+
+    fn into_stored(self, last_read_id: i64) -> Result<StoredMessage, StorageError> {
+        let conversation = self.conversation.parse().map_err(StorageError::InvalidRecord)?;
+        Ok(StoredMessage {
+            id: self.id,
+            conversation,
+            outgoing: self.outgoing,
+            read: self.outgoing || self.id <= last_read_id,
+            reply_to: self.parent_id,
+            reactions: Vec::new(),
+            message: self.into_message(),
+        })
+    }
+
+    fn into_reaction(self) -> Reaction {
+        Reaction {
+            id: self.id,
+            from: self.from,
+            emoji: self.text,
+            outgoing: self.outgoing,
+            timestamp: DateTime::from_timestamp_millis(self.timestamp_ms).unwrap_or_default(),
+        }
+    }
+
+    fn into_message(self) -> MeshMessage {
+        MeshMessage {
+            from: self.from,
+            to: self.to,
+            text: self.text,
+            timestamp: DateTime::from_timestamp_millis(self.timestamp_ms).unwrap_or_default(),
+            want_ack: self.want_ack,
+            packet_id: self.packet_id,
+            hop_limit: self.hop_limit,
+            channel: self.channel,
+            message_type: serde_json::from_value(serde_json::Value::String(self.message_type))
+                .unwrap_or(MessageType::Unknown),
+            reply_id: self.reply_id,
+            is_reaction: self.is_reaction,
+        }
+    }
+}
+
+struct NodeRow {
+    num: u32,
+    user: Option<String>,
+    position: Option<String>,
+    device_metrics: Option<String>,
+    snr: f32,
+    last_heard: u32,
+    channel: u32,
+    via_mqtt: bool,
+    hops_away: u32,
+    is_favorite: bool,
+}
+
+const NODE_COLUMNS: &str =
+    "num, user, position, device_metrics, snr, last_heard, channel, via_mqtt, hops_away, is_favorite";
+
+impl NodeRow {
+    fn read(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            num: row.get(0)?,
+            user: row.get(1)?,
+            position: row.get(2)?,
+            device_metrics: row.get(3)?,
+            snr: row.get(4)?,
+            last_heard: row.get(5)?,
+            channel: row.get(6)?,
+            via_mqtt: row.get(7)?,
+            hops_away: row.get(8)?,
+            is_favorite: row.get(9)?,
+        })
+    }
+
+    fn into_node(self) -> Result<MeshNodeInfo, StorageError> {
+        Ok(MeshNodeInfo {
+            num: self.num,
+            user: from_json(self.user)?,
+            position: from_json(self.position)?,
+            device_metrics: from_json(self.device_metrics)?,
+            snr: self.snr,
+            last_heard: self.last_heard,
+            channel: self.channel,
+            via_mqtt: self.via_mqtt,
+            hops_away: self.hops_away,
+            is_favorite: self.is_favorite,
+        })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn save_message(
+        &self,
+        conversation: ConversationId,
+        outgoing: bool,
+        message: &MeshMessage,
+    ) -> Result<i64, StorageError> {
+        let message_type = message_type_name(&message.message_type)?;
+        let key = conversation.to_string();
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        // Packet ids are only unique per sender, so look in this thread only
+        let parent_id: Option<i64> = match message.reply_id {
+            Some(reply_id) => tx.query_row(
+                "SELECT id FROM messages WHERE packet_id = ?1 AND conversation = ?2 ORDER BY id DESC LIMIT 1",
+                params![reply_id, key],
+                |row| row.get(0),
+            ).optional()?,
+            None => None,
+        };
+        tx.execute(
+            "INSERT INTO messages (packet_id, from_node, to_node, channel, text, timestamp_ms, want_ack, hop_limit,
+                 message_type, conversation, outgoing, reply_id, is_reaction, parent_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![
+                message.packet_id,
+                message.from,
+                message.to,
+                message.channel,
+                message.text,
+                message.timestamp.timestamp_millis(),
+                message.want_ack,
+                message.hop_limit,
+                message_type,
+                key,
+                outgoing,
+                message.reply_id,
+                message.is_reaction,
+                parent_id,
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+        // Replying to a thread means it has been read
+        let last_read_id = if outgoing { id } else { 0 };
+        tx.execute(
+            "INSERT INTO conversations (key, last_activity_ms, last_read_id) VALUES (?1, ?2, ?3)
+             ON CONFLICT (key) DO UPDATE SET
+                 last_activity_ms = MAX(last_activity_ms, excluded.last_activity_ms),
+                 last_read_id = MAX(last_read_id, excluded.last_read_id)",
+            params![key, message.timestamp.timestamp_millis(), last_read_id],
+        )?;
+        tx.commit()?;
+        Ok(id)
+    }
+
+    fn messages(&self, limit: Option<usize>) -> Result<Vec<MeshMessage>, StorageError> {
+        let conn = self.conn.lock().unwrap();
+        // Newest `limit` rows, then flipped back to oldest first
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM messages ORDER BY id DESC LIMIT ?1", MESSAGE_COLUMNS))?;
+        let limit = limit.map(|limit| limit as i64).unwrap_or(-1);
+        let rows = stmt.query_map([limit], MessageRow::read)?.collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows.into_iter().rev().map(MessageRow::into_message).collect())
+    }
+
+    fn clear_messages(&self) -> Result<usize, StorageError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let deleted = tx.execute("DELETE FROM messages", [])?;
+        tx.execute("DELETE FROM conversations", [])?;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    fn conversations(&self) -> Result<Vec<ConversationSummary>, StorageError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT c.key, c.last_activity_ms, c.last_read_id,
+                 (SELECT COUNT(*) FROM messages m
+                  WHERE m.conversation = c.key AND m.outgoing = 0 AND m.id > c.last_read_id
+                      AND NOT (m.is_reaction = 1 AND m.parent_id IS NOT NULL))
+             FROM conversations c ORDER BY c.last_activity_ms DESC",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?, row.get::<_, u32>(3)?))
+        })?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut last_stmt = conn.prepare(&format!(
+            "SELECT {} FROM messages WHERE conversation = ?1 AND {} ORDER BY id DESC LIMIT 1",
+            MESSAGE_COLUMNS, NOT_ATTACHED_REACTION
+        ))?;
+        rows.into_iter().map(|(key, last_activity_ms, last_read_id, unread_count)| {
+            let last_message = last_stmt.query_row([&key], MessageRow::read).optional()?
+                .map(|row| row.into_stored(last_read_id))
+                .transpose()?;
+            Ok(ConversationSummary {
+                id: key.parse().map_err(StorageError::InvalidRecord)?,
+                last_activity: DateTime::from_timestamp_millis(last_activity_ms).unwrap_or_default(),
+                unread_count,
+                last_message,
+            })
+        }).collect()
+    }
+
+    fn conversation_messages(
+        &self,
+        conversation: ConversationId,
+        before: Option<i64>,
+        limit: usize,
+    ) -> Result<MessagePage, StorageError> {
+        let key = conversation.to_string();
+        let conn = self.conn.lock().unwrap();
+        let last_read_id: i64 = conn
+            .query_row("SELECT last_read_id FROM conversations WHERE key = ?1", [&key], |row| row.get(0))
+            .optional()?
+            .unwrap_or(0);
+
+        // One extra row tells whether an older page exists
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM messages WHERE conversation = ?1 AND id < ?2 AND {} ORDER BY id DESC LIMIT ?3",
+            MESSAGE_COLUMNS, NOT_ATTACHED_REACTION
+        ))?;
+        let mut rows = stmt
+            .query_map(params![key, before.unwrap_or(i64::MAX), limit as i64 + 1], MessageRow::read)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let has_more = rows.len() > limit;
+        rows.truncate(limit);
+
+        let mut messages = rows.into_iter().rev()
+            .map(|row| row.into_stored(last_read_id))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut reactions_stmt = conn.prepare(&format!(
+            "SELECT {} FROM messages WHERE parent_id = ?1 AND is_reaction = 1 ORDER BY id",
+            MESSAGE_COLUMNS
+        ))?;
+        for message in &mut messages {
+            message.reactions = reactions_stmt
+                .query_map([message.id], MessageRow::read)?
+                .map(|row| row.map(MessageRow::into_reaction))
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+        }
+        let next_cursor = if has_more { messages.first().map(|message| message.id) } else { None };
+        Ok(MessagePage { messages, next_cursor })
+    }
+
+    fn message_by_packet_id(&self, packet_id: u32) -> Result<Option<StoredMessage>, StorageError> {
+        let conn = self.conn.lock().unwrap();
+        let row = conn
+            .query_row(
+                &format!("SELECT {} FROM messages WHERE packet_id = ?1 ORDER BY id DESC LIMIT 1", MESSAGE_COLUMNS),
+                [packet_id],
+                MessageRow::read,
+            )
+            .optional()?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let last_read_id: i64 = conn
+            .query_row("SELECT last_read_id FROM conversations WHERE key = ?1", [&row.conversation], |row| row.get(0))
+            .optional()?
+            .unwrap_or(0);
+        row.into_stored(last_read_id).map(Some)
+    }
+
+    fn mark_read(&self, conversation: ConversationId, up_to: Option<i64>) -> Result<(), StorageError> {
+        self.conn.lock().unwrap().execute(
+            "UPDATE conversations SET last_read_id = MAX(last_read_id, COALESCE(?2,
+                 (SELECT MAX(id) FROM messages WHERE conversation = ?1), 0))
+             WHERE key = ?1",
+            params![conversation.to_string(), up_to],
+        )?;
+        Ok(())
+    }
+
+    fn save_node(&self, node: &MeshNodeInfo) -> Result<(), StorageError> {
+        self.conn.lock().unwrap().execute(
+            &format!("INSERT OR REPLACE INTO nodes ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)", NODE_COLUMNS),
+            params![
+                node.num,
+                to_json(&node.user)?,
+                to_json(&node.position)?,
+                to_json(&node.device_metrics)?,
+                node.snr,
+                node.last_heard,
+                node.channel,
+                node.via_mqtt,
+                node.hops_away,
+                node.is_favorite,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn node(&self, num: u32) -> Result<Option<MeshNodeInfo>, StorageError> {
+        let row = self.conn.lock().unwrap()
+            .query_row(&format!("SELECT {} FROM nodes WHERE num = ?1", NODE_COLUMNS), [num], NodeRow::read)
+            .optional()?;
+        row.map(NodeRow::into_node).transpose()
+    }
+
+    fn nodes(&self) -> Result<Vec<MeshNodeInfo>, StorageError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM nodes ORDER BY last_heard DESC", NODE_COLUMNS))?;
+        let rows = stmt.query_map([], NodeRow::read)?.collect::<rusqlite::Result<Vec<_>>>()?;
+        rows.into_iter().map(NodeRow::into_node).collect()
+    }
+
+    fn save_position(&self, node_num: u32, position: &Position) -> Result<(), StorageError> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO positions (node_num, latitude_i, longitude_i, altitude, time, recorded_at, data)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                node_num,
+                position.latitude_i,
+                position.longitude_i,
+                position.altitude,
+                position.time,
+                now_ms(),
+                serde_json::to_string(position)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn positions(&self, node_num: u32, limit: usize) -> Result<Vec<Position>, StorageError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT data FROM positions WHERE node_num = ?1 ORDER BY id DESC LIMIT ?2")?;
+        let rows = stmt.query_map(params![node_num, limit as i64], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        rows.iter().map(|data| Ok(serde_json::from_str(data)?)).collect()
+    }
+
+    fn save_telemetry(&self, node_num: u32, telemetry: &TelemetryData) -> Result<(), StorageError> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO telemetry (node_num, kind, time, recorded_at, data) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                node_num,
+                telemetry_kind(telemetry),
+                telemetry.time,
+                now_ms(),
+                serde_json::to_string(telemetry)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn telemetry(&self, node_num: u32, limit: usize) -> Result<Vec<TelemetryData>, StorageError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT data FROM telemetry WHERE node_num = ?1 ORDER BY id DESC LIMIT ?2")?;
+        let rows = stmt.query_map(params![node_num, limit as i64], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        rows.iter().map(|data| Ok(serde_json::from_str(data)?)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{DeviceMetrics, User};
+
+    #[test]
+    fn test_history_survives_reopen() {
+        let path = std::env::temp_dir().join(format!("lora-comms-{}.db", uuid::Uuid::new_v4()));
+        {
+            let storage = SqliteStorage::open(&path).unwrap();
+            assert_eq!(storage.schema_version().unwrap(), MIGRATIONS.len() as u32);
+            for text in ["one", "two", "three"] {
+                let message = MeshMessage::new_broadcast("1".into(), text.into());
+                storage.save_message(ConversationId::Channel { index: 0 }, false, &message).unwrap();
+            }
+            storage.save_node(&MeshNodeInfo {
+                num: 1,
+                user: Some(User { long_name: "Base".into(), ..Default::default() }),
+                device_metrics: Some(DeviceMetrics { battery_level: 80, ..Default::default() }),
+                last_heard: 1000,
+                ..Default::default()
+            }).unwrap();
+        }
+
+        let storage = SqliteStorage::open(&path).unwrap();
+        let recent: Vec<String> = storage.messages(Some(2)).unwrap().into_iter().map(|m| m.text).collect();
+        assert_eq!(recent, ["two", "three"]);
+        let node = storage.node(1).unwrap().unwrap();
+        assert_eq!(node.user.unwrap().long_name, "Base");
+        assert_eq!(node.device_metrics.unwrap().battery_level, 80);
+
+        assert_eq!(storage.clear_messages().unwrap(), 3);
+        assert!(storage.messages(None).unwrap().is_empty());
+        drop(storage);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_conversations_unread_and_pages() {
+        let storage = SqliteStorage::in_memory().unwrap();
+        let dm = ConversationId::Direct { peer: 7 };
+        let channel = ConversationId::Channel { index: 1 };
+        let mut ids = Vec::new();
+        for n in 0..5 {
+            let message = MeshMessage::new_text("7".into(), "1".into(), format!("dm {}", n));
+            ids.push(storage.save_message(dm, false, &message).unwrap());
+        }
+        storage.save_message(channel, false, &MeshMessage::new_broadcast("9".into(), "hello".into())).unwrap();
+
+        let list = storage.conversations().unwrap();
+        assert_eq!(list.len(), 2);
+        let summary = list.iter().find(|c| c.id == dm).unwrap();
+        assert_eq!(summary.unread_count, 5);
+        assert_eq!(summary.last_message.as_ref().unwrap().message.text, "dm 4");
+
+        let page = storage.conversation_messages(dm, None, 2).unwrap();
+        let texts: Vec<&str> = page.messages.iter().map(|m| m.message.text.as_str()).collect();
+        assert_eq!(texts, ["dm 3", "dm 4"]);
+        let page = storage.conversation_messages(dm, page.next_cursor, 10).unwrap();
+        assert_eq!(page.messages.len(), 3);
+        assert_eq!(page.next_cursor, None);
+
+        storage.mark_read(dm, Some(ids[2])).unwrap();
+        // The marker never moves back
+        storage.mark_read(dm, Some(ids[0])).unwrap();
+        let page = storage.conversation_messages(dm, None, 10).unwrap();
+        let read: Vec<bool> = page.messages.iter().map(|m| m.read).collect();
+        assert_eq!(read, [true, true, true, false, false]);
+
+        // Replying reads the thread
+        let reply = MeshMessage::new_text("1".into(), "7".into(), "ok".into());
+        storage.save_message(dm, true, &reply).unwrap();
+        storage.mark_read(channel, None).unwrap();
+        assert!(storage.conversations().unwrap().iter().all(|c| c.unread_count == 0));
+    }
+
+    #[test]
+    fn test_replies_and_reactions_link_to_parent() {
+        let storage = SqliteStorage::in_memory().unwrap();
+        let dm = ConversationId::Direct { peer: 7 };
+        let parent = MeshMessage { packet_id: Some(100), ..MeshMessage::new_text("7".into(), "1".into(), "hi".into()) };
+        let parent_id = storage.save_message(dm, false, &parent).unwrap();
+
+        let reply = MeshMessage {
+            reply_id: Some(100),
+            ..MeshMessage::new_text("1".into(), "7".into(), "hello".into())
+        };
+        storage.save_message(dm, true, &reply).unwrap();
+        let reaction = MeshMessage {
+            reply_id: Some(100),
+            is_reaction: true,
+            ..MeshMessage::new_text("7".into(), "1".into(), "❤️".into())
+        };
+        storage.save_message(dm, false, &reaction).unwrap();
+        // Same packet id in another thread is a different message
+        storage.save_message(ConversationId::Channel { index: 0 }, false, &reaction).unwrap();
+
+        let page = storage.conversation_messages(dm, None, 10).unwrap();
+        assert_eq!(page.messages.len(), 2);
+        assert_eq!(page.messages[0].reactions.len(), 1);
+        assert_eq!(page.messages[0].reactions[0].emoji, "❤️");
+        assert_eq!(page.messages[1].reply_to, Some(parent_id));
+        // The attached reaction neither shows as the latest message nor counts as unread
+        let summary = storage.conversations().unwrap().into_iter().find(|c| c.id == dm).unwrap();
+        assert_eq!(summary.unread_count, 0);
+        assert_eq!(summary.last_message.unwrap().message.text, "hello");
+
+        let orphan = storage.conversation_messages(ConversationId::Channel { index: 0 }, None, 10).unwrap();
+        assert!(orphan.messages[0].message.is_reaction);
+        assert_eq!(orphan.messages[0].reply_to, None);
+    }
+
+    #[test]
+    fn test_migration_files_existing_messages() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0]).unwrap();
+        conn.pragma_update(None, "user_version", 1).unwrap();
+        conn.execute(
+            "INSERT INTO messages (from_node, to_node, channel, text, timestamp_ms, message_type)
+             VALUES ('7', '1', 0, 'old dm', 1000, 'Text'), ('9', 'broadcast', 2, 'old broadcast', 2000, 'Text')",
+            [],
+        ).unwrap();
+
+        let storage = SqliteStorage::from_connection(conn).unwrap();
+        let list = storage.conversations().unwrap();
+        let ids: Vec<ConversationId> = list.iter().map(|c| c.id).collect();
+        assert_eq!(ids, [ConversationId::Channel { index: 2 }, ConversationId::Direct { peer: 7 }]);
+        assert!(list.iter().all(|c| c.unread_count == 0));
+    }
+
+    #[test]
+    fn test_position_and_telemetry_history() {
+        let storage = SqliteStorage::in_memory().unwrap();
+        for latitude_i in [1, 2, 3] {
+            storage.save_position(7, &Position { latitude_i, ..Default::default() }).unwrap();
+        }
+        storage.save_position(8, &Position::default()).unwrap();
+        let track: Vec<i32> = storage.positions(7, 2).unwrap().iter().map(|p| p.latitude_i).collect();
+        assert_eq!(track, [3, 2]);
+
+        storage.save_telemetry(7, &TelemetryData {
+            time: 42,
+            variant: Some(TelemetryVariant::DeviceMetrics(DeviceMetrics::default())),
+        }).unwrap();
+        assert_eq!(storage.telemetry(7, 10).unwrap()[0].time, 42);
+        assert!(storage.telemetry(8, 10).unwrap().is_empty());
+    }
+}
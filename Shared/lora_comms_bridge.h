@@ -3,6 +3,7 @@
 
 #include <stdint.h>
 #include <stdbool.h>
+#include <stddef.h>
 
 // Opaque pointer for the manager
 typedef void* LoraManagerPtr;
@@ -29,6 +30,13 @@ typedef struct {
     char* name;
     char* short_name;
     bool is_online;
+    uint32_t hw_model;
+    uint32_t role;
+    uint32_t battery_level;  // 0 if unknown
+    float voltage;
+    int64_t last_seen;       // seconds since the epoch, 0 if never heard
+    float snr;
+    int32_t hops_away;       // -1 if unknown
 } CNodeInfo;
 
 typedef struct {
@@ -39,11 +47,76 @@ typedef struct {
 // Function declarations
 char* lora_comms_test(void);
 LoraManagerPtr lora_comms_init(void);
+// Persists history and nodes in the SQLite database at db_path; NULL on failure
+LoraManagerPtr lora_comms_init_with_storage(const char* db_path);
 void lora_comms_cleanup(LoraManagerPtr manager);
 CDeviceArray lora_comms_scan_devices(LoraManagerPtr manager);
 char* lora_comms_connect_device(LoraManagerPtr manager, const char* device_path, uint32_t device_type);
 bool lora_comms_send_message(LoraManagerPtr manager, const char* device_id, const char* message, const char* destination);
+uint32_t lora_comms_send_tracked_message(LoraManagerPtr manager, const char* device_id, const char* message, const char* destination, const char* channel);
+// Text of any length as one message, fragmented if needed; returns the message id, 0 on failure
+uint32_t lora_comms_send_long_message(LoraManagerPtr manager, const char* device_id, const char* message, const char* destination, const char* channel);
+// Arbitrary bytes on a port, fragmented; the receiver gets one packet. Returns the message id, 0 on failure
+uint32_t lora_comms_send_fragmented(LoraManagerPtr manager, const char* device_id, uint32_t portnum, const uint8_t* data, size_t len, const char* destination, const char* channel);
+// Bytes the message text takes (-1 on error); max_bytes receives what fits in one packet
+int32_t lora_comms_message_size(LoraManagerPtr manager, const char* message, uint32_t reply_to, bool is_reaction, uint32_t* max_bytes);
+// Reply or react to a stored message by packet id; 0 on failure
+uint32_t lora_comms_send_reply(LoraManagerPtr manager, const char* device_id, uint32_t reply_to, const char* message);
+uint32_t lora_comms_send_reaction(LoraManagerPtr manager, const char* device_id, uint32_t reply_to, const char* emoji);
+// -1 unknown, 0 pending, 1 delivered, 2 implicitly acked, 3 failed (error_reason set), 4 not sent (local I/O error)
+int32_t lora_comms_get_delivery_status(LoraManagerPtr manager, uint32_t packet_id, uint32_t* error_reason);
 CNodeArray lora_comms_get_nodes(LoraManagerPtr manager, const char* device_id);
+bool lora_comms_set_node_online_threshold(LoraManagerPtr manager, uint32_t seconds);
+// Ask a node for its position, user record or device metrics; blocks up to 60 s.
+// JSON result, NULL on failure or timeout; free with lora_comms_free_string
+char* lora_comms_request_position(LoraManagerPtr manager, const char* device_id, const char* node);
+char* lora_comms_request_node_info(LoraManagerPtr manager, const char* device_id, const char* node);
+char* lora_comms_request_telemetry(LoraManagerPtr manager, const char* device_id, const char* node);
+// Blocks until the reply or timeout (0 = 60 s). JSON route with per-hop SNR in dB,
+// NULL on failure or timeout; free with lora_comms_free_string
+char* lora_comms_traceroute(LoraManagerPtr manager, const char* device_id, const char* node, uint32_t timeout_secs);
+char* lora_comms_get_channel_url(LoraManagerPtr manager, const char* device_id);
+bool lora_comms_set_channel_url(LoraManagerPtr manager, const char* device_id, const char* url);
+// Config section as JSON (config_type 0 = device, 1 = position, 2 = power, 3 = network,
+// 4 = display, 5 = LoRa, 6 = Bluetooth); blocks up to 60 s. Free with lora_comms_free_string
+char* lora_comms_get_config(LoraManagerPtr manager, const char* device_id, uint32_t config_type);
+// Write a config section given as JSON in the shape lora_comms_get_config returns
+bool lora_comms_set_config(LoraManagerPtr manager, const char* device_id, const char* config_json);
+// Module settings as JSON (config_type 0 = MQTT, 1 = serial, 2 = external notification,
+// 3 = store & forward, 4 = range test, 5 = telemetry, 6 = canned messages); blocks up to 60 s
+char* lora_comms_get_module_config(LoraManagerPtr manager, const char* device_id, uint32_t config_type);
+bool lora_comms_set_module_config(LoraManagerPtr manager, const char* device_id, const char* config_json);
+// Canned messages as a JSON array of strings; at most 200 bytes joined with '|'
+char* lora_comms_get_canned_messages(LoraManagerPtr manager, const char* device_id);
+bool lora_comms_set_canned_messages(LoraManagerPtr manager, const char* device_id, const char* messages_json);
+// Apply a JSON config session in one begin/commit edit, stopping (uncommitted) at the first rejected change and rebooting the radio to discard it; returns the report JSON or NULL
+char* lora_comms_apply_config_session(LoraManagerPtr manager, const char* device_id, const char* session_json);
+// Profile backup as YAML (yaml = true) or JSON, without Wi-Fi/MQTT credentials unless include_secrets;
+// restore accepts either, writes only the sections that differ unless full, and returns the report JSON
+char* lora_comms_backup_profile(LoraManagerPtr manager, const char* device_id, bool yaml, bool include_secrets);
+char* lora_comms_restore_profile(LoraManagerPtr manager, const char* device_id, const char* profile, bool full);
+// Field-level diff against a profile as a JSON plan; applying sends only the changed sections
+char* lora_comms_plan_profile(LoraManagerPtr manager, const char* device_id, const char* profile);
+char* lora_comms_apply_profile_plan(LoraManagerPtr manager, const char* device_id, const char* plan_json);
+// JSON array, oldest first; limit 0 returns everything. Free with lora_comms_free_string
+char* lora_comms_get_message_history(LoraManagerPtr manager, const char* device_id, uint32_t limit);
+bool lora_comms_clear_message_history(LoraManagerPtr manager, const char* device_id);
+// Conversation keys are "direct:<node num>" or "channel:<index>". JSON results
+// are freed with lora_comms_free_string; before/up_to of 0 mean "none".
+char* lora_comms_get_conversations(LoraManagerPtr manager);
+char* lora_comms_get_conversation_messages(LoraManagerPtr manager, const char* conversation, int64_t before, uint32_t limit);
+bool lora_comms_mark_conversation_read(LoraManagerPtr manager, const char* conversation, int64_t up_to);
+// File transfers. destination is a node id such as "!12345678"; send returns
+// the transfer id, 0 on failure. The callback gets a JSON transfer record on
+// every offer, progress step and outcome, valid only during the call; pass
+// NULL to stop. lora_comms_get_file_transfers is freed with lora_comms_free_string.
+uint32_t lora_comms_send_file(LoraManagerPtr manager, const char* device_id, const char* path, const char* destination, const char* channel);
+bool lora_comms_accept_file(LoraManagerPtr manager, uint32_t transfer_id, const char* save_path);
+bool lora_comms_reject_file(LoraManagerPtr manager, uint32_t transfer_id);
+bool lora_comms_cancel_file_transfer(LoraManagerPtr manager, uint32_t transfer_id);
+bool lora_comms_resume_file_transfer(LoraManagerPtr manager, uint32_t transfer_id);
+char* lora_comms_get_file_transfers(LoraManagerPtr manager);
+bool lora_comms_set_transfer_callback(LoraManagerPtr manager, void (*callback)(const char* transfer_json));
 void lora_comms_free_device_array(CDeviceArray array);
 void lora_comms_free_node_array(CNodeArray array);
 void lora_comms_free_string(char* string);